@@ -1,41 +1,287 @@
 //! A command-line tool to scrape Certificate Transparency logs
 
-use clap::{value_parser, Parser};
+use clap::{value_parser, Parser, Subcommand};
+#[cfg(feature = "compression")]
+use scrape_ct_log::compression::{Completion, CompressedWriter, Compression};
+#[cfg(feature = "decode")]
+use scrape_ct_log::domain_writer::{self, DomainWriter};
+#[cfg(feature = "parquet")]
+use scrape_ct_log::parquet_writer::{self, ParquetWriter};
+#[cfg(feature = "serve")]
+use scrape_ct_log::server::Server;
+#[cfg(feature = "sqlite")]
+use scrape_ct_log::sqlite_writer::{self, SqliteWriter};
+#[cfg(feature = "decode")]
+use scrape_ct_log::summary::{self, Summary, SummaryWriter};
 use scrape_ct_log::{
-	file_writer::{self, FileWriter, OutputFormat},
-	fix_url, runner,
+	document::EntryType,
+	file_writer::{self, DocumentWriter, Field, Fields, FileWriter, OutputFormat, Split},
+	filter::{EntryTypeMatch, Filter, TimestampRange},
+	fix_url,
+	locate::Locator,
+	log_list::{self, LogList, LogState, Selector},
+	parse_time,
+	pem_writer::{self, PemWriter},
+	precert_link::PrecertLinks,
+	reader, runner,
+	template::{log_slug, Template},
 };
-use std::path::PathBuf;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::Arc;
+#[cfg(feature = "compression")]
+use std::sync::Mutex;
+use std::thread::available_parallelism;
 use url::Url;
 
+type Writer = Box<dyn Write + Send + Sync>;
+
+/// What a scrape gets written out as: either a document in one of the streaming formats, or
+/// something that needs a processor of its own
+#[derive(Clone, Copy, Debug)]
+enum Format {
+	Stream(OutputFormat),
+	Pem,
+	#[cfg(feature = "decode")]
+	Domains,
+	#[cfg(feature = "decode")]
+	Summary,
+	#[cfg(feature = "parquet")]
+	Parquet,
+	#[cfg(feature = "sqlite")]
+	Sqlite,
+}
+
+impl Format {
+	/// The streaming format, if that's what this is
+	fn stream(self) -> Option<OutputFormat> {
+		match self {
+			Format::Stream(format) => Some(format),
+			Format::Pem => None,
+			#[cfg(feature = "decode")]
+			Format::Domains => None,
+			#[cfg(feature = "decode")]
+			Format::Summary => None,
+			#[cfg(feature = "parquet")]
+			Format::Parquet => None,
+			#[cfg(feature = "sqlite")]
+			Format::Sqlite => None,
+		}
+	}
+}
+
+impl Default for Format {
+	fn default() -> Self {
+		Format::Stream(OutputFormat::default())
+	}
+}
+
+impl std::fmt::Display for Format {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		match self {
+			Format::Stream(format) => format.fmt(formatter),
+			Format::Pem => formatter.write_str("pem"),
+			#[cfg(feature = "decode")]
+			Format::Domains => formatter.write_str("domains"),
+			#[cfg(feature = "decode")]
+			Format::Summary => formatter.write_str("summary"),
+			#[cfg(feature = "parquet")]
+			Format::Parquet => formatter.write_str("parquet"),
+			#[cfg(feature = "sqlite")]
+			Format::Sqlite => formatter.write_str("sqlite"),
+		}
+	}
+}
+
+impl TryFrom<&str> for Format {
+	type Error = String;
+
+	fn try_from(s: &str) -> Result<Self, Self::Error> {
+		match s {
+			"pem" => Ok(Format::Pem),
+			#[cfg(feature = "decode")]
+			"domains" => Ok(Format::Domains),
+			#[cfg(feature = "decode")]
+			"summary" => Ok(Format::Summary),
+			#[cfg(feature = "parquet")]
+			"parquet" => Ok(Format::Parquet),
+			#[cfg(feature = "sqlite")]
+			"sqlite" => Ok(Format::Sqlite),
+			_ => OutputFormat::try_from(s).map(Format::Stream),
+		}
+	}
+}
+
+/// Where a scrape gets written to
+enum Destination {
+	Writer(Writer),
+	Directory(PathBuf),
+	/// A series of files, with a new one started whenever the current one gets too big
+	Split(Split<Writer>),
+	/// Databases get opened by the processor itself, so all we can give it is where to find it
+	#[cfg(feature = "sqlite")]
+	Database(PathBuf),
+}
+
+impl std::fmt::Debug for Destination {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		match self {
+			Destination::Writer(_) => formatter.write_str("a file"),
+			Destination::Directory(path) => write!(formatter, "the directory {}", path.display()),
+			Destination::Split(split) => write!(formatter, "{split:?}"),
+			#[cfg(feature = "sqlite")]
+			Destination::Database(path) => write!(formatter, "the database {}", path.display()),
+		}
+	}
+}
+
 #[derive(Clone, Debug, Parser)]
 #[command(
 	name = "scrape-ct-log",
 	about = "Fast, efficient scraping of Certificate Transparency logs",
-	version
+	version,
+	args_conflicts_with_subcommands = true,
+	subcommand_negates_reqs = true
 )]
+#[allow(clippy::struct_excessive_bools)] // They're all command-line flags
 struct Config {
-	/// The base URL of the Certificate Transparency log to scrape
-	#[arg(name = "log_url")]
-	log_url: Url,
+	#[command(subcommand)]
+	command: Option<Command>,
+
+	/// The base URL(s) of the Certificate Transparency log(s) to scrape
+	#[arg(
+		name = "log_url",
+		required_unless_present = "log_list",
+		conflicts_with = "log_list"
+	)]
+	log_url: Vec<Url>,
+
+	/// Find the log(s) to scrape in a CT log list (v3 JSON format), from a local file or URL
+	#[arg(long, value_name = "FILE_OR_URL")]
+	log_list: Option<String>,
+
+	/// Select logs from the log list with this (base64) log ID
+	#[arg(long, requires = "log_list", conflicts_with = "log_url", value_parser = |s: &str| log_list::parse_log_id(s))]
+	log_id: Vec<Vec<u8>>,
+
+	/// Select logs from the log list whose description contains this string
+	#[arg(long, requires = "log_list", conflicts_with = "log_url")]
+	log_description: Vec<String>,
+
+	/// Select logs from the log list run by this operator
+	#[arg(long, requires = "log_list", conflicts_with = "log_url")]
+	log_operator: Vec<String>,
+
+	/// Select logs from the log list in this state
+	#[arg(long, requires = "log_list", conflicts_with = "log_url", value_parser = |s: &str| LogState::try_from(s))]
+	log_state: Vec<LogState>,
 
 	/// The format of the output produced from the scrape
-	#[arg(short, long, default_value_t, value_parser = |s: &str| OutputFormat::try_from(s))]
-	format: OutputFormat,
+	#[arg(short, long, default_value_t, value_parser = |s: &str| Format::try_from(s))]
+	format: Format,
 
-	/// Write the scraped data to the specified file
+	/// Write the scraped data to the specified file (or, for pem output, directory)
 	#[arg(short, long)]
 	output: Option<PathBuf>,
 
+	/// Write each log's scraped data to a separate file, named according to this template
+	/// ("{log}" is replaced with a name derived from the log's URL, and, when splitting the
+	/// output, "{start}" and "{end}" with the first and last entry numbers in each file)
+	#[arg(long, value_name = "TEMPLATE", conflicts_with = "output", value_parser = |s: &str| Template::new(s, &["log", "start", "end"]))]
+	output_template: Option<Template>,
+
+	/// Start a new output file after this many entries
+	#[arg(long, value_name = "N", requires = "output_template", value_parser = value_parser!(u64).range(1..))]
+	split_entries: Option<u64>,
+
+	/// Start a new output file once the current one is this many bytes (before compression)
+	#[arg(long, value_name = "N", requires = "output_template", value_parser = value_parser!(u64).range(1..))]
+	split_bytes: Option<u64>,
+
+	/// Compress the output with this algorithm (gzip, zstd, or xz); inferred from the output
+	/// file's extension if not specified
+	#[cfg(feature = "compression")]
+	#[arg(long, value_name = "ALGORITHM", value_parser = |s: &str| Compression::try_from(s))]
+	compress: Option<Compression>,
+
+	/// How hard to try when compressing the output (the range, and the default, depends on the
+	/// algorithm)
+	#[cfg(feature = "compression")]
+	#[arg(long, value_name = "LEVEL")]
+	compression_level: Option<i32>,
+
+	/// Link each precert to the certificate entries it became, and write the links to this file
+	#[arg(long, value_name = "FILE")]
+	link_precerts: Option<PathBuf>,
+
+	/// Count up the issuers, days, entry types, key types and validity periods of the entries
+	/// scraped, and write a summary of them to stderr at the end (use "--format summary" to
+	/// write only the summary)
+	#[arg(long, default_value = "false")]
+	summary: bool,
+
+	/// With domains output, how many of the most recently written names to remember, so they
+	/// aren't written again (0 writes every name, every time)
+	#[cfg(feature = "decode")]
+	#[arg(long, value_name = "N", default_value = "100000")]
+	dedupe_window: usize,
+
+	/// The maximum number of concurrent fetchers to run against any one host
+	#[arg(long, value_parser = value_parser!(u64).range(1..=1024))]
+	max_fetchers_per_host: Option<u64>,
+
+	/// Write out entries in the order they appear in the log
+	#[arg(long, default_value = "false")]
+	ordered: bool,
+
+	/// With --ordered, how many entries ahead of the next one to be written the fetchers may
+	/// get (and so how many entries may be held in memory at once)
+	#[arg(long, value_name = "N", requires = "ordered", value_parser = value_parser!(u64).range(1..))]
+	reorder_buffer: Option<u64>,
+
 	/// Include the submitted chain in the output
 	#[arg(long, default_value = "false")]
 	include_chains: bool,
 
+	/// With --include-chains, write each distinct chain certificate only once, and list the
+	/// chains as certificate hashes
+	#[arg(long, default_value = "false")]
+	dedupe_chains: bool,
+
 	/// Include the raw precert data
 	#[arg(long, default_value = "false")]
 	include_precert_data: bool,
 
+	/// Include the bytes the log sent for each entry, so it can be reproduced exactly
+	#[arg(long, default_value = "false")]
+	include_raw_entries: bool,
+
+	/// Include each entry's leaf hash, and the SHA-256 hash of its certificate
+	#[arg(long, default_value = "false")]
+	include_hashes: bool,
+
+	/// Include whether each entry is an X.509 certificate or a precertificate
+	#[arg(long, default_value = "false")]
+	include_entry_type: bool,
+
+	/// Include the subject, issuer, names, validity period and key type out of each certificate
+	/// (needs the decode feature)
+	#[arg(long, default_value = "false")]
+	include_decoded: bool,
+
+	/// Write exactly these fields (a comma-separated list), rather than the usual ones plus
+	/// whatever has been included
+	#[arg(
+		long,
+		value_name = "FIELD,...",
+		value_parser = |s: &str| Fields::try_from(s),
+		conflicts_with_all = ["include_chains", "include_precert_data", "include_raw_entries", "include_hashes", "include_entry_type", "include_decoded"],
+	)]
+	fields: Option<Fields>,
+
 	/// The maximum number of entries to fetch from the log
 	#[arg(short = 'n', long = "number-of-entries", value_parser = value_parser!(u64).range(1..=u64::MAX), default_value = "18446744073709551615")]
 	count: u64,
@@ -44,15 +290,1029 @@ struct Config {
 	#[arg(short, long, value_parser = value_parser!(u64).range(0..=u64::MAX), default_value = "0")]
 	start: u64,
 
+	/// Only fetch entries logged at or after this time (RFC 3339, or a date), finding where
+	/// they start by searching the log
+	#[arg(long, value_name = "TIME", conflicts_with = "start", value_parser = |s: &str| parse_time(s))]
+	since: Option<u64>,
+
+	/// Only fetch entries logged before this time (RFC 3339, or a date), finding where they
+	/// end by searching the log from --start
+	#[arg(long, value_name = "TIME", value_parser = |s: &str| parse_time(s))]
+	until: Option<u64>,
+
+	/// How far out of order (in seconds) entries' timestamps might be, when searching for
+	/// --since and --until
+	#[arg(long, value_name = "SECONDS", default_value = "3600")]
+	time_margin: u64,
+
+	/// Only keep entries whose certificate has a DNS name matching this pattern (a glob, like
+	/// "*.example.com", or a regex between slashes); can be given more than once
+	#[arg(long, value_name = "PATTERN")]
+	match_domain: Vec<String>,
+
+	/// Read more patterns for --match-domain from this file, one per line
+	#[arg(long, value_name = "FILE")]
+	match_domains_file: Option<PathBuf>,
+
+	/// Only keep entries whose certificate's issuer DN contains this text (ignoring case); can
+	/// be given more than once
+	#[arg(long, value_name = "TEXT")]
+	issuer: Vec<String>,
+
+	/// Only keep entries issued by the key with this hash (the hex SHA-256 of the issuer's
+	/// public key info, as CT uses for precerts); can be given more than once
+	#[arg(long, value_name = "HEX")]
+	issuer_key_hash: Vec<String>,
+
+	/// Only keep entries of this type ("x509" or "precert")
+	#[arg(long, value_name = "TYPE", value_parser = |s: &str| EntryType::try_from(s))]
+	entry_type: Option<EntryType>,
+
+	/// Only keep certificates whose notBefore is at or after this time (RFC 3339, or a date)
+	#[arg(long, value_name = "TIME", value_parser = |s: &str| parse_time(s))]
+	not_before_since: Option<u64>,
+
+	/// Only keep certificates whose notBefore is before this time (RFC 3339, or a date)
+	#[arg(long, value_name = "TIME", value_parser = |s: &str| parse_time(s))]
+	not_before_until: Option<u64>,
+
+	/// Only keep certificates whose notAfter is at or after this time (RFC 3339, or a date)
+	#[arg(long, value_name = "TIME", value_parser = |s: &str| parse_time(s))]
+	not_after_since: Option<u64>,
+
+	/// Only keep certificates whose notAfter is before this time (RFC 3339, or a date)
+	#[arg(long, value_name = "TIME", value_parser = |s: &str| parse_time(s))]
+	not_after_until: Option<u64>,
+
+	/// The filters built from the options above, which every run gets a copy of
+	#[arg(skip)]
+	filters: Vec<Arc<dyn Filter>>,
+
+	/// The compression of every compressed output file, which has to be waited on before the
+	/// output is known to have all been written
+	#[cfg(feature = "compression")]
+	#[arg(skip)]
+	compressions: Arc<Mutex<Vec<Completion>>>,
+
 	/// Increase the amount of informative and debugging output
-	#[arg(short, long, action = clap::ArgAction::Count, default_value = "0")]
+	#[arg(short, long, action = clap::ArgAction::Count, default_value = "0", global = true)]
 	verbose: u8,
 }
 
+#[derive(Clone, Debug, Subcommand)]
+enum Command {
+	/// Serve a previously-scraped log over HTTP, as a read-only RFC 6962 log
+	#[cfg(feature = "serve")]
+	Serve(ServeConfig),
+	/// Rewrite a previously-scraped log in a different output format
+	Convert(ConvertConfig),
+}
+
+#[cfg(feature = "serve")]
+#[derive(Clone, Debug, clap::Args)]
+struct ServeConfig {
+	/// The scrape output file to serve
+	file: PathBuf,
+
+	/// The format of the file (detected automatically if not specified)
+	#[arg(short, long, value_parser = |s: &str| OutputFormat::try_from(s))]
+	format: Option<OutputFormat>,
+
+	/// The address (and port) to listen on
+	#[arg(short, long, default_value = "127.0.0.1:6962")]
+	listen: String,
+
+	/// The maximum number of entries to return from a single get-entries request
+	#[arg(long, value_parser = value_parser!(u64).range(1..), default_value = "1000")]
+	page_size: u64,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+struct ConvertConfig {
+	/// The scrape output file to convert
+	file: PathBuf,
+
+	/// The format of the input file (detected automatically if not specified)
+	#[arg(long, value_parser = |s: &str| OutputFormat::try_from(s))]
+	from: Option<OutputFormat>,
+
+	/// The format to convert to
+	#[arg(short, long, default_value_t, value_parser = |s: &str| OutputFormat::try_from(s))]
+	format: OutputFormat,
+
+	/// Write the converted data to the specified file
+	#[arg(short, long)]
+	output: Option<PathBuf>,
+
+	/// Leave the submitted chains out of the converted data
+	#[arg(long, default_value = "false")]
+	strip_chains: bool,
+
+	/// Leave the raw precert data out of the converted data
+	#[arg(long, default_value = "false")]
+	strip_precert_data: bool,
+
+	/// Leave the bytes the log sent for each entry out of the converted data
+	#[arg(long, default_value = "false")]
+	strip_raw_entries: bool,
+}
+
 const LOG_VERBOSITY_CONFIG: &[&str] = &["warn", "info", "debug", "trace, rustls=debug"];
+const USER_AGENT: &str = "scrape-ct-log/0.0.0";
+
+/// Everything we need to know about a log we're going to scrape
+struct Target {
+	url: Url,
+	public_key: Option<Vec<u8>>,
+	log_id: Option<Vec<u8>>,
+}
+
+fn targets(cfg: &Config) -> Result<Vec<Target>, String> {
+	if let Some(source) = &cfg.log_list {
+		let list = LogList::load(source).map_err(|e| e.to_string())?;
+
+		let mut selector = Selector::new();
+		for id in &cfg.log_id {
+			selector = selector.log_id(id.clone());
+		}
+		for desc in &cfg.log_description {
+			selector = selector.description(desc);
+		}
+		for op in &cfg.log_operator {
+			selector = selector.operator(op);
+		}
+		for state in &cfg.log_state {
+			selector = selector.state(*state);
+		}
+
+		let logs = list.select(&selector);
+		if logs.is_empty() {
+			return Err("no logs in the log list matched the selection criteria".to_string());
+		}
+
+		Ok(logs
+			.into_iter()
+			.map(|log| {
+				log::info!(
+					"Selected log {:?} ({}), with MMD {}s",
+					log.description,
+					log.url,
+					log.mmd
+				);
+				Target {
+					url: fix_url(log.url.clone()),
+					public_key: Some(log.key.clone()),
+					log_id: Some(log.log_id.clone()),
+				}
+			})
+			.collect())
+	} else if cfg.log_url.is_empty() {
+		Err("no log URL or log list provided".to_string())
+	} else {
+		Ok(cfg
+			.log_url
+			.iter()
+			.map(|url| Target {
+				url: fix_url(url.clone()),
+				public_key: None,
+				log_id: None,
+			})
+			.collect())
+	}
+}
+
+/// Build the filters that decide which entries get kept
+fn filters(cfg: &Config) -> Result<Vec<Arc<dyn Filter>>, String> {
+	let mut filters: Vec<Arc<dyn Filter>> = vec![];
+
+	// The search for --since and --until errs on the side of fetching too much, so the extras
+	// need to be trimmed off
+	if cfg.since.is_some() || cfg.until.is_some() {
+		let mut range = TimestampRange::new();
+		if let Some(t) = cfg.since {
+			range = range.since(t);
+		}
+		if let Some(t) = cfg.until {
+			range = range.until(t);
+		}
+		filters.push(Arc::new(range));
+	}
+	if let Some(domain_match) = domain_match(cfg)? {
+		filters.push(domain_match);
+	}
+	if let Some(entry_type) = cfg.entry_type {
+		filters.push(Arc::new(EntryTypeMatch::new(entry_type)));
+	}
+	filters.extend(decoded_filters(cfg)?);
+
+	Ok(filters)
+}
+
+/// The filters that need to look inside certificates: --issuer, --issuer-key-hash, and the
+/// validity period options
+fn decoded_filters(cfg: &Config) -> Result<Vec<Arc<dyn Filter>>, String> {
+	let not_before = (cfg.not_before_since, cfg.not_before_until);
+	let not_after = (cfg.not_after_since, cfg.not_after_until);
+	if cfg.issuer.is_empty()
+		&& cfg.issuer_key_hash.is_empty()
+		&& not_before == (None, None)
+		&& not_after == (None, None)
+	{
+		return Ok(vec![]);
+	}
+
+	#[cfg(not(feature = "decode"))]
+	return Err(
+		"filtering on issuers or validity periods needs the decode feature, which this binary was built without"
+			.to_string(),
+	);
+
+	#[cfg(feature = "decode")]
+	{
+		use scrape_ct_log::filter::{
+			IssuerKeyHashMatch, IssuerNameMatch, ValidityField, ValidityRange,
+		};
+
+		let mut filters: Vec<Arc<dyn Filter>> = vec![];
+		if !cfg.issuer.is_empty() {
+			filters.push(Arc::new(IssuerNameMatch::new(&cfg.issuer)));
+		}
+		if !cfg.issuer_key_hash.is_empty() {
+			filters.push(Arc::new(
+				IssuerKeyHashMatch::new(&cfg.issuer_key_hash).map_err(|e| e.to_string())?,
+			));
+		}
+		for (field, (since, until)) in [
+			(ValidityField::NotBefore, not_before),
+			(ValidityField::NotAfter, not_after),
+		] {
+			if since.is_none() && until.is_none() {
+				continue;
+			}
+			let mut range = ValidityRange::new(field);
+			if let Some(t) = since {
+				range = range.since(t);
+			}
+			if let Some(t) = until {
+				range = range.until(t);
+			}
+			filters.push(Arc::new(range));
+		}
+
+		Ok(filters)
+	}
+}
+
+/// The filter for --match-domain and --match-domains-file, if either was given
+fn domain_match(cfg: &Config) -> Result<Option<Arc<dyn Filter>>, String> {
+	if cfg.match_domain.is_empty() && cfg.match_domains_file.is_none() {
+		return Ok(None);
+	}
+
+	#[cfg(not(feature = "domain-match"))]
+	return Err(
+		"matching domains needs the domain-match feature, which this binary was built without"
+			.to_string(),
+	);
+
+	#[cfg(feature = "domain-match")]
+	{
+		let mut patterns = cfg.match_domain.clone();
+		if let Some(path) = &cfg.match_domains_file {
+			let file = std::fs::read_to_string(path).map_err(|e| {
+				format!(
+					"Could not read domain patterns from {}: {e}",
+					path.display()
+				)
+			})?;
+			patterns.extend(
+				file.lines()
+					.map(str::trim)
+					.filter(|l| !l.is_empty() && !l.starts_with('#'))
+					.map(str::to_string),
+			);
+		}
+
+		Ok(Some(Arc::new(
+			scrape_ct_log::filter::DomainMatch::new(&patterns).map_err(|e| e.to_string())?,
+		)))
+	}
+}
+
+/// Let the user know how a scrape went, beyond the entries themselves
+fn report(target: &Target, stats: &runner::RunStats) {
+	if stats.filtered_count > 0 {
+		log::info!(
+			"{} of the {} entries fetched from {} were filtered out",
+			stats.filtered_count,
+			stats.fetched_count,
+			target.url
+		);
+		for (filter, count) in &stats.filtered_by {
+			log::info!("  {count} turned away by the filter for {filter}");
+		}
+	}
+}
+
+fn create_file(path: &Path) -> Result<Writer, String> {
+	let file = std::fs::File::create(path)
+		.map_err(|e| format!("Could not open output file {}: {}", path.display(), e))?;
+	Ok(Box::new(file))
+}
+
+/// Get ready to write a scrape to the given path (or stdout, if there isn't one), in whatever
+/// way the format needs
+fn destination(cfg: &Config, path: Option<&Path>) -> Result<Destination, String> {
+	let destination = match (cfg.format, path) {
+		(Format::Stream(_), Some(path)) => create_file(path).map(Destination::Writer),
+		(Format::Stream(_), None) => Ok(Destination::Writer(Box::new(std::io::stdout()))),
+		(Format::Pem, Some(path)) => Ok(Destination::Directory(path.to_path_buf())),
+		(Format::Pem, None) => Err(format!(
+			"{} output is written to a directory; use --output or --output-template",
+			cfg.format
+		)),
+		#[cfg(feature = "decode")]
+		(Format::Domains, Some(path)) => create_file(path).map(Destination::Writer),
+		#[cfg(feature = "decode")]
+		(Format::Domains, None) => Ok(Destination::Writer(Box::new(std::io::stdout()))),
+		#[cfg(feature = "decode")]
+		(Format::Summary, Some(path)) => create_file(path).map(Destination::Writer),
+		#[cfg(feature = "decode")]
+		(Format::Summary, None) => Ok(Destination::Writer(Box::new(std::io::stdout()))),
+		#[cfg(feature = "parquet")]
+		(Format::Parquet, Some(path)) => create_file(path).map(Destination::Writer),
+		#[cfg(feature = "parquet")]
+		(Format::Parquet, None) => Ok(Destination::Writer(Box::new(std::io::stdout()))),
+		#[cfg(feature = "sqlite")]
+		(Format::Sqlite, Some(path)) => Ok(Destination::Database(path.to_path_buf())),
+		#[cfg(feature = "sqlite")]
+		(Format::Sqlite, None) => Err(format!(
+			"{} output can only be written to a file; use --output or --output-template",
+			cfg.format
+		)),
+	}?;
+
+	#[cfg(feature = "compression")]
+	let destination = compressed(cfg, path, destination)?;
+
+	Ok(destination)
+}
+
+/// Get ready to write a scrape as a series of files, named from the template
+fn split_destination(cfg: &Config, template: &str) -> Result<Destination, String> {
+	if cfg.format.stream().is_none() {
+		return Err(format!(
+			"{} output can't be split into several files",
+			cfg.format
+		));
+	}
+
+	let template = Template::new(template, &["start", "end"])?;
+
+	#[cfg(feature = "compression")]
+	let (compression, level, compressions) = (
+		cfg.compress
+			.or_else(|| Compression::from_path(Path::new(&template.render(&HashMap::new())))),
+		cfg.compression_level,
+		Arc::clone(&cfg.compressions),
+	);
+
+	let mut split = Split::new(template, move |path| {
+		let writer = create_file(path)
+			.map_err(|e| scrape_ct_log::Error::OutputError("split file".to_string(), e))?;
+
+		#[cfg(feature = "compression")]
+		let writer: Writer = match compression {
+			Some(compression) => compress(&compressions, writer, compression, level)?,
+			None => writer,
+		};
+
+		Ok(writer)
+	});
+	if let Some(n) = cfg.split_entries {
+		split = split.max_entries(n);
+	}
+	if let Some(n) = cfg.split_bytes {
+		split = split.max_bytes(n);
+	}
+
+	Ok(Destination::Split(split))
+}
+
+/// Compress whatever gets written to the destination, if we were asked to (or the file name
+/// suggests we should)
+#[cfg(feature = "compression")]
+fn compressed(
+	cfg: &Config,
+	path: Option<&Path>,
+	destination: Destination,
+) -> Result<Destination, String> {
+	let Some(compression) = cfg
+		.compress
+		.or_else(|| path.and_then(Compression::from_path))
+	else {
+		return Ok(destination);
+	};
+
+	if let Destination::Writer(writer) = destination {
+		compress(
+			&cfg.compressions,
+			writer,
+			compression,
+			cfg.compression_level,
+		)
+		.map(Destination::Writer)
+		.map_err(|e| e.to_string())
+	} else if cfg.compress.is_none() {
+		// Something that only *looks* like it should be compressed can be left alone
+		Ok(destination)
+	} else {
+		Err(format!("{} output can't be compressed", cfg.format))
+	}
+}
+
+/// Compress whatever gets written to the writer, keeping track of how the compression goes
+#[cfg(feature = "compression")]
+fn compress(
+	compressions: &Mutex<Vec<Completion>>,
+	writer: Writer,
+	compression: Compression,
+	level: Option<i32>,
+) -> Result<Writer, scrape_ct_log::Error> {
+	let mut writer = CompressedWriter::new(writer, compression, level)?;
+	// If the lock's poisoned, the writer's left to finish the compression (and log any
+	// problem) itself when it's dropped
+	if let Ok(mut compressions) = compressions.lock() {
+		compressions.extend(writer.completion());
+	}
+	Ok(Box::new(writer))
+}
+
+/// Wait for all the compressed output to be written, making sure it all went well
+#[cfg(feature = "compression")]
+fn finish_compression(cfg: &Config) -> Result<(), String> {
+	let completions = cfg
+		.compressions
+		.lock()
+		.map(|mut c| std::mem::take(&mut *c))
+		.map_err(|e| format!("Could not find out how the compression went: {e}"))?;
+
+	for completion in completions {
+		completion
+			.wait()
+			.map_err(|e| format!("Failed to write compressed output: {e}"))?;
+	}
+	Ok(())
+}
+
+/// The fields to write into each document, either as asked for directly, or as the usual ones
+/// plus whatever's been included
+fn output_fields(cfg: &Config) -> Fields {
+	cfg.fields.clone().unwrap_or_else(|| {
+		Fields::default()
+			.with(Field::Chain, cfg.include_chains)
+			.with(Field::Precert, cfg.include_precert_data)
+			.with(Field::LeafInput, cfg.include_raw_entries)
+			.with(Field::ExtraData, cfg.include_raw_entries)
+			.with(Field::LeafHash, cfg.include_hashes)
+			.with(Field::CertificateSha256, cfg.include_hashes)
+			.with(Field::EntryType, cfg.include_entry_type)
+			.with(Field::Decoded, cfg.include_decoded)
+	})
+}
+
+/// How to go about scraping a single log
+fn run_config(
+	cfg: &Config,
+	target: &Target,
+	fields: &Fields,
+	host_limiter: &runner::HostLimiter,
+) -> Result<runner::Config, scrape_ct_log::Error> {
+	let (offset, limit) = entry_range(cfg, target)?;
+	let mut run_config = runner::Config::new(target.url.clone())
+		.user_agent(USER_AGENT)
+		.limit(limit)
+		.offset(offset)
+		.host_limiter(host_limiter.clone())
+		.ordered(cfg.ordered)
+		.raw_entries(fields.need_raw_entries());
+
+	if let Some(key) = &target.public_key {
+		run_config = run_config.public_key(key.clone());
+	}
+	if let Some(n) = cfg.reorder_buffer {
+		run_config = run_config.reorder_buffer(n);
+	}
+	for filter in &cfg.filters {
+		run_config = run_config.filter(Arc::clone(filter));
+	}
+
+	Ok(run_config)
+}
+
+/// Scrape a single log, writing the results to the given destination
+fn scrape(
+	cfg: &Config,
+	target: &Target,
+	destination: Destination,
+	host_limiter: &runner::HostLimiter,
+) -> Result<(), scrape_ct_log::Error> {
+	let fields = output_fields(cfg);
+	let mut run_config = run_config(cfg, target, &fields, host_limiter)?;
+	// The links file is opened up front, so that a bad path doesn't waste a whole scrape
+	let precert_links = cfg
+		.link_precerts
+		.as_deref()
+		.map(|path| {
+			create_file(path)
+				.map(|writer| (writer, Arc::new(PrecertLinks::new())))
+				.map_err(|e| scrape_ct_log::Error::SystemError("precert links".to_string(), e))
+		})
+		.transpose()?;
+	if let Some((_, links)) = &precert_links {
+		run_config = run_config.precert_links(Arc::clone(links));
+	}
+	#[cfg(feature = "decode")]
+	let summary = cfg.summary.then(|| Arc::new(Summary::new()));
+	#[cfg(feature = "decode")]
+	if let Some(summary) = &summary {
+		run_config = run_config.summary(Arc::clone(summary));
+	}
+
+	let stats = match (cfg.format, destination) {
+		(Format::Stream(format), Destination::Writer(writer)) => {
+			let mut args = file_writer::Args::new(writer, target.url.clone())
+				.fields(fields.clone())
+				.dedupe_chains(cfg.dedupe_chains)
+				.raw_entries(fields.need_raw_entries())
+				.format(format);
+			if let Some(log_id) = &target.log_id {
+				args = args.log_id(log_id.clone());
+			}
+
+			runner::run::<FileWriter<'_, _>>(&run_config, args)
+		}
+		(Format::Stream(format), Destination::Split(split)) => {
+			let mut args = file_writer::Args::split(split, target.url.clone())
+				.fields(fields.clone())
+				.dedupe_chains(cfg.dedupe_chains)
+				.raw_entries(fields.need_raw_entries())
+				.format(format);
+			if let Some(log_id) = &target.log_id {
+				args = args.log_id(log_id.clone());
+			}
+
+			runner::run::<FileWriter<'_, _>>(&run_config, args)
+		}
+		#[cfg(feature = "decode")]
+		(Format::Domains, Destination::Writer(writer)) => runner::run::<DomainWriter<_>>(
+			&run_config,
+			domain_writer::Args::new(writer).dedupe_window(cfg.dedupe_window),
+		),
+		#[cfg(feature = "decode")]
+		(Format::Summary, Destination::Writer(writer)) => runner::run::<SummaryWriter<_>>(
+			&run_config,
+			summary::Args::new(writer, target.url.clone()),
+		),
+		#[cfg(feature = "parquet")]
+		(Format::Parquet, Destination::Writer(writer)) => {
+			let mut args = parquet_writer::Args::new(writer, target.url.clone())
+				.include_precert_data(cfg.include_precert_data)
+				.include_chains(cfg.include_chains);
+			if let Some(log_id) = &target.log_id {
+				args = args.log_id(log_id.clone());
+			}
+
+			runner::run::<ParquetWriter<_>>(&run_config, args)
+		}
+		#[cfg(feature = "sqlite")]
+		(Format::Sqlite, Destination::Database(path)) => {
+			let mut args = sqlite_writer::Args::new(path, target.url.clone())
+				.include_precert_data(cfg.include_precert_data)
+				.include_chains(cfg.include_chains);
+			if let Some(log_id) = &target.log_id {
+				args = args.log_id(log_id.clone());
+			}
+
+			runner::run::<SqliteWriter>(&run_config, args)
+		}
+		(Format::Pem, Destination::Directory(dir)) => {
+			let mut args =
+				pem_writer::Args::new(dir, target.url.clone()).include_chains(cfg.include_chains);
+			if let Some(log_id) = &target.log_id {
+				args = args.log_id(log_id.clone());
+			}
+
+			runner::run::<PemWriter>(&run_config, args)
+		}
+		(format, destination) => Err(scrape_ct_log::Error::InternalError(format!(
+			"can't write {format} output to {destination:?}"
+		))),
+	}?;
+
+	report(target, &stats);
+	if let Some((writer, links)) = precert_links {
+		write_precert_links(target, writer, &links)?;
+	}
+	#[cfg(feature = "decode")]
+	if let Some(summary) = summary {
+		let mut stderr = std::io::stderr();
+		write!(stderr, "Summary of {}\n\n{summary}", target.url)
+			.map_err(|e| scrape_ct_log::Error::OutputError("summary".to_string(), e.to_string()))?;
+	}
+	Ok(())
+}
+
+/// Write out which precerts became which certificates, and let the user know how many did
+fn write_precert_links(
+	target: &Target,
+	writer: Writer,
+	links: &PrecertLinks,
+) -> Result<(), scrape_ct_log::Error> {
+	let mut writer = std::io::BufWriter::new(writer);
+	let (mut linked, mut unlinked) = (0u64, 0u64);
+	for link in links.links() {
+		if link.certificate_entry_number.is_some() {
+			linked = linked.saturating_add(1);
+		} else {
+			unlinked = unlinked.saturating_add(1);
+		}
+		writeln!(writer, "{link}").map_err(|e| {
+			scrape_ct_log::Error::OutputError("precert links".to_string(), e.to_string())
+		})?;
+	}
+	writer.flush().map_err(|e| {
+		scrape_ct_log::Error::OutputError("precert links".to_string(), e.to_string())
+	})?;
+
+	log::info!(
+		"Linked precerts to {linked} certificates in {}; {unlinked} precerts had no certificate in the scrape",
+		target.url
+	);
+	Ok(())
+}
+
+/// The first entry to fetch, and how many to fetch, searching the log for them if we've been
+/// given times rather than entry numbers
+fn entry_range(cfg: &Config, target: &Target) -> Result<(u64, u64), scrape_ct_log::Error> {
+	if cfg.since.is_none() && cfg.until.is_none() {
+		return Ok((cfg.start, cfg.count));
+	}
+
+	let range = Locator::new(&target.url, USER_AGENT)?
+		.margin(cfg.time_margin.saturating_mul(1000))
+		.entry_range(cfg.start, cfg.since, cfg.until)?;
+	log::info!(
+		"Entries logged at the requested times in {} are somewhere in entries {range:?}",
+		target.url
+	);
+
+	Ok((
+		range.start,
+		min(cfg.count, range.end.saturating_sub(range.start)),
+	))
+}
+
+/// Scrape several logs at once, each to its own destination
+fn scrape_concurrently(
+	cfg: &Config,
+	jobs: Vec<(&Target, Destination)>,
+	host_limiter: &runner::HostLimiter,
+) -> Result<(), String> {
+	let total = jobs.len();
+
+	let failures = std::thread::scope(|scope| {
+		let handles = jobs
+			.into_iter()
+			.map(|(target, destination)| {
+				(
+					target,
+					scope.spawn(move || scrape(cfg, target, destination, host_limiter)),
+				)
+			})
+			.collect::<Vec<_>>();
+
+		handles
+			.into_iter()
+			.map(|(target, h)| match h.join() {
+				Ok(Ok(())) => false,
+				Ok(Err(e)) => {
+					log::error!("Scrape of {} failed: {e}", target.url);
+					true
+				}
+				Err(_) => {
+					log::error!("Scrape of {} panicked", target.url);
+					true
+				}
+			})
+			.filter(|failed| *failed)
+			.count()
+	});
+
+	if failures == 0 {
+		Ok(())
+	} else {
+		Err(format!("{failures} of {total} scrapes failed"))
+	}
+}
+
+/// Scrape each log into its own file, named from the template
+fn scrape_to_files(
+	cfg: &Config,
+	targets: &[Target],
+	template: &Template,
+	host_limiter: &runner::HostLimiter,
+) -> Result<(), String> {
+	if targets.len() > 1 && !template.uses("log") {
+		return Err(
+			"the output template must include {log} when scraping more than one log".to_string(),
+		);
+	}
+
+	let split = cfg.split_entries.is_some() || cfg.split_bytes.is_some();
+	if split && !template.uses("start") {
+		return Err("the output template must include {start} when splitting output".to_string());
+	}
+	if !split && (template.uses("start") || template.uses("end")) {
+		return Err(
+			"{start} and {end} can only be used in the output template with --split-entries or --split-bytes"
+				.to_string(),
+		);
+	}
+
+	let mut seen = HashSet::new();
+	let mut jobs = vec![];
+	for target in targets {
+		let path = template.render(&HashMap::from([("log", log_slug(&target.url))]));
+		if !seen.insert(path.clone()) {
+			return Err(format!("more than one log would be written to {path}"));
+		}
+		jobs.push((
+			target,
+			if split {
+				split_destination(cfg, &path)?
+			} else {
+				destination(cfg, Some(Path::new(&path)))?
+			},
+		));
+	}
+
+	scrape_concurrently(cfg, jobs, host_limiter)
+}
+
+/// Scrape several logs into a single combined output
+///
+/// Each log is scraped into a temporary file of its own, and once they're all done, those files
+/// are stitched together into one document.
+fn scrape_combined(
+	cfg: &Config,
+	targets: &[Target],
+	output: Writer,
+	host_limiter: &runner::HostLimiter,
+) -> Result<(), String> {
+	let format = cfg.format.stream().ok_or_else(|| {
+		format!(
+			"{} output can't combine several logs into one file; use --output-template instead",
+			cfg.format
+		)
+	})?;
+
+	let tmp_files = (0..targets.len())
+		.map(|i| tmp_file(cfg.output.as_deref(), i))
+		.collect::<Vec<_>>();
+
+	let result = (|| {
+		let jobs = targets
+			.iter()
+			.zip(&tmp_files)
+			.map(|(target, path)| Ok((target, Destination::Writer(create_file(path)?))))
+			.collect::<Result<Vec<_>, String>>()?;
+
+		scrape_concurrently(cfg, jobs, host_limiter)?;
+
+		combine(output, format, &tmp_files)
+	})();
+
+	remove_tmp_files(&tmp_files);
+	result
+}
+
+/// Where to put the `i`th part of a combined document while it's being written, which is next
+/// to the output file (so it's on the same filesystem), if there is one
+fn tmp_file(output: Option<&Path>, i: usize) -> PathBuf {
+	output
+		.and_then(Path::parent)
+		.filter(|p| !p.as_os_str().is_empty())
+		.map_or_else(std::env::temp_dir, Path::to_path_buf)
+		.join(format!(".scrape-ct-log-{}-{i}.tmp", std::process::id()))
+}
+
+fn remove_tmp_files(paths: &[PathBuf]) {
+	for path in paths {
+		if let Err(e) = std::fs::remove_file(path) {
+			log::debug!("Failed to remove temporary file {}: {e}", path.display());
+		}
+	}
+}
+
+/// Put the documents in `parts` together into one
+fn combine(output: Writer, format: OutputFormat, parts: &[PathBuf]) -> Result<(), String> {
+	let parts = parts
+		.iter()
+		.map(|path| {
+			std::fs::File::open(path)
+				.map(BufReader::new)
+				.map_err(|e| format!("Could not re-open {}: {e}", path.display()))
+		})
+		.collect::<Result<Vec<_>, String>>()?;
+
+	file_writer::combine(output, format, parts)
+		.map_err(|e| format!("Failed to write combined output: {e}"))
+}
+
+/// Open a previously-written scrape file, and figure out what format it's in (if we haven't
+/// been told)
+fn open_scrape(
+	path: &Path,
+	format: Option<OutputFormat>,
+	format_option: &str,
+) -> Result<(BufReader<std::fs::File>, OutputFormat), String> {
+	let file =
+		std::fs::File::open(path).map_err(|e| format!("Could not open {}: {e}", path.display()))?;
+	let mut reader = BufReader::new(file);
+
+	let format = if let Some(format) = format {
+		format
+	} else {
+		let buf = reader
+			.fill_buf()
+			.map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+		reader::detect_format(buf).ok_or_else(|| {
+			format!(
+				"Could not figure out what format {} is in; try specifying {format_option}",
+				path.display()
+			)
+		})?
+	};
+
+	Ok((reader, format))
+}
+
+/// Serve up a scrape file until we're killed
+#[cfg(feature = "serve")]
+fn serve(cfg: &ServeConfig) -> Result<(), String> {
+	// The server reads the file for itself, as many times as it needs to, so we only need to
+	// know what format it's in
+	let (_, format) = open_scrape(&cfg.file, cfg.format, "--format")?;
+
+	let server = Server::new(&cfg.file, format, &cfg.listen)
+		.map_err(|e| e.to_string())?
+		.page_size(cfg.page_size);
+
+	if let Some(addr) = server.local_addr() {
+		log::info!("Listening on {addr}");
+		// Let whoever started us know where to find us, which is particularly handy if they
+		// asked for port 0
+		let mut stdout = std::io::stdout();
+		writeln!(stdout, "http://{addr}/")
+			.and_then(|()| stdout.flush())
+			.map_err(|e| format!("Could not write to stdout: {e}"))?;
+	}
+
+	server.run().map_err(|e| e.to_string())
+}
+
+/// Read in a scrape file, and write it out again in the requested format
+fn convert(cfg: &ConvertConfig) -> Result<(), String> {
+	let (reader, format) = open_scrape(&cfg.file, cfg.from, "--from")?;
+	let mut output: Writer = if let Some(output_file) = &cfg.output {
+		create_file(output_file)?
+	} else {
+		Box::new(std::io::stdout())
+	};
+
+	// There's no knowing whether the file has more than one log's scrape in it until we get to
+	// the second one, so each of them is written to a file of its own, and they're put together
+	// once we know how many there were
+	let mut parts: Vec<PathBuf> = vec![];
+	let result = convert_documents(cfg, reader, format, &mut parts).and_then(|()| {
+		if let [part] = parts.as_slice() {
+			std::fs::File::open(part)
+				.and_then(|mut f| std::io::copy(&mut f, &mut output))
+				.and_then(|_| output.flush())
+				.map_err(|e| format!("Failed to write converted output: {e}"))
+		} else {
+			combine(output, cfg.format, &parts)
+		}
+	});
+
+	remove_tmp_files(&parts);
+	result
+}
+
+/// Convert each of the documents in the file, adding the file each one is written to to `parts`
+fn convert_documents(
+	cfg: &ConvertConfig,
+	reader: BufReader<std::fs::File>,
+	format: OutputFormat,
+	parts: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+	let mut doc: Option<DocumentWriter<'_>> = None;
+
+	reader::stream(reader, format, |item| match item {
+		reader::Item::Metadata(metadata) => {
+			let path = tmp_file(cfg.output.as_deref(), parts.len());
+			let w = create_file(&path).map_err(scrape_ct_log::Error::InternalError)?;
+			parts.push(path);
+			doc = Some(DocumentWriter::from_metadata(w, cfg.format, &metadata)?);
+			Ok(())
+		}
+		reader::Item::Entry(mut entry) => {
+			if cfg.strip_chains {
+				entry.chain = None;
+			}
+			if cfg.strip_precert_data {
+				entry.precert = None;
+			}
+			if cfg.strip_raw_entries {
+				entry.leaf_input = None;
+				entry.extra_data = None;
+			}
+			doc.as_mut()
+				.ok_or_else(|| {
+					scrape_ct_log::Error::InternalError(
+						"found an entry before the metadata".to_string(),
+					)
+				})?
+				.entry(&entry)
+		}
+		reader::Item::End(mut trailer) => {
+			if cfg.strip_chains {
+				trailer.issuers = None;
+			}
+			doc.take()
+				.ok_or_else(|| {
+					scrape_ct_log::Error::InternalError(
+						"found the end before the metadata".to_string(),
+					)
+				})?
+				.finish(&trailer)
+		}
+		other => Err(scrape_ct_log::Error::InternalError(format!(
+			"don't know how to convert {other:?}"
+		))),
+	})
+	.map_err(|e| format!("Conversion of {} failed: {e}", cfg.file.display()))
+}
+
+/// Make sure the options for what gets written make sense together, and with the format
+fn check_output_options(cfg: &Config) -> Result<(), String> {
+	for (wanted, what) in [
+		(cfg.include_raw_entries, "include raw entries"),
+		(cfg.dedupe_chains, "deduplicate chains"),
+		(cfg.include_hashes, "include hashes"),
+		(cfg.include_entry_type, "include entry types"),
+		(cfg.include_decoded, "include decoded certificates"),
+		(cfg.fields.is_some(), "select fields"),
+	] {
+		if wanted && cfg.format.stream().is_none() {
+			return Err(format!("{} output can't {what}", cfg.format));
+		}
+	}
+	// The other non-streaming formats have somewhere to put chains and precert data, but a list
+	// of names or a summary doesn't
+	#[cfg(feature = "decode")]
+	if matches!(cfg.format, Format::Domains | Format::Summary) {
+		for (wanted, what) in [
+			(cfg.include_chains, "include chains"),
+			(cfg.include_precert_data, "include precertificate data"),
+		] {
+			if wanted {
+				return Err(format!("{} output can't {what}", cfg.format));
+			}
+		}
+	}
+	if cfg.dedupe_chains && !output_fields(cfg).contains(Field::Chain) {
+		return Err("--dedupe-chains needs chains to be written (with --include-chains, or the chain field)".to_string());
+	}
+	if cfg!(not(feature = "decode")) && cfg.summary {
+		return Err(
+			"summarising entries needs the decode feature, which this binary was built without"
+				.to_string(),
+		);
+	}
+	if cfg!(not(feature = "decode")) && output_fields(cfg).contains(Field::Decoded) {
+		return Err(
+			"decoding certificates needs the decode feature, which this binary was built without"
+				.to_string(),
+		);
+	}
+
+	Ok(())
+}
 
 fn main() {
-	let cfg = Config::parse();
+	let mut cfg = Config::parse();
 
 	#[allow(clippy::indexing_slicing, clippy::unwrap_used)]
 	// If this craps out, we have many problems
@@ -62,33 +1322,92 @@ fn main() {
 		.start()
 		.unwrap();
 
-	let writer: Box<dyn std::io::Write + Send + Sync> = if let Some(output_file) = &cfg.output {
-		match std::fs::File::create(output_file) {
-			Ok(writer) => Box::new(writer),
+	if let Some(command) = &cfg.command {
+		let result = match command {
+			#[cfg(feature = "serve")]
+			Command::Serve(serve_cfg) => serve(serve_cfg),
+			Command::Convert(convert_cfg) => convert(convert_cfg),
+		};
+		if let Err(e) = result {
+			log::error!("{e}");
+			exit(1);
+		}
+		return;
+	}
+
+	if let Err(e) = check_output_options(&cfg) {
+		log::error!("{e}");
+		exit(1);
+	}
+
+	cfg.filters = match filters(&cfg) {
+		Ok(f) => f,
+		Err(e) => {
+			log::error!("{e}");
+			exit(1);
+		}
+	};
+
+	let targets = match targets(&cfg) {
+		Ok(t) => t,
+		Err(e) => {
+			log::error!("{e}");
+			exit(1);
+		}
+	};
+
+	if cfg.link_precerts.is_some() && targets.len() > 1 {
+		log::error!("--link-precerts can only be used when scraping a single log");
+		exit(1);
+	}
+
+	let host_limiter = runner::HostLimiter::new(cfg.max_fetchers_per_host.map_or_else(
+		|| available_parallelism().map_or(1, std::num::NonZeroUsize::get),
+		|n| usize::try_from(n).unwrap_or(usize::MAX),
+	));
+
+	let result = if let Some(template) = &cfg.output_template {
+		scrape_to_files(&cfg, &targets, template, &host_limiter)
+	} else {
+		let destination = match destination(&cfg, cfg.output.as_deref()) {
+			Ok(d) => d,
 			Err(e) => {
-				log::error!(
-					"Could not open output file {}: {}",
-					output_file.display(),
-					e
-				);
+				log::error!("{e}");
 				exit(1);
 			}
+		};
+
+		if let [target] = targets.as_slice() {
+			scrape(&cfg, target, destination, &host_limiter)
+				.map_err(|e| format!("Scrape failed: {e}"))
+		} else {
+			match destination {
+				Destination::Writer(writer) => {
+					scrape_combined(&cfg, &targets, writer, &host_limiter)
+				}
+				Destination::Directory(_) => Err(format!("{} output can't combine several logs into one directory; use --output-template instead", cfg.format)),
+				Destination::Split(_) => Err("split output can only be written with --output-template".to_string()),
+				// A database can hold as many logs as you like, so they can all go straight in
+				#[cfg(feature = "sqlite")]
+				Destination::Database(path) => scrape_concurrently(
+					&cfg,
+					targets
+						.iter()
+						.map(|target| (target, Destination::Database(path.clone())))
+						.collect(),
+					&host_limiter,
+				),
+			}
 		}
-	} else {
-		Box::new(std::io::stdout())
 	};
 
-	let args = file_writer::Args::new(writer, fix_url(cfg.log_url.clone()))
-		.include_precert_data(cfg.include_precert_data)
-		.include_chains(cfg.include_chains)
-		.format(cfg.format);
-	let run_config = runner::Config::new(fix_url(cfg.log_url))
-		.user_agent("scrape-ct-log/0.0.0")
-		.limit(cfg.count)
-		.offset(cfg.start);
-
-	if let Err(e) = runner::run::<FileWriter<'_, _>>(&run_config, args) {
-		log::error!("Scrape failed: {e}");
+	// Everything that was written to has been dropped by now, so the compression can only
+	// be waiting on itself
+	#[cfg(feature = "compression")]
+	let result = result.and_then(|()| finish_compression(&cfg));
+
+	if let Err(e) = result {
+		log::error!("{e}");
 		exit(1);
 	}
 }
@@ -102,6 +1421,8 @@ use ct_structs as _;
 use gen_server as _;
 use num as _;
 use rand as _;
+use ring as _;
+use serde as _;
 use serde_json as _;
 use thiserror as _;
 use ureq as _;
@@ -110,7 +1431,34 @@ use ureq as _;
 use webpki_root_certs as _;
 use webpki_roots as _;
 
+#[cfg(feature = "serve")]
+use tiny_http as _;
+
+#[cfg(feature = "parquet")]
+use arrow_array as _;
+#[cfg(feature = "parquet")]
+use arrow_schema as _;
+#[cfg(feature = "cbor")]
+use ciborium as _;
 #[cfg(feature = "cbor")]
 use ciborium_io as _;
 #[cfg(feature = "cbor")]
 use ciborium_ll as _;
+#[cfg(feature = "compression")]
+use flate2 as _;
+#[cfg(feature = "parquet")]
+use parquet as _;
+#[cfg(feature = "domain-match")]
+use regex as _;
+#[cfg(feature = "msgpack")]
+use rmp as _;
+#[cfg(feature = "msgpack")]
+use rmp_serde as _;
+#[cfg(feature = "sqlite")]
+use rusqlite as _;
+#[cfg(feature = "decode")]
+use x509_parser as _;
+#[cfg(feature = "compression")]
+use xz2 as _;
+#[cfg(feature = "compression")]
+use zstd as _;
@@ -179,3 +179,55 @@ fn x509_log_entry_is_properly_decoded() {
 	assert!(entry_map.get("chain").is_none());
 	assert!(entry_map.get("precert").is_none());
 }
+
+#[test]
+fn cbor_seq_writes_an_item_per_record() {
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(1, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.add_entry(
+			0,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", "cbor-seq"])
+		.arg(log_url.clone())
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+
+	res.assert().success().stderr(is_empty());
+
+	let mut rest = &stdout[..];
+	let mut items = vec![];
+	while !rest.is_empty() {
+		items.push(ciborium::from_reader::<Value, _>(&mut rest).unwrap());
+	}
+	assert_eq!(4, items.len());
+
+	// RFC 9277 magic number
+	let (tag, magic) = items[0].as_tag().expect("magic to be tagged");
+	assert_eq!(55800, tag);
+	assert_eq!(b"BOR", &magic.as_bytes().expect("magic to be bytes")[..]);
+
+	let header = remap(&items[1]);
+	assert_eq!(log_url, header["log_url"].as_text().unwrap());
+	assert!(header.get("entries").is_none());
+
+	let entry = remap(&items[2]);
+	assert_eq!(0, intify(entry["entry_number"]));
+
+	let trailer = remap(&items[3]);
+	assert!(trailer["scrape_end_timestamp"].is_integer());
+	assert_eq!(1, intify(trailer["entry_count"]));
+}
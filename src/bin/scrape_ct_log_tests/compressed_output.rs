@@ -0,0 +1,83 @@
+use assert_cmd::prelude::*;
+use predicates::str::{contains, is_empty};
+use serde_json::Value as SerdeValue;
+use std::io::Read as _;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn infers_compression_from_the_file_name() {
+	let (_log, log_url) = test_log(&[TestEntry::X509], 1..2);
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let path = tmpdir.child("scrape.json.gz");
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.arg("-o")
+		.arg(&path)
+		.arg(&log_url)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	let mut json = vec![];
+	flate2::read::GzDecoder::new(std::fs::File::open(&path).unwrap())
+		.read_to_end(&mut json)
+		.unwrap();
+
+	let output: SerdeValue = serde_json::from_slice(&json).unwrap();
+	assert_eq!(log_url, output["log_url"]);
+	assert_eq!(1, output["entries"].as_array().unwrap().len());
+	assert!(output["scrape_end_timestamp"].is_u64());
+}
+
+#[test]
+fn compresses_stdout_when_asked() {
+	let (_log, log_url) = test_log(&[TestEntry::X509], 1..2);
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--compress", "zstd", "--compression-level", "19"])
+		.arg(&log_url)
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let output: SerdeValue =
+		serde_json::from_slice(&zstd::decode_all(&stdout[..]).unwrap()).unwrap();
+	assert_eq!(1, output["entries"].as_array().unwrap().len());
+}
+
+#[test]
+fn refuses_a_silly_compression_level() {
+	let (_log, log_url) = test_log(&[TestEntry::X509], 1..2);
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--compress", "xz", "--compression-level", "42"])
+		.arg(&log_url)
+		.assert()
+		.failure()
+		.stderr(contains("out of range"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn fails_when_the_compressed_output_cant_be_written() {
+	let (_log, log_url) = test_log(&[TestEntry::X509], 1..2);
+
+	cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(&["--compress", "gzip", "-o", "/dev/full"])
+		.arg(&log_url)
+		.assert()
+		.code(1)
+		.stderr(contains("Failed to write compressed output"));
+}
@@ -0,0 +1,282 @@
+use assert_cmd::prelude::*;
+use predicates::str::is_empty;
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+fn scrape_faux_log(dir: &temp_dir::TempDir, args: &[&str]) -> String {
+	let (_log, log_url) = test_log(&[TestEntry::X509, TestEntry::Precert], 1..2);
+
+	let path = dir
+		.child("scrape.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(args)
+		.args(&["-o", &path])
+		.arg(log_url)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	path
+}
+
+#[test]
+fn strips_chains_and_precert_data() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let original = scrape_faux_log(&tmpdir, &["--include-chains", "--include-precert-data"]);
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"convert",
+			"--strip-chains",
+			"--strip-precert-data",
+			&original,
+		])
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let original: SerdeValue =
+		serde_json::from_reader(std::fs::File::open(original).unwrap()).unwrap();
+	let converted: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+
+	assert_eq!(original["log_url"], converted["log_url"]);
+	assert_eq!(original["sth"], converted["sth"]);
+	assert_eq!(
+		original["scrape_end_timestamp"],
+		converted["scrape_end_timestamp"]
+	);
+
+	let entries = converted["entries"].as_array().unwrap();
+	assert_eq!(2, entries.len());
+	for (o, c) in original["entries"].as_array().unwrap().iter().zip(entries) {
+		assert_eq!(o["certificate"], c["certificate"]);
+		assert!(o.get("chain").is_some());
+		assert!(c.get("chain").is_none());
+		assert!(c.get("precert").is_none());
+	}
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn converts_to_cbor_and_back() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let original = scrape_faux_log(&tmpdir, &["--include-chains", "--include-precert-data"]);
+	let cbor_path = tmpdir
+		.child("scrape.cbor")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["convert", "-f", "cbor", "-o", &cbor_path, &original])
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	let cbor: ciborium::Value =
+		ciborium::de::from_reader(std::fs::File::open(&cbor_path).unwrap()).unwrap();
+	assert!(cbor.is_map());
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["convert", &cbor_path])
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let original: SerdeValue =
+		serde_json::from_reader(std::fs::File::open(original).unwrap()).unwrap();
+	let round_tripped: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+
+	assert_eq!(original, round_tripped);
+}
+
+#[test]
+fn converts_a_scrape_without_certificates() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let original = scrape_faux_log(&tmpdir, &["--fields", "entry_number,timestamp"]);
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["convert", &original])
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let original: SerdeValue =
+		serde_json::from_reader(std::fs::File::open(original).unwrap()).unwrap();
+	let converted: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+
+	assert_eq!(vec![0, 1], entry_numbers(&converted));
+	assert_eq!(original, converted);
+}
+
+/// Scrape two logs into one combined file, returning its path and the logs' URLs
+fn scrape_two_faux_logs(dir: &temp_dir::TempDir, format: &str) -> (String, [String; 2]) {
+	let (_log1, url1) = test_log(&[TestEntry::X509], 1..2);
+	let (_log2, url2) = test_log(&[TestEntry::X509, TestEntry::Precert], 1..2);
+
+	let path = dir
+		.child(format!("combined.{format}"))
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", format, "-o", &path])
+		.arg(&url1)
+		.arg(&url2)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	(path, [url1, url2])
+}
+
+fn convert_file(input: &str, format: &str, output: &str) {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["convert", "-f", format, "-o", output, input])
+		.assert()
+		.success()
+		.stderr(is_empty());
+}
+
+#[test]
+fn converts_combined_json_lines() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let (original, urls) = scrape_two_faux_logs(&tmpdir, "jsonl");
+	let converted = tmpdir
+		.child("converted.jsonl")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	convert_file(&original, "jsonl", &converted);
+
+	let original = std::fs::read(original).unwrap();
+	let converted = std::fs::read(converted).unwrap();
+	assert_eq!(original, converted);
+
+	let headers = converted
+		.split(|b| *b == b'\n')
+		.filter(|l| !l.is_empty())
+		.map(|l| serde_json::from_slice::<SerdeValue>(l).unwrap())
+		.filter_map(|r| {
+			r.get("log_url")
+				.and_then(SerdeValue::as_str)
+				.map(String::from)
+		})
+		.collect::<Vec<_>>();
+	assert_eq!(urls.to_vec(), headers);
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn converts_combined_record_streams_to_cbor_and_back() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let (original, _) = scrape_two_faux_logs(&tmpdir, "jsonl");
+	let cbor_path = tmpdir
+		.child("converted.cbor-seq")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+	let round_tripped = tmpdir
+		.child("round-tripped.jsonl")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	convert_file(&original, "cbor-seq", &cbor_path);
+
+	// The magic number that starts a CBOR sequence only appears once, at the very beginning
+	let cbor = std::fs::read(&cbor_path).unwrap();
+	let magic = [0xd9, 0xd9, 0xf8, 0x43, 0x42, 0x4f, 0x52];
+	assert!(cbor.starts_with(&magic));
+	assert_eq!(1, cbor.windows(magic.len()).filter(|w| *w == magic).count());
+
+	convert_file(&cbor_path, "jsonl", &round_tripped);
+
+	assert_eq!(
+		std::fs::read(original).unwrap(),
+		std::fs::read(round_tripped).unwrap()
+	);
+}
+
+#[test]
+fn converts_combined_json() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let (original, urls) = scrape_two_faux_logs(&tmpdir, "json");
+	let jsonl_path = tmpdir
+		.child("converted.jsonl")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+	let round_tripped = tmpdir
+		.child("round-tripped.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	convert_file(&original, "jsonl", &jsonl_path);
+	convert_file(&jsonl_path, "json", &round_tripped);
+
+	let original: SerdeValue =
+		serde_json::from_reader(std::fs::File::open(original).unwrap()).unwrap();
+	let round_tripped: SerdeValue =
+		serde_json::from_reader(std::fs::File::open(round_tripped).unwrap()).unwrap();
+	assert_eq!(original, round_tripped);
+
+	let logs = round_tripped.as_array().expect("output to be an array");
+	assert_eq!(2, logs.len());
+	for (log, url) in logs.iter().zip(urls) {
+		assert_eq!(url, log["log_url"].as_str().unwrap());
+	}
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn converts_combined_cbor() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let (original, _) = scrape_two_faux_logs(&tmpdir, "cbor");
+	let json_path = tmpdir
+		.child("converted.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+	let round_tripped = tmpdir
+		.child("round-tripped.cbor")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	convert_file(&original, "json", &json_path);
+	convert_file(&json_path, "cbor", &round_tripped);
+
+	let original: ciborium::Value =
+		ciborium::de::from_reader(std::fs::File::open(original).unwrap()).unwrap();
+	let round_tripped: ciborium::Value =
+		ciborium::de::from_reader(std::fs::File::open(round_tripped).unwrap()).unwrap();
+	assert_eq!(original, round_tripped);
+	assert_eq!(2, round_tripped.as_array().unwrap().len());
+}
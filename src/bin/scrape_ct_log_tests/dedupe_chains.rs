@@ -0,0 +1,101 @@
+use assert_cmd::prelude::*;
+use base64::{engine::general_purpose::STANDARD_NO_PAD as b64, Engine as _};
+use predicates::str::{contains, is_empty};
+use ring::digest::{digest, SHA256};
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn writes_each_issuer_once() {
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(3, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.add_entry(
+			0,
+			include_bytes!("precert_leaf_input"),
+			include_bytes!("precert_extra_data"),
+		);
+		mlog.add_entry(
+			1,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+		mlog.add_entry(
+			2,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--include-chains", "--dedupe-chains"])
+		.arg(log_url.clone())
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let output: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+	let issuers = output["issuers"].as_object().expect("issuers to be a map");
+
+	for (hash, cert) in issuers {
+		let cert = b64.decode(cert.as_str().unwrap()).unwrap();
+		let expected: String = digest(&SHA256, &cert)
+			.as_ref()
+			.iter()
+			.map(|b| format!("{b:02x}"))
+			.collect();
+		assert_eq!(&expected, hash);
+	}
+
+	let mut referenced = std::collections::BTreeSet::new();
+	for entry in output["entries"].as_array().unwrap() {
+		let chain = entry["chain"].as_array().expect("entry to have a chain");
+		assert!(!chain.is_empty());
+		for link in chain {
+			let hash = link.as_str().expect("chain link to be a string");
+			assert!(issuers.contains_key(hash), "{hash} isn't in the issuers");
+			referenced.insert(hash.to_string());
+		}
+	}
+	assert_eq!(referenced.len(), issuers.len());
+}
+
+#[test]
+fn needs_chains() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--dedupe-chains", "https://ct.example.com/"])
+		.assert()
+		.failure()
+		.stderr(contains("--include-chains"));
+}
+
+#[test]
+fn refuses_formats_that_cant_hold_them() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"-f",
+			"pem",
+			"-o",
+			"/tmp/nope",
+			"--include-chains",
+			"--dedupe-chains",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("can't deduplicate chains"));
+}
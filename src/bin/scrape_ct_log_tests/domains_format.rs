@@ -0,0 +1,42 @@
+use predicates::str::contains;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn writes_each_name_once() {
+	assert_eq!(
+		"0\t1532471986235\ttest7232018-1-1.msitvalidcert.com\n\
+		 1\t1666198004098\tcrt.sh\n\
+		 1\t1666198004098\twww.crt.sh\n",
+		String::from_utf8(scrape(&precert_and_certs_log(2), &["-f", "domains"])).unwrap()
+	);
+}
+
+#[test]
+fn writes_every_name_without_a_window() {
+	let output = String::from_utf8(scrape(
+		&precert_and_certs_log(2),
+		&["-f", "domains", "--dedupe-window", "0"],
+	))
+	.unwrap();
+
+	assert_eq!(5, output.lines().count());
+	assert!(output.ends_with("2\t1666198004098\twww.crt.sh\n"));
+}
+
+#[test]
+fn only_writes_names() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"-f",
+			"domains",
+			"--include-chains",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("domains output can't include chains"));
+}
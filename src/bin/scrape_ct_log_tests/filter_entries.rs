@@ -0,0 +1,113 @@
+use predicates::str::contains;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn keeps_only_certificates() {
+	assert_eq!(
+		vec![1],
+		entry_numbers(&scrape_json(
+			&precert_and_certs_log(1),
+			&["--entry-type", "x509"]
+		))
+	);
+}
+
+#[test]
+fn keeps_only_precertificates() {
+	assert_eq!(
+		vec![0],
+		entry_numbers(&scrape_json(
+			&precert_and_certs_log(1),
+			&["--entry-type", "precert"]
+		))
+	);
+}
+
+#[test]
+fn refuses_unknown_entry_types() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--entry-type", "cert", "https://ct.example.com/"])
+		.assert()
+		.failure()
+		.stderr(contains("unknown entry type"));
+}
+
+#[cfg(feature = "decode")]
+#[test]
+fn keeps_only_matching_issuer_names() {
+	assert_eq!(
+		vec![1],
+		entry_numbers(&scrape_json(
+			&precert_and_certs_log(1),
+			&["--issuer", "o=sectigo limited"]
+		))
+	);
+}
+
+#[cfg(feature = "decode")]
+#[test]
+fn keeps_only_matching_issuer_keys() {
+	let output = scrape_json(
+		&precert_and_certs_log(1),
+		&[
+			"--issuer-key-hash",
+			"d241053c65bc85d51c270185dafbe25caf36e8497f9b50cb501f3d18c7950db2",
+		],
+	);
+
+	assert_eq!(vec![0], entry_numbers(&output));
+}
+
+#[cfg(feature = "decode")]
+#[test]
+fn keeps_only_certificates_valid_at_the_right_time() {
+	assert_eq!(
+		vec![1],
+		entry_numbers(&scrape_json(
+			&precert_and_certs_log(1),
+			&["--not-before-since", "2020-01-01"]
+		))
+	);
+	assert_eq!(
+		vec![0],
+		entry_numbers(&scrape_json(
+			&precert_and_certs_log(1),
+			&[
+				"--not-after-since",
+				"2018-08-01T00:00:00Z",
+				"--not-after-until",
+				"2018-09-01T00:00:00+10:00",
+			]
+		))
+	);
+}
+
+#[cfg(feature = "decode")]
+#[test]
+fn needs_every_filter_to_match() {
+	let output = scrape_json(
+		&precert_and_certs_log(1),
+		&["--entry-type", "x509", "--not-before-until", "2020-01-01"],
+	);
+
+	assert!(entry_numbers(&output).is_empty());
+}
+
+#[test]
+fn refuses_bad_times() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"--not-before-since",
+			"last tuesday",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("isn't an RFC 3339 timestamp"));
+}
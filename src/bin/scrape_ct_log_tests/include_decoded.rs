@@ -0,0 +1,57 @@
+use predicates::str::contains;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn decodes_certificates() {
+	let output = scrape_json(&precert_and_certs_log(1), &["--include-decoded"]);
+
+	for entry in output["entries"].as_array().unwrap() {
+		let decoded = &entry["decoded"];
+
+		assert!(decoded.get("error").is_none(), "{decoded}");
+		assert!(decoded["subject"].is_string());
+		assert!(decoded["issuer"].is_string());
+		assert!(decoded["key_type"].is_string());
+		assert!(!decoded["dns_names"].as_array().unwrap().is_empty());
+		assert!(decoded["not_before"].as_u64().unwrap() < decoded["not_after"].as_u64().unwrap());
+	}
+}
+
+#[test]
+fn selects_decoded_as_a_field() {
+	let output = scrape_json(
+		&precert_and_certs_log(1),
+		&["--fields", "entry_number,decoded"],
+	);
+	let entry = &output["entries"][1];
+
+	assert!(entry.get("certificate").is_none());
+	assert!(entry["decoded"]["subject"].is_string());
+}
+
+#[test]
+fn leaves_them_out_by_default() {
+	let output = scrape_json(&precert_and_certs_log(1), &[]);
+
+	assert!(output["entries"][0].get("decoded").is_none());
+}
+
+#[test]
+fn refuses_formats_that_cant_hold_them() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"-f",
+			"pem",
+			"-o",
+			"/tmp/nope",
+			"--include-decoded",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("can't include decoded certificates"));
+}
@@ -0,0 +1,116 @@
+use base64::{engine::general_purpose::STANDARD_NO_PAD as b64, Engine as _};
+use predicates::str::contains;
+use ring::digest::{digest, SHA256};
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+fn leaf_hash(leaf_input: &[u8]) -> Vec<u8> {
+	digest(&SHA256, &[&[0u8][..], leaf_input].concat())
+		.as_ref()
+		.to_vec()
+}
+
+#[test]
+fn includes_hashes() {
+	let output = scrape_json(&precert_and_certs_log(1), &["--include-hashes"]);
+	let entries = output["entries"].as_array().unwrap();
+
+	let bytes = |v: &SerdeValue| b64.decode(v.as_str().unwrap()).unwrap();
+	assert_eq!(
+		leaf_hash(include_bytes!("precert_leaf_input")),
+		bytes(&entries[0]["leaf_hash"])
+	);
+	assert_eq!(
+		leaf_hash(include_bytes!("x509_leaf_input")),
+		bytes(&entries[1]["leaf_hash"])
+	);
+
+	for entry in entries {
+		assert_eq!(
+			digest(&SHA256, &bytes(&entry["certificate"])).as_ref(),
+			bytes(&entry["certificate_sha256"])
+		);
+	}
+}
+
+#[test]
+fn hashes_the_raw_entry_if_we_have_it() {
+	let output = scrape_json(
+		&precert_and_certs_log(1),
+		&["--include-hashes", "--include-raw-entries"],
+	);
+
+	for entry in output["entries"].as_array().unwrap() {
+		let leaf_input = b64.decode(entry["leaf_input"].as_str().unwrap()).unwrap();
+		assert_eq!(
+			leaf_hash(&leaf_input),
+			b64.decode(entry["leaf_hash"].as_str().unwrap()).unwrap()
+		);
+	}
+}
+
+#[test]
+fn hashes_exactly_what_the_log_sent() {
+	// The x509 test entry, but with some extensions on the end, which aren't in the decoded
+	// entry anywhere
+	let mut leaf_input = include_bytes!("x509_leaf_input").to_vec();
+	leaf_input.truncate(leaf_input.len() - 2);
+	leaf_input.extend_from_slice(&[0, 3, 1, 2, 3]);
+
+	let log = faux_log(1..2);
+	{
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(1, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.add_entry(0, &leaf_input, include_bytes!("x509_extra_data"));
+	}
+
+	let output = scrape_json(&log, &["--include-hashes"]);
+	let entry = &output["entries"][0];
+	assert_eq!(
+		leaf_hash(&leaf_input),
+		b64.decode(entry["leaf_hash"].as_str().unwrap()).unwrap()
+	);
+	// Keeping the raw entry around to hash it doesn't mean writing it out
+	assert!(entry.get("leaf_input").is_none());
+}
+
+#[test]
+fn includes_entry_types() {
+	let output = scrape_json(&precert_and_certs_log(1), &["--include-entry-type"]);
+	let entries = output["entries"].as_array().unwrap();
+
+	assert_eq!("precert", entries[0]["entry_type"]);
+	assert_eq!("x509", entries[1]["entry_type"]);
+	assert!(entries[0].get("leaf_hash").is_none());
+}
+
+#[test]
+fn leaves_them_out_by_default() {
+	let output = scrape_json(&precert_and_certs_log(1), &[]);
+	let entry = &output["entries"][0];
+
+	assert!(entry.get("leaf_hash").is_none());
+	assert!(entry.get("certificate_sha256").is_none());
+	assert!(entry.get("entry_type").is_none());
+}
+
+#[test]
+fn refuses_formats_that_cant_hold_them() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"-f",
+			"pem",
+			"-o",
+			"/tmp/nope",
+			"--include-hashes",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("can't include hashes"));
+}
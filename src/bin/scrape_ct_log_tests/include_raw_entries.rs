@@ -0,0 +1,110 @@
+use assert_cmd::prelude::*;
+use base64::{engine::general_purpose::STANDARD_NO_PAD as b64, Engine as _};
+use predicates::str::{contains, is_empty};
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn includes_the_bytes_the_log_sent() {
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(2, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.add_entry(
+			0,
+			include_bytes!("precert_leaf_input"),
+			include_bytes!("precert_extra_data"),
+		);
+		mlog.add_entry(
+			1,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--ordered", "--include-raw-entries"])
+		.arg(log_url.clone())
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let output: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+	let entries = output["entries"].as_array().unwrap();
+
+	let bytes = |v: &SerdeValue| b64.decode(v.as_str().unwrap()).unwrap();
+	assert_eq!(
+		include_bytes!("precert_leaf_input").to_vec(),
+		bytes(&entries[0]["leaf_input"])
+	);
+	assert_eq!(
+		include_bytes!("precert_extra_data").to_vec(),
+		bytes(&entries[0]["extra_data"])
+	);
+	assert_eq!(
+		include_bytes!("x509_leaf_input").to_vec(),
+		bytes(&entries[1]["leaf_input"])
+	);
+	assert_eq!(
+		include_bytes!("x509_extra_data").to_vec(),
+		bytes(&entries[1]["extra_data"])
+	);
+}
+
+#[test]
+fn leaves_out_raw_entries_by_default() {
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(1, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.add_entry(
+			0,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.arg(log_url.clone())
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let output: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+	assert!(output["entries"][0].get("leaf_input").is_none());
+	assert!(output["entries"][0].get("extra_data").is_none());
+}
+
+#[test]
+fn refuses_formats_that_cant_hold_them() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"-f",
+			"pem",
+			"-o",
+			"/tmp/nope",
+			"--include-raw-entries",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("raw entries"));
+}
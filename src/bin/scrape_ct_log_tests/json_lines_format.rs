@@ -0,0 +1,62 @@
+use assert_cmd::prelude::*;
+use predicates::str::is_empty;
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn writes_a_line_per_entry() {
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(2, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.add_entry(
+			0,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+		mlog.add_entry(
+			1,
+			include_bytes!("precert_leaf_input"),
+			include_bytes!("precert_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", "jsonl"])
+		.arg(&log_url)
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let lines: Vec<SerdeValue> = std::str::from_utf8(&stdout)
+		.unwrap()
+		.lines()
+		.map(|l| serde_json::from_str(l).unwrap())
+		.collect();
+	assert_eq!(4, lines.len());
+
+	let header = &lines[0];
+	assert_eq!(log_url, header["log_url"]);
+	assert_eq!(2, header["sth"]["tree_size"]);
+	assert!(header.get("entries").is_none());
+
+	let mut entry_numbers: Vec<u64> = lines[1..3]
+		.iter()
+		.map(|e| e["entry_number"].as_u64().unwrap())
+		.collect();
+	entry_numbers.sort();
+	assert_eq!(vec![0, 1], entry_numbers);
+
+	let trailer = &lines[3];
+	assert!(header["scrape_begin_timestamp"].as_u64() <= trailer["scrape_end_timestamp"].as_u64());
+	assert_eq!(2, trailer["entry_count"]);
+}
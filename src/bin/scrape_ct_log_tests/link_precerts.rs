@@ -0,0 +1,90 @@
+use predicates::str::contains;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+/// Scrape a log with precerts both with and without their certificates, and hand back the links
+/// that were written
+fn scrape_links(args: &[&str]) -> String {
+	let log = faux_log(1..2);
+
+	{
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(4, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		// The certificate for crt.sh, logged twice...
+		for i in [0, 3] {
+			mlog.add_entry(
+				i,
+				include_bytes!("x509_leaf_input"),
+				include_bytes!("x509_extra_data"),
+			);
+		}
+		// ... the precert it was issued from...
+		mlog.add_entry(
+			1,
+			include_bytes!("linked_precert_leaf_input"),
+			include_bytes!("precert_extra_data"),
+		);
+		// ... and a precert for something else entirely
+		mlog.add_entry(
+			2,
+			include_bytes!("precert_leaf_input"),
+			include_bytes!("precert_extra_data"),
+		);
+	}
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let filepath = tmpdir
+		.child("links.tsv")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	scrape(&log, &[&["--link-precerts", &filepath], args].concat());
+
+	std::fs::read_to_string(filepath).unwrap()
+}
+
+#[test]
+fn links_precerts_to_their_certificates() {
+	let links = scrape_links(&[]);
+
+	let lines = links
+		.lines()
+		.map(|l| l.split('\t').collect::<Vec<_>>())
+		.collect::<Vec<_>>();
+	assert_eq!(3, lines.len());
+	assert_eq!(["1", "0"], lines[0][..2]);
+	assert_eq!(["1", "3"], lines[1][..2]);
+	assert_eq!(["2", "-"], lines[2][..2]);
+	assert_eq!(lines[0][2], lines[1][2]);
+	assert_ne!(lines[0][2], lines[2][2]);
+	assert_eq!(64, lines[2][2].len());
+}
+
+#[test]
+fn only_links_what_gets_through_the_filters() {
+	let links = scrape_links(&["--entry-type", "precert"]);
+
+	assert_eq!(2, links.lines().count());
+	assert!(links.starts_with("1\t-\t"));
+}
+
+#[test]
+fn only_links_within_a_single_log() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"--link-precerts",
+			"/tmp/links.tsv",
+			"https://ct.example.com/",
+			"https://ct.example.org/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains(
+			"--link-precerts can only be used when scraping a single log",
+		));
+}
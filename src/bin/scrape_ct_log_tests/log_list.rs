@@ -0,0 +1,249 @@
+use assert_cmd::prelude::*;
+use base64::{
+	engine::general_purpose::{STANDARD, STANDARD_NO_PAD as b64},
+	Engine as _,
+};
+use predicates::str::{contains, is_empty};
+use ring::{
+	digest::{digest, SHA256},
+	rand::SystemRandom,
+	signature::{EcdsaKeyPair, KeyPair as _, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+use serde_json::{json, Value as SerdeValue};
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+/// The SPKI header for a P-256 key, which just needs the uncompressed point tacked on the end
+const P256_SPKI_PREFIX: &[u8] = &[
+	0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01, 0x06, 0x08, 0x2A,
+	0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+struct LogKey {
+	keypair: EcdsaKeyPair,
+	spki: Vec<u8>,
+}
+
+impl LogKey {
+	fn new() -> Self {
+		let rng = SystemRandom::new();
+		let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+		let keypair =
+			EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+				.unwrap();
+		let mut spki = P256_SPKI_PREFIX.to_vec();
+		spki.extend_from_slice(keypair.public_key().as_ref());
+
+		LogKey { keypair, spki }
+	}
+
+	fn log_id(&self) -> Vec<u8> {
+		digest(&SHA256, &self.spki).as_ref().to_vec()
+	}
+
+	fn sign_sth(&self, tree_size: u64, timestamp: u64, root_hash: &[u8]) -> Vec<u8> {
+		let mut tbs = vec![0u8, 1u8];
+		tbs.extend_from_slice(&timestamp.to_be_bytes());
+		tbs.extend_from_slice(&tree_size.to_be_bytes());
+		tbs.extend_from_slice(root_hash);
+
+		let sig = self.keypair.sign(&SystemRandom::new(), &tbs).unwrap();
+		let mut v = vec![4u8, 3u8];
+		v.extend_from_slice(&u16::try_from(sig.as_ref().len()).unwrap().to_be_bytes());
+		v.extend_from_slice(sig.as_ref());
+		v
+	}
+}
+
+fn write_log_list(dir: &temp_dir::TempDir, logs: &[(&str, &str, &LogKey)]) -> String {
+	let list = json!({
+		"version": "1.0",
+		"log_list_timestamp": "2023-06-01T00:00:00Z",
+		"operators": [
+			{
+				"name": "Faux Logs Inc",
+				"email": ["ct@example.com"],
+				"logs": logs.iter().map(|(desc, url, key)| json!({
+					"description": desc,
+					"log_id": STANDARD.encode(key.log_id()),
+					"key": STANDARD.encode(&key.spki),
+					"url": url,
+					"mmd": 86400,
+					"state": { "usable": { "timestamp": "2023-01-01T00:00:00Z" } },
+				})).collect::<Vec<_>>(),
+			}
+		]
+	});
+
+	let path = dir
+		.child("log_list.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+	std::fs::write(&path, list.to_string()).unwrap();
+	path
+}
+
+#[test]
+fn scrapes_a_log_selected_from_the_log_list() {
+	let key = LogKey::new();
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(
+			1,
+			1234567890,
+			vec![0u8; 32],
+			key.sign_sth(1, 1234567890, &[0u8; 32]),
+		);
+		mlog.add_entry(
+			0,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let other_key = LogKey::new();
+	let list_path = write_log_list(
+		&tmpdir,
+		&[
+			("Faux Log", &log_url, &key),
+			("Other Log", "https://ct.example.com/", &other_key),
+		],
+	);
+
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--log-list", &list_path])
+		.args(&["--log-description", "faux"])
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let output: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+
+	assert_eq!(
+		log_url,
+		output
+			.get("log_url")
+			.expect("stdout to have log_url")
+			.as_str()
+			.expect("log_url to be a string")
+	);
+	assert_eq!(
+		b64.encode(key.log_id()),
+		output
+			.get("log_id")
+			.expect("stdout to have log_id")
+			.as_str()
+			.expect("log_id to be a string")
+	);
+	assert_eq!(1, output["entries"].as_array().unwrap().len());
+}
+
+#[test]
+fn selects_by_log_id() {
+	let key = LogKey::new();
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(
+			1,
+			1234567890,
+			vec![0u8; 32],
+			key.sign_sth(1, 1234567890, &[0u8; 32]),
+		);
+		mlog.add_entry(
+			0,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let other_key = LogKey::new();
+	let list_path = write_log_list(
+		&tmpdir,
+		&[
+			("Other Log", "https://ct.example.com/", &other_key),
+			("Faux Log", &log_url, &key),
+		],
+	);
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--log-list", &list_path])
+		.args(&["--log-id", &STANDARD.encode(key.log_id())])
+		.assert()
+		.success()
+		.stderr(is_empty());
+}
+
+#[test]
+fn refuses_an_sth_with_a_bad_signature() {
+	let key = LogKey::new();
+	let log = faux_log(0..1);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		// Signed over a different tree size
+		mlog.sth(
+			1,
+			1234567890,
+			vec![0u8; 32],
+			key.sign_sth(2, 1234567890, &[0u8; 32]),
+		);
+
+		mlog.url()
+	};
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let list_path = write_log_list(&tmpdir, &[("Faux Log", &log_url, &key)]);
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--log-list", &list_path])
+		.assert()
+		.code(1)
+		.stderr(contains("failed to verify STH signature"));
+}
+
+#[test]
+fn refuses_an_empty_selection() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let key = LogKey::new();
+	let list_path = write_log_list(&tmpdir, &[("Faux Log", "https://ct.example.com/", &key)]);
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--log-list", &list_path])
+		.args(&["--log-description", "nonexistent"])
+		.assert()
+		.code(1)
+		.stderr(contains("no logs in the log list matched"));
+}
+
+#[test]
+fn log_list_selection_requires_a_log_list() {
+	cmd()
+		.args(&["--log-operator", "Faux Logs Inc", "https://ct.example.com/"])
+		.assert()
+		.code(2)
+		.stderr(contains("--log-list"));
+}
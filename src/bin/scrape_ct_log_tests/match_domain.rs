@@ -0,0 +1,76 @@
+use predicates::str::contains;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn keeps_only_matching_entries() {
+	let output = scrape_json(&precert_and_certs_log(1), &["--match-domain", "www.crt.sh"]);
+
+	assert_eq!(vec![1], entry_numbers(&output));
+}
+
+#[test]
+fn matches_any_of_several_patterns() {
+	let output = scrape_json(
+		&precert_and_certs_log(1),
+		&[
+			"--match-domain",
+			"*.MSITVALIDCERT.com",
+			"--match-domain",
+			"/^crt\\.sh$/",
+		],
+	);
+
+	assert_eq!(vec![0, 1], entry_numbers(&output));
+}
+
+#[test]
+fn reads_patterns_from_a_file() {
+	let dir = temp_dir::TempDir::new().unwrap();
+	let patterns = dir.child("patterns");
+	std::fs::write(&patterns, "# Things we care about\n\n*msitvalidcert*\n").unwrap();
+
+	let output = scrape_json(
+		&precert_and_certs_log(1),
+		&["--match-domains-file", patterns.to_str().unwrap()],
+	);
+
+	assert_eq!(vec![0], entry_numbers(&output));
+}
+
+#[test]
+fn writes_nothing_when_nothing_matches() {
+	let output = scrape_json(
+		&precert_and_certs_log(1),
+		&["--match-domain", "example.com"],
+	);
+
+	assert_eq!(Some(0), output["entries"].as_array().map(Vec::len));
+}
+
+#[test]
+fn reports_how_many_were_filtered_out() {
+	let log = precert_and_certs_log(1);
+	let log_url = log.lock().unwrap().url();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "info")
+		.args(&["--match-domain", "crt.sh"])
+		.arg(log_url)
+		.assert()
+		.success()
+		.stderr(contains("1 of the 2 entries fetched"));
+}
+
+#[test]
+fn refuses_bad_regexes() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--match-domain", "/(/", "https://ct.example.com/"])
+		.assert()
+		.failure()
+		.stderr(contains("invalid domain pattern"));
+}
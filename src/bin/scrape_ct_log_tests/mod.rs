@@ -6,13 +6,45 @@ mod test_helpers;
 
 mod all_defaults;
 mod basic;
+mod convert;
+mod dedupe_chains;
+mod filter_entries;
 mod include_chains;
+mod include_hashes;
 mod include_precert_data;
+mod include_raw_entries;
+mod json_lines_format;
+mod link_precerts;
+mod log_list;
+mod multiple_logs;
+mod ordered_output;
 mod output_file;
+mod pem_directory;
 mod range_limits;
+mod select_fields;
+mod since_until;
+mod split_output;
 
 #[cfg(feature = "cbor")]
 mod cbor_format;
+#[cfg(feature = "compression")]
+mod compressed_output;
+#[cfg(feature = "decode")]
+mod domains_format;
+#[cfg(feature = "decode")]
+mod include_decoded;
+#[cfg(feature = "domain-match")]
+mod match_domain;
+#[cfg(feature = "msgpack")]
+mod msgpack_format;
+#[cfg(feature = "parquet")]
+mod parquet_format;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "sqlite")]
+mod sqlite_format;
+#[cfg(feature = "decode")]
+mod summary;
 
 // CBOR-only test dependency
 #[cfg(not(feature = "cbor"))]
@@ -0,0 +1,69 @@
+use assert_cmd::prelude::*;
+use predicates::str::is_empty;
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn writes_a_record_per_entry() {
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(2, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.add_entry(
+			0,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+		mlog.add_entry(
+			1,
+			include_bytes!("precert_leaf_input"),
+			include_bytes!("precert_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let path = tmpdir
+		.child("scrape.msgpack")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", "msgpack", "--include-chains", "-o", &path])
+		.arg(&log_url)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	// Header record: a fixmap, starting with the log_url key
+	let raw = std::fs::read(&path).unwrap();
+	assert_eq!(&[0x83, 0xA7][..], &raw[..2]);
+	assert_eq!(b"log_url", &raw[2..9]);
+
+	// Easiest way to see what's in there is to turn it into something we can read
+	let res = cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["convert", &path])
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let output: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+	assert_eq!(log_url, output["log_url"]);
+	assert_eq!(2, output["sth"]["tree_size"]);
+	assert!(output["scrape_end_timestamp"].is_u64());
+
+	let entries = output["entries"].as_array().unwrap();
+	assert_eq!(2, entries.len());
+	assert!(entries.iter().all(|e| e["chain"].is_array()));
+}
@@ -0,0 +1,103 @@
+use assert_cmd::prelude::*;
+use predicates::str::{contains, is_empty};
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn combines_the_output_of_several_logs() {
+	let (_log1, url1) = test_log(&[TestEntry::X509], 1..2);
+	let (_log2, url2) = test_log(&[TestEntry::X509], 1..2);
+
+	let res = cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.arg(&url1)
+		.arg(&url2)
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let output: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+	let logs = output.as_array().expect("output to be an array");
+
+	assert_eq!(2, logs.len());
+	assert_eq!(url1, logs[0]["log_url"].as_str().unwrap());
+	assert_eq!(url2, logs[1]["log_url"].as_str().unwrap());
+	for log in logs {
+		assert_eq!(1, log["entries"].as_array().unwrap().len());
+	}
+}
+
+#[test]
+fn writes_each_log_to_its_own_file() {
+	let (_log1, url1) = test_log(&[TestEntry::X509], 1..2);
+	let (_log2, url2) = test_log(&[TestEntry::X509], 1..2);
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let template = tmpdir
+		.child("{log}.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(&["--output-template", &template])
+		.arg(&url1)
+		.arg(&url2)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	let files = std::fs::read_dir(tmpdir.path())
+		.unwrap()
+		.map(|e| e.unwrap().path())
+		.collect::<Vec<_>>();
+	assert_eq!(2, files.len());
+
+	let mut urls = files
+		.iter()
+		.map(|f| {
+			let output: SerdeValue =
+				serde_json::from_reader(std::fs::File::open(f).unwrap()).unwrap();
+			output["log_url"].as_str().unwrap().to_string()
+		})
+		.collect::<Vec<_>>();
+	urls.sort();
+	let mut expected = vec![url1, url2];
+	expected.sort();
+
+	assert_eq!(expected, urls);
+}
+
+#[test]
+fn template_must_distinguish_logs() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let template = tmpdir
+		.child("all.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--output-template", &template])
+		.args(&["https://ct.example.com/one/", "https://ct.example.com/two/"])
+		.assert()
+		.code(1)
+		.stderr(contains("must include {log}"));
+}
+
+#[test]
+fn rejects_unknown_template_placeholders() {
+	cmd()
+		.args(&["--output-template", "{wat}.json", "https://ct.example.com/"])
+		.assert()
+		.code(2)
+		.stderr(contains("unknown placeholder"));
+}
@@ -0,0 +1,55 @@
+use assert_cmd::prelude::*;
+use predicates::str::is_empty;
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn writes_entries_in_log_order() {
+	let log = faux_log(1..10);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(250, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		for i in 0..250 {
+			mlog.add_entry(
+				i,
+				include_bytes!("x509_leaf_input"),
+				include_bytes!("x509_extra_data"),
+			);
+		}
+
+		mlog.url()
+	};
+
+	let res = cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(&["--ordered", "--reorder-buffer", "10"])
+		.arg(&log_url)
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let output: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+	let entry_numbers = output["entries"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.map(|e| e["entry_number"].as_u64().unwrap())
+		.collect::<Vec<_>>();
+
+	assert_eq!((0..250).collect::<Vec<_>>(), entry_numbers);
+}
+
+#[test]
+fn reorder_buffer_needs_ordered() {
+	cmd()
+		.args(&["--reorder-buffer", "10", "https://ct.example.com/"])
+		.assert()
+		.failure()
+		.stderr(predicates::str::contains("--ordered"));
+}
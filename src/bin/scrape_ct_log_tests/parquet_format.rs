@@ -0,0 +1,76 @@
+use predicates::str::{contains, is_empty};
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+/// What every Parquet file starts and ends with
+const PARQUET_MAGIC: &[u8] = b"PAR1";
+
+#[test]
+fn writes_a_parquet_file() {
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(2, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.add_entry(
+			0,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+		mlog.add_entry(
+			1,
+			include_bytes!("precert_leaf_input"),
+			include_bytes!("precert_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let path = tmpdir
+		.child("scrape.parquet")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", "parquet", "--include-chains", "-o", &path])
+		.arg(&log_url)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	let raw = std::fs::read(&path).unwrap();
+	assert!(raw.starts_with(PARQUET_MAGIC));
+	assert!(raw.ends_with(PARQUET_MAGIC));
+
+	// The metadata is stored in the footer as plain strings, so we can at least see it's there
+	let footer = String::from_utf8_lossy(&raw);
+	assert!(footer.contains(&log_url));
+	assert!(footer.contains("scrape_end_timestamp"));
+}
+
+#[test]
+fn refuses_to_combine_several_logs() {
+	let log1 = faux_log(1..2);
+	let log2 = faux_log(1..2);
+
+	let urls = [&log1, &log2].map(|log| {
+		let mut mlog = log.lock().unwrap();
+		mlog.sth(0, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.url()
+	});
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", "parquet"])
+		.args(&urls)
+		.assert()
+		.failure()
+		.stderr(contains("--output-template"));
+}
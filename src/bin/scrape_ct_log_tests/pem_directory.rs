@@ -0,0 +1,76 @@
+use predicates::str::{contains, is_empty};
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+#[test]
+fn writes_a_pem_file_per_entry() {
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(2, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.add_entry(
+			0,
+			include_bytes!("x509_leaf_input"),
+			include_bytes!("x509_extra_data"),
+		);
+		mlog.add_entry(
+			1,
+			include_bytes!("precert_leaf_input"),
+			include_bytes!("precert_extra_data"),
+		);
+
+		mlog.url()
+	};
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let dir = tmpdir.child("certs");
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", "pem", "--include-chains", "-o"])
+		.arg(&dir)
+		.arg(&log_url)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	for n in 0..2 {
+		let pem = std::fs::read(dir.join(format!("0-9999/{n}.pem"))).unwrap();
+		let (_, pem) = x509_parser::pem::parse_x509_pem(&pem).unwrap();
+		assert_eq!("CERTIFICATE", pem.label);
+		pem.parse_x509().unwrap();
+
+		let chain = std::fs::read(dir.join(format!("0-9999/{n}.chain.pem"))).unwrap();
+		assert!(x509_parser::pem::Pem::iter_from_buffer(&chain).count() > 0);
+	}
+
+	let metadata: SerdeValue =
+		serde_json::from_slice(&std::fs::read(dir.join("metadata.json")).unwrap()).unwrap();
+	assert_eq!(log_url, metadata["log_url"]);
+	assert_eq!(2, metadata["sth"]["tree_size"]);
+	assert_eq!(2, metadata["entry_count"]);
+}
+
+#[test]
+fn needs_a_directory() {
+	let log = faux_log(1..2);
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+		mlog.sth(0, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		mlog.url()
+	};
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", "pem"])
+		.arg(&log_url)
+		.assert()
+		.failure()
+		.stderr(contains("--output"));
+}
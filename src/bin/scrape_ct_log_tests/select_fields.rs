@@ -0,0 +1,91 @@
+use predicates::str::contains;
+use serde_json::Value as SerdeValue;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+fn keys(v: &SerdeValue) -> Vec<&str> {
+	let mut keys: Vec<_> = v.as_object().unwrap().keys().map(String::as_str).collect();
+	keys.sort_unstable();
+	keys
+}
+
+#[test]
+fn writes_only_the_selected_fields() {
+	let output = scrape_json(
+		&precert_and_certs_log(1),
+		&["--fields", "entry_number,timestamp,leaf_hash"],
+	);
+
+	assert_eq!(vec!["entries"], keys(&output));
+
+	let entries = output["entries"].as_array().unwrap();
+	assert_eq!(2, entries.len());
+	for entry in entries {
+		assert_eq!(vec!["entry_number", "leaf_hash", "timestamp"], keys(entry));
+	}
+}
+
+#[test]
+fn selects_top_level_fields_too() {
+	let output = scrape_json(
+		&precert_and_certs_log(1),
+		&["--fields", "log_url,sth,chain"],
+	);
+
+	assert_eq!(vec!["entries", "log_url", "sth"], keys(&output));
+	assert_eq!(vec!["chain"], keys(&output["entries"][0]));
+}
+
+#[test]
+fn selects_fields_in_record_streams() {
+	let output = scrape(
+		&precert_and_certs_log(1),
+		&["-f", "jsonl", "--fields", "scrape_end_timestamp,entry_type"],
+	);
+	let records: Vec<SerdeValue> = output
+		.split(|b| *b == b'\n')
+		.filter(|l| !l.is_empty())
+		.map(|l| serde_json::from_slice(l).unwrap())
+		.collect();
+
+	assert_eq!(4, records.len());
+	assert!(keys(&records[0]).is_empty());
+	assert_eq!("precert", records[1]["entry_type"]);
+	assert_eq!("x509", records[2]["entry_type"]);
+	assert_eq!(
+		vec!["entry_count", "scrape_end_timestamp"],
+		keys(&records[3])
+	);
+}
+
+#[test]
+fn refuses_unknown_fields() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"--fields",
+			"timestamp,fingerprint",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("unknown field \"fingerprint\""));
+}
+
+#[test]
+fn refuses_fields_alongside_includes() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"--fields",
+			"timestamp",
+			"--include-chains",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("cannot be used with"));
+}
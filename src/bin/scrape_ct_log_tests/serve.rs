@@ -0,0 +1,187 @@
+use assert_cmd::prelude::*;
+use predicates::str::{contains, is_empty};
+use serde_json::Value as SerdeValue;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Stdio};
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+/// Kills the server when the test is done with it, even if the test fails
+struct Server(Child);
+
+impl Drop for Server {
+	fn drop(&mut self) {
+		drop(self.0.kill());
+		drop(self.0.wait());
+	}
+}
+
+fn serve(file: &str, extra_args: &[&str]) -> (Server, String) {
+	let mut child = std_cmd()
+		.env("RUST_LOG", "warn")
+		.args(["serve", "--listen", "127.0.0.1:0"])
+		.args(extra_args)
+		.arg(file)
+		.stdout(Stdio::piped())
+		.spawn()
+		.unwrap();
+
+	let mut url = String::new();
+	BufReader::new(child.stdout.take().unwrap())
+		.read_line(&mut url)
+		.unwrap();
+
+	(Server(child), url.trim().to_string())
+}
+
+/// What a scrape needs for every entry to be reconstructed exactly
+const RECONSTRUCTABLE: &[&str] = &[
+	"--include-chains",
+	"--include-precert-data",
+	"--include-hashes",
+];
+
+fn scrape_faux_log(dir: &temp_dir::TempDir, args: &[&str]) -> String {
+	let (_log, log_url) = test_log(&[TestEntry::X509, TestEntry::Precert], 1..2);
+
+	let path = dir
+		.child("scrape.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(args)
+		.args(&["-o", &path])
+		.arg(log_url)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	path
+}
+
+#[test]
+fn serves_a_scrape_that_can_be_scraped_again() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let original = scrape_faux_log(&tmpdir, RECONSTRUCTABLE);
+	let (_server, url) = serve(&original, &["--page-size", "1"]);
+
+	let res = cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(RECONSTRUCTABLE)
+		.arg(&url)
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	let original: SerdeValue =
+		serde_json::from_reader(std::fs::File::open(original).unwrap()).unwrap();
+	let rescraped: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+
+	assert_eq!(original["sth"], rescraped["sth"]);
+
+	let sorted = |v: &SerdeValue| {
+		let mut entries = v["entries"].as_array().unwrap().clone();
+		entries.sort_by_key(|e| e["entry_number"].as_u64().unwrap());
+		entries
+	};
+	assert_eq!(2, sorted(&rescraped).len());
+	assert_eq!(sorted(&original), sorted(&rescraped));
+}
+
+#[test]
+fn warns_about_scrapes_it_cant_check() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let original = scrape_faux_log(&tmpdir, &["--include-chains", "--include-precert-data"]);
+
+	let mut child = std_cmd()
+		.env("RUST_LOG", "warn")
+		.args(["serve", "--listen", "127.0.0.1:0", &original])
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.unwrap();
+	let stderr = child.stderr.take().unwrap();
+
+	let mut url = String::new();
+	BufReader::new(child.stdout.take().unwrap())
+		.read_line(&mut url)
+		.unwrap();
+	let server = Server(child);
+
+	let res = cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(&["--include-chains", "--include-precert-data"])
+		.arg(url.trim())
+		.unwrap();
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+	drop(server);
+
+	let mut warning = String::new();
+	BufReader::new(stderr).read_line(&mut warning).unwrap();
+	assert!(warning.contains("2 entries"), "{warning}");
+	assert!(warning.contains("no leaf hash"), "{warning}");
+
+	let original: SerdeValue =
+		serde_json::from_reader(std::fs::File::open(original).unwrap()).unwrap();
+	let rescraped: SerdeValue = serde_json::from_slice(&stdout).unwrap();
+	assert_eq!(original["entries"], rescraped["entries"]);
+}
+
+#[test]
+fn refuses_scrapes_of_more_than_one_log() {
+	let (_log1, url1) = test_log(&[TestEntry::X509], 1..2);
+	let (_log2, url2) = test_log(&[TestEntry::X509], 1..2);
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let path = tmpdir
+		.child("combined.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(&["-o", &path])
+		.arg(&url1)
+		.arg(&url2)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["serve", "--listen", "127.0.0.1:0", &path])
+		.assert()
+		.code(1)
+		.stderr(contains("more than one log"));
+}
+
+#[test]
+fn complains_about_unreadable_files() {
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let path = tmpdir
+		.child("garbage")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+	std::fs::write(&path, "ohai!").unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["serve", &path])
+		.assert()
+		.code(1)
+		.stderr(contains("Could not figure out what format"));
+}
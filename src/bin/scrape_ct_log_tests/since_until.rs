@@ -0,0 +1,165 @@
+use predicates::prelude::*;
+use predicates::str::contains;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::faux_log::FauxLog;
+use super::test_helpers::*;
+
+// 2024-01-01T00:00:00Z
+const NEW_YEAR: u64 = 1704067200000;
+
+/// The x509 test entry, but logged at a different time
+fn leaf_input_at(timestamp: u64) -> Vec<u8> {
+	let mut leaf_input = include_bytes!("x509_leaf_input").to_vec();
+	leaf_input[2..10].copy_from_slice(&timestamp.to_be_bytes());
+	leaf_input
+}
+
+/// A log whose entries were logged at the given number of seconds into the new year
+fn log_logged_at(logged_at: &[u64]) -> Arc<Mutex<FauxLog<'static>>> {
+	let log = faux_log(1..100);
+
+	{
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(
+			logged_at.len() as u64,
+			1234567890,
+			vec![0u8; 32],
+			vec![0u8; 64],
+		);
+		for (i, secs) in logged_at.iter().enumerate() {
+			mlog.add_entry(
+				i as u64,
+				&leaf_input_at(NEW_YEAR + secs * 1000),
+				include_bytes!("x509_extra_data"),
+			);
+		}
+	}
+
+	log
+}
+
+#[test]
+fn fetches_entries_logged_between_two_times() {
+	let output = scrape_json(
+		&log_logged_at(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]),
+		&[
+			"--since",
+			"2024-01-01T00:00:03Z",
+			"--until",
+			"2024-01-01T00:00:06Z",
+			"--time-margin",
+			"0",
+		],
+	);
+
+	assert_eq!(vec![3, 4, 5], entry_numbers(&output));
+}
+
+#[test]
+fn fetches_everything_since_a_time() {
+	let output = scrape_json(
+		&log_logged_at(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]),
+		&["--since", "2024-01-01T00:00:07Z"],
+	);
+
+	assert_eq!(vec![7, 8, 9], entry_numbers(&output));
+}
+
+#[test]
+fn fetches_everything_until_a_time() {
+	let output = scrape_json(
+		&log_logged_at(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]),
+		&["--until", "2024-01-01T00:00:02Z"],
+	);
+
+	assert_eq!(vec![0, 1], entry_numbers(&output));
+}
+
+#[test]
+fn finds_out_of_order_entries_within_the_margin() {
+	// Entry 1 was logged well after the entries around it, and entry 6 well before
+	let logged_at = [0, 60, 2, 3, 4, 5, 1, 7, 8, 9];
+
+	let output = scrape_json(
+		&log_logged_at(&logged_at),
+		&[
+			"--since",
+			"2024-01-01T00:00:01Z",
+			"--until",
+			"2024-01-01T00:00:05Z",
+			"--time-margin",
+			"10",
+		],
+	);
+
+	assert_eq!(vec![2, 3, 4, 6], entry_numbers(&output));
+}
+
+#[test]
+fn fetches_nothing_when_nothing_was_logged_then() {
+	let output = scrape_json(&log_logged_at(&[0, 1, 2, 3]), &["--since", "2025-01-01"]);
+
+	assert!(entry_numbers(&output).is_empty());
+}
+
+#[test]
+fn doesnt_fetch_anything_when_nothing_was_logged_then() {
+	let log = log_logged_at(&[0, 1, 2, 3]);
+	let log_url = log.lock().unwrap().url();
+
+	cmd()
+		.timeout(Duration::from_secs(5))
+		.env("RUST_LOG", "info")
+		.args(["--since", "2025-01-01"])
+		.arg(log_url)
+		.assert()
+		.success()
+		.stderr(contains("the range to fetch is empty").and(contains("filtered out").not()));
+}
+
+#[test]
+fn fetches_from_the_start_until_a_time() {
+	let output = scrape_json(
+		&log_logged_at(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]),
+		&["--start", "3", "--until", "2024-01-01T00:00:06Z"],
+	);
+
+	assert_eq!(vec![3, 4, 5], entry_numbers(&output));
+}
+
+#[test]
+fn fetches_nothing_when_the_start_is_after_the_time() {
+	let output = scrape_json(
+		&log_logged_at(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]),
+		&[
+			"--start",
+			"7",
+			"--until",
+			"2024-01-01T00:00:06Z",
+			"--time-margin",
+			"0",
+		],
+	);
+
+	assert!(entry_numbers(&output).is_empty());
+}
+
+#[test]
+fn since_and_start_dont_mix() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"--since",
+			"2024-01-01",
+			"--start",
+			"42",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("cannot be used with"));
+}
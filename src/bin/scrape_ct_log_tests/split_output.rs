@@ -0,0 +1,147 @@
+use predicates::str::{contains, is_empty};
+use serde_json::Value as SerdeValue;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::faux_log::FauxLog;
+use super::test_helpers::*;
+
+fn five_entry_log() -> (Arc<Mutex<FauxLog<'static>>>, String) {
+	let log = faux_log(1..2);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(5, 1234567890, vec![0u8; 32], vec![0u8; 64]);
+		for i in 0..5 {
+			mlog.add_entry(
+				i,
+				include_bytes!("x509_leaf_input"),
+				include_bytes!("x509_extra_data"),
+			);
+		}
+
+		mlog.url()
+	};
+
+	(log, log_url)
+}
+
+fn files(dir: &std::path::Path) -> Vec<String> {
+	let mut names = std::fs::read_dir(dir)
+		.unwrap()
+		.map(|e| e.unwrap().file_name().into_string().unwrap())
+		.collect::<Vec<_>>();
+	names.sort();
+	names
+}
+
+#[test]
+fn splits_by_entry_count() {
+	let (_log, log_url) = five_entry_log();
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let template = tmpdir
+		.child("scrape-{start}-{end}.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(&["--split-entries", "2", "--output-template", &template])
+		.arg(&log_url)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	assert_eq!(
+		vec!["scrape-0-1.json", "scrape-2-3.json", "scrape-4-4.json"],
+		files(tmpdir.path())
+	);
+
+	let mut entries = vec![];
+	for name in files(tmpdir.path()) {
+		let output: SerdeValue =
+			serde_json::from_slice(&std::fs::read(tmpdir.child(name)).unwrap()).unwrap();
+		assert_eq!(log_url, output["log_url"]);
+		assert_eq!(5, output["sth"]["tree_size"]);
+		assert!(output["scrape_end_timestamp"].is_u64());
+		entries.extend(
+			output["entries"]
+				.as_array()
+				.unwrap()
+				.iter()
+				.map(|e| e["entry_number"].as_u64().unwrap()),
+		);
+	}
+	entries.sort_unstable();
+	assert_eq!(vec![0, 1, 2, 3, 4], entries);
+}
+
+#[test]
+fn splits_by_size() {
+	let (_log, log_url) = five_entry_log();
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let template = tmpdir
+		.child("{start}-{end}.json")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(&["--split-bytes", "1", "--output-template", &template])
+		.arg(&log_url)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	assert_eq!(5, files(tmpdir.path()).len());
+}
+
+#[test]
+fn needs_start_in_the_template() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"--split-entries",
+			"2",
+			"--output-template",
+			"/tmp/scrape.json",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("{start}"));
+}
+
+#[test]
+fn needs_a_split_for_start_and_end() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"--output-template",
+			"/tmp/{start}-{end}.json",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stderr(contains("--split-entries"));
+}
+
+#[test]
+fn needs_an_output_template() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--split-entries", "2", "https://ct.example.com/"])
+		.assert()
+		.failure()
+		.stderr(contains("--output-template"));
+}
@@ -0,0 +1,93 @@
+use predicates::str::{contains, is_empty};
+use rusqlite::Connection;
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+fn count(conn: &Connection, query: &str) -> i64 {
+	conn.query_row(query, [], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn appends_each_scrape_to_the_database() {
+	let (_log, log_url) = test_log(&[TestEntry::X509, TestEntry::Precert], 1..3);
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let path = tmpdir
+		.child("scrapes.sqlite")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	for _ in 0..2 {
+		cmd()
+			.timeout(Duration::from_secs(2))
+			.env("RUST_LOG", "warn")
+			.args(&["-f", "sqlite", "--include-chains", "-o", &path])
+			.arg(&log_url)
+			.assert()
+			.success()
+			.stderr(is_empty());
+	}
+
+	let conn = Connection::open(&path).unwrap();
+	assert_eq!(1, count(&conn, "SELECT COUNT(*) FROM logs"));
+	assert_eq!(
+		2,
+		count(
+			&conn,
+			"SELECT COUNT(*) FROM scrapes WHERE tree_size = 2 AND scrape_end_timestamp IS NOT NULL"
+		)
+	);
+	assert_eq!(2, count(&conn, "SELECT COUNT(*) FROM entries"));
+	assert!(count(&conn, "SELECT COUNT(*) FROM entry_chains") > 0);
+	// Scraping the same chains twice shouldn't leave extra copies of the certs lying around
+	assert_eq!(
+		count(&conn, "SELECT COUNT(*) FROM chain_certificates"),
+		count(
+			&conn,
+			"SELECT COUNT(DISTINCT certificate) FROM entry_chains"
+		)
+	);
+}
+
+#[test]
+fn writes_several_logs_to_one_database() {
+	let (_log1, url1) = test_log(&[TestEntry::X509, TestEntry::Precert], 1..3);
+	let (_log2, url2) = test_log(&[TestEntry::X509, TestEntry::Precert], 1..3);
+
+	let tmpdir = temp_dir::TempDir::new().unwrap();
+	let path = tmpdir
+		.child("scrapes.sqlite")
+		.into_os_string()
+		.into_string()
+		.unwrap();
+
+	cmd()
+		.timeout(Duration::from_secs(2))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", "sqlite", "-o", &path])
+		.arg(&url1)
+		.arg(&url2)
+		.assert()
+		.success()
+		.stderr(is_empty());
+
+	let conn = Connection::open(&path).unwrap();
+	assert_eq!(2, count(&conn, "SELECT COUNT(*) FROM logs"));
+	assert_eq!(4, count(&conn, "SELECT COUNT(*) FROM entries"));
+}
+
+#[test]
+fn needs_an_output_file() {
+	let (_log, log_url) = test_log(&[TestEntry::X509, TestEntry::Precert], 1..3);
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["-f", "sqlite"])
+		.arg(&log_url)
+		.assert()
+		.failure()
+		.stderr(contains("--output"));
+}
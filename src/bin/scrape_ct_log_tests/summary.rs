@@ -0,0 +1,89 @@
+use predicates::prelude::*;
+use predicates::str::{contains, is_empty};
+use std::time::Duration;
+
+use super::test_helpers::*;
+
+const REPORT: &str = "           3  entries
+
+Entry types:
+           1  precert
+           2  x509
+
+Issuers:
+           2  C=GB, ST=Greater Manchester, L=Salford, O=Sectigo Limited, CN=Sectigo RSA Domain Validation Secure Server CA
+           1  CN=Test SSLAdmin 4
+
+Days logged:
+           1  2018-07-24
+           2  2022-10-19
+
+Key types:
+           3  rsa-2048
+
+Validity periods:
+           1  up to 47 days
+           2  up to 100 days
+";
+
+#[test]
+fn writes_only_the_summary() {
+	let log = precert_and_certs_log(2);
+	let log_url = log.lock().unwrap().url();
+
+	let stdout = String::from_utf8(scrape(&log, &["-f", "summary"])).unwrap();
+
+	assert_eq!(format!("Summary of {log_url}\n\n{REPORT}"), stdout);
+}
+
+#[test]
+fn writes_the_summary_as_well_as_the_entries() {
+	let log = precert_and_certs_log(2);
+	let log_url = log.lock().unwrap().url();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.arg("--summary")
+		.arg(log_url.clone())
+		.assert()
+		.success()
+		.stdout(contains("\"entries\""))
+		.stderr(format!("Summary of {log_url}\n\n{REPORT}"));
+}
+
+#[test]
+fn only_summarises_the_entries_that_get_through() {
+	let log = precert_and_certs_log(2);
+	let log_url = log.lock().unwrap().url();
+
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&["--summary", "--entry-type", "precert"])
+		.arg(log_url)
+		.assert()
+		.success()
+		.stderr(
+			contains("           1  entries\n")
+				.and(contains("           1  CN=Test SSLAdmin 4\n"))
+				.and(contains("x509").not()),
+		);
+}
+
+#[test]
+fn only_writes_the_summary() {
+	cmd()
+		.timeout(Duration::from_secs(1))
+		.env("RUST_LOG", "warn")
+		.args(&[
+			"-f",
+			"summary",
+			"--include-precert-data",
+			"https://ct.example.com/",
+		])
+		.assert()
+		.failure()
+		.stdout(is_empty())
+		.stderr(contains("summary output can't include precertificate data"));
+}
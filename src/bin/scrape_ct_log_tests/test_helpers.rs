@@ -1,12 +1,16 @@
+use assert_cmd::prelude::*;
 use assert_cmd::Command;
 use lazy_static::lazy_static;
 use std::ffi::OsString;
 use std::ops::Range;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use super::faux_log::FauxLog;
 
 use itertools::Itertools;
+use predicates::str::is_empty;
+use serde_json::Value as SerdeValue;
 
 lazy_static! {
 	static ref COMMAND_PATH: OsString = {
@@ -14,6 +18,20 @@ lazy_static! {
 		let features = <std::vec::IntoIter<&str> as Itertools>::join(&mut vec![
 			#[cfg(feature = "cbor")]
 			"cbor",
+			#[cfg(feature = "msgpack")]
+			"msgpack",
+			#[cfg(feature = "parquet")]
+			"parquet",
+			#[cfg(feature = "sqlite")]
+			"sqlite",
+			#[cfg(feature = "compression")]
+			"compression",
+			#[cfg(feature = "decode")]
+			"decode",
+			#[cfg(feature = "domain-match")]
+			"domain-match",
+			#[cfg(feature = "serve")]
+			"serve",
 		].into_iter(), ",");
 
 		assert!(std::process::Command::new("cargo").arg("build").arg("--bin").arg("scrape-ct-log").arg("--no-default-features").arg("--features").arg(features).status().expect("build failed").success(), "binary build failed");
@@ -25,6 +43,102 @@ pub(crate) fn cmd() -> Command {
 	Command::new(COMMAND_PATH.to_str().unwrap())
 }
 
+/// For when we need to leave the command running in the background
+pub(crate) fn std_cmd() -> std::process::Command {
+	std::process::Command::new(COMMAND_PATH.to_str().unwrap())
+}
+
 pub(crate) fn faux_log(expected_entries_requests: Range<usize>) -> Arc<Mutex<FauxLog<'static>>> {
 	FauxLog::new(expected_entries_requests)
 }
+
+/// The entries a [`test_log`] can be stocked with
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum TestEntry {
+	/// The test certificate (for crt.sh and www.crt.sh, issued by Sectigo, valid from
+	/// 2022-10-19 to 2023-01-17)
+	X509,
+	/// The test precert (for test7232018-1-1.msitvalidcert.com, issued by "Test SSLAdmin 4",
+	/// valid from 2018-07-23 to 2018-08-23)
+	Precert,
+}
+
+/// A log with `entries`, from entry 0 onwards, that expects to be asked for them
+/// `expected_entries_requests` times, along with its URL
+pub(crate) fn test_log(
+	entries: &[TestEntry],
+	expected_entries_requests: Range<usize>,
+) -> (Arc<Mutex<FauxLog<'static>>>, String) {
+	let log = faux_log(expected_entries_requests);
+
+	let log_url = {
+		let mut mlog = log.lock().unwrap();
+
+		mlog.sth(
+			entries.len() as u64,
+			1234567890,
+			vec![0u8; 32],
+			vec![0u8; 64],
+		);
+		for (i, entry) in entries.iter().enumerate() {
+			match entry {
+				TestEntry::X509 => mlog.add_entry(
+					i as u64,
+					include_bytes!("x509_leaf_input"),
+					include_bytes!("x509_extra_data"),
+				),
+				TestEntry::Precert => mlog.add_entry(
+					i as u64,
+					include_bytes!("precert_leaf_input"),
+					include_bytes!("precert_extra_data"),
+				),
+			}
+		}
+
+		mlog.url()
+	};
+
+	(log, log_url)
+}
+
+/// A [`test_log`] with the test precert at entry 0, followed by `certs` copies of the test
+/// certificate
+pub(crate) fn precert_and_certs_log(certs: usize) -> Arc<Mutex<FauxLog<'static>>> {
+	let entries = [vec![TestEntry::Precert], vec![TestEntry::X509; certs]].concat();
+
+	test_log(&entries, 1..2).0
+}
+
+/// Scrape `log` in order, make sure that went off without a word of complaint, and hand back
+/// whatever was written to stdout
+pub(crate) fn scrape(log: &Arc<Mutex<FauxLog<'static>>>, args: &[&str]) -> Vec<u8> {
+	let log_url = log.lock().unwrap().url();
+
+	let res = cmd()
+		.timeout(Duration::from_secs(5))
+		.env("RUST_LOG", "warn")
+		.arg("--ordered")
+		.args(args)
+		.arg(log_url)
+		.unwrap();
+
+	let stdout = res.stdout.clone();
+	res.assert().success().stderr(is_empty());
+
+	stdout
+}
+
+/// As for [`scrape`], for when the output is a single JSON document
+pub(crate) fn scrape_json(log: &Arc<Mutex<FauxLog<'static>>>, args: &[&str]) -> SerdeValue {
+	serde_json::from_slice(&scrape(log, args)).unwrap()
+}
+
+/// The entry numbers in a scrape's JSON output, in the order they were written
+pub(crate) fn entry_numbers(output: &SerdeValue) -> Vec<u64> {
+	output["entries"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.map(|e| e["entry_number"].as_u64().unwrap())
+		.collect()
+}
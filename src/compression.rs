@@ -0,0 +1,402 @@
+//! Compressing output on the fly
+//!
+//! Compression is slow enough that doing it on the same thread as the output processor would
+//! hold up the processing of entries, so [`CompressedWriter`] hands everything that's written
+//! to it off to a thread of its own, which does the compressing and writes the result to the
+//! underlying writer.
+//!
+
+use std::{
+	io::{self, Write},
+	path::Path,
+	sync::mpsc::{sync_channel, Receiver, SyncSender},
+	thread::JoinHandle,
+};
+
+use crate::Error;
+
+/// How much gets written before it's sent off to be compressed
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How many chunks can be waiting to be compressed before writes start to block
+const CHUNK_BACKLOG: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+	Gzip,
+	Zstd,
+	Xz,
+}
+
+impl Compression {
+	/// Figure out what compression a file should have, from its extension
+	#[must_use]
+	pub fn from_path(path: &Path) -> Option<Self> {
+		match path.extension()?.to_str()? {
+			"gz" => Some(Compression::Gzip),
+			"zst" | "zstd" => Some(Compression::Zstd),
+			"xz" => Some(Compression::Xz),
+			_ => None,
+		}
+	}
+
+	/// The compression levels that are allowed, and the one that's used if no level is given
+	#[must_use]
+	pub fn levels(self) -> (std::ops::RangeInclusive<i32>, i32) {
+		match self {
+			Compression::Gzip | Compression::Xz => (0..=9, 6),
+			Compression::Zstd => (1..=22, zstd::DEFAULT_COMPRESSION_LEVEL),
+		}
+	}
+}
+
+impl std::fmt::Display for Compression {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		f.write_str(match self {
+			Compression::Gzip => "gzip",
+			Compression::Zstd => "zstd",
+			Compression::Xz => "xz",
+		})
+	}
+}
+
+impl TryFrom<&str> for Compression {
+	type Error = String;
+
+	fn try_from(s: &str) -> Result<Self, Self::Error> {
+		match s {
+			"gzip" => Ok(Compression::Gzip),
+			"zstd" => Ok(Compression::Zstd),
+			"xz" => Ok(Compression::Xz),
+			_ => Err(format!(
+				"unknown compression algorithm {s:?} (must be one of gzip, zstd, or xz)"
+			)),
+		}
+	}
+}
+
+/// A writer that compresses everything written to it (on another thread), before passing it on
+///
+/// The compressed stream is only complete once the writer has been [finished](Self::finish);
+/// if it gets dropped without that, it is finished then, but any error can only be logged,
+/// unless someone took its [completion](Self::completion) to wait on.
+#[derive(Debug)]
+pub struct CompressedWriter {
+	sender: Option<SyncSender<Vec<u8>>>,
+	thread: Option<JoinHandle<io::Result<()>>>,
+	buffer: Vec<u8>,
+}
+
+impl CompressedWriter {
+	/// Start compressing into the given writer
+	///
+	/// # Errors
+	///
+	/// If the level isn't one that the compression algorithm supports, or the compression
+	/// thread couldn't be started.
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn new<W: Write + Send + 'static>(
+		writer: W,
+		compression: Compression,
+		level: Option<i32>,
+	) -> Result<Self, Error> {
+		let (levels, default_level) = compression.levels();
+		let level = level.unwrap_or(default_level);
+		if !levels.contains(&level) {
+			return Err(Error::output(
+				compression,
+				format!(
+					"compression level {level} is out of range ({}-{})",
+					levels.start(),
+					levels.end()
+				),
+			));
+		}
+
+		let (sender, receiver) = sync_channel(CHUNK_BACKLOG);
+		let thread = std::thread::Builder::new()
+			.name(format!("{compression} compressor"))
+			.spawn(move || compress(receiver, writer, compression, level))
+			.map_err(|e| Error::system("failed to spawn compression thread", e))?;
+
+		Ok(Self {
+			sender: Some(sender),
+			thread: Some(thread),
+			buffer: Vec::with_capacity(CHUNK_SIZE),
+		})
+	}
+
+	/// Send off whatever's been written so far to be compressed
+	fn send(&mut self) -> io::Result<()> {
+		if self.buffer.is_empty() {
+			return Ok(());
+		}
+
+		let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK_SIZE));
+		let sent = self
+			.sender
+			.as_ref()
+			.ok_or_else(|| io::Error::other("compressed writer has already been finished"))?
+			.send(chunk);
+
+		if sent.is_err() {
+			// The only way the channel goes away is if the compression thread has stopped,
+			// which it only does if it went wrong, so go and find out what happened
+			self.join()?;
+			return Err(io::Error::other("compression thread stopped unexpectedly"));
+		}
+
+		Ok(())
+	}
+
+	fn join(&mut self) -> io::Result<()> {
+		self.sender = None;
+		self.thread
+			.take()
+			.map_or(Ok(()), |thread| Completion(thread).wait())
+	}
+
+	/// Take charge of finding out how the compression went
+	///
+	/// Once the completion has been taken, finishing (or dropping) the writer no longer waits
+	/// for the compression thread, so the completion needs to be [waited on](Completion::wait)
+	/// to know that the compressed stream has all been written.  Useful when the writer is
+	/// going to be handed off somewhere that will only ever drop it.
+	pub fn completion(&mut self) -> Option<Completion> {
+		self.thread.take().map(Completion)
+	}
+
+	/// Compress everything that's left, and finish off the compressed stream
+	///
+	/// # Errors
+	///
+	/// If anything went wrong with compressing or writing out the stream.
+	pub fn finish(mut self) -> io::Result<()> {
+		self.send()?;
+		self.join()
+	}
+}
+
+impl Write for CompressedWriter {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.buffer.extend_from_slice(buf);
+		if self.buffer.len() >= CHUNK_SIZE {
+			self.send()?;
+		}
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.send()
+	}
+}
+
+impl Drop for CompressedWriter {
+	fn drop(&mut self) {
+		// Whoever took the completion will hear about anything that went wrong
+		let waited_on = self.thread.is_none();
+		if let Err(e) = self.send().and_then(|()| self.join()) {
+			if waited_on {
+				log::debug!("Failed to finish writing compressed output: {e}");
+			} else {
+				log::error!("Failed to finish writing compressed output: {e}");
+			}
+		}
+	}
+}
+
+/// The end of a [`CompressedWriter`]'s compression, which can be waited on once the writer is
+/// finished (or dropped)
+#[derive(Debug)]
+pub struct Completion(JoinHandle<io::Result<()>>);
+
+impl Completion {
+	/// Wait for the compressed stream to be written out completely
+	///
+	/// This won't return until the writer has been finished or dropped.
+	///
+	/// # Errors
+	///
+	/// If anything went wrong with compressing or writing out the stream.
+	pub fn wait(self) -> io::Result<()> {
+		match self.0.join() {
+			Ok(result) => result,
+			Err(_) => Err(io::Error::other("compression thread panicked")),
+		}
+	}
+}
+
+/// Feed everything that comes in through the channel into the encoder
+fn pump<E: Write>(receiver: Receiver<Vec<u8>>, encoder: &mut E) -> io::Result<()> {
+	for chunk in receiver {
+		encoder.write_all(&chunk)?;
+	}
+	Ok(())
+}
+
+/// The body of the compression thread
+fn compress<W: Write>(
+	receiver: Receiver<Vec<u8>>,
+	writer: W,
+	compression: Compression,
+	level: i32,
+) -> io::Result<()> {
+	// Level ranges are all checked before we get here, so they're all non-negative
+	let unsigned_level = u32::try_from(level).map_err(io::Error::other)?;
+
+	let mut writer = match compression {
+		Compression::Gzip => {
+			let mut encoder =
+				flate2::write::GzEncoder::new(writer, flate2::Compression::new(unsigned_level));
+			pump(receiver, &mut encoder)?;
+			encoder.finish()?
+		}
+		Compression::Zstd => {
+			let mut encoder = zstd::Encoder::new(writer, level)?;
+			pump(receiver, &mut encoder)?;
+			encoder.finish()?
+		}
+		Compression::Xz => {
+			let mut encoder = xz2::write::XzEncoder::new(writer, unsigned_level);
+			pump(receiver, &mut encoder)?;
+			encoder.finish()?
+		}
+	};
+
+	writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Read as _;
+	use std::sync::{Arc, Mutex};
+
+	/// A writer we can get the contents of after it's been handed off to another thread
+	#[derive(Clone, Default)]
+	struct Shared(Arc<Mutex<Vec<u8>>>);
+
+	impl Write for Shared {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.lock().unwrap().write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	fn compressed(compression: Compression, data: &[u8]) -> Vec<u8> {
+		let out = Shared::default();
+		let mut w = CompressedWriter::new(out.clone(), compression, None).unwrap();
+		// Lots of little writes, so that it takes more than one chunk
+		for piece in data.chunks(1000) {
+			w.write_all(piece).unwrap();
+		}
+		w.finish().unwrap();
+
+		let buf = out.0.lock().unwrap().clone();
+		buf
+	}
+
+	fn data() -> Vec<u8> {
+		(0..200_000u32)
+			.flat_map(|n| (n % 251).to_be_bytes())
+			.collect()
+	}
+
+	#[test]
+	fn round_trips_gzip() {
+		let mut out = vec![];
+		flate2::read::GzDecoder::new(&compressed(Compression::Gzip, &data())[..])
+			.read_to_end(&mut out)
+			.unwrap();
+		assert_eq!(data(), out);
+	}
+
+	#[test]
+	fn round_trips_zstd() {
+		assert_eq!(
+			data(),
+			zstd::decode_all(&compressed(Compression::Zstd, &data())[..]).unwrap()
+		);
+	}
+
+	#[test]
+	fn round_trips_xz() {
+		let mut out = vec![];
+		xz2::read::XzDecoder::new(&compressed(Compression::Xz, &data())[..])
+			.read_to_end(&mut out)
+			.unwrap();
+		assert_eq!(data(), out);
+	}
+
+	#[test]
+	fn finishes_the_stream_when_dropped() {
+		let out = Shared::default();
+		{
+			let mut w = CompressedWriter::new(out.clone(), Compression::Zstd, Some(19)).unwrap();
+			w.write_all(b"ohai").unwrap();
+		}
+
+		let buf = out.0.lock().unwrap().clone();
+		assert_eq!(b"ohai".to_vec(), zstd::decode_all(&buf[..]).unwrap());
+	}
+
+	/// A writer that can't be written to
+	struct Full;
+
+	impl Write for Full {
+		fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+			Err(io::Error::other("no space left on device"))
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn reports_write_failures_when_finished() {
+		let mut w = CompressedWriter::new(Full, Compression::Gzip, None).unwrap();
+		w.write_all(b"ohai").unwrap();
+
+		assert!(w.finish().is_err());
+	}
+
+	#[test]
+	fn reports_write_failures_to_whoever_waits() {
+		let mut w = CompressedWriter::new(Full, Compression::Gzip, None).unwrap();
+		let completion = w.completion().unwrap();
+		w.write_all(b"ohai").unwrap();
+		drop(w);
+
+		let err = completion.wait().unwrap_err();
+		assert!(err.to_string().contains("no space left"), "{err}");
+	}
+
+	#[test]
+	fn refuses_silly_levels() {
+		assert!(CompressedWriter::new(io::sink(), Compression::Gzip, Some(10)).is_err());
+		assert!(CompressedWriter::new(io::sink(), Compression::Zstd, Some(0)).is_err());
+	}
+
+	#[test]
+	fn infers_compression_from_extension() {
+		assert_eq!(
+			Some(Compression::Gzip),
+			Compression::from_path(Path::new("scrape.json.gz"))
+		);
+		assert_eq!(
+			Some(Compression::Zstd),
+			Compression::from_path(Path::new("/tmp/scrape.cbor.zst"))
+		);
+		assert_eq!(
+			Some(Compression::Xz),
+			Compression::from_path(Path::new("scrape.xz"))
+		);
+		assert_eq!(None, Compression::from_path(Path::new("scrape.json")));
+		assert_eq!(None, Compression::from_path(Path::new("scrape")));
+	}
+}
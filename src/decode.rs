@@ -0,0 +1,206 @@
+//! Picking apart the certificates in entries
+//!
+//! Pretty much everyone who looks at a scrape wants the same handful of things out of each
+//! certificate -- who it's for, who issued it, when it's valid, and what sort of key it has --
+//! so we can save them the trouble of parsing everything again themselves.
+//!
+//! Logs are full of certificates that are broken in all sorts of exciting ways, so failing to
+//! parse one isn't an error; the reason is recorded in the [`Decoded`] instead.
+//!
+
+use ring::digest::{digest, SHA256};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use x509_parser::{
+	prelude::{GeneralName, SubjectPublicKeyInfo},
+	public_key::PublicKey,
+};
+
+use crate::document::Decoded;
+
+const RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+const EC_PUBLIC_KEY: &str = "1.2.840.10045.2.1";
+const DSA: &str = "1.2.840.10040.4.1";
+const ED25519: &str = "1.3.101.112";
+const ED448: &str = "1.3.101.113";
+
+const P256: &str = "1.2.840.10045.3.1.7";
+const P384: &str = "1.3.132.0.34";
+const P521: &str = "1.3.132.0.35";
+
+/// Pull the interesting bits out of a DER-encoded certificate (or precertificate)
+#[must_use]
+pub fn decode(certificate: &[u8]) -> Decoded {
+	let cert = match x509_parser::parse_x509_certificate(certificate) {
+		Ok((_, cert)) => cert,
+		Err(e) => return Decoded::error(format!("couldn't parse certificate: {e}")),
+	};
+
+	let mut decoded = Decoded {
+		subject: Some(cert.subject().to_string()),
+		common_name: cert
+			.subject()
+			.iter_common_name()
+			.next()
+			.and_then(|cn| cn.as_str().ok())
+			.map(str::to_string),
+		issuer: Some(cert.issuer().to_string()),
+		serial: Some(cert.raw_serial().to_vec()),
+		not_before: millis(cert.validity().not_before.timestamp()),
+		not_after: millis(cert.validity().not_after.timestamp()),
+		key_type: Some(key_type(cert.public_key())),
+		..Decoded::default()
+	};
+
+	match cert.subject_alternative_name() {
+		Ok(Some(san)) => {
+			for name in &san.value.general_names {
+				if let GeneralName::DNSName(dns_name) = name {
+					decoded.dns_names.push((*dns_name).to_string());
+				} else if let GeneralName::IPAddress(ip) = name {
+					decoded.ip_addresses.push(ip_address(ip));
+				} else {
+					// Email addresses, URIs, and the like aren't what anyone's after
+				}
+			}
+		}
+		Ok(None) => (),
+		Err(e) => decoded.error = Some(format!("couldn't parse subjectAltName: {e}")),
+	}
+
+	decoded
+}
+
+/// The SHA-256 hash of a DER-encoded certificate's `SubjectPublicKeyInfo`, which is how CT
+/// identifies issuers
+#[must_use]
+pub(crate) fn spki_hash(certificate: &[u8]) -> Option<Vec<u8>> {
+	let (_, cert) = x509_parser::parse_x509_certificate(certificate).ok()?;
+
+	Some(digest(&SHA256, cert.public_key().raw).as_ref().to_vec())
+}
+
+/// Turn seconds since the epoch into milliseconds, as long as it's after the epoch
+fn millis(seconds: i64) -> Option<u64> {
+	u64::try_from(seconds).ok().map(|s| s.saturating_mul(1000))
+}
+
+/// An IP address from a subjectAltName, which is written in the usual way if it's the right
+/// size to be one, and as hex otherwise
+fn ip_address(ip: &[u8]) -> String {
+	if let Ok(v4) = <[u8; 4]>::try_from(ip) {
+		IpAddr::from(Ipv4Addr::from(v4)).to_string()
+	} else if let Ok(v6) = <[u8; 16]>::try_from(ip) {
+		IpAddr::from(Ipv6Addr::from(v6)).to_string()
+	} else {
+		crate::utils::hex(ip)
+	}
+}
+
+/// Describe the subject's key, as briefly as possible
+fn key_type(spki: &SubjectPublicKeyInfo<'_>) -> String {
+	let algorithm = spki.algorithm.algorithm.to_id_string();
+
+	match algorithm.as_str() {
+		RSA_ENCRYPTION => {
+			if let Ok(PublicKey::RSA(rsa)) = spki.parsed() {
+				format!("rsa-{}", bit_length(rsa.modulus))
+			} else {
+				"rsa".to_string()
+			}
+		}
+		EC_PUBLIC_KEY => {
+			let curve = spki
+				.algorithm
+				.parameters
+				.as_ref()
+				.and_then(|p| p.as_oid().ok())
+				.map(|oid| oid.to_id_string());
+			match curve.as_deref() {
+				Some(P256) => "ecdsa-p256".to_string(),
+				Some(P384) => "ecdsa-p384".to_string(),
+				Some(P521) => "ecdsa-p521".to_string(),
+				Some(other) => format!("ecdsa-{other}"),
+				None => "ecdsa".to_string(),
+			}
+		}
+		ED25519 => "ed25519".to_string(),
+		ED448 => "ed448".to_string(),
+		DSA => "dsa".to_string(),
+		// Something exotic, which can be looked up by anyone who really cares
+		_ => algorithm,
+	}
+}
+
+/// How many bits there are in a big-endian unsigned integer, not counting leading zeroes
+fn bit_length(n: &[u8]) -> usize {
+	let Some(start) = n.iter().position(|b| *b != 0) else {
+		return 0;
+	};
+	let n = n.get(start..).unwrap_or_default();
+	let leading = n
+		.first()
+		.map_or(0, |b| usize::try_from(b.leading_zeros()).unwrap_or(0));
+
+	n.len().saturating_mul(8).saturating_sub(leading)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The certificate out of one of the CLI tests' log entries
+	fn certificate() -> Vec<u8> {
+		let leaf = include_bytes!("bin/scrape_ct_log_tests/x509_leaf_input");
+		// version, leaf type, timestamp, entry type, and then a 24-bit length
+		let len = leaf
+			.get(12..15)
+			.unwrap()
+			.iter()
+			.fold(0usize, |n, b| (n << 8) | usize::from(*b));
+		leaf.get(15..15 + len).unwrap().to_vec()
+	}
+
+	#[test]
+	fn decodes_certificates() {
+		let decoded = decode(&certificate());
+
+		assert_eq!(None, decoded.error);
+		assert!(decoded.subject.is_some());
+		assert!(decoded.issuer.is_some());
+		assert!(!decoded.serial.unwrap().is_empty());
+		assert!(decoded.not_before.unwrap() < decoded.not_after.unwrap());
+		assert!(decoded.key_type.is_some());
+	}
+
+	#[test]
+	fn records_parse_failures() {
+		let decoded = decode(b"this is not a certificate");
+
+		assert!(decoded.error.is_some());
+		assert_eq!(None, decoded.subject);
+	}
+
+	#[test]
+	fn counts_bits() {
+		assert_eq!(0, bit_length(&[]));
+		assert_eq!(0, bit_length(&[0, 0]));
+		assert_eq!(1, bit_length(&[1]));
+		assert_eq!(2048, bit_length(&[&[0u8, 0x80][..], &[0; 255]].concat()));
+	}
+
+	#[test]
+	fn formats_ip_addresses() {
+		assert_eq!("192.0.2.1", ip_address(&[192, 0, 2, 1]));
+		assert_eq!(
+			"2001:db8::1",
+			ip_address(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1])
+		);
+		assert_eq!("0102", ip_address(&[1, 2]));
+	}
+
+	#[test]
+	fn doesnt_count_the_epoch_as_negative() {
+		assert_eq!(Some(1_000), millis(1));
+		assert_eq!(None, millis(-1));
+	}
+}
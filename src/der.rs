@@ -0,0 +1,165 @@
+//! Just enough DER to pick apart the handful of ASN.1 structures we need to look inside
+//!
+//! This is deliberately minimal: it only understands single-octet tags and definite lengths,
+//! which is all that DER allows for the structures we care about (SPKIs, certificates and
+//! the like).
+//!
+
+/// Tag for an ASN.1 SEQUENCE
+pub(crate) const SEQUENCE: u8 = 0x30;
+/// Tag for an ASN.1 OBJECT IDENTIFIER
+pub(crate) const OID: u8 = 0x06;
+/// Tag for an ASN.1 BIT STRING
+pub(crate) const BIT_STRING: u8 = 0x03;
+
+/// A single tag-length-value element
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Tlv<'a> {
+	pub(crate) tag: u8,
+	pub(crate) value: &'a [u8],
+}
+
+/// Read one element from the front of `input`, returning it along with whatever follows it.
+pub(crate) fn parse(input: &[u8]) -> Result<(Tlv<'_>, &[u8]), String> {
+	let (&tag, rest) = input
+		.split_first()
+		.ok_or("unexpected end of input reading tag")?;
+	if tag & 0x1f == 0x1f {
+		return Err(format!(
+			"multi-octet tags are not supported (tag {tag:#04x})"
+		));
+	}
+	let (&len_octet, rest) = rest
+		.split_first()
+		.ok_or("unexpected end of input reading length")?;
+
+	let (len, rest) = if len_octet & 0x80 == 0 {
+		(usize::from(len_octet), rest)
+	} else {
+		let n = usize::from(len_octet & 0x7f);
+		if n == 0 || n > 4 {
+			return Err(format!("unsupported length encoding {len_octet:#04x}"));
+		}
+		let (len_bytes, rest) = rest
+			.split_at_checked(n)
+			.ok_or("unexpected end of input reading long-form length")?;
+		let len = len_bytes
+			.iter()
+			.try_fold(0usize, |acc, &b| {
+				acc.checked_mul(256)?.checked_add(usize::from(b))
+			})
+			.ok_or("length overflowed")?;
+		(len, rest)
+	};
+
+	let (value, remainder) = rest.split_at_checked(len).ok_or_else(|| {
+		format!(
+			"element claims length {len}, but only {} bytes remain",
+			rest.len()
+		)
+	})?;
+	Ok((Tlv { tag, value }, remainder))
+}
+
+/// Read one element from the front of `input`, insisting that it has the given tag.
+pub(crate) fn expect(input: &[u8], tag: u8) -> Result<(Tlv<'_>, &[u8]), String> {
+	let (tlv, rest) = parse(input)?;
+	if tlv.tag == tag {
+		Ok((tlv, rest))
+	} else {
+		Err(format!("expected tag {tag:#04x}, found {:#04x}", tlv.tag))
+	}
+}
+
+/// Read one element from the front of `input`, returning its complete encoding (tag, length
+/// and all) along with the element itself and whatever follows it.
+pub(crate) fn parse_encoded(input: &[u8]) -> Result<(&[u8], Tlv<'_>, &[u8]), String> {
+	let (tlv, rest) = parse(input)?;
+	let encoded = input
+		.get(..input.len().saturating_sub(rest.len()))
+		.ok_or("element overran its input")?;
+	Ok((encoded, tlv, rest))
+}
+
+/// Encode an element, with the shortest length encoding that'll do
+pub(crate) fn encode(tag: u8, value: &[u8]) -> Vec<u8> {
+	let len = value.len().to_be_bytes();
+	let significant = len
+		.iter()
+		.skip_while(|&&b| b == 0)
+		.copied()
+		.collect::<Vec<_>>();
+
+	let mut encoded = vec![tag];
+	match significant.as_slice() {
+		[] => encoded.push(0),
+		[b] if *b < 0x80 => encoded.push(*b),
+		bytes => {
+			encoded.push(0x80 | u8::try_from(bytes.len()).unwrap_or(0x7f));
+			encoded.extend_from_slice(bytes);
+		}
+	}
+	encoded.extend_from_slice(value);
+	encoded
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_a_short_element() {
+		let (tlv, rest) = parse(&[0x04, 0x02, 0xAB, 0xCD, 0xFF]).unwrap();
+
+		assert_eq!(0x04, tlv.tag);
+		assert_eq!(&[0xAB, 0xCD], tlv.value);
+		assert_eq!(&[0xFF], rest);
+	}
+
+	#[test]
+	fn parses_a_long_element() {
+		let mut input = vec![0x04, 0x82, 0x01, 0x00];
+		input.extend_from_slice(&[0x42; 256]);
+
+		let (tlv, rest) = parse(&input).unwrap();
+
+		assert_eq!(256, tlv.value.len());
+		assert!(rest.is_empty());
+	}
+
+	#[test]
+	fn rejects_truncated_elements() {
+		assert!(parse(&[0x04, 0x03, 0xAB]).is_err());
+		assert!(parse(&[0x04, 0x82, 0x01]).is_err());
+		assert!(parse(&[0x04]).is_err());
+	}
+
+	#[test]
+	fn keeps_the_encoding() {
+		let (encoded, tlv, rest) = parse_encoded(&[0x30, 0x01, 0x05, 0x00]).unwrap();
+
+		assert_eq!(&[0x30, 0x01, 0x05], encoded);
+		assert_eq!(&[0x05], tlv.value);
+		assert_eq!(&[0x00], rest);
+	}
+
+	#[test]
+	fn encodes_elements() {
+		assert_eq!(vec![0x04, 0x00], encode(0x04, &[]));
+		assert_eq!(vec![0x04, 0x02, 0xAB, 0xCD], encode(0x04, &[0xAB, 0xCD]));
+
+		let long = encode(0x04, &[0x42; 256]);
+		assert_eq!(&[0x04, 0x82, 0x01, 0x00], &long[..4]);
+		assert_eq!(260, long.len());
+
+		let encoded = encode(0x30, &[0x42; 200]);
+		let (tlv, rest) = parse(&encoded).unwrap();
+		assert_eq!(200, tlv.value.len());
+		assert!(rest.is_empty());
+	}
+
+	#[test]
+	fn rejects_unexpected_tags() {
+		assert!(expect(&[0x04, 0x00], SEQUENCE).is_err());
+	}
+}
@@ -0,0 +1,545 @@
+//! The pieces that make up a scrape document
+//!
+//! Whatever the output format, a scrape is written as some metadata about the log (including
+//! the STH that was retrieved), followed by the entries, followed by a little bit of
+//! information about how the scrape went.  These types are what gets written by the
+//! [`DocumentWriter`](crate::file_writer::DocumentWriter), and read back in by the
+//! [`reader`](crate::reader).
+//!
+
+use base64::{
+	alphabet,
+	engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+	Engine as _,
+};
+use ct_structs::v1::{
+	response::{GetSth as GetSthResponse, ResponseEntry},
+	ExtraData, SignedEntry, TreeLeafEntry,
+};
+use ring::digest::{digest, SHA256, SHA256_OUTPUT_LEN};
+use serde::{
+	de::{self, Deserializer, Visitor},
+	Deserialize,
+};
+use std::collections::BTreeMap;
+use url::Url;
+
+use crate::{processor::RawEntry, tls::leaf_hash, utils::unhex, Error};
+
+/// `bytes` values in JSON output are unpadded, but there's no need to be fussy about that
+const B64: GeneralPurpose = GeneralPurpose::new(
+	&alphabet::STANDARD,
+	GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Everything we know about the log, before we start writing out entries
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Metadata {
+	/// Only missing if the document was written without it
+	pub log_url: Option<Url>,
+	/// Only present if the log was picked from a log list
+	pub log_id: Option<Vec<u8>>,
+	pub scrape_begin_timestamp: Option<u64>,
+	/// Only missing if the document was written without it
+	pub sth: Option<Sth>,
+}
+
+impl Metadata {
+	#[must_use]
+	pub fn new(log_url: Option<Url>, sth: Option<Sth>) -> Self {
+		Self {
+			log_url,
+			log_id: None,
+			scrape_begin_timestamp: None,
+			sth,
+		}
+	}
+
+	#[must_use]
+	pub fn log_id(mut self, log_id: Option<Vec<u8>>) -> Self {
+		self.log_id = log_id;
+		self
+	}
+
+	#[must_use]
+	pub fn scrape_begin_timestamp(mut self, timestamp: Option<u64>) -> Self {
+		self.scrape_begin_timestamp = timestamp;
+		self
+	}
+}
+
+/// The Signed Tree Head that was retrieved at the start of the scrape
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Sth {
+	pub tree_size: u64,
+	pub timestamp: u64,
+	#[serde(deserialize_with = "bytes")]
+	pub sha256_root_hash: Vec<u8>,
+	#[serde(deserialize_with = "bytes")]
+	pub tree_head_signature: Vec<u8>,
+}
+
+impl From<&GetSthResponse> for Sth {
+	fn from(sth: &GetSthResponse) -> Self {
+		Self {
+			tree_size: sth.tree_size,
+			timestamp: sth.timestamp,
+			sha256_root_hash: sth.sha256_root_hash.clone(),
+			tree_head_signature: sth.tree_head_signature.clone(),
+		}
+	}
+}
+
+impl From<Sth> for GetSthResponse {
+	fn from(sth: Sth) -> Self {
+		Self {
+			tree_size: sth.tree_size,
+			timestamp: sth.timestamp,
+			sha256_root_hash: sth.sha256_root_hash,
+			tree_head_signature: sth.tree_head_signature,
+		}
+	}
+}
+
+/// A single entry from the log
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Entry {
+	pub entry_number: u64,
+	pub timestamp: u64,
+	/// Always there in entries from a log, but a document can be written without it
+	#[serde(default, deserialize_with = "optional_bytes")]
+	pub certificate: Option<Vec<u8>>,
+	/// Only present if the scrape was done with chains included
+	#[serde(default, deserialize_with = "optional_chain")]
+	pub chain: Option<Vec<ChainLink>>,
+	/// Only present for precert entries, if the scrape was done with precert data included
+	pub precert: Option<Precert>,
+	/// The entry's `leaf_input`, exactly as the log sent it; only present if the scrape was
+	/// done with raw entries included
+	#[serde(default, deserialize_with = "optional_bytes")]
+	pub leaf_input: Option<Vec<u8>>,
+	/// The entry's `extra_data`, exactly as the log sent it; only present if the scrape was
+	/// done with raw entries included
+	#[serde(default, deserialize_with = "optional_bytes")]
+	pub extra_data: Option<Vec<u8>>,
+	/// The RFC 6962 leaf hash of the entry; only present if the scrape was done with hashes
+	/// included
+	#[serde(default, deserialize_with = "optional_bytes")]
+	pub leaf_hash: Option<Vec<u8>>,
+	/// The SHA-256 hash of `certificate`; only present if the scrape was done with hashes
+	/// included
+	#[serde(default, deserialize_with = "optional_bytes")]
+	pub certificate_sha256: Option<Vec<u8>>,
+	/// Only present if the scrape was done with entry types included
+	#[serde(default)]
+	pub entry_type: Option<EntryType>,
+	/// What's in the certificate; only present if the scrape was done with decoded
+	/// certificates included
+	#[serde(default)]
+	pub decoded: Option<Box<Decoded>>,
+}
+
+/// The commonly-wanted parts of an entry's certificate, so they don't have to be dug out of
+/// the DER
+///
+/// If the certificate couldn't be parsed, all there is is the `error`; if only part of it
+/// couldn't be parsed, the `error` says which part.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Decoded {
+	pub subject: Option<String>,
+	/// The first common name in the subject, if there is one
+	pub common_name: Option<String>,
+	pub issuer: Option<String>,
+	#[serde(default, deserialize_with = "optional_bytes")]
+	pub serial: Option<Vec<u8>>,
+	/// Milliseconds since the epoch
+	pub not_before: Option<u64>,
+	/// Milliseconds since the epoch
+	pub not_after: Option<u64>,
+	/// The DNS names in the subjectAltName extension
+	#[serde(default)]
+	pub dns_names: Vec<String>,
+	/// The IP addresses in the subjectAltName extension
+	#[serde(default)]
+	pub ip_addresses: Vec<String>,
+	/// The type (and size) of the subject's public key, such as `rsa-2048` or `ecdsa-p256`
+	pub key_type: Option<String>,
+	/// Why the certificate couldn't be decoded
+	pub error: Option<String>,
+}
+
+impl Decoded {
+	/// A certificate that couldn't be decoded
+	#[must_use]
+	pub fn error(error: impl Into<String>) -> Self {
+		Self {
+			error: Some(error.into()),
+			..Self::default()
+		}
+	}
+}
+
+/// Whether an entry is for a certificate, or a precertificate
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum EntryType {
+	X509,
+	Precert,
+}
+
+impl EntryType {
+	/// What an entry of this type is called in the output
+	#[must_use]
+	pub fn as_str(self) -> &'static str {
+		match self {
+			EntryType::X509 => "x509",
+			EntryType::Precert => "precert",
+		}
+	}
+
+	/// What sort of entry the log gave us
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub(crate) fn of(entry: &ResponseEntry) -> Result<Self, Error> {
+		if let TreeLeafEntry::TimestampedEntry(ts_entry) = &entry.leaf_input.entry {
+			Ok(match ts_entry.signed_entry {
+				SignedEntry::X509Entry(_) => EntryType::X509,
+				SignedEntry::PrecertEntry(_) => EntryType::Precert,
+			})
+		} else {
+			Err(Error::EntryDecodingError(
+				"leaf_input was not a TimestampedEntry".to_string(),
+			))
+		}
+	}
+}
+
+impl TryFrom<&str> for EntryType {
+	type Error = String;
+
+	fn try_from(s: &str) -> Result<Self, Self::Error> {
+		match s {
+			"x509" => Ok(EntryType::X509),
+			"precert" => Ok(EntryType::Precert),
+			other => Err(format!(
+				"unknown entry type {other:?} (expected x509 or precert)"
+			)),
+		}
+	}
+}
+
+impl std::fmt::Display for EntryType {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl Entry {
+	#[must_use]
+	pub fn new(entry_number: u64, timestamp: u64, certificate: Vec<u8>) -> Self {
+		Self {
+			entry_number,
+			timestamp,
+			certificate: Some(certificate),
+			chain: None,
+			precert: None,
+			leaf_input: None,
+			extra_data: None,
+			leaf_hash: None,
+			certificate_sha256: None,
+			entry_type: None,
+			decoded: None,
+		}
+	}
+
+	/// Pull the parts of an entry we care about out of what the log gave us
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub(crate) fn from_response(
+		entry_number: u64,
+		entry: &ResponseEntry,
+		include_chains: bool,
+		include_precert_data: bool,
+	) -> Result<Self, Error> {
+		let (timestamp, certificate, chain_certs, precert) =
+			if let TreeLeafEntry::TimestampedEntry(ts_entry) = &entry.leaf_input.entry {
+				match (&ts_entry.signed_entry, &entry.extra_data) {
+					(SignedEntry::X509Entry(x509_entry), ExtraData::X509ExtraData(extra_data)) => {
+						Ok((
+							ts_entry.timestamp,
+							&x509_entry.certificate,
+							&extra_data.certificate_chain,
+							None,
+						))
+					}
+					(
+						SignedEntry::PrecertEntry(precert_entry),
+						ExtraData::PrecertExtraData(extra_data),
+					) => Ok((
+						ts_entry.timestamp,
+						&extra_data.pre_certificate.certificate,
+						&extra_data.precertificate_chain,
+						Some(precert_entry),
+					)),
+					_ => Err(Error::InternalError(format!(
+						"incompatible combination of signed_entry and extra_data ({:?} vs {:?})",
+						ts_entry.signed_entry, entry.extra_data
+					))),
+				}
+			} else {
+				Err(Error::EntryDecodingError(
+					"leaf_input was not a TimestampedEntry".to_string(),
+				))
+			}?;
+
+		Ok(Self::new(entry_number, timestamp, certificate.clone())
+			.chain(
+				include_chains.then(|| chain_certs.iter().map(|c| c.certificate.clone()).collect()),
+			)
+			.precert(
+				precert
+					.filter(|_| include_precert_data)
+					.map(|p| Precert::new(p.issuer_key_hash.clone(), p.tbs_certificate.clone())),
+			))
+	}
+
+	#[must_use]
+	pub fn chain<L: Into<ChainLink>>(mut self, chain: Option<Vec<L>>) -> Self {
+		self.chain = chain.map(|c| c.into_iter().map(Into::into).collect());
+		self
+	}
+
+	#[must_use]
+	pub fn precert(mut self, precert: Option<Precert>) -> Self {
+		self.precert = precert;
+		self
+	}
+
+	/// Keep the bytes the log sent for the entry, so it can be reproduced exactly
+	#[must_use]
+	pub fn raw(mut self, raw: Option<RawEntry>) -> Self {
+		(self.leaf_input, self.extra_data) = raw.map(|r| (r.leaf_input, r.extra_data)).unzip();
+		self
+	}
+
+	#[must_use]
+	pub fn leaf_hash(mut self, leaf_hash: Option<Vec<u8>>) -> Self {
+		self.leaf_hash = leaf_hash;
+		self
+	}
+
+	#[must_use]
+	pub fn certificate_sha256(mut self, certificate_sha256: Option<Vec<u8>>) -> Self {
+		self.certificate_sha256 = certificate_sha256;
+		self
+	}
+
+	#[must_use]
+	pub fn entry_type(mut self, entry_type: Option<EntryType>) -> Self {
+		self.entry_type = entry_type;
+		self
+	}
+
+	#[must_use]
+	pub fn decoded(mut self, decoded: Option<Decoded>) -> Self {
+		self.decoded = decoded.map(Box::new);
+		self
+	}
+
+	/// Work out the entry's leaf hash and certificate fingerprint
+	///
+	/// The leaf hash is taken over the `leaf_input` exactly as the log sent it, so there's only
+	/// a leaf hash if we've [kept it](Self::raw); re-encoding the leaf from the decoded entry
+	/// would quietly come up with the wrong hash for any entry with extensions.
+	#[must_use]
+	pub(crate) fn hashes(self) -> Self {
+		let leaf_hash = self.leaf_input.as_deref().map(leaf_hash);
+		let certificate_sha256 = self
+			.certificate
+			.as_deref()
+			.map(|c| digest(&SHA256, c).as_ref().to_vec());
+		self.leaf_hash(leaf_hash)
+			.certificate_sha256(certificate_sha256)
+	}
+}
+
+/// A certificate in an entry's chain
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChainLink {
+	/// The certificate itself
+	Cert(Vec<u8>),
+	/// The SHA-256 hash of the certificate, which is in the document's `issuers`, because the
+	/// chains were deduplicated
+	Hash([u8; SHA256_OUTPUT_LEN]),
+}
+
+impl ChainLink {
+	/// The certificate, if it's here rather than in the `issuers`
+	///
+	/// Entries straight from a log always have the certificates themselves.
+	#[must_use]
+	pub fn cert(&self) -> Option<&[u8]> {
+		match self {
+			ChainLink::Cert(cert) => Some(cert),
+			ChainLink::Hash(_) => None,
+		}
+	}
+}
+
+impl From<Vec<u8>> for ChainLink {
+	fn from(cert: Vec<u8>) -> Self {
+		ChainLink::Cert(cert)
+	}
+}
+
+/// The parts of a precert entry that aren't in the precertificate itself
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Precert {
+	#[serde(deserialize_with = "bytes")]
+	pub issuer_key_hash: Vec<u8>,
+	#[serde(deserialize_with = "bytes")]
+	pub tbs_certificate: Vec<u8>,
+}
+
+impl Precert {
+	#[must_use]
+	pub fn new(issuer_key_hash: Vec<u8>, tbs_certificate: Vec<u8>) -> Self {
+		Self {
+			issuer_key_hash,
+			tbs_certificate,
+		}
+	}
+}
+
+/// What gets written after all the entries
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Trailer {
+	pub scrape_end_timestamp: Option<u64>,
+	/// How many entries were written; only present in record stream formats
+	pub entry_count: Option<u64>,
+	/// The chain certificates, by SHA-256 hash, if the entries' chains are lists of hashes
+	/// rather than the certificates themselves
+	pub issuers: Option<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl Trailer {
+	#[must_use]
+	pub fn new(scrape_end_timestamp: Option<u64>) -> Self {
+		Self {
+			scrape_end_timestamp,
+			entry_count: None,
+			issuers: None,
+		}
+	}
+
+	#[must_use]
+	pub fn entry_count(mut self, entry_count: Option<u64>) -> Self {
+		self.entry_count = entry_count;
+		self
+	}
+
+	#[must_use]
+	pub fn issuers(mut self, issuers: Option<BTreeMap<Vec<u8>, Vec<u8>>>) -> Self {
+		self.issuers = issuers;
+		self
+	}
+}
+
+/// A `bytes` value, which is either a binary string (in formats that support them) or a
+/// base64-encoded string (in formats that don't)
+#[derive(Debug, Deserialize)]
+pub(crate) struct Bytes(#[serde(deserialize_with = "bytes")] pub(crate) Vec<u8>);
+
+/// The `issuers` map, which is keyed by the hex of each certificate's SHA-256 hash
+#[derive(Debug)]
+pub(crate) struct Issuers(pub(crate) BTreeMap<Vec<u8>, Vec<u8>>);
+
+impl<'de> Deserialize<'de> for Issuers {
+	fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+		BTreeMap::<String, Bytes>::deserialize(d)?
+			.into_iter()
+			.map(|(k, v)| {
+				Ok((
+					unhex(&k).ok_or_else(|| {
+						de::Error::invalid_value(de::Unexpected::Str(&k), &"a hex SHA-256 hash")
+					})?,
+					v.0,
+				))
+			})
+			.collect::<Result<_, _>>()
+			.map(Self)
+	}
+}
+
+struct BytesVisitor;
+
+impl Visitor<'_> for BytesVisitor {
+	type Value = Vec<u8>;
+
+	fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		formatter.write_str("a byte string, or a base64-encoded string")
+	}
+
+	fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+		B64.decode(s).map_err(E::custom)
+	}
+
+	fn visit_bytes<E: de::Error>(self, b: &[u8]) -> Result<Self::Value, E> {
+		Ok(b.to_vec())
+	}
+
+	fn visit_byte_buf<E: de::Error>(self, b: Vec<u8>) -> Result<Self::Value, E> {
+		Ok(b)
+	}
+}
+
+fn bytes<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+	d.deserialize_any(BytesVisitor)
+}
+
+fn optional_bytes<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+	bytes(d).map(Some)
+}
+
+fn optional_chain<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<ChainLink>>, D::Error> {
+	Vec::<ChainLink>::deserialize(d).map(Some)
+}
+
+/// There's no mistaking a certificate for the hex of a hash: a DER certificate starts with a
+/// SEQUENCE tag (`0x30`), which base64-encodes to an `M`, and that's not a hex digit.
+impl<'de> Deserialize<'de> for ChainLink {
+	fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+		d.deserialize_any(ChainLinkVisitor)
+	}
+}
+
+struct ChainLinkVisitor;
+
+impl Visitor<'_> for ChainLinkVisitor {
+	type Value = ChainLink;
+
+	fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		formatter.write_str("a certificate, or the hex of a SHA-256 hash")
+	}
+
+	fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+		match unhex(s).map(<[u8; SHA256_OUTPUT_LEN]>::try_from) {
+			Some(Ok(hash)) => Ok(ChainLink::Hash(hash)),
+			Some(Err(_)) | None => BytesVisitor.visit_str(s).map(ChainLink::Cert),
+		}
+	}
+
+	fn visit_bytes<E: de::Error>(self, b: &[u8]) -> Result<Self::Value, E> {
+		BytesVisitor.visit_bytes(b).map(ChainLink::Cert)
+	}
+
+	fn visit_byte_buf<E: de::Error>(self, b: Vec<u8>) -> Result<Self::Value, E> {
+		BytesVisitor.visit_byte_buf(b).map(ChainLink::Cert)
+	}
+}
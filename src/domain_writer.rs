@@ -0,0 +1,271 @@
+//! Thread that writes out just the DNS names in each scraped entry.
+//!
+//! Each name goes on a line of its own, along with the number and timestamp of the entry it
+//! came from, separated by tabs, like `1234\t1666198004098\twww.example.com`.  The names come
+//! from the certificate's subjectAltName extension, plus its subject's common name, if that
+//! looks like a DNS name.  Names are lower-cased, and have any trailing dot removed.
+//!
+//! Logs are full of the same names over and over -- every renewal, every precert and its
+//! final certificate -- so a name that's already been written recently isn't written again.
+//! "Recently" is a window of (by default) the last 100,000 distinct names written; a name
+//! that's fallen out of the window will be written again the next time it turns up.
+//!
+
+use gen_server::{GenServer, Status::Continue};
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+
+use crate::{decode::decode, document::Entry, processor, Error};
+
+/// How many distinct names are remembered for deduplication, unless told otherwise
+const DEFAULT_DEDUPE_WINDOW: usize = 100_000;
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Args<W: Write + Sync + Send> {
+	writer: W,
+	dedupe_window: usize,
+
+	_m: PhantomData<W>,
+}
+
+impl<W: Write + Sync + Send> Args<W> {
+	#[must_use]
+	pub fn new(writer: W) -> Self {
+		Args {
+			writer,
+			dedupe_window: DEFAULT_DEDUPE_WINDOW,
+			_m: PhantomData,
+		}
+	}
+
+	/// Set how many of the most recently written names are remembered, so they aren't written
+	/// again (zero writes every name, every time)
+	#[must_use]
+	pub fn dedupe_window(mut self, dedupe_window: usize) -> Self {
+		self.dedupe_window = dedupe_window;
+		self
+	}
+}
+
+#[derive(Debug)]
+pub struct DomainWriter<W: Write + Sync + Send> {
+	writer: BufWriter<W>,
+	recent: RecentNames,
+}
+
+/// The names that have been written lately, forgetting the oldest once there are too many
+#[derive(Debug, Default)]
+struct RecentNames {
+	window: usize,
+	names: HashSet<String>,
+	order: VecDeque<String>,
+}
+
+impl RecentNames {
+	fn new(window: usize) -> Self {
+		Self {
+			window,
+			..Self::default()
+		}
+	}
+
+	/// Remember the name, returning whether it's new to us
+	fn insert(&mut self, name: &str) -> bool {
+		if self.window == 0 {
+			return true;
+		}
+		if self.names.contains(name) {
+			return false;
+		}
+
+		self.names.insert(name.to_string());
+		self.order.push_back(name.to_string());
+		if self.order.len() > self.window {
+			if let Some(oldest) = self.order.pop_front() {
+				self.names.remove(&oldest);
+			}
+		}
+
+		true
+	}
+}
+
+/// Whether a common name is plausibly a DNS name, rather than something like "Bob's Widgets"
+fn looks_like_dns_name(name: &str) -> bool {
+	name.contains('.')
+		&& name
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '*'))
+}
+
+/// All the (normalised) names in a certificate, each one only once
+fn names(certificate: &[u8]) -> Vec<String> {
+	let decoded = decode(certificate);
+	if let Some(e) = &decoded.error {
+		log::debug!("Certificate didn't decode cleanly: {e}");
+	}
+
+	let mut names: Vec<String> = vec![];
+	for name in decoded.dns_names.iter().chain(
+		decoded
+			.common_name
+			.iter()
+			.filter(|cn| looks_like_dns_name(cn)),
+	) {
+		let normalised = name.strip_suffix('.').unwrap_or(name).to_lowercase();
+		if !normalised.is_empty() && !names.contains(&normalised) {
+			names.push(normalised);
+		}
+	}
+	names
+}
+
+impl<W: Write + Sync + Send> DomainWriter<W> {
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn entry(&mut self, entry: &Entry) -> Result<(), Error> {
+		for name in names(entry.certificate.as_deref().unwrap_or_default()) {
+			if self.recent.insert(&name) {
+				writeln!(
+					self.writer,
+					"{}\t{}\t{name}",
+					entry.entry_number, entry.timestamp
+				)
+				.map_err(|e| Error::output("domain list", e))?;
+			}
+		}
+
+		Ok(())
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn finish(&mut self) -> Result<(), Error> {
+		self.writer
+			.flush()
+			.map_err(|e| Error::output("domain list", e))
+	}
+}
+
+impl<W: Write + Sync + Send> GenServer for DomainWriter<W> {
+	type Args = Args<W>;
+	type Error = Error;
+	type Request = processor::Request;
+	type StopReason = ();
+
+	fn init(args: Args<W>) -> Result<Self, Self::Error> {
+		Ok(Self {
+			writer: BufWriter::new(args.writer),
+			recent: RecentNames::new(args.dedupe_window),
+		})
+	}
+
+	fn handle_cast(
+		&mut self,
+		request: Self::Request,
+	) -> Result<gen_server::Status<Self>, Self::Error> {
+		match request {
+			// There's nowhere to put anything other than names
+			processor::Request::Metadata(_) => Ok(Continue),
+			processor::Request::Entry(id, entry, _) => {
+				self.entry(&Entry::from_response(id, &entry, false, false)?)?;
+
+				Ok(Continue)
+			}
+		}
+	}
+
+	fn terminate(&mut self, _reason: Result<(), Error>) {
+		if let Err(e) = self.finish() {
+			log::error!("Failed to finish writing domain list: {e}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::filter::tests::{precert_response, x509_response};
+	use ct_structs::v1::response::ResponseEntry;
+
+	/// The certificate out of one of the filter tests' entries
+	fn certificate(response: &ResponseEntry) -> Vec<u8> {
+		Entry::from_response(0, response, false, false)
+			.unwrap()
+			.certificate
+			.unwrap()
+	}
+
+	fn write(entries: &[Entry], dedupe_window: usize) -> String {
+		let mut buf = vec![];
+		{
+			let mut dw =
+				DomainWriter::init(Args::new(&mut buf).dedupe_window(dedupe_window)).unwrap();
+			for entry in entries {
+				dw.entry(entry).unwrap();
+			}
+			dw.finish().unwrap();
+		}
+		String::from_utf8(buf).unwrap()
+	}
+
+	#[test]
+	fn writes_each_name_with_its_entry() {
+		let cert = certificate(&x509_response());
+
+		assert_eq!(
+			"42\t1234\tcrt.sh\n42\t1234\twww.crt.sh\n",
+			write(&[Entry::new(42, 1234, cert)], 10)
+		);
+	}
+
+	#[test]
+	fn only_writes_recent_names_once() {
+		let cert = certificate(&x509_response());
+		let entries = [
+			Entry::new(1, 10, cert.clone()),
+			Entry::new(2, 20, cert.clone()),
+		];
+
+		assert_eq!("1\t10\tcrt.sh\n1\t10\twww.crt.sh\n", write(&entries, 10));
+		assert_eq!(
+			"1\t10\tcrt.sh\n1\t10\twww.crt.sh\n2\t20\tcrt.sh\n2\t20\twww.crt.sh\n",
+			write(&entries, 0)
+		);
+	}
+
+	#[test]
+	fn forgets_names_that_fall_out_of_the_window() {
+		let mut recent = RecentNames::new(2);
+
+		assert!(recent.insert("a.example"));
+		assert!(recent.insert("b.example"));
+		assert!(!recent.insert("a.example"));
+		assert!(recent.insert("c.example"));
+		assert!(recent.insert("a.example"));
+		assert!(!recent.insert("c.example"));
+	}
+
+	#[test]
+	fn writes_names_in_both_the_cn_and_san_once() {
+		let cert = certificate(&precert_response());
+
+		assert_eq!(
+			"7\t70\ttest7232018-1-1.msitvalidcert.com\n",
+			write(&[Entry::new(7, 70, cert)], 10)
+		);
+	}
+
+	#[test]
+	fn writes_nothing_for_certificates_that_dont_parse() {
+		assert_eq!("", write(&[Entry::new(1, 10, vec![1, 2, 3])], 10));
+	}
+
+	#[test]
+	fn only_uses_common_names_that_look_like_names() {
+		assert!(looks_like_dns_name("www.example.com"));
+		assert!(looks_like_dns_name("*.example.com"));
+		assert!(!looks_like_dns_name("Test SSLAdmin 4"));
+		assert!(!looks_like_dns_name("localhost"));
+	}
+}
@@ -34,6 +34,21 @@ pub enum Error {
 
 	#[error("arithmetic operation overflowed: {0}")]
 	ArithmeticOverflow(String),
+
+	#[error("failed to verify STH signature: {0}")]
+	SthVerificationError(String),
+
+	#[error("failed to load log list from {0}: {1}")]
+	LogListError(String, String),
+
+	#[error("failed to read {0} input: {1}")]
+	InputError(String, String),
+
+	#[error("failed to serve scrape: {0}")]
+	ServerError(String),
+
+	#[error("invalid filter: {0}")]
+	FilterError(String),
 }
 
 impl Error {
@@ -74,4 +89,43 @@ impl Error {
 	{
 		Self::ArithmeticOverflow(op.to_string())
 	}
+
+	pub(crate) fn sth_verification<D>(desc: D) -> Self
+	where
+		D: Display,
+	{
+		Self::SthVerificationError(desc.to_string())
+	}
+
+	pub(crate) fn log_list<S, E>(source: S, e: E) -> Self
+	where
+		S: Display,
+		E: Display,
+	{
+		Self::LogListError(source.to_string(), e.to_string())
+	}
+
+	pub(crate) fn input<D, E>(desc: D, e: E) -> Self
+	where
+		D: Display,
+		E: Display,
+	{
+		Self::InputError(desc.to_string(), e.to_string())
+	}
+
+	#[cfg(feature = "serve")]
+	pub(crate) fn server<D>(desc: D) -> Self
+	where
+		D: Display,
+	{
+		Self::ServerError(desc.to_string())
+	}
+
+	#[cfg(feature = "decode")]
+	pub(crate) fn filter<D>(desc: D) -> Self
+	where
+		D: Display,
+	{
+		Self::FilterError(desc.to_string())
+	}
 }
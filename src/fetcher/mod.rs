@@ -1,17 +1,27 @@
 //! Workers that actually get the entries from the log.
 //!
 
-use ct_structs::v1::response::GetEntries as GetEntriesResponse;
+use base64::{engine::general_purpose::STANDARD as b64, Engine as _};
+use ct_structs::v1::response::{GetEntries as GetEntriesResponse, ResponseEntry};
+use serde::Deserialize as _;
 
+use std::cmp::min;
 use std::ops::RangeInclusive;
-use std::sync::mpsc;
+use std::sync::{
+	atomic::{AtomicU64, Ordering},
+	mpsc, Arc,
+};
 use std::thread;
 use url::Url;
 
-use crate::{error::Error, processor, runner::RunCtl};
+use crate::{
+	error::Error,
+	processor::RawEntry,
+	runner::{EntrySink, RunCtl},
+};
 
 mod retryer;
-use self::retryer::Retryer;
+pub(crate) use self::retryer::Retryer;
 
 #[derive(Clone, Debug)]
 pub(crate) enum FetchStatus {
@@ -20,6 +30,57 @@ pub(crate) enum FetchStatus {
 	Complete(usize),
 }
 
+/// The largest number of entries the log has been seen to return from a single get-entries
+/// request, shared between all the fetchers for a log
+///
+/// Logs cap the number of entries they'll return per request, and many of them cache
+/// responses for "pages" aligned to that cap.  Until we've seen a truncated response, we
+/// don't know what the page size is, and just ask for whatever we were told to fetch.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PageSize(Arc<AtomicU64>);
+
+impl PageSize {
+	/// The detected page size, if we've figured it out yet
+	pub(crate) fn get(&self) -> Option<u64> {
+		match self.0.load(Ordering::Relaxed) {
+			0 => None,
+			n => Some(n),
+		}
+	}
+
+	/// Take note of how many entries came back from a request for `requested` entries
+	///
+	/// A request that starts part way through a page only gets the rest of that page, which
+	/// says nothing about how big a page is, so a short response only counts if the request is
+	/// known to have started at the beginning of a page: at the start of the log, or where the
+	/// log cut off the previous response.
+	fn observe(&self, page_start: bool, requested: u64, received: u64) {
+		if page_start && received > 0 && received < requested {
+			let previous = self.0.fetch_max(received, Ordering::Relaxed);
+			if previous < received {
+				log::info!("Log appears to return at most {received} entries per request");
+			}
+		}
+	}
+
+	/// Trim a range so that it doesn't extend past the end of the page its start is in
+	fn trim(&self, range: &RangeInclusive<u64>) -> RangeInclusive<u64> {
+		self.get()
+			.and_then(|page| {
+				range
+					.start()
+					.checked_div(page)?
+					.checked_add(1)?
+					.checked_mul(page)?
+					.checked_sub(1)
+			})
+			.map_or_else(
+				|| range.clone(),
+				|page_end| *range.start()..=min(*range.end(), page_end),
+			)
+	}
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct FetchCtl {
 	tx: mpsc::Sender<Cmd>,
@@ -54,7 +115,9 @@ impl Fetcher {
 		log_url: Url,
 		user_agent: String,
 		status: RunCtl,
-		processor: processor::Mic,
+		sink: EntrySink,
+		page_size: PageSize,
+		raw_entries: bool,
 	) -> Result<Self, Error> {
 		let (tx, rx) = mpsc::channel();
 
@@ -62,7 +125,18 @@ impl Fetcher {
 			h: Some(
 				thread::Builder::new()
 					.name(format!("Fetcher{n}"))
-					.spawn(move || Self::run(n, &rx, &log_url, &user_agent, &status, &processor))
+					.spawn(move || {
+						Self::run(
+							n,
+							&rx,
+							&log_url,
+							&user_agent,
+							&status,
+							&sink,
+							&page_size,
+							raw_entries,
+						)
+					})
 					.map_err(|e| Error::system("failed to spawn Fetcher thread", e))?,
 			),
 			c: FetchCtl { tx },
@@ -106,13 +180,16 @@ impl Fetcher {
 	}
 
 	#[allow(clippy::result_large_err)] // Oh shoosh
+	#[allow(clippy::too_many_arguments)] // TODO: refactor
 	fn run(
 		n: usize,
 		rx: &mpsc::Receiver<Cmd>,
 		log_url: &Url,
 		user_agent: &str,
 		status: &RunCtl,
-		processor: &processor::Mic,
+		sink: &EntrySink,
+		page_size: &PageSize,
+		raw_entries: bool,
 	) -> Result<(), Error> {
 		log::debug!("Fetcher::run({log_url:?})");
 		let http_client = ureq::Agent::config_builder()
@@ -135,10 +212,17 @@ impl Fetcher {
 			match cmd {
 				Ok(Cmd::Stop) => return Ok(()),
 				Ok(Cmd::FetchRange(range)) => {
-					if let Err(e) =
-						Self::fetch_range(&http_client, &entries_url, range, status, processor)
-					{
+					if let Err(e) = Self::fetch_range(
+						&http_client,
+						&entries_url,
+						range.clone(),
+						status,
+						sink,
+						page_size,
+						raw_entries,
+					) {
 						log::error!("{e}");
+						sink.skip(range)?;
 					} else {
 						status.complete(n)?;
 					}
@@ -155,18 +239,22 @@ impl Fetcher {
 		entries_url: &Url,
 		mut range: RangeInclusive<u64>,
 		status: &RunCtl,
-		processor: &processor::Mic,
+		sink: &EntrySink,
+		page_size: &PageSize,
+		raw_entries: bool,
 	) -> Result<(), Error> {
 		log::debug!("Fetching entries {range:?} from {entries_url}");
 		let mut retryer = Retryer::new();
+		let mut page_start = *range.start() == 0;
 
 		while range.start() <= range.end() {
-			log::debug!("Requesting {entries_url}, {range:?}");
+			let request = page_size.trim(&range);
+			log::debug!("Requesting {entries_url}, {request:?}");
 
 			let response = match client
 				.get(entries_url.as_ref())
-				.query("start", format!("{}", range.start()))
-				.query("end", format!("{}", range.end()))
+				.query("start", format!("{}", request.start()))
+				.query("end", format!("{}", request.end()))
 				.call()
 			{
 				Ok(response) if response.status().as_u16() == 429 => {
@@ -189,10 +277,10 @@ impl Fetcher {
 					continue;
 				}
 				Ok(response) => {
-					let result: GetEntriesResponse =
-						serde_json::from_reader(response.into_body().into_reader()).map_err(
-							|e| Error::json_parse(format!("get-entries({range:?}) response"), e),
-						)?;
+					let result = parse_entries(response.into_body().into_reader(), raw_entries)
+						.map_err(|e| {
+							Error::json_parse(format!("get-entries({range:?}) response"), e)
+						})?;
 					status.success()?;
 					retryer.reset();
 					result
@@ -206,15 +294,25 @@ impl Fetcher {
 				Err(e) => return Err(Error::RequestError(e)),
 			};
 
-			log::debug!("Received {} entries from {range:?}", response.entries.len());
+			log::debug!("Received {} entries from {range:?}", response.len());
 
-			let len = response.entries.len() as u64;
+			let len = response.len() as u64;
+			let requested = request
+				.end()
+				.checked_sub(*request.start())
+				.and_then(|n| n.checked_add(1))
+				.ok_or_else(|| Error::arithmetic("calculating requested entry count"))?;
+			page_size.observe(page_start, requested, len);
+			// A response that was cut short stopped at the end of a page, so the next request
+			// starts at the beginning of one
+			page_start = len > 0 && len < requested;
 			#[allow(clippy::reversed_empty_ranges)] // An empty range is what I want here
 			if len == 0 {
 				log::warn!("received no entries fetching {range:?}; possible log misbehaviour");
+				sink.skip(range)?;
 				range = 1..=0;
 			} else {
-				for (e, i) in response.entries.into_iter().zip(0u64..) {
+				for ((e, raw), i) in response.into_iter().zip(0u64..) {
 					log::debug!(
 						"Sending entry {} ({i} of this request) to processor",
 						range
@@ -225,12 +323,13 @@ impl Fetcher {
 							))?
 					);
 					log::trace!("{e:?}");
-					processor.cast(processor::Request::Entry(
+					sink.entry(
 						range.start().checked_add(i).ok_or_else(|| {
 							Error::arithmetic("calculating absolute entry ID (SHOULDN'T HAPPEN)")
 						})?,
 						e,
-					));
+						raw,
+					)?;
 				}
 				range =
 					(range.start().checked_add(len).ok_or_else(|| {
@@ -242,3 +341,95 @@ impl Fetcher {
 		Ok(())
 	}
 }
+
+/// Parse a get-entries response, hanging on to the bytes of each entry as well, if asked to
+fn parse_entries(
+	body: impl std::io::Read,
+	raw_entries: bool,
+) -> Result<Vec<(ResponseEntry, Option<RawEntry>)>, serde_json::Error> {
+	#[derive(serde::Deserialize)]
+	struct RawResponse {
+		entries: Vec<serde_json::Value>,
+	}
+
+	if !raw_entries {
+		let response: GetEntriesResponse = serde_json::from_reader(body)?;
+		return Ok(response.entries.into_iter().map(|e| (e, None)).collect());
+	}
+
+	let bytes = |v: &serde_json::Value, name: &'static str| {
+		v.get(name)
+			.and_then(serde_json::Value::as_str)
+			.ok_or_else(|| serde::de::Error::missing_field(name))
+			.and_then(|s| b64.decode(s).map_err(serde::de::Error::custom))
+	};
+
+	let response: RawResponse = serde_json::from_reader(body)?;
+	response
+		.entries
+		.into_iter()
+		.map(|v| {
+			let raw = RawEntry::new(bytes(&v, "leaf_input")?, bytes(&v, "extra_data")?);
+			Ok((ResponseEntry::deserialize(&v)?, Some(raw)))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn page_size_is_unknown_until_a_short_response() {
+		let ps = PageSize::default();
+
+		ps.observe(true, 100, 100);
+		assert_eq!(None, ps.get());
+		assert_eq!(0..=99, ps.trim(&(0..=99)));
+
+		ps.observe(true, 100, 32);
+		assert_eq!(Some(32), ps.get());
+	}
+
+	#[test]
+	fn page_size_is_the_largest_short_response() {
+		let ps = PageSize::default();
+
+		ps.observe(true, 100, 10);
+		ps.observe(true, 100, 32);
+		ps.observe(true, 100, 20);
+
+		assert_eq!(Some(32), ps.get());
+	}
+
+	#[test]
+	fn empty_responses_say_nothing_about_page_size() {
+		let ps = PageSize::default();
+
+		ps.observe(true, 100, 0);
+
+		assert_eq!(None, ps.get());
+	}
+
+	#[test]
+	fn responses_from_part_way_through_a_page_say_nothing_about_page_size() {
+		let ps = PageSize::default();
+
+		// Starting at entry 250 of a log with 256 entry pages only gets the last 6 of the page
+		ps.observe(false, 100, 6);
+		assert_eq!(None, ps.get());
+
+		ps.observe(true, 1000, 256);
+		assert_eq!(Some(256), ps.get());
+	}
+
+	#[test]
+	fn trims_ranges_to_page_boundaries() {
+		let ps = PageSize::default();
+		ps.observe(true, 1000, 256);
+
+		assert_eq!(0..=255, ps.trim(&(0..=999)));
+		assert_eq!(300..=511, ps.trim(&(300..=999)));
+		assert_eq!(300..=400, ps.trim(&(300..=400)));
+	}
+}
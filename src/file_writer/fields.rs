@@ -0,0 +1,223 @@
+//! Choosing which keys get written
+//!
+//! By default, a document has everything needed to make sense of it, plus whichever of the
+//! optional extras were asked for, but lightweight jobs that only care about (say) timestamps
+//! and fingerprints can pick exactly the keys they want.  The structural parts of a document
+//! (`entries`, and the `issuers` and `entry_count` that go with them) are always written.
+//!
+
+use std::collections::BTreeSet;
+
+/// A key that can be written into a document, either at the top level or in each entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Field {
+	LogUrl,
+	LogId,
+	ScrapeBeginTimestamp,
+	Sth,
+	ScrapeEndTimestamp,
+	EntryNumber,
+	Timestamp,
+	EntryType,
+	Certificate,
+	CertificateSha256,
+	LeafHash,
+	Chain,
+	Precert,
+	LeafInput,
+	ExtraData,
+	Decoded,
+}
+
+impl Field {
+	/// Every field there is, in the order they're written
+	pub const ALL: [Field; 16] = [
+		Field::LogUrl,
+		Field::LogId,
+		Field::ScrapeBeginTimestamp,
+		Field::Sth,
+		Field::ScrapeEndTimestamp,
+		Field::EntryNumber,
+		Field::Timestamp,
+		Field::EntryType,
+		Field::Certificate,
+		Field::CertificateSha256,
+		Field::LeafHash,
+		Field::Chain,
+		Field::Precert,
+		Field::LeafInput,
+		Field::ExtraData,
+		Field::Decoded,
+	];
+
+	/// The key the field is written under
+	#[must_use]
+	pub fn name(self) -> &'static str {
+		match self {
+			Field::LogUrl => "log_url",
+			Field::LogId => "log_id",
+			Field::ScrapeBeginTimestamp => "scrape_begin_timestamp",
+			Field::Sth => "sth",
+			Field::ScrapeEndTimestamp => "scrape_end_timestamp",
+			Field::EntryNumber => "entry_number",
+			Field::Timestamp => "timestamp",
+			Field::EntryType => "entry_type",
+			Field::Certificate => "certificate",
+			Field::CertificateSha256 => "certificate_sha256",
+			Field::LeafHash => "leaf_hash",
+			Field::Chain => "chain",
+			Field::Precert => "precert",
+			Field::LeafInput => "leaf_input",
+			Field::ExtraData => "extra_data",
+			Field::Decoded => "decoded",
+		}
+	}
+}
+
+impl std::fmt::Display for Field {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl TryFrom<&str> for Field {
+	type Error = String;
+
+	fn try_from(s: &str) -> Result<Self, Self::Error> {
+		Field::ALL
+			.into_iter()
+			.find(|f| f.name() == s)
+			.ok_or_else(|| {
+				format!(
+					"unknown field {s:?} (known fields are {})",
+					Field::ALL.map(Field::name).join(", ")
+				)
+			})
+	}
+}
+
+/// The set of fields to write
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Fields(BTreeSet<Field>);
+
+impl Fields {
+	/// Every field, for when whatever's there should be written
+	#[must_use]
+	pub fn all() -> Self {
+		Field::ALL.into_iter().collect()
+	}
+
+	#[must_use]
+	pub fn contains(&self, field: Field) -> bool {
+		self.0.contains(&field)
+	}
+
+	/// Add the field to the set (or take it out)
+	#[must_use]
+	pub fn with(mut self, field: Field, included: bool) -> Self {
+		if included {
+			self.0.insert(field);
+		} else {
+			self.0.remove(&field);
+		}
+		self
+	}
+
+	/// Whether writing these fields needs the runner to keep each entry's `leaf_input` and
+	/// `extra_data` exactly as the log sent them ([`Config::raw_entries`])
+	///
+	/// [`Config::raw_entries`]: crate::runner::Config::raw_entries
+	#[must_use]
+	pub fn need_raw_entries(&self) -> bool {
+		// Leaf hashes are taken over the leaf_input exactly as the log sent it
+		self.contains(Field::LeafInput)
+			|| self.contains(Field::ExtraData)
+			|| self.contains(Field::LeafHash)
+	}
+}
+
+impl Default for Fields {
+	/// Everything except the optional extras
+	fn default() -> Self {
+		[
+			Field::LogUrl,
+			Field::LogId,
+			Field::ScrapeBeginTimestamp,
+			Field::Sth,
+			Field::ScrapeEndTimestamp,
+			Field::EntryNumber,
+			Field::Timestamp,
+			Field::Certificate,
+		]
+		.into_iter()
+		.collect()
+	}
+}
+
+impl FromIterator<Field> for Fields {
+	fn from_iter<I: IntoIterator<Item = Field>>(iter: I) -> Self {
+		Self(iter.into_iter().collect())
+	}
+}
+
+impl TryFrom<&str> for Fields {
+	type Error = String;
+
+	/// Parse a comma-separated list of field names
+	fn try_from(s: &str) -> Result<Self, Self::Error> {
+		let fields = s
+			.split(',')
+			.map(str::trim)
+			.filter(|f| !f.is_empty())
+			.map(Field::try_from)
+			.collect::<Result<Self, _>>()?;
+
+		if fields.0.is_empty() {
+			Err("no fields given".to_string())
+		} else {
+			Ok(fields)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_a_list_of_fields() {
+		let fields = Fields::try_from("entry_number, timestamp,leaf_hash").unwrap();
+
+		assert!(fields.contains(Field::EntryNumber));
+		assert!(fields.contains(Field::LeafHash));
+		assert!(!fields.contains(Field::Certificate));
+	}
+
+	#[test]
+	fn refuses_unknown_fields() {
+		let err = Fields::try_from("timestamp,fingerprint").unwrap_err();
+		assert!(err.contains("\"fingerprint\""), "{err}");
+	}
+
+	#[test]
+	fn leaf_hashes_need_raw_entries() {
+		assert!(Fields::try_from("leaf_hash").unwrap().need_raw_entries());
+		assert!(!Fields::try_from("certificate_sha256")
+			.unwrap()
+			.need_raw_entries());
+	}
+
+	#[test]
+	fn refuses_an_empty_list() {
+		assert!(Fields::try_from(" , ").is_err());
+	}
+
+	#[test]
+	fn names_round_trip() {
+		for field in Field::ALL {
+			assert_eq!(field, Field::try_from(field.name()).unwrap());
+		}
+	}
+}
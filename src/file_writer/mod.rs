@@ -1,38 +1,33 @@
 //! Thread that deals with outputting the data that is scraped.
 //!
-use ct_structs::v1::{ExtraData, SignedEntry, TreeLeafEntry};
-
 use gen_server::{GenServer, Status::Continue};
+use ring::digest::{digest, SHA256};
 use url::Url;
 
-use std::io::BufWriter;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::BTreeMap;
+use std::io::{BufRead as _, BufReader, BufWriter, Read, Write as _};
 
-use crate::{processor, Error};
+use crate::{
+	document::{ChainLink, Decoded, Entry, EntryType, Metadata, Precert, Sth, Trailer},
+	processor,
+	utils::{current_time, hex},
+	Error,
+};
 
 pub use self::streaming_serializer::StreamFormat as OutputFormat;
 
-#[allow(clippy::result_large_err)] // Oh shoosh
-fn current_time() -> Result<u64, Error> {
-	#[allow(clippy::expect_used)] // I'll take the risk
-	Ok(SystemTime::now()
-		.duration_since(UNIX_EPOCH)
-		.map_err(|e| Error::system("we went back in time somehow", e))?
-		.as_millis()
-		.try_into()
-		.expect("wow this code has excellent shelf life"))
-}
-
 use std::marker::PhantomData;
 
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct Args<W: std::io::Write + Sync + Send> {
-	writer: W,
+	output: Output<W>,
 	format: OutputFormat,
-	include_chains: bool,
-	include_precert_data: bool,
+	fields: Fields,
+	dedupe_chains: bool,
+	raw_entries: bool,
 	log_url: Url,
+	log_id: Option<Vec<u8>>,
 
 	_m: PhantomData<W>,
 }
@@ -40,12 +35,24 @@ pub struct Args<W: std::io::Write + Sync + Send> {
 impl<W: std::io::Write + Sync + Send> Args<W> {
 	#[must_use]
 	pub fn new(writer: W, log_url: Url) -> Self {
+		Self::with_output(Output::Writer(writer), log_url)
+	}
+
+	/// Write the scrape as a series of files, rather than to a single writer
+	#[must_use]
+	pub fn split(split: Split<W>, log_url: Url) -> Self {
+		Self::with_output(Output::Split(split), log_url)
+	}
+
+	fn with_output(output: Output<W>, log_url: Url) -> Self {
 		Args {
-			writer,
+			output,
 			log_url,
+			log_id: None,
 			format: OutputFormat::JSON,
-			include_chains: false,
-			include_precert_data: false,
+			fields: Fields::default(),
+			dedupe_chains: false,
+			raw_entries: false,
 			_m: PhantomData,
 		}
 	}
@@ -58,38 +65,765 @@ impl<W: std::io::Write + Sync + Send> Args<W> {
 
 	#[must_use]
 	pub fn include_chains(mut self, include_chains: bool) -> Self {
-		self.include_chains = include_chains;
+		self.fields = self.fields.with(Field::Chain, include_chains);
 		self
 	}
 
 	#[must_use]
 	pub fn include_precert_data(mut self, include_precert_data: bool) -> Self {
-		self.include_precert_data = include_precert_data;
+		self.fields = self.fields.with(Field::Precert, include_precert_data);
+		self
+	}
+
+	/// Write each entry's `leaf_input` and `extra_data`, if the runner was told to keep them
+	#[must_use]
+	pub fn include_raw_entries(mut self, include_raw_entries: bool) -> Self {
+		self.fields = self
+			.fields
+			.with(Field::LeafInput, include_raw_entries)
+			.with(Field::ExtraData, include_raw_entries);
+		self
+	}
+
+	/// Give each entry its RFC 6962 leaf hash, and the SHA-256 hash of its certificate
+	#[must_use]
+	pub fn include_hashes(mut self, include_hashes: bool) -> Self {
+		self.fields = self
+			.fields
+			.with(Field::LeafHash, include_hashes)
+			.with(Field::CertificateSha256, include_hashes);
+		self
+	}
+
+	/// Say whether each entry is an X.509 certificate or a precertificate
+	#[must_use]
+	pub fn include_entry_type(mut self, include_entry_type: bool) -> Self {
+		self.fields = self.fields.with(Field::EntryType, include_entry_type);
+		self
+	}
+
+	/// Write the interesting parts of each entry's certificate, already picked apart
+	///
+	/// This needs the `decode` feature; without it, the writer will refuse to start.
+	#[must_use]
+	pub fn include_decoded(mut self, include_decoded: bool) -> Self {
+		self.fields = self.fields.with(Field::Decoded, include_decoded);
+		self
+	}
+
+	/// Write exactly these fields, regardless of what's been included (or not) so far
+	#[must_use]
+	pub fn fields(mut self, fields: Fields) -> Self {
+		self.fields = fields;
+		self
+	}
+
+	/// Write each distinct chain certificate once, in the document's `issuers` map, and have
+	/// each entry's chain list the hashes of its certificates
+	#[must_use]
+	pub fn dedupe_chains(mut self, dedupe_chains: bool) -> Self {
+		self.dedupe_chains = dedupe_chains;
+		self
+	}
+
+	#[must_use]
+	pub fn log_id(mut self, log_id: Vec<u8>) -> Self {
+		self.log_id = Some(log_id);
 		self
 	}
+
+	/// Say whether the runner has been told to keep [raw entries](crate::runner::Config::raw_entries),
+	/// so that the writer can refuse to start if it's been asked for fields that need them and
+	/// there won't be any
+	#[must_use]
+	pub fn raw_entries(mut self, raw_entries: bool) -> Self {
+		self.raw_entries = raw_entries;
+		self
+	}
+}
+
+/// Where the scrape ends up
+#[derive(Clone, Debug)]
+enum Output<W> {
+	Writer(W),
+	Split(Split<W>),
 }
 
 pub type StopReason = ();
 
 pub struct FileWriter<'a, W: std::io::Write + Sync + Send> {
-	map: StreamingMap<'a>,
-	entries: Option<StreamingSeq<'a>>,
-	include_chains: bool,
-	include_precert_data: bool,
+	doc: Document<'a, W>,
+	fields: Fields,
 
 	_m: PhantomData<W>,
 }
 
+/// The document being written, or the series of them if the scrape is being split
+enum Document<'a, W> {
+	Single(Box<DocumentWriter<'a>>),
+	Split(Box<Splitter<'a, W>>),
+}
+
+impl<'a, W: std::io::Write + Sync + Send + 'a> Document<'a, W> {
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn sth(&mut self, sth: &Sth) -> Result<(), Error> {
+		match self {
+			Document::Single(doc) => doc.sth(sth),
+			Document::Split(splitter) => {
+				splitter.sth(sth);
+				Ok(())
+			}
+		}
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn entry(&mut self, entry: &Entry) -> Result<(), Error> {
+		match self {
+			Document::Single(doc) => doc.entry(entry),
+			Document::Split(splitter) => splitter.entry(entry),
+		}
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn finish(&mut self, trailer: &Trailer) -> Result<(), Error> {
+		match self {
+			Document::Single(doc) => doc.finish(trailer),
+			Document::Split(splitter) => splitter.finish(trailer),
+		}
+	}
+}
+
 impl<'a, W: std::io::Write + Sync + Send + 'a> std::fmt::Debug for FileWriter<'a, W> {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
 		f.debug_struct("FileWriter").finish()
 	}
 }
 
+/// Writes out the pieces of a scrape document, one at a time
+///
+/// This is what does the actual writing for a [`FileWriter`], but it can also be fed with the
+/// [`Item`](crate::reader::Item)s read from a previously-written document, to write it out
+/// again in a different format.
+///
+/// For most formats, the document is a single map, with the entries in a sequence inside it.
+/// Formats that are [record streams](OutputFormat::is_record_stream) get the top-level keys
+/// before the entries as a header record, then each entry as a record of its own, and then the
+/// remaining top-level keys as a trailer record.
+///
+/// If chains are [deduplicated](Self::dedupe_chains), each entry's chain is written as a list
+/// of the SHA-256 hashes of its certificates, and every distinct certificate is written once,
+/// in an `issuers` map (keyed by the hex of its hash) at the end of the document.
+pub struct DocumentWriter<'a> {
+	ser: StreamingSerializer<'a>,
+	/// The top-level map, or the header record; `None` once the header record has been ended
+	map: Option<StreamingMap<'a>>,
+	entries: Option<StreamingSeq<'a>>,
+	records: bool,
+	entry_count: u64,
+	/// The top-level keys that come before the STH, until they've been written
+	header: Option<Header>,
+	fields: Fields,
+	dedupe_chains: bool,
+	/// Chain certificates seen so far, by hash, when chains are being deduplicated
+	issuers: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// The first few top-level keys, which are held back until we know which of them to write
+struct Header {
+	log_url: Option<String>,
+	log_id: Option<Vec<u8>>,
+	scrape_begin_timestamp: Option<u64>,
+}
+
+impl std::fmt::Debug for DocumentWriter<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		f.debug_struct("DocumentWriter").finish_non_exhaustive()
+	}
+}
+
+impl<'a> DocumentWriter<'a> {
+	/// Start a new document
+	///
+	/// The log URL, ID, and scrape start time are written as soon as anything else is, but the
+	/// STH has to wait until it's given to [`sth`](Self::sth).
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn new(
+		writer: impl std::io::Write + Sync + Send + 'a,
+		format: OutputFormat,
+		log_url: &Url,
+		log_id: Option<&[u8]>,
+		scrape_begin_timestamp: Option<u64>,
+	) -> Result<Self, Error> {
+		Self::unbuffered(
+			BufWriter::new(writer),
+			format,
+			Some(log_url),
+			log_id,
+			scrape_begin_timestamp,
+		)
+	}
+
+	/// Start a new document, without putting a buffer in front of the writer
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub(crate) fn unbuffered(
+		writer: impl std::io::Write + Sync + Send + 'a,
+		format: OutputFormat,
+		log_url: Option<&Url>,
+		log_id: Option<&[u8]>,
+		scrape_begin_timestamp: Option<u64>,
+	) -> Result<Self, Error> {
+		let ser = StreamingSerializer::new(writer, format);
+		ser.start_stream()
+			.map_err(|e| Error::output("stream start", e))?;
+
+		let map = ser.map().map_err(|e| Error::output("map open", e))?;
+
+		Ok(Self {
+			ser,
+			map: Some(map),
+			entries: None,
+			records: format.is_record_stream(),
+			entry_count: 0,
+			header: Some(Header {
+				log_url: log_url.map(Url::to_string),
+				log_id: log_id.map(<[u8]>::to_vec),
+				scrape_begin_timestamp,
+			}),
+			fields: Fields::all(),
+			dedupe_chains: false,
+			issuers: BTreeMap::new(),
+		})
+	}
+
+	/// Only write these fields (out of the ones that are present)
+	#[must_use]
+	pub fn fields(mut self, fields: Fields) -> Self {
+		self.fields = fields;
+		self
+	}
+
+	/// Write the log URL, ID, and scrape start time, if they haven't been already
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn write_header(&mut self) -> Result<(), Error> {
+		let Some(header) = self.header.take() else {
+			return Ok(());
+		};
+		let Some(map) = &mut self.map else {
+			return Err(Error::internal("top-level map went missing"));
+		};
+
+		if let Some(log_url) = header
+			.log_url
+			.filter(|_| self.fields.contains(Field::LogUrl))
+		{
+			map.key("log_url")
+				.map_err(|e| Error::output("log_url key", e))?;
+			map.string(&log_url)
+				.map_err(|e| Error::output("log_url", e))?;
+		}
+		if let Some(log_id) = header.log_id.filter(|_| self.fields.contains(Field::LogId)) {
+			map.key("log_id")
+				.map_err(|e| Error::output("log_id key", e))?;
+			map.bytes(&log_id).map_err(|e| Error::output("log_id", e))?;
+		}
+		if let Some(timestamp) = header
+			.scrape_begin_timestamp
+			.filter(|_| self.fields.contains(Field::ScrapeBeginTimestamp))
+		{
+			map.key("scrape_begin_timestamp")
+				.map_err(|e| Error::output("scrape_begin_timestamp key", e))?;
+			map.uint(timestamp)
+				.map_err(|e| Error::output("scrape_begin_timestamp", e))?;
+		}
+
+		Ok(())
+	}
+
+	/// Write each chain certificate only once, and have the entries' chains refer to them by
+	/// hash
+	#[must_use]
+	pub fn dedupe_chains(mut self, dedupe_chains: bool) -> Self {
+		self.dedupe_chains = dedupe_chains;
+		self
+	}
+
+	/// Start a new document from the metadata read from another one
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn from_metadata(
+		writer: impl std::io::Write + Sync + Send + 'a,
+		format: OutputFormat,
+		metadata: &Metadata,
+	) -> Result<Self, Error> {
+		let mut doc = Self::unbuffered(
+			BufWriter::new(writer),
+			format,
+			metadata.log_url.as_ref(),
+			metadata.log_id.as_deref(),
+			metadata.scrape_begin_timestamp,
+		)?;
+		if let Some(sth) = &metadata.sth {
+			doc.sth(sth)?;
+		}
+		Ok(doc)
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn sth(&mut self, sth: &Sth) -> Result<(), Error> {
+		self.write_header()?;
+		if !self.fields.contains(Field::Sth) {
+			return Ok(());
+		}
+		let Some(map) = &mut self.map else {
+			return Err(Error::internal("STH written after the header was finished"));
+		};
+
+		map.key("sth").map_err(|e| Error::output("sth key", e))?;
+		let mut sth_map = map.map().map_err(|e| Error::output("sth map open", e))?;
+		sth_map
+			.key("tree_size")
+			.map_err(|e| Error::output("tree_size key", e))?;
+		sth_map
+			.uint(sth.tree_size)
+			.map_err(|e| Error::output("tree_size", e))?;
+		sth_map
+			.key("timestamp")
+			.map_err(|e| Error::output("timestamp key", e))?;
+		sth_map
+			.uint(sth.timestamp)
+			.map_err(|e| Error::output("timestamp", e))?;
+		sth_map
+			.key("sha256_root_hash")
+			.map_err(|e| Error::output("sha256_root_hash key", e))?;
+		sth_map
+			.bytes(&sth.sha256_root_hash)
+			.map_err(|e| Error::output("sha256_root_hash", e))?;
+		sth_map
+			.key("tree_head_signature")
+			.map_err(|e| Error::output("tree_head_signature key", e))?;
+		sth_map
+			.bytes(&sth.tree_head_signature)
+			.map_err(|e| Error::output("tree_head_signature", e))?;
+		sth_map.end().map_err(|e| Error::output("sth map close", e))
+	}
+
+	/// Write out an entry
+	///
+	/// The optional parts of the entry are written if (and only if) they're present in the
+	/// entry, and their fields have been asked for.
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn entry(&mut self, entry: &Entry) -> Result<(), Error> {
+		self.write_header()?;
+		let mut map = if self.records {
+			self.end_header()?;
+			self.ser
+				.map()
+				.map_err(|e| Error::output("entry map open", e))?
+		} else {
+			self.open_entries()?;
+			let Some(entries) = &mut self.entries else {
+				return Err(Error::internal("entries seq went missing"));
+			};
+
+			entries
+				.map()
+				.map_err(|e| Error::output("entry map open", e))?
+		};
+
+		self.entry_fields(&mut map, entry)?;
+
+		map.end().map_err(|e| Error::output("entry map close", e))?;
+		self.entry_count = self
+			.entry_count
+			.checked_add(1)
+			.ok_or_else(|| Error::arithmetic("counting entries"))?;
+
+		self.end_record()
+	}
+
+	/// Start the sequence of entries in the top-level map, if it hasn't been already
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn open_entries(&mut self) -> Result<(), Error> {
+		if self.entries.is_none() {
+			let Some(map) = &mut self.map else {
+				return Err(Error::internal("top-level map went missing"));
+			};
+			map.key("entries")
+				.map_err(|e| Error::output("entries key", e))?;
+			self.entries = Some(map.seq().map_err(|e| Error::output("entries open", e))?);
+		}
+
+		Ok(())
+	}
+
+	/// Write whichever of the entry's fields have been asked for
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn entry_fields(&mut self, map: &mut StreamingMap<'_>, entry: &Entry) -> Result<(), Error> {
+		if self.fields.contains(Field::EntryNumber) {
+			map.key("entry_number")
+				.map_err(|e| Error::output("entry_number key", e))?;
+			map.uint(entry.entry_number)
+				.map_err(|e| Error::output("entry_number", e))?;
+		}
+		if self.fields.contains(Field::Timestamp) {
+			map.key("timestamp")
+				.map_err(|e| Error::output("timestamp key", e))?;
+			map.uint(entry.timestamp)
+				.map_err(|e| Error::output("timestamp", e))?;
+		}
+		if let Some(entry_type) = entry
+			.entry_type
+			.filter(|_| self.fields.contains(Field::EntryType))
+		{
+			map.key("entry_type")
+				.map_err(|e| Error::output("entry_type key", e))?;
+			map.string(entry_type.as_str())
+				.map_err(|e| Error::output("entry_type", e))?;
+		}
+		for (field, value) in [
+			(Field::Certificate, entry.certificate.as_deref()),
+			(
+				Field::CertificateSha256,
+				entry.certificate_sha256.as_deref(),
+			),
+			(Field::LeafHash, entry.leaf_hash.as_deref()),
+		] {
+			optional_bytes(
+				map,
+				field.name(),
+				value.filter(|_| self.fields.contains(field)),
+			)?;
+		}
+
+		if let Some(chain) = entry
+			.chain
+			.as_ref()
+			.filter(|_| self.fields.contains(Field::Chain))
+		{
+			self.chain(map, chain)?;
+		}
+		if let Some(precert) = entry
+			.precert
+			.as_ref()
+			.filter(|_| self.fields.contains(Field::Precert))
+		{
+			write_precert(map, precert)?;
+		}
+
+		for (field, value) in [
+			(Field::LeafInput, entry.leaf_input.as_deref()),
+			(Field::ExtraData, entry.extra_data.as_deref()),
+		] {
+			optional_bytes(
+				map,
+				field.name(),
+				value.filter(|_| self.fields.contains(field)),
+			)?;
+		}
+		if let Some(decoded) = entry
+			.decoded
+			.as_ref()
+			.filter(|_| self.fields.contains(Field::Decoded))
+		{
+			write_decoded(map, decoded)?;
+		}
+
+		Ok(())
+	}
+
+	/// Write the entry's chain, either as the certificates themselves, or as their hashes
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn chain(&mut self, map: &mut StreamingMap<'_>, links: &[ChainLink]) -> Result<(), Error> {
+		map.key("chain")
+			.map_err(|e| Error::output("chain key", e))?;
+		let mut chain = map.seq().map_err(|e| Error::output("chain open", e))?;
+
+		for link in links {
+			match link {
+				ChainLink::Cert(c) if self.dedupe_chains => {
+					let hash = digest(&SHA256, c).as_ref().to_vec();
+					chain
+						.string(&hex(&hash))
+						.map_err(|e| Error::output("chain entry", e))?;
+					self.issuers.entry(hash).or_insert_with(|| c.clone());
+				}
+				ChainLink::Cert(c) => chain
+					.bytes(c)
+					.map_err(|e| Error::output("chain entry", e))?,
+				// Only a document with deduplicated chains, that's being converted, has these
+				ChainLink::Hash(hash) => chain
+					.string(&hex(hash))
+					.map_err(|e| Error::output("chain entry", e))?,
+			}
+		}
+
+		chain.end().map_err(|e| Error::output("chain close", e))
+	}
+
+	/// Wrap up the document
+	///
+	/// Record stream formats also get the number of entries that were written put in the
+	/// trailer, so that you can tell whether you've got all of them.
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn finish(&mut self, trailer: &Trailer) -> Result<(), Error> {
+		self.write_header()?;
+		// A document with no entries still gets an (empty) sequence of them
+		if !self.records {
+			self.open_entries()?;
+		}
+		if let Some(entries) = self.entries.take() {
+			entries
+				.end()
+				.map_err(|e| Error::output("entries close", e))?;
+		}
+		if self.records {
+			self.end_header()?;
+			self.map = Some(
+				self.ser
+					.map()
+					.map_err(|e| Error::output("trailer open", e))?,
+			);
+		}
+		let Some(mut map) = self.map.take() else {
+			return Err(Error::internal("document was already finished"));
+		};
+
+		// Issuers can come from a document we're converting, as well as our own deduplication
+		if let Some(issuers) = &trailer.issuers {
+			for (hash, cert) in issuers {
+				self.issuers
+					.entry(hash.clone())
+					.or_insert_with(|| cert.clone());
+			}
+		}
+		if !self.issuers.is_empty() {
+			map.key("issuers")
+				.map_err(|e| Error::output("issuers key", e))?;
+			let mut issuers = map.map().map_err(|e| Error::output("issuers open", e))?;
+			for (hash, cert) in &self.issuers {
+				issuers
+					.key(&hex(hash))
+					.map_err(|e| Error::output("issuer key", e))?;
+				issuers
+					.bytes(cert)
+					.map_err(|e| Error::output("issuer", e))?;
+			}
+			issuers
+				.end()
+				.map_err(|e| Error::output("issuers close", e))?;
+		}
+
+		if let Some(time) = trailer
+			.scrape_end_timestamp
+			.filter(|_| self.fields.contains(Field::ScrapeEndTimestamp))
+		{
+			map.key("scrape_end_timestamp")
+				.map_err(|e| Error::output("scrape_end_timestamp key", e))?;
+			map.uint(time)
+				.map_err(|e| Error::output("scrape_end_timestamp", e))?;
+		}
+		if self.records {
+			map.key("entry_count")
+				.map_err(|e| Error::output("entry_count key", e))?;
+			map.uint(self.entry_count)
+				.map_err(|e| Error::output("entry_count", e))?;
+		}
+		map.end().map_err(|e| Error::output("map close", e))?;
+
+		self.end_record()
+	}
+
+	/// Finish off the header record, if it hasn't been already
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn end_header(&mut self) -> Result<(), Error> {
+		if let Some(map) = self.map.take() {
+			map.end().map_err(|e| Error::output("header close", e))?;
+			self.end_record()?;
+		}
+		Ok(())
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn end_record(&self) -> Result<(), Error> {
+		if self.records {
+			self.ser
+				.end_record()
+				.map_err(|e| Error::output("record end", e))
+		} else {
+			Ok(())
+		}
+	}
+}
+
+mod fields;
+mod split;
 mod streaming_serializer;
 
+pub use fields::{Field, Fields};
+pub use split::Split;
+use split::Splitter;
+
+#[cfg(feature = "cbor")]
+pub(crate) use streaming_serializer::CBOR_SEQ_MAGIC;
 use streaming_serializer::{StreamingMap, StreamingSeq, StreamingSerializer};
 
+/// Write the precert data of an entry
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn write_precert(map: &mut StreamingMap<'_>, precert: &Precert) -> Result<(), Error> {
+	map.key("precert")
+		.map_err(|e| Error::output("precert key", e))?;
+	let mut precert_map = map.map().map_err(|e| Error::output("precert open", e))?;
+
+	precert_map
+		.key("issuer_key_hash")
+		.map_err(|e| Error::output("issuer_key_hash key", e))?;
+	precert_map
+		.bytes(&precert.issuer_key_hash)
+		.map_err(|e| Error::output("issuer_key_hash", e))?;
+	precert_map
+		.key("tbs_certificate")
+		.map_err(|e| Error::output("tbs_certificate key", e))?;
+	precert_map
+		.bytes(&precert.tbs_certificate)
+		.map_err(|e| Error::output("tbs_certificate", e))?;
+
+	precert_map
+		.end()
+		.map_err(|e| Error::output("precert close", e))
+}
+
+/// Write the decoded parts of an entry's certificate
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn write_decoded(map: &mut StreamingMap<'_>, decoded: &Decoded) -> Result<(), Error> {
+	map.key("decoded")
+		.map_err(|e| Error::output("decoded key", e))?;
+	let mut decoded_map = map.map().map_err(|e| Error::output("decoded open", e))?;
+
+	for (key, value) in [
+		("subject", &decoded.subject),
+		("common_name", &decoded.common_name),
+		("issuer", &decoded.issuer),
+	] {
+		optional_string(&mut decoded_map, key, value.as_deref())?;
+	}
+	optional_bytes(&mut decoded_map, "serial", decoded.serial.as_deref())?;
+	for (key, value) in [
+		("not_before", decoded.not_before),
+		("not_after", decoded.not_after),
+	] {
+		if let Some(value) = value {
+			decoded_map
+				.key(key)
+				.map_err(|e| Error::output(format!("{key} key"), e))?;
+			decoded_map.uint(value).map_err(|e| Error::output(key, e))?;
+		}
+	}
+	for (key, values) in [
+		("dns_names", &decoded.dns_names),
+		("ip_addresses", &decoded.ip_addresses),
+	] {
+		if !values.is_empty() {
+			decoded_map
+				.key(key)
+				.map_err(|e| Error::output(format!("{key} key"), e))?;
+			let mut seq = decoded_map
+				.seq()
+				.map_err(|e| Error::output(format!("{key} open"), e))?;
+			for value in values {
+				seq.string(value).map_err(|e| Error::output(key, e))?;
+			}
+			seq.end()
+				.map_err(|e| Error::output(format!("{key} close"), e))?;
+		}
+	}
+	optional_string(&mut decoded_map, "key_type", decoded.key_type.as_deref())?;
+	optional_string(&mut decoded_map, "error", decoded.error.as_deref())?;
+
+	decoded_map
+		.end()
+		.map_err(|e| Error::output("decoded close", e))
+}
+
+/// Write a `string` field into a map, if there's anything to write
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn optional_string(
+	map: &mut StreamingMap<'_>,
+	key: &str,
+	value: Option<&str>,
+) -> Result<(), Error> {
+	if let Some(value) = value {
+		map.key(key)
+			.map_err(|e| Error::output(format!("{key} key"), e))?;
+		map.string(value).map_err(|e| Error::output(key, e))?;
+	}
+	Ok(())
+}
+
+/// Write a `bytes` field into a map, if there's anything to write
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn optional_bytes(
+	map: &mut StreamingMap<'_>,
+	key: &str,
+	value: Option<&[u8]>,
+) -> Result<(), Error> {
+	if let Some(value) = value {
+		map.key(key)
+			.map_err(|e| Error::output(format!("{key} key"), e))?;
+		map.bytes(value).map_err(|e| Error::output(key, e))?;
+	}
+	Ok(())
+}
+
+/// Write the output of several scrapes as a single document
+///
+/// Each of the `parts` must be a complete document in the given format, as written by a
+/// `FileWriter`.  The combined document is a sequence of those documents, in the order given,
+/// or, for record stream formats, simply all the records of each document, one after the other
+/// (with only the one magic number at the start, for formats that have one).
+#[allow(clippy::result_large_err)] // Oh shoosh
+pub fn combine<W, R>(
+	writer: W,
+	format: OutputFormat,
+	parts: impl IntoIterator<Item = R>,
+) -> Result<(), Error>
+where
+	W: std::io::Write + Sync + Send,
+	R: Read,
+{
+	if format.is_record_stream() {
+		let mut writer = BufWriter::new(writer);
+		let magic = format.magic();
+		for (i, part) in parts.into_iter().enumerate() {
+			let mut part = BufReader::new(part);
+			if i > 0 {
+				let start = part
+					.fill_buf()
+					.map_err(|e| Error::output("combined records", e))?;
+				if start.starts_with(magic) {
+					part.consume(magic.len());
+				}
+			}
+			std::io::copy(&mut part, &mut writer)
+				.map_err(|e| Error::output("combined records", e))?;
+		}
+		return writer
+			.flush()
+			.map_err(|e| Error::output("combined records", e));
+	}
+
+	let ser = StreamingSerializer::new(BufWriter::new(writer), format);
+	let mut seq = ser
+		.seq()
+		.map_err(|e| Error::output("combined seq open", e))?;
+
+	for mut part in parts {
+		seq.raw(&mut part)
+			.map_err(|e| Error::output("combined seq element", e))?;
+	}
+
+	seq.end()
+		.map_err(|e| Error::output("combined seq close", e))
+}
+
 impl<'a, W: std::io::Write + Sync + Send + 'a> GenServer for FileWriter<'a, W> {
 	type Args = Args<W>;
 	type Error = Error;
@@ -97,155 +831,87 @@ impl<'a, W: std::io::Write + Sync + Send + 'a> GenServer for FileWriter<'a, W> {
 	type StopReason = ();
 
 	fn init(args: Args<W>) -> Result<Self, Self::Error> {
-		let ser = StreamingSerializer::new(Box::new(BufWriter::new(args.writer)), args.format);
-
-		let mut map = ser.map().map_err(|e| Error::output("map open", e))?;
-		map.key("log_url")
-			.map_err(|e| Error::output("log_url key", e))?;
-		map.string(args.log_url.as_ref())
-			.map_err(|e| Error::output("log_url", e))?;
-		map.key("scrape_begin_timestamp")
-			.map_err(|e| Error::output("scrape_begin_timestamp key", e))?;
-		map.uint(current_time()?)
-			.map_err(|e| Error::output("scrape_begin_timestamp", e))?;
+		if cfg!(not(feature = "decode")) && args.fields.contains(Field::Decoded) {
+			return Err(Error::internal(
+				"decoding certificates needs the decode feature",
+			));
+		}
+		if args.fields.contains(Field::LeafHash) && !args.raw_entries {
+			return Err(Error::internal(
+				"leaf hashes need the raw leaf_input, which the runner hasn't been told to keep",
+			));
+		}
+
+		let doc = match args.output {
+			Output::Writer(writer) => Document::Single(Box::new(
+				DocumentWriter::new(
+					writer,
+					args.format,
+					&args.log_url,
+					args.log_id.as_deref(),
+					Some(current_time()?),
+				)?
+				.dedupe_chains(args.dedupe_chains)
+				.fields(args.fields.clone()),
+			)),
+			Output::Split(split) => Document::Split(Box::new(
+				Splitter::new(
+					split,
+					args.format,
+					args.log_url,
+					args.log_id,
+					current_time()?,
+				)
+				.dedupe_chains(args.dedupe_chains)
+				.fields(args.fields.clone()),
+			)),
+		};
 
 		Ok(Self {
-			map,
-			entries: None,
-			include_chains: args.include_chains,
-			include_precert_data: args.include_precert_data,
+			doc,
+			fields: args.fields,
 			_m: PhantomData,
 		})
 	}
 
-	#[allow(clippy::too_many_lines)] // TODO: refactor
 	fn handle_cast(
 		&mut self,
 		request: Self::Request,
 	) -> Result<gen_server::Status<Self>, Self::Error> {
 		match request {
 			processor::Request::Metadata(sth) => {
-				self.map
-					.key("sth")
-					.map_err(|e| Error::output("sth key", e))?;
-				let mut sth_map = self
-					.map
-					.map()
-					.map_err(|e| Error::output("sth map open", e))?;
-				sth_map
-					.key("tree_size")
-					.map_err(|e| Error::output("tree_size key", e))?;
-				sth_map
-					.uint(sth.tree_size)
-					.map_err(|e| Error::output("tree_size", e))?;
-				sth_map
-					.key("timestamp")
-					.map_err(|e| Error::output("timestamp key", e))?;
-				sth_map
-					.uint(sth.timestamp)
-					.map_err(|e| Error::output("timestamp", e))?;
-				sth_map
-					.key("sha256_root_hash")
-					.map_err(|e| Error::output("sha256_root_hash key", e))?;
-				sth_map
-					.bytes(&sth.sha256_root_hash)
-					.map_err(|e| Error::output("sha256_root_hash", e))?;
-				sth_map
-					.key("tree_head_signature")
-					.map_err(|e| Error::output("tree_head_signature key", e))?;
-				sth_map
-					.bytes(&sth.tree_head_signature)
-					.map_err(|e| Error::output("tree_head_signature", e))?;
-				sth_map
-					.end()
-					.map_err(|e| Error::output("sth map close", e))?;
+				self.doc.sth(&Sth::from(&sth))?;
 
 				Ok(Continue)
 			}
-			processor::Request::Entry(id, entry) => {
-				if self.entries.is_none() {
-					self.map
-						.key("entries")
-						.map_err(|e| Error::output("entries key", e))?;
-					let entries = self
-						.map
-						.seq()
-						.map_err(|e| Error::output("entries open", e))?;
-					self.entries = Some(entries);
-				}
-
-				if let Some(entries) = &mut self.entries {
-					let mut map = entries
-						.map()
-						.map_err(|e| Error::output("entry map open", e))?;
-
-					let (timestamp, certificate, chain_certs, precert) =
-						if let TreeLeafEntry::TimestampedEntry(ts_entry) = &entry.leaf_input.entry {
-							match (&ts_entry.signed_entry, &entry.extra_data) {
-							 (SignedEntry::X509Entry(x509_entry), ExtraData::X509ExtraData(extra_data)) => Ok((ts_entry.timestamp, &x509_entry.certificate, &extra_data.certificate_chain, None)),
-							 (SignedEntry::PrecertEntry(precert_entry), ExtraData::PrecertExtraData(extra_data)) => Ok((ts_entry.timestamp, &extra_data.pre_certificate.certificate, &extra_data.precertificate_chain, Some(precert_entry.clone()))),
-							 _ => Err(Error::InternalError(format!("incompatible combination of signed_entry and extra_data ({:?} vs {:?})", ts_entry.signed_entry, entry.extra_data))),
-						 }
-						} else {
-							Err(Error::EntryDecodingError(
-								"leaf_input was not a TimestampedEntry".to_string(),
-							))
-						}?;
-
-					map.key("entry_number")
-						.map_err(|e| Error::output("entry_number key", e))?;
-					map.uint(id).map_err(|e| Error::output("entry_number", e))?;
-					map.key("timestamp")
-						.map_err(|e| Error::output("timestamp key", e))?;
-					map.uint(timestamp)
-						.map_err(|e| Error::output("timestamp", e))?;
-					map.key("certificate")
-						.map_err(|e| Error::output("certificate key", e))?;
-					map.bytes(certificate)
-						.map_err(|e| Error::output("certificate", e))?;
-
-					if self.include_chains {
-						map.key("chain")
-							.map_err(|e| Error::output("chain key", e))?;
-						let mut chain = map.seq().map_err(|e| Error::output("chain open", e))?;
-
-						for c in chain_certs {
-							chain
-								.bytes(&c.certificate)
-								.map_err(|e| Error::output("chain entry", e))?;
-						}
-
-						chain.end().map_err(|e| Error::output("chain close", e))?;
-					}
-
-					if self.include_precert_data {
-						if let Some(precert) = precert {
-							map.key("precert")
-								.map_err(|e| Error::output("precert key", e))?;
-							let mut precert_map =
-								map.map().map_err(|e| Error::output("precert open", e))?;
-
-							precert_map
-								.key("issuer_key_hash")
-								.map_err(|e| Error::output("issuer_key_hash key", e))?;
-							precert_map
-								.bytes(&precert.issuer_key_hash)
-								.map_err(|e| Error::output("issuer_key_hash", e))?;
-							precert_map
-								.key("tbs_certificate")
-								.map_err(|e| Error::output("tbs_certificate key", e))?;
-							precert_map
-								.bytes(&precert.tbs_certificate)
-								.map_err(|e| Error::output("tbs_certificate", e))?;
-
-							precert_map
-								.end()
-								.map_err(|e| Error::output("precert close", e))?;
-						}
+			processor::Request::Entry(id, entry, raw) => {
+				let mut doc_entry = Entry::from_response(
+					id,
+					&entry,
+					self.fields.contains(Field::Chain),
+					self.fields.contains(Field::Precert),
+				)?
+				.raw(raw);
+				if self.fields.contains(Field::LeafHash)
+					|| self.fields.contains(Field::CertificateSha256)
+				{
+					doc_entry = doc_entry.hashes();
+					if self.fields.contains(Field::LeafHash) && doc_entry.leaf_hash.is_none() {
+						return Err(Error::internal(
+							"leaf hashes need the raw leaf_input, which the runner wasn't told to keep",
+						));
 					}
-
-					map.end().map_err(|e| Error::output("entry map close", e))?;
 				}
+				if self.fields.contains(Field::EntryType) {
+					doc_entry = doc_entry.entry_type(Some(EntryType::of(&entry)?));
+				}
+				#[cfg(feature = "decode")]
+				if self.fields.contains(Field::Decoded) {
+					let decoded =
+						crate::decode::decode(doc_entry.certificate.as_deref().unwrap_or_default());
+					doc_entry = doc_entry.decoded(Some(decoded));
+				}
+				self.doc.entry(&doc_entry)?;
 
 				Ok(Continue)
 			}
@@ -253,21 +919,21 @@ impl<'a, W: std::io::Write + Sync + Send + 'a> GenServer for FileWriter<'a, W> {
 	}
 
 	fn terminate(&mut self, _reason: Result<(), Error>) {
-		if let Some(ref mut entries) = &mut self.entries {
-			drop(entries.end().map_err(|e| Error::output("entries close", e)));
-		}
-		if let Ok(time) = current_time() {
-			drop(
-				self.map
-					.key("scrape_end_timestamp")
-					.map_err(|e| Error::output("scrape_end_timestamp key", e)),
-			);
-			drop(
-				self.map
-					.uint(time)
-					.map_err(|e| Error::output("scrape_end_timestamp", e)),
-			);
-		}
-		drop(self.map.end().map_err(|e| Error::output("map close", e)));
+		drop(self.doc.finish(&Trailer::new(current_time().ok())));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args() -> Args<Vec<u8>> {
+		Args::new(vec![], Url::parse("https://ct.example.com/").unwrap()).include_hashes(true)
+	}
+
+	#[test]
+	fn refuses_to_start_on_leaf_hashes_without_raw_entries() {
+		assert!(FileWriter::init(args()).is_err());
+		assert!(FileWriter::init(args().raw_entries(true)).is_ok());
 	}
 }
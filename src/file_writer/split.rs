@@ -0,0 +1,375 @@
+//! Splitting a scrape across several files
+//!
+//! Rather than one (potentially enormous) document, a split scrape is written as a series of
+//! smaller ones, each of which is a complete document in its own right, with the log's details
+//! and STH, and its share of the entries.  A new file is started whenever the current one has
+//! as many entries, or as many bytes, as it's allowed.
+//!
+//! Files are named from a [`Template`], with `{start}` and `{end}` filled in with the lowest and
+//! highest entry numbers in the file.  As we don't know the highest entry number until the
+//! file's done, it's written with an `{end}` of `partial` to begin with, and renamed once it's
+//! finished.
+//!
+
+use std::{
+	collections::HashMap,
+	io::{BufWriter, Write},
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+};
+use url::Url;
+
+use super::{DocumentWriter, Fields, OutputFormat};
+use crate::{
+	document::{Entry, Sth, Trailer},
+	template::Template,
+	utils::current_time,
+	Error,
+};
+
+/// What goes in the `{end}` of a file's name until we know what it should be
+const PARTIAL: &str = "partial";
+
+type Opener<W> = dyn Fn(&Path) -> Result<W, Error> + Send + Sync;
+
+/// How to split up a scrape, and where to put the pieces
+pub struct Split<W> {
+	template: Template,
+	max_entries: Option<u64>,
+	max_bytes: Option<u64>,
+	open: Arc<Opener<W>>,
+}
+
+impl<W> Clone for Split<W> {
+	fn clone(&self) -> Self {
+		Self {
+			template: self.template.clone(),
+			max_entries: self.max_entries,
+			max_bytes: self.max_bytes,
+			open: Arc::clone(&self.open),
+		}
+	}
+}
+
+impl<W> std::fmt::Debug for Split<W> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		f.debug_struct("Split")
+			.field("template", &self.template)
+			.field("max_entries", &self.max_entries)
+			.field("max_bytes", &self.max_bytes)
+			.finish_non_exhaustive()
+	}
+}
+
+impl<W> Split<W> {
+	/// Split a scrape into files named by the template, each of which is opened with `open`
+	///
+	/// Until a limit is set with [`max_entries`](Self::max_entries) or
+	/// [`max_bytes`](Self::max_bytes), everything ends up in the one file.
+	#[must_use]
+	pub fn new(
+		template: Template,
+		open: impl Fn(&Path) -> Result<W, Error> + Send + Sync + 'static,
+	) -> Self {
+		Self {
+			template,
+			max_entries: None,
+			max_bytes: None,
+			open: Arc::new(open),
+		}
+	}
+
+	/// Start a new file once this many entries have been written to the current one
+	#[must_use]
+	pub fn max_entries(mut self, max_entries: u64) -> Self {
+		self.max_entries = Some(max_entries.max(1));
+		self
+	}
+
+	/// Start a new file once (roughly) this many bytes have been written to the current one
+	///
+	/// The size is checked after each entry is written, so files will usually end up a little
+	/// bigger than this.
+	#[must_use]
+	pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+		self.max_bytes = Some(max_bytes.max(1));
+		self
+	}
+
+	fn path(&self, start: u64, end: &str) -> PathBuf {
+		PathBuf::from(self.template.render(&HashMap::from([
+			("start", start.to_string()),
+			("end", end.to_string()),
+		])))
+	}
+}
+
+/// Keeps track of how much has been written through it
+struct Counted<W> {
+	inner: W,
+	count: Arc<AtomicU64>,
+}
+
+impl<W: Write> Write for Counted<W> {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		let n = self.inner.write(buf)?;
+		self.count
+			.fetch_add(u64::try_from(n).unwrap_or(u64::MAX), Ordering::Relaxed);
+		Ok(n)
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// The file that's currently being written
+struct Part<'a> {
+	doc: DocumentWriter<'a>,
+	path: PathBuf,
+	bytes: Arc<AtomicU64>,
+	entries: u64,
+	first: u64,
+	last: u64,
+}
+
+/// Writes a scrape out as a series of documents
+pub(super) struct Splitter<'a, W> {
+	split: Split<W>,
+	format: OutputFormat,
+	log_url: Url,
+	log_id: Option<Vec<u8>>,
+	scrape_begin_timestamp: u64,
+	dedupe_chains: bool,
+	fields: Fields,
+	sth: Option<Sth>,
+	part: Option<Part<'a>>,
+}
+
+impl<'a, W: Write + Sync + Send + 'a> Splitter<'a, W> {
+	pub(super) fn new(
+		split: Split<W>,
+		format: OutputFormat,
+		log_url: Url,
+		log_id: Option<Vec<u8>>,
+		scrape_begin_timestamp: u64,
+	) -> Self {
+		Self {
+			split,
+			format,
+			log_url,
+			log_id,
+			scrape_begin_timestamp,
+			dedupe_chains: false,
+			fields: Fields::all(),
+			sth: None,
+			part: None,
+		}
+	}
+
+	/// Deduplicate the chains in each file (each file gets its own set of issuers, so that it
+	/// can be read on its own)
+	#[must_use]
+	pub(super) fn dedupe_chains(mut self, dedupe_chains: bool) -> Self {
+		self.dedupe_chains = dedupe_chains;
+		self
+	}
+
+	/// Only write these fields into each file
+	#[must_use]
+	pub(super) fn fields(mut self, fields: Fields) -> Self {
+		self.fields = fields;
+		self
+	}
+
+	/// Hang on to the STH, so it can be written into every file
+	pub(super) fn sth(&mut self, sth: &Sth) {
+		self.sth = Some(sth.clone());
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub(super) fn entry(&mut self, entry: &Entry) -> Result<(), Error> {
+		let part = match &mut self.part {
+			Some(part) => part,
+			None => self.part.insert(self.open(entry.entry_number)?),
+		};
+
+		part.doc.entry(entry)?;
+		part.entries = part
+			.entries
+			.checked_add(1)
+			.ok_or_else(|| Error::arithmetic("counting entries in split file"))?;
+		part.first = part.first.min(entry.entry_number);
+		part.last = part.last.max(entry.entry_number);
+
+		let full = self
+			.split
+			.max_entries
+			.is_some_and(|max| part.entries >= max)
+			|| self
+				.split
+				.max_bytes
+				.is_some_and(|max| part.bytes.load(Ordering::Relaxed) >= max);
+		if full {
+			self.close(&Trailer::new(current_time().ok()))?;
+		}
+
+		Ok(())
+	}
+
+	/// Finish off whichever file is currently being written
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub(super) fn finish(&mut self, trailer: &Trailer) -> Result<(), Error> {
+		self.close(trailer)
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn open(&self, first: u64) -> Result<Part<'a>, Error> {
+		let path = self.split.path(first, PARTIAL);
+		let bytes = Arc::new(AtomicU64::new(0));
+		// Counting has to happen before the buffering, otherwise we'd only find out how big
+		// the file is every few kilobytes
+		let writer = Counted {
+			inner: BufWriter::new((self.split.open)(&path)?),
+			count: Arc::clone(&bytes),
+		};
+
+		let mut doc = DocumentWriter::unbuffered(
+			writer,
+			self.format,
+			Some(&self.log_url),
+			self.log_id.as_deref(),
+			Some(self.scrape_begin_timestamp),
+		)?
+		.dedupe_chains(self.dedupe_chains)
+		.fields(self.fields.clone());
+		if let Some(sth) = &self.sth {
+			doc.sth(sth)?;
+		}
+
+		Ok(Part {
+			doc,
+			path,
+			bytes,
+			entries: 0,
+			first,
+			last: first,
+		})
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn close(&mut self, trailer: &Trailer) -> Result<(), Error> {
+		let Some(mut part) = self.part.take() else {
+			return Ok(());
+		};
+
+		part.doc.finish(trailer)?;
+		// Make sure everything's been written out before the file gets its final name
+		drop(part.doc);
+
+		let path = self.split.path(part.first, &part.last.to_string());
+		std::fs::rename(&part.path, &path).map_err(|e| {
+			Error::output(
+				"split file",
+				format!(
+					"could not rename {} to {}: {e}",
+					part.path.display(),
+					path.display()
+				),
+			)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::Value;
+
+	fn scrape(
+		dir: &Path,
+		split: impl FnOnce(Split<std::fs::File>) -> Split<std::fs::File>,
+		entries: &[u64],
+	) {
+		let template = Template::new(
+			&format!("{}/log-{{start}}-{{end}}.json", dir.display()),
+			&["start", "end"],
+		)
+		.unwrap();
+		let mut splitter = Splitter::new(
+			split(Split::new(template, |path| {
+				std::fs::File::create(path).map_err(|e| Error::output("test file", e))
+			})),
+			OutputFormat::JSON,
+			Url::parse("https://ct.example.com/").unwrap(),
+			None,
+			1234,
+		);
+
+		splitter.sth(&Sth {
+			tree_size: 10,
+			timestamp: 5678,
+			sha256_root_hash: vec![0; 32],
+			tree_head_signature: vec![0; 64],
+		});
+		for n in entries {
+			splitter.entry(&Entry::new(*n, 42, vec![1, 2, 3])).unwrap();
+		}
+		splitter.finish(&Trailer::new(Some(9999))).unwrap();
+	}
+
+	fn files(dir: &Path) -> Vec<String> {
+		let mut names = std::fs::read_dir(dir)
+			.unwrap()
+			.map(|e| e.unwrap().file_name().into_string().unwrap())
+			.collect::<Vec<_>>();
+		names.sort();
+		names
+	}
+
+	fn entry_numbers(path: &Path) -> Vec<u64> {
+		let doc: Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+		assert_eq!("https://ct.example.com/", doc["log_url"]);
+		assert_eq!(10, doc["sth"]["tree_size"]);
+		doc["entries"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|e| e["entry_number"].as_u64().unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn splits_by_entry_count() {
+		let dir = temp_dir::TempDir::new().unwrap();
+		// Entries don't always turn up in order
+		scrape(dir.path(), |s| s.max_entries(2), &[1, 0, 2, 3, 4]);
+
+		assert_eq!(
+			vec!["log-0-1.json", "log-2-3.json", "log-4-4.json"],
+			files(dir.path())
+		);
+		assert_eq!(vec![1, 0], entry_numbers(&dir.child("log-0-1.json")));
+		assert_eq!(vec![2, 3], entry_numbers(&dir.child("log-2-3.json")));
+		assert_eq!(vec![4], entry_numbers(&dir.child("log-4-4.json")));
+	}
+
+	#[test]
+	fn splits_by_size() {
+		let dir = temp_dir::TempDir::new().unwrap();
+		scrape(dir.path(), |s| s.max_bytes(1), &[7, 8]);
+
+		assert_eq!(vec!["log-7-7.json", "log-8-8.json"], files(dir.path()));
+	}
+
+	#[test]
+	fn writes_nothing_without_entries() {
+		let dir = temp_dir::TempDir::new().unwrap();
+		scrape(dir.path(), |s| s.max_entries(2), &[]);
+
+		assert!(files(dir.path()).is_empty());
+	}
+}
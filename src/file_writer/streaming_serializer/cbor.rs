@@ -8,3 +8,7 @@ pub(crate) fn cbor<F: FnOnce(Encoder<&mut Vec<u8>>) -> std::io::Result<()>>(
 	f(Encoder::from(&mut v))?;
 	Ok(v)
 }
+
+/// The "magic number" that identifies a CBOR sequence (RFC 9277 section 2.2): the string "BOR",
+/// with tag 55800
+pub(crate) const CBOR_SEQ_MAGIC: [u8; 7] = [0xD9, 0xD9, 0xF8, 0x43, 0x42, 0x4F, 0x52];
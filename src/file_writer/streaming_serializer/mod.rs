@@ -11,16 +11,58 @@ use std::{
 pub enum StreamFormat {
 	#[default]
 	JSON,
+	JSONLines,
 	#[cfg(feature = "cbor")]
 	CBOR,
+	#[cfg(feature = "cbor")]
+	CBORSeq,
+	#[cfg(feature = "msgpack")]
+	MsgPack,
+}
+
+impl StreamFormat {
+	/// Whether documents in this format are written as a stream of separate records (a header,
+	/// one per entry, and a trailer), rather than as a single map
+	#[must_use]
+	pub fn is_record_stream(self) -> bool {
+		match self {
+			StreamFormat::JSON => false,
+			StreamFormat::JSONLines => true,
+			#[cfg(feature = "cbor")]
+			StreamFormat::CBOR => false,
+			#[cfg(feature = "cbor")]
+			StreamFormat::CBORSeq => true,
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => true,
+		}
+	}
+
+	/// What goes at the very start of a stream in this format, before anything else
+	#[must_use]
+	pub(crate) fn magic(self) -> &'static [u8] {
+		match self {
+			StreamFormat::JSON | StreamFormat::JSONLines => &[],
+			#[cfg(feature = "cbor")]
+			StreamFormat::CBOR => &[],
+			#[cfg(feature = "cbor")]
+			StreamFormat::CBORSeq => &CBOR_SEQ_MAGIC,
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => &[],
+		}
+	}
 }
 
 impl std::fmt::Display for StreamFormat {
 	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
 		match self {
 			StreamFormat::JSON => formatter.write_str("json"),
+			StreamFormat::JSONLines => formatter.write_str("jsonl"),
 			#[cfg(feature = "cbor")]
 			StreamFormat::CBOR => formatter.write_str("cbor"),
+			#[cfg(feature = "cbor")]
+			StreamFormat::CBORSeq => formatter.write_str("cbor-seq"),
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => formatter.write_str("msgpack"),
 		}
 	}
 }
@@ -30,16 +72,28 @@ mod cbor;
 #[cfg(feature = "cbor")]
 use self::cbor::cbor;
 #[cfg(feature = "cbor")]
+pub(crate) use self::cbor::CBOR_SEQ_MAGIC;
+#[cfg(feature = "cbor")]
 use ciborium_ll::Header as CBORHeader;
 
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(feature = "msgpack")]
+use self::msgpack::{msgpack, Pending};
+
 impl TryFrom<&str> for StreamFormat {
 	type Error = String;
 
 	fn try_from(s: &str) -> Result<Self, Self::Error> {
 		match s {
 			"json" => Ok(Self::JSON),
+			"jsonl" => Ok(Self::JSONLines),
 			#[cfg(feature = "cbor")]
 			"cbor" => Ok(Self::CBOR),
+			#[cfg(feature = "cbor")]
+			"cbor-seq" => Ok(Self::CBORSeq),
+			#[cfg(feature = "msgpack")]
+			"msgpack" => Ok(Self::MsgPack),
 			_ => Err(format!("unknown output format {s:?}")),
 		}
 	}
@@ -58,6 +112,14 @@ impl<'a> StreamingSerializer<'a> {
 		}
 	}
 
+	/// Another serializer that writes to the same place as this one
+	fn share(&self) -> Self {
+		Self {
+			writer: Arc::<RwLock<Box<dyn io::Write + Send + Sync>>>::clone(&self.writer),
+			format: self.format,
+		}
+	}
+
 	fn write(&self, o: &[u8]) -> io::Result<()> {
 		#[allow(clippy::expect_used)] // At the point this happens, we're *right* fucked
 		self.writer
@@ -66,60 +128,121 @@ impl<'a> StreamingSerializer<'a> {
 			.write_all(o)
 	}
 
+	fn copy(&self, r: &mut dyn io::Read) -> io::Result<()> {
+		#[allow(clippy::expect_used)] // At the point this happens, we're *right* fucked
+		io::copy(
+			r,
+			&mut *self.writer.write().expect("writer to not be poisoned"),
+		)
+		.map(|_| ())
+	}
+
 	pub(crate) fn string(&self, s: &str) -> io::Result<()> {
 		self.write(&match self.format {
-			StreamFormat::JSON => json!(s).to_string().into_bytes(),
+			StreamFormat::JSON | StreamFormat::JSONLines => json!(s).to_string().into_bytes(),
 			#[cfg(feature = "cbor")]
-			StreamFormat::CBOR => cbor(|mut enc| enc.text(s, None))?,
+			StreamFormat::CBOR | StreamFormat::CBORSeq => cbor(|mut enc| enc.text(s, None))?,
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => msgpack(|buf| rmp::encode::write_str(buf, s))?,
 		})
 	}
 
 	pub(crate) fn bytes(&self, b: &[u8]) -> io::Result<()> {
 		self.write(&match self.format {
-			StreamFormat::JSON => json!(b64.encode(b)).to_string().into_bytes(),
+			StreamFormat::JSON | StreamFormat::JSONLines => {
+				json!(b64.encode(b)).to_string().into_bytes()
+			}
 			#[cfg(feature = "cbor")]
-			StreamFormat::CBOR => cbor(|mut enc| enc.bytes(b, None))?,
+			StreamFormat::CBOR | StreamFormat::CBORSeq => cbor(|mut enc| enc.bytes(b, None))?,
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => msgpack(|buf| rmp::encode::write_bin(buf, b))?,
 		})
 	}
 
 	pub(crate) fn uint(&self, u: u64) -> io::Result<()> {
 		self.write(&match self.format {
-			StreamFormat::JSON => json!(u).to_string().into_bytes(),
+			StreamFormat::JSON | StreamFormat::JSONLines => json!(u).to_string().into_bytes(),
 			#[cfg(feature = "cbor")]
-			StreamFormat::CBOR => cbor(|mut enc| enc.push(CBORHeader::Positive(u)))?,
+			StreamFormat::CBOR | StreamFormat::CBORSeq => cbor(|mut enc| enc.push(CBORHeader::Positive(u)))?,
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => msgpack(|buf| rmp::encode::write_uint(buf, u))?,
 		})
 	}
 
+	/// Write whatever needs to come before anything else
+	pub(crate) fn start_stream(&self) -> io::Result<()> {
+		self.write(self.format.magic())
+	}
+
+	/// Finish off a top-level record, in a record stream format
+	pub(crate) fn end_record(&self) -> io::Result<()> {
+		match self.format {
+			StreamFormat::JSON => Ok(()),
+			StreamFormat::JSONLines => self.write(b"\n"),
+			#[cfg(feature = "cbor")]
+			StreamFormat::CBOR | StreamFormat::CBORSeq => Ok(()),
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => Ok(()),
+		}
+	}
+
 	pub(crate) fn map(&self) -> io::Result<StreamingMap<'a>> {
-		self.write(&match self.format {
-			StreamFormat::JSON => b"{".to_vec(),
+		#[cfg(feature = "msgpack")]
+		let mut pending = None;
+		let s = match self.format {
+			StreamFormat::JSON | StreamFormat::JSONLines => {
+				self.write(b"{")?;
+				self.share()
+			}
 			#[cfg(feature = "cbor")]
-			StreamFormat::CBOR => cbor(|mut enc| enc.push(CBORHeader::Map(None)))?,
-		})?;
+			StreamFormat::CBOR | StreamFormat::CBORSeq => {
+				self.write(&cbor(|mut enc| enc.push(CBORHeader::Map(None)))?)?;
+				self.share()
+			}
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => {
+				let (s, p) = Pending::new(self.share());
+				pending = Some(p);
+				s
+			}
+		};
+
 		Ok(StreamingMap {
-			s: Self {
-				writer: Arc::<RwLock<Box<dyn io::Write + Send + Sync>>>::clone(&self.writer),
-				format: self.format,
-			},
-			element_written: false,
+			s,
+			elements: 0,
 			format: self.format,
+			#[cfg(feature = "msgpack")]
+			pending,
 		})
 	}
 
 	pub(crate) fn seq(&self) -> io::Result<StreamingSeq<'a>> {
-		self.write(&match self.format {
-			StreamFormat::JSON => b"[".to_vec(),
+		#[cfg(feature = "msgpack")]
+		let mut pending = None;
+		let s = match self.format {
+			StreamFormat::JSON | StreamFormat::JSONLines => {
+				self.write(b"[")?;
+				self.share()
+			}
 			#[cfg(feature = "cbor")]
-			StreamFormat::CBOR => cbor(|mut enc| enc.push(CBORHeader::Array(None)))?,
-		})?;
+			StreamFormat::CBOR | StreamFormat::CBORSeq => {
+				self.write(&cbor(|mut enc| enc.push(CBORHeader::Array(None)))?)?;
+				self.share()
+			}
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => {
+				let (s, p) = Pending::new(self.share());
+				pending = Some(p);
+				s
+			}
+		};
 
 		Ok(StreamingSeq {
-			s: Self {
-				writer: Arc::<RwLock<Box<dyn io::Write + Send + Sync>>>::clone(&self.writer),
-				format: self.format,
-			},
-			element_written: false,
+			s,
+			elements: 0,
 			format: self.format,
+			#[cfg(feature = "msgpack")]
+			pending,
 		})
 	}
 }
@@ -127,28 +250,40 @@ impl<'a> StreamingSerializer<'a> {
 pub(crate) struct StreamingMap<'a> {
 	format: StreamFormat,
 	s: StreamingSerializer<'a>,
-	element_written: bool,
+	elements: u32,
+	/// Where the map gets written once we know how big it is
+	#[cfg(feature = "msgpack")]
+	pending: Option<Pending<'a>>,
 }
 
 impl<'a> StreamingMap<'a> {
 	pub(crate) fn key(&mut self, key: &str) -> io::Result<()> {
 		self.element()?;
 		match self.format {
-			StreamFormat::JSON => {
+			StreamFormat::JSON | StreamFormat::JSONLines => {
 				self.s.string(key)?;
 				self.s.write(b":")
 			}
 			#[cfg(feature = "cbor")]
-			StreamFormat::CBOR => self.s.string(key),
+			StreamFormat::CBOR | StreamFormat::CBORSeq => self.s.string(key),
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => self.s.string(key),
 		}
 	}
 
 	pub(crate) fn end(&self) -> io::Result<()> {
-		self.s.write(&match self.format {
-			StreamFormat::JSON => b"}".to_vec(),
+		match self.format {
+			StreamFormat::JSON | StreamFormat::JSONLines => self.s.write(b"}"),
 			#[cfg(feature = "cbor")]
-			StreamFormat::CBOR => cbor(|mut enc| enc.push(CBORHeader::Break))?,
-		})
+			StreamFormat::CBOR | StreamFormat::CBORSeq => {
+				self.s.write(&cbor(|mut enc| enc.push(CBORHeader::Break))?)
+			}
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => Pending::finish(
+				self.pending.as_ref(),
+				&msgpack(|buf| rmp::encode::write_map_len(buf, self.elements))?,
+			),
+		}
 	}
 
 	pub(crate) fn string(&self, s: &str) -> io::Result<()> {
@@ -172,15 +307,20 @@ impl<'a> StreamingMap<'a> {
 	}
 
 	fn element(&mut self) -> io::Result<()> {
-		if self.element_written {
+		if self.elements > 0 {
 			match self.format {
-				StreamFormat::JSON => self.s.write(b","),
+				StreamFormat::JSON | StreamFormat::JSONLines => self.s.write(b","),
 				#[cfg(feature = "cbor")]
-				StreamFormat::CBOR => Ok::<(), io::Error>(()),
+				StreamFormat::CBOR | StreamFormat::CBORSeq => Ok::<(), io::Error>(()),
+				#[cfg(feature = "msgpack")]
+				StreamFormat::MsgPack => Ok::<(), io::Error>(()),
 			}?;
 		}
 
-		self.element_written = true;
+		self.elements = self
+			.elements
+			.checked_add(1)
+			.ok_or_else(|| io::Error::other("too many elements"))?;
 		Ok(())
 	}
 }
@@ -188,16 +328,26 @@ impl<'a> StreamingMap<'a> {
 pub(crate) struct StreamingSeq<'a> {
 	format: StreamFormat,
 	s: StreamingSerializer<'a>,
-	element_written: bool,
+	elements: u32,
+	/// Where the seq gets written once we know how big it is
+	#[cfg(feature = "msgpack")]
+	pending: Option<Pending<'a>>,
 }
 
 impl<'a> StreamingSeq<'a> {
 	pub(crate) fn end(&self) -> io::Result<()> {
-		self.s.write(&match self.format {
-			StreamFormat::JSON => b"]".to_vec(),
+		match self.format {
+			StreamFormat::JSON | StreamFormat::JSONLines => self.s.write(b"]"),
 			#[cfg(feature = "cbor")]
-			StreamFormat::CBOR => cbor(|mut enc| enc.push(CBORHeader::Break))?,
-		})
+			StreamFormat::CBOR | StreamFormat::CBORSeq => {
+				self.s.write(&cbor(|mut enc| enc.push(CBORHeader::Break))?)
+			}
+			#[cfg(feature = "msgpack")]
+			StreamFormat::MsgPack => Pending::finish(
+				self.pending.as_ref(),
+				&msgpack(|buf| rmp::encode::write_array_len(buf, self.elements))?,
+			),
+		}
 	}
 
 	#[allow(unused)] // At some point, I may need this...
@@ -228,16 +378,27 @@ impl<'a> StreamingSeq<'a> {
 		self.s.seq()
 	}
 
+	/// Copy an element that has already been serialized (in the same format) into the sequence
+	pub(crate) fn raw(&mut self, r: &mut dyn io::Read) -> io::Result<()> {
+		self.element()?;
+		self.s.copy(r)
+	}
+
 	fn element(&mut self) -> io::Result<()> {
-		if self.element_written {
+		if self.elements > 0 {
 			match self.format {
-				StreamFormat::JSON => self.s.write(b","),
+				StreamFormat::JSON | StreamFormat::JSONLines => self.s.write(b","),
 				#[cfg(feature = "cbor")]
-				StreamFormat::CBOR => Ok::<(), io::Error>(()),
+				StreamFormat::CBOR | StreamFormat::CBORSeq => Ok::<(), io::Error>(()),
+				#[cfg(feature = "msgpack")]
+				StreamFormat::MsgPack => Ok::<(), io::Error>(()),
 			}?;
 		}
 
-		self.element_written = true;
+		self.elements = self
+			.elements
+			.checked_add(1)
+			.ok_or_else(|| io::Error::other("too many elements"))?;
 		Ok(())
 	}
 }
@@ -375,6 +536,21 @@ mod tests {
 			);
 		}
 
+		#[test]
+		fn serialize_a_seq_with_raw_elements() {
+			let mut buf = vec![];
+
+			let s = StreamingSerializer::new(&mut buf, StreamFormat::JSON);
+			let mut seq = s.seq().unwrap();
+			seq.raw(&mut &br#"{"foo":"bar"}"#[..]).unwrap();
+			seq.raw(&mut &br#"[1,2,3]"#[..]).unwrap();
+			seq.end().unwrap();
+			drop(seq);
+			drop(s);
+
+			assert_eq!(&br#"[{"foo":"bar"},[1,2,3]]"#[..], &buf[..]);
+		}
+
 		#[test]
 		fn serialize_an_empty_map() {
 			let mut buf = vec![];
@@ -591,6 +767,25 @@ mod tests {
 					    &buf[..]);
 		}
 
+		#[test]
+		fn serialize_a_seq_with_raw_elements() {
+			let mut buf = vec![];
+
+			let s = StreamingSerializer::new(&mut buf, StreamFormat::CBOR);
+			let mut seq = s.seq().unwrap();
+			seq.raw(&mut &hex!["BF 63 666F6F 63 626172 FF"][..])
+				.unwrap();
+			seq.raw(&mut &hex!["83 01 02 03"][..]).unwrap();
+			seq.end().unwrap();
+			drop(seq);
+			drop(s);
+
+			assert_eq!(
+				hex!["9F BF 63 666F6F 63 626172 FF 83 01 02 03 FF"],
+				&buf[..]
+			);
+		}
+
 		#[test]
 		fn serialize_an_empty_map() {
 			let mut buf = vec![];
@@ -677,4 +872,121 @@ mod tests {
 			assert_eq!(hex!["BF 66 737472696E67 65 6F68616921 65 6279746573 45 6F68616921 64 75696E74 19 01A4 63 736571 9F 63 6F6E65 43 B70A28 03 FF 64 666F7572 05 FF"], &buf[..]);
 		}
 	}
+
+	#[cfg(feature = "msgpack")]
+	mod msgpack {
+		use super::*;
+		use hex_literal::hex;
+
+		#[test]
+		fn serialize_a_string() {
+			let mut buf = vec![];
+
+			let s = StreamingSerializer::new(&mut buf, StreamFormat::MsgPack);
+			s.string("ohai!").unwrap();
+			drop(s);
+
+			assert_eq!(hex!["A5 6F68616921"], &buf[..]);
+		}
+
+		#[test]
+		fn serialize_bytes() {
+			let mut buf = vec![];
+
+			let s = StreamingSerializer::new(&mut buf, StreamFormat::MsgPack);
+			s.bytes(b"ohai!").unwrap();
+			drop(s);
+
+			assert_eq!(hex!["C4 05 6F68616921"], &buf[..]);
+		}
+
+		#[test]
+		fn serialize_a_uint() {
+			let mut buf = vec![];
+
+			let s = StreamingSerializer::new(&mut buf, StreamFormat::MsgPack);
+			s.uint(420).unwrap();
+			drop(s);
+
+			assert_eq!(hex!["CD 01A4"], &buf[..]);
+		}
+
+		#[test]
+		fn serialize_an_empty_seq() {
+			let mut buf = vec![];
+
+			let s = StreamingSerializer::new(&mut buf, StreamFormat::MsgPack);
+			let seq = s.seq().unwrap();
+			seq.end().unwrap();
+			drop(seq);
+			drop(s);
+
+			assert_eq!(hex!["90"], &buf[..]);
+		}
+
+		#[test]
+		fn serialize_a_seq_with_a_bunch_of_stuff() {
+			let mut buf = vec![];
+
+			let s = StreamingSerializer::new(&mut buf, StreamFormat::MsgPack);
+			let mut seq = s.seq().unwrap();
+			seq.string("ohai!").unwrap();
+			seq.bytes(b"ohai!").unwrap();
+			seq.uint(420).unwrap();
+			let mut map = seq.map().unwrap();
+			map.key("foo").unwrap();
+			map.string("bar").unwrap();
+			map.key("baz").unwrap();
+			map.bytes(b"\xC2\x89\x9Bj").unwrap();
+			map.end().unwrap();
+			drop(map);
+			seq.string("woot").unwrap();
+			seq.end().unwrap();
+			drop(seq);
+			drop(s);
+
+			assert_eq!(hex!["95 A5 6F68616921 C4 05 6F68616921 CD 01A4 82 A3 666F6F A3 626172 A3 62617A C4 04 C2899B6A A4 776F6F74"],
+					    &buf[..]);
+		}
+
+		#[test]
+		fn serialize_a_map_with_a_bunch_of_stuff() {
+			let mut buf = vec![];
+
+			let s = StreamingSerializer::new(&mut buf, StreamFormat::MsgPack);
+			let mut map = s.map().unwrap();
+			map.key("string").unwrap();
+			map.string("ohai!").unwrap();
+			map.key("seq").unwrap();
+			let mut seq = map.seq().unwrap();
+			seq.string("one").unwrap();
+			seq.uint(3).unwrap();
+			seq.end().unwrap();
+			drop(seq);
+			map.key("four").unwrap();
+			map.uint(5).unwrap();
+			map.end().unwrap();
+			drop(map);
+			drop(s);
+
+			assert_eq!(
+				hex!["83 A6 737472696E67 A5 6F68616921 A3 736571 92 A3 6F6E65 03 A4 666F7572 05"],
+				&buf[..]
+			);
+		}
+
+		#[test]
+		fn nothing_is_written_until_the_container_is_ended() {
+			let mut buf = vec![];
+
+			let s = StreamingSerializer::new(&mut buf, StreamFormat::MsgPack);
+			let mut map = s.map().unwrap();
+			map.key("uint").unwrap();
+			map.uint(420).unwrap();
+			drop(map);
+			drop(s);
+
+			assert!(buf.is_empty());
+		}
+	}
 }
@@ -0,0 +1,69 @@
+use rmp::encode::ValueWriteError;
+use std::{
+	io,
+	sync::{Arc, RwLock},
+};
+
+use super::StreamingSerializer;
+
+pub(crate) fn msgpack<T, F: FnOnce(&mut Vec<u8>) -> Result<T, ValueWriteError>>(
+	f: F,
+) -> io::Result<Vec<u8>> {
+	let mut v: Vec<u8> = vec![];
+
+	f(&mut v)?;
+	Ok(v)
+}
+
+/// msgpack containers have to say how many elements they have up front, so the elements
+/// are written into a buffer, and the whole lot is written out once the container is ended
+pub(crate) struct Pending<'a> {
+	buffer: Buffer,
+	parent: StreamingSerializer<'a>,
+}
+
+impl<'a> Pending<'a> {
+	/// Returns the serializer that the container's elements should be written to
+	pub(crate) fn new(parent: StreamingSerializer<'a>) -> (StreamingSerializer<'a>, Self) {
+		let buffer = Buffer::default();
+
+		(
+			StreamingSerializer::new(buffer.clone(), parent.format),
+			Self { buffer, parent },
+		)
+	}
+
+	/// Write out the container's header, followed by everything that was written into it
+	pub(crate) fn finish(pending: Option<&Self>, header: &[u8]) -> io::Result<()> {
+		let Some(pending) = pending else {
+			return Err(io::Error::other("MessagePack container went missing"));
+		};
+
+		pending.parent.write(header)?;
+		pending.parent.write(&pending.buffer.take())
+	}
+}
+
+#[derive(Clone, Debug, Default)]
+struct Buffer(Arc<RwLock<Vec<u8>>>);
+
+impl Buffer {
+	fn take(&self) -> Vec<u8> {
+		#[allow(clippy::expect_used)] // At the point this happens, we're *right* fucked
+		std::mem::take(&mut *self.0.write().expect("buffer to not be poisoned"))
+	}
+}
+
+impl io::Write for Buffer {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		#[allow(clippy::expect_used)] // At the point this happens, we're *right* fucked
+		self.0
+			.write()
+			.expect("buffer to not be poisoned")
+			.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
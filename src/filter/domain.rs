@@ -0,0 +1,186 @@
+//! Keeping only the certificates for names we're interested in
+//!
+//! Patterns are globs by default, where `*` matches any run of characters (dots included)
+//! and `?` matches any single character, so `*.example.com` and `*paypal*` both do what
+//! you'd expect.  Anything more involved can be written as a regex between slashes, like
+//! `/^(www\.)?example\.(com|net)$/`.  Either way, matching ignores case, and any trailing dot
+//! on a name.
+//!
+
+use regex::{escape, RegexSet, RegexSetBuilder};
+
+use super::{Candidate, Filter};
+use crate::error::Error;
+
+/// Accepts entries whose certificate has a DNS name (or common name) matching any of a set of
+/// patterns
+#[derive(Clone, Debug)]
+pub struct DomainMatch {
+	patterns: RegexSet,
+}
+
+impl DomainMatch {
+	/// Match names against the given patterns
+	///
+	/// # Errors
+	///
+	/// If a regex pattern isn't a valid regex, or there are no patterns at all (which would
+	/// match nothing, and is almost certainly a mistake).
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn new<I, S>(patterns: I) -> Result<Self, Error>
+	where
+		I: IntoIterator<Item = S>,
+		S: AsRef<str>,
+	{
+		let regexes = patterns
+			.into_iter()
+			.map(|p| to_regex(p.as_ref()))
+			.collect::<Vec<_>>();
+		if regexes.is_empty() {
+			return Err(Error::filter("no domain patterns given"));
+		}
+
+		Ok(Self {
+			patterns: RegexSetBuilder::new(regexes)
+				.case_insensitive(true)
+				.build()
+				.map_err(|e| Error::filter(format!("invalid domain pattern: {e}")))?,
+		})
+	}
+
+	/// Whether the name matches any of the patterns
+	#[must_use]
+	pub fn matches(&self, name: &str) -> bool {
+		self.patterns
+			.is_match(name.strip_suffix('.').unwrap_or(name))
+	}
+}
+
+impl std::fmt::Display for DomainMatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "names matching {} pattern(s)", self.patterns.len())
+	}
+}
+
+impl Filter for DomainMatch {
+	fn accepts(&self, candidate: &Candidate<'_>) -> bool {
+		let decoded = candidate.decoded();
+
+		decoded
+			.dns_names
+			.iter()
+			.chain(decoded.common_name.iter())
+			.any(|name| self.matches(name))
+	}
+}
+
+/// Turn a pattern into the regex that does the matching
+fn to_regex(pattern: &str) -> String {
+	if let Some(regex) = pattern
+		.strip_prefix('/')
+		.and_then(|p| p.strip_suffix('/'))
+		.filter(|p| !p.is_empty())
+	{
+		return regex.to_string();
+	}
+
+	let glob = pattern.strip_suffix('.').unwrap_or(pattern);
+	let mut regex = String::from("^");
+	let mut literal = [0u8; 4];
+	for c in glob.chars() {
+		match c {
+			'*' => regex.push_str(".*"),
+			'?' => regex.push('.'),
+			other => regex.push_str(&escape(other.encode_utf8(&mut literal))),
+		}
+	}
+	regex.push('$');
+	regex
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::filter::tests::{accepts, x509_response};
+
+	fn matcher(patterns: &[&str]) -> DomainMatch {
+		DomainMatch::new(patterns).unwrap()
+	}
+
+	#[test]
+	fn matches_globs() {
+		let m = matcher(&["*.example.com"]);
+
+		assert!(m.matches("www.example.com"));
+		assert!(m.matches("a.b.example.com"));
+		assert!(m.matches("*.example.com"));
+		assert!(!m.matches("example.com"));
+		assert!(!m.matches("www.example.com.evil.net"));
+		assert!(!m.matches("wwwexample.com"));
+	}
+
+	#[test]
+	fn matches_anywhere_with_wildcards_at_both_ends() {
+		let m = matcher(&["*paypal*"]);
+
+		assert!(m.matches("paypal.com"));
+		assert!(m.matches("secure-paypal.login.example"));
+		assert!(!m.matches("pay-pal.com"));
+	}
+
+	#[test]
+	fn matches_single_characters() {
+		let m = matcher(&["ex?mple.com"]);
+
+		assert!(m.matches("example.com"));
+		assert!(m.matches("exomple.com"));
+		assert!(!m.matches("exmple.com"));
+	}
+
+	#[test]
+	fn matches_regexes() {
+		let m = matcher(&["/^(www\\.)?example\\.(com|net)$/"]);
+
+		assert!(m.matches("example.com"));
+		assert!(m.matches("www.example.net"));
+		assert!(!m.matches("mail.example.com"));
+	}
+
+	#[test]
+	fn ignores_case_and_trailing_dots() {
+		let m = matcher(&["Example.COM."]);
+
+		assert!(m.matches("example.com"));
+		assert!(m.matches("EXAMPLE.com."));
+	}
+
+	#[test]
+	fn matches_any_pattern() {
+		let m = matcher(&["example.com", "example.net"]);
+
+		assert!(m.matches("example.com"));
+		assert!(m.matches("example.net"));
+		assert!(!m.matches("example.org"));
+	}
+
+	#[test]
+	fn dots_in_globs_are_just_dots() {
+		assert!(!matcher(&["example.com"]).matches("exampleXcom"));
+	}
+
+	#[test]
+	fn filters_entries_by_their_names() {
+		assert!(accepts(matcher(&["www.crt.sh"]), &x509_response()));
+		assert!(!accepts(matcher(&["*.example.com"]), &x509_response()));
+	}
+
+	#[test]
+	fn refuses_bad_regexes() {
+		assert!(DomainMatch::new(["/(/"]).is_err());
+	}
+
+	#[test]
+	fn refuses_no_patterns() {
+		assert!(DomainMatch::new(Vec::<String>::new()).is_err());
+	}
+}
@@ -0,0 +1,52 @@
+//! Keeping only certificates, or only precertificates
+//!
+
+use super::{Candidate, Filter};
+use crate::document::EntryType;
+
+/// Accepts entries of one type
+#[derive(Clone, Copy, Debug)]
+pub struct EntryTypeMatch {
+	entry_type: EntryType,
+}
+
+impl EntryTypeMatch {
+	#[must_use]
+	pub fn new(entry_type: EntryType) -> Self {
+		Self { entry_type }
+	}
+}
+
+impl std::fmt::Display for EntryTypeMatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} entries", self.entry_type)
+	}
+}
+
+impl Filter for EntryTypeMatch {
+	fn accepts(&self, candidate: &Candidate<'_>) -> bool {
+		EntryType::of(candidate.response()).is_ok_and(|t| t == self.entry_type)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::filter::tests::{accepts, precert_response, x509_response};
+
+	#[test]
+	fn keeps_certificates() {
+		let filter = EntryTypeMatch::new(EntryType::X509);
+
+		assert!(accepts(filter, &x509_response()));
+		assert!(!accepts(filter, &precert_response()));
+	}
+
+	#[test]
+	fn keeps_precertificates() {
+		let filter = EntryTypeMatch::new(EntryType::Precert);
+
+		assert!(!accepts(filter, &x509_response()));
+		assert!(accepts(filter, &precert_response()));
+	}
+}
@@ -0,0 +1,149 @@
+//! Keeping only certificates from particular CAs
+//!
+//! Issuers can be picked out by name, or (more reliably, since anyone can call their CA
+//! anything they like) by the hash of their key, which is what CT itself uses to identify the
+//! issuer of a precertificate.
+//!
+
+use std::collections::BTreeSet;
+
+use super::{Candidate, Filter};
+use crate::{error::Error, utils::unhex};
+
+/// Accepts entries whose certificate's issuer DN contains any of a set of strings (ignoring
+/// case), such as `O=Let's Encrypt`
+#[derive(Clone, Debug)]
+pub struct IssuerNameMatch {
+	names: Vec<String>,
+}
+
+impl IssuerNameMatch {
+	#[must_use]
+	pub fn new<I, S>(names: I) -> Self
+	where
+		I: IntoIterator<Item = S>,
+		S: AsRef<str>,
+	{
+		Self {
+			names: names
+				.into_iter()
+				.map(|n| n.as_ref().to_lowercase())
+				.collect(),
+		}
+	}
+}
+
+impl std::fmt::Display for IssuerNameMatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "issuer names containing {}", self.names.join(" or "))
+	}
+}
+
+impl Filter for IssuerNameMatch {
+	fn accepts(&self, candidate: &Candidate<'_>) -> bool {
+		candidate.decoded().issuer.as_ref().is_some_and(|issuer| {
+			let issuer = issuer.to_lowercase();
+			self.names.iter().any(|n| issuer.contains(n))
+		})
+	}
+}
+
+/// Accepts entries issued by any of a set of keys, identified by the SHA-256 hash of their
+/// `SubjectPublicKeyInfo`
+#[derive(Clone, Debug)]
+pub struct IssuerKeyHashMatch {
+	hashes: BTreeSet<Vec<u8>>,
+}
+
+impl IssuerKeyHashMatch {
+	/// Match issuers against the given hex-encoded hashes
+	///
+	/// # Errors
+	///
+	/// If any of the hashes isn't a hex-encoded SHA-256 hash.
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn new<I, S>(hashes: I) -> Result<Self, Error>
+	where
+		I: IntoIterator<Item = S>,
+		S: AsRef<str>,
+	{
+		Ok(Self {
+			hashes: hashes
+				.into_iter()
+				.map(|h| {
+					unhex(h.as_ref())
+						.filter(|hash| hash.len() == 32)
+						.ok_or_else(|| {
+							Error::filter(format!(
+								"{:?} isn't a hex-encoded SHA-256 hash",
+								h.as_ref()
+							))
+						})
+				})
+				.collect::<Result<_, _>>()?,
+		})
+	}
+}
+
+impl std::fmt::Display for IssuerKeyHashMatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} issuer key hash(es)", self.hashes.len())
+	}
+}
+
+impl Filter for IssuerKeyHashMatch {
+	fn accepts(&self, candidate: &Candidate<'_>) -> bool {
+		candidate
+			.issuer_key_hash()
+			.is_some_and(|hash| self.hashes.contains(hash))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::filter::tests::{accepts, precert_response, x509_response};
+
+	const SECTIGO_KEY_HASH: &str =
+		"e1ae9c3de848ece1ba72e0d991ae4d0d9ec547c6bad1dddab9d6beb0a7e0e0d8";
+	const TEST_SSLADMIN_KEY_HASH: &str =
+		"d241053c65bc85d51c270185dafbe25caf36e8497f9b50cb501f3d18c7950db2";
+
+	#[test]
+	fn matches_issuer_names() {
+		assert!(accepts(
+			IssuerNameMatch::new(["o=sectigo limited"]),
+			&x509_response()
+		));
+		assert!(accepts(
+			IssuerNameMatch::new(["Let's Encrypt", "SSLAdmin"]),
+			&precert_response()
+		));
+		assert!(!accepts(
+			IssuerNameMatch::new(["Let's Encrypt"]),
+			&x509_response()
+		));
+	}
+
+	#[test]
+	fn matches_certificate_issuer_keys_from_the_chain() {
+		let filter = IssuerKeyHashMatch::new([SECTIGO_KEY_HASH]).unwrap();
+
+		assert!(accepts(filter.clone(), &x509_response()));
+		assert!(!accepts(filter, &precert_response()));
+	}
+
+	#[test]
+	fn matches_precert_issuer_keys_from_the_entry() {
+		let filter = IssuerKeyHashMatch::new([TEST_SSLADMIN_KEY_HASH]).unwrap();
+
+		assert!(accepts(filter.clone(), &precert_response()));
+		assert!(!accepts(filter, &x509_response()));
+	}
+
+	#[test]
+	fn refuses_things_that_arent_hashes() {
+		assert!(IssuerKeyHashMatch::new(["e1ae9c3d"]).is_err());
+		assert!(IssuerKeyHashMatch::new(["not even hex"]).is_err());
+	}
+}
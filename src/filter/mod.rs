@@ -0,0 +1,309 @@
+//! Deciding which entries are worth keeping
+//!
+//! Most scrapes want every entry in the log, but plenty of jobs only care about a sliver of
+//! it.  Rather than writing out everything and throwing most of it away afterwards, a run can
+//! be given [`Filter`]s, and only the entries that every filter accepts are passed on to the
+//! processor.
+//!
+//! Filters are run by the fetchers, as each entry comes in, so that the work of picking
+//! entries apart is spread across all the fetcher threads, rather than piling up on the one
+//! thread that's writing the output.
+//!
+
+use ct_structs::v1::response::ResponseEntry;
+#[cfg(feature = "decode")]
+use ct_structs::v1::ExtraData;
+#[cfg(feature = "decode")]
+use std::cell::OnceCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "decode")]
+use crate::document::Decoded;
+use crate::document::Entry;
+use crate::precert_link::PrecertLinks;
+#[cfg(feature = "decode")]
+use crate::summary::Summary;
+
+#[cfg(feature = "domain-match")]
+mod domain;
+mod entry_type;
+#[cfg(feature = "decode")]
+mod issuer;
+mod timestamp;
+#[cfg(feature = "decode")]
+mod validity;
+
+#[cfg(feature = "domain-match")]
+pub use domain::DomainMatch;
+pub use entry_type::EntryTypeMatch;
+#[cfg(feature = "decode")]
+pub use issuer::{IssuerKeyHashMatch, IssuerNameMatch};
+pub use timestamp::TimestampRange;
+#[cfg(feature = "decode")]
+pub use validity::{ValidityField, ValidityRange};
+
+/// Something that decides whether an entry should be kept
+///
+/// The `Display` form says what the filter is looking for, for reporting how many entries it
+/// turned away.
+pub trait Filter: std::fmt::Debug + std::fmt::Display + Send + Sync {
+	/// Whether the entry should be passed on to the processor
+	fn accepts(&self, candidate: &Candidate<'_>) -> bool;
+}
+
+/// An entry that's up for filtering
+///
+/// Anything that's expensive to work out (like decoding the certificate) is only done if a
+/// filter asks for it, and then only once, however many filters ask.
+#[derive(Debug)]
+pub struct Candidate<'a> {
+	response: &'a ResponseEntry,
+	entry: Entry,
+	#[cfg(feature = "decode")]
+	decoded: OnceCell<Decoded>,
+	#[cfg(feature = "decode")]
+	issuer_key_hash: OnceCell<Option<Vec<u8>>>,
+}
+
+impl<'a> Candidate<'a> {
+	pub(crate) fn new(response: &'a ResponseEntry, entry: Entry) -> Self {
+		Self {
+			response,
+			entry,
+			#[cfg(feature = "decode")]
+			decoded: OnceCell::new(),
+			#[cfg(feature = "decode")]
+			issuer_key_hash: OnceCell::new(),
+		}
+	}
+
+	/// The entry as the log sent it
+	#[must_use]
+	pub fn response(&self) -> &ResponseEntry {
+		self.response
+	}
+
+	/// The entry's number, timestamp, certificate, and (for precerts) precertificate data
+	#[must_use]
+	pub fn entry(&self) -> &Entry {
+		&self.entry
+	}
+
+	/// The interesting parts of the entry's certificate
+	#[cfg(feature = "decode")]
+	#[must_use]
+	pub fn decoded(&self) -> &Decoded {
+		self.decoded.get_or_init(|| {
+			crate::decode::decode(self.entry.certificate.as_deref().unwrap_or_default())
+		})
+	}
+
+	/// The SHA-256 hash of the issuer's `SubjectPublicKeyInfo`
+	///
+	/// Precert entries come with this already worked out; for certificate entries, it's taken
+	/// from the first certificate in the submitted chain, so if there isn't one (or it can't
+	/// be parsed), there's no hash.
+	#[cfg(feature = "decode")]
+	#[must_use]
+	pub fn issuer_key_hash(&self) -> Option<&[u8]> {
+		self.issuer_key_hash
+			.get_or_init(|| {
+				if let Some(precert) = &self.entry.precert {
+					Some(precert.issuer_key_hash.clone())
+				} else if let ExtraData::X509ExtraData(extra_data) = &self.response.extra_data {
+					extra_data
+						.certificate_chain
+						.first()
+						.and_then(|issuer| crate::decode::spki_hash(&issuer.certificate))
+				} else {
+					None
+				}
+			})
+			.as_deref()
+	}
+}
+
+/// The filters for a run, and counts of how many entries each of them has turned away
+///
+/// As the filters have already gone to the trouble of picking each entry apart, the run's
+/// [`Summary`] and [`PrecertLinks`] (if it's keeping them) are kept here too, and take note
+/// of the entries that make it through.
+#[derive(Debug, Default)]
+#[allow(clippy::struct_field_names)] // What else would you call a list of filters?
+pub(crate) struct Filters {
+	filters: Vec<(Arc<dyn Filter>, AtomicU64)>,
+	links: Option<Arc<PrecertLinks>>,
+	#[cfg(feature = "decode")]
+	summary: Option<Arc<Summary>>,
+}
+
+impl Filters {
+	pub(crate) fn new(filters: Vec<Arc<dyn Filter>>) -> Self {
+		Self {
+			filters: filters
+				.into_iter()
+				.map(|f| (f, AtomicU64::new(0)))
+				.collect(),
+			links: None,
+			#[cfg(feature = "decode")]
+			summary: None,
+		}
+	}
+
+	/// Link up the precerts and certificates that every filter accepts
+	#[must_use]
+	pub(crate) fn link_precerts(mut self, links: Option<Arc<PrecertLinks>>) -> Self {
+		self.links = links;
+		self
+	}
+
+	/// Count the entries that every filter accepts in the summary
+	#[cfg(feature = "decode")]
+	#[must_use]
+	pub(crate) fn summarise(mut self, summary: Option<Arc<Summary>>) -> Self {
+		self.summary = summary;
+		self
+	}
+
+	/// Whether there's no point looking at entries at all
+	pub(crate) fn is_empty(&self) -> bool {
+		#[cfg(feature = "decode")]
+		if self.summary.is_some() {
+			return false;
+		}
+
+		self.filters.is_empty() && self.links.is_none()
+	}
+
+	/// Whether every filter is happy with the entry
+	///
+	/// The entry is counted against the first filter that turns it away (and the rest of the
+	/// filters don't get a look at it).  Entries that can't be made sense of are let through,
+	/// so that the processor can complain about them in the usual way.
+	pub(crate) fn accepts(&self, entry_number: u64, response: &ResponseEntry) -> bool {
+		let Ok(entry) = Entry::from_response(entry_number, response, false, true) else {
+			return true;
+		};
+		let candidate = Candidate::new(response, entry);
+
+		if let Some((_, count)) = self.filters.iter().find(|(f, _)| !f.accepts(&candidate)) {
+			count.fetch_add(1, Ordering::Relaxed);
+			return false;
+		}
+
+		if let Some(links) = &self.links {
+			links.add(&candidate);
+		}
+		#[cfg(feature = "decode")]
+		if let Some(summary) = &self.summary {
+			summary.add(&candidate);
+		}
+		true
+	}
+
+	/// How many entries have been turned away so far
+	pub(crate) fn filtered(&self) -> u64 {
+		self.filtered_by()
+			.iter()
+			.fold(0, |total, (_, n)| total.saturating_add(*n))
+	}
+
+	/// How many entries each filter has turned away so far
+	pub(crate) fn filtered_by(&self) -> Vec<(String, u64)> {
+		self.filters
+			.iter()
+			.map(|(f, count)| (f.to_string(), count.load(Ordering::Relaxed)))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use base64::{engine::general_purpose::STANDARD as b64, Engine as _};
+
+	/// An entry just as a log would have sent it
+	pub(crate) fn response(leaf_input: &[u8], extra_data: &[u8]) -> ResponseEntry {
+		serde_json::from_value(serde_json::json!({
+			"leaf_input": b64.encode(leaf_input),
+			"extra_data": b64.encode(extra_data),
+		}))
+		.unwrap()
+	}
+
+	pub(crate) fn x509_response() -> ResponseEntry {
+		response(
+			include_bytes!("../bin/scrape_ct_log_tests/x509_leaf_input"),
+			include_bytes!("../bin/scrape_ct_log_tests/x509_extra_data"),
+		)
+	}
+
+	pub(crate) fn precert_response() -> ResponseEntry {
+		response(
+			include_bytes!("../bin/scrape_ct_log_tests/precert_leaf_input"),
+			include_bytes!("../bin/scrape_ct_log_tests/precert_extra_data"),
+		)
+	}
+
+	/// Whether a filter would let an entry through
+	pub(crate) fn accepts(filter: impl Filter + 'static, response: &ResponseEntry) -> bool {
+		Filters::new(vec![Arc::new(filter)]).accepts(0, response)
+	}
+
+	#[derive(Debug)]
+	struct EvenEntries;
+
+	impl Filter for EvenEntries {
+		fn accepts(&self, candidate: &Candidate<'_>) -> bool {
+			candidate.entry().entry_number % 2 == 0
+		}
+	}
+
+	impl std::fmt::Display for EvenEntries {
+		fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+			f.write_str("even entries")
+		}
+	}
+
+	#[derive(Debug)]
+	struct Everything;
+
+	impl Filter for Everything {
+		fn accepts(&self, _candidate: &Candidate<'_>) -> bool {
+			true
+		}
+	}
+
+	impl std::fmt::Display for Everything {
+		fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+			f.write_str("everything")
+		}
+	}
+
+	#[test]
+	fn needs_every_filter_to_accept() {
+		let filters = Filters::new(vec![Arc::new(Everything), Arc::new(EvenEntries)]);
+
+		assert!(filters.accepts(0, &x509_response()));
+		assert!(!filters.accepts(1, &x509_response()));
+		assert!(filters.accepts(2, &x509_response()));
+		assert!(!filters.accepts(3, &x509_response()));
+		assert_eq!(2, filters.filtered());
+		assert_eq!(
+			vec![
+				("everything".to_string(), 0),
+				("even entries".to_string(), 2)
+			],
+			filters.filtered_by()
+		);
+	}
+
+	#[test]
+	fn accepts_everything_without_filters() {
+		let filters = Filters::default();
+
+		assert!(filters.accepts(1, &x509_response()));
+		assert_eq!(0, filters.filtered());
+	}
+}
@@ -0,0 +1,97 @@
+//! Keeping only entries logged at particular times
+//!
+
+use super::{Candidate, Filter};
+use crate::utils::format_time;
+
+/// Accepts entries whose timestamp (when the log says it saw them) is within a range
+///
+/// The range includes its start, but not its end, and either end can be left open.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimestampRange {
+	since: Option<u64>,
+	until: Option<u64>,
+}
+
+impl TimestampRange {
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Only accept entries logged at or after this time (in milliseconds since the epoch)
+	#[must_use]
+	pub fn since(mut self, since: u64) -> Self {
+		self.since = Some(since);
+		self
+	}
+
+	/// Only accept entries logged before this time (in milliseconds since the epoch)
+	#[must_use]
+	pub fn until(mut self, until: u64) -> Self {
+		self.until = Some(until);
+		self
+	}
+}
+
+impl std::fmt::Display for TimestampRange {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str("entries logged")?;
+		if let Some(since) = self.since {
+			write!(f, " since {}", format_time(since))?;
+		}
+		if let Some(until) = self.until {
+			write!(f, " until {}", format_time(until))?;
+		}
+		Ok(())
+	}
+}
+
+impl Filter for TimestampRange {
+	fn accepts(&self, candidate: &Candidate<'_>) -> bool {
+		let timestamp = candidate.entry().timestamp;
+
+		self.since.is_none_or(|since| timestamp >= since)
+			&& self.until.is_none_or(|until| timestamp < until)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::filter::tests::{accepts, x509_response};
+
+	// When the x509 entry was logged
+	const LOGGED_AT: u64 = 1_666_198_004_098;
+
+	#[test]
+	fn matches_entries_logged_within_the_range() {
+		assert!(accepts(TimestampRange::new(), &x509_response()));
+		assert!(accepts(
+			TimestampRange::new().since(LOGGED_AT),
+			&x509_response()
+		));
+		assert!(!accepts(
+			TimestampRange::new().until(LOGGED_AT),
+			&x509_response()
+		));
+		assert!(accepts(
+			TimestampRange::new()
+				.since(LOGGED_AT - 1)
+				.until(LOGGED_AT + 1),
+			&x509_response()
+		));
+		assert!(!accepts(
+			TimestampRange::new().since(LOGGED_AT + 1),
+			&x509_response()
+		));
+	}
+
+	#[test]
+	fn describes_itself() {
+		assert_eq!(
+			"entries logged since 2022-10-19T16:46:44.098Z",
+			TimestampRange::new().since(LOGGED_AT).to_string()
+		);
+	}
+}
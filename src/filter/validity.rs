@@ -0,0 +1,133 @@
+//! Keeping only certificates that are valid at particular times
+//!
+
+use super::{Candidate, Filter};
+use crate::utils::format_time;
+
+/// Which end of a certificate's validity period to look at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidityField {
+	NotBefore,
+	NotAfter,
+}
+
+impl std::fmt::Display for ValidityField {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			ValidityField::NotBefore => "notBefore",
+			ValidityField::NotAfter => "notAfter",
+		})
+	}
+}
+
+/// Accepts entries whose certificate's notBefore (or notAfter) is within a range
+///
+/// The range includes its start, but not its end, and either end can be left open.
+/// Certificates whose validity period couldn't be decoded are never accepted.
+#[derive(Clone, Copy, Debug)]
+pub struct ValidityRange {
+	field: ValidityField,
+	since: Option<u64>,
+	until: Option<u64>,
+}
+
+impl ValidityRange {
+	#[must_use]
+	pub fn new(field: ValidityField) -> Self {
+		Self {
+			field,
+			since: None,
+			until: None,
+		}
+	}
+
+	/// Only accept certificates where the field is at or after this time (in milliseconds
+	/// since the epoch)
+	#[must_use]
+	pub fn since(mut self, since: u64) -> Self {
+		self.since = Some(since);
+		self
+	}
+
+	/// Only accept certificates where the field is before this time (in milliseconds since
+	/// the epoch)
+	#[must_use]
+	pub fn until(mut self, until: u64) -> Self {
+		self.until = Some(until);
+		self
+	}
+}
+
+impl std::fmt::Display for ValidityRange {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.field)?;
+		if let Some(since) = self.since {
+			write!(f, " since {}", format_time(since))?;
+		}
+		if let Some(until) = self.until {
+			write!(f, " until {}", format_time(until))?;
+		}
+		Ok(())
+	}
+}
+
+impl Filter for ValidityRange {
+	fn accepts(&self, candidate: &Candidate<'_>) -> bool {
+		let decoded = candidate.decoded();
+		let value = match self.field {
+			ValidityField::NotBefore => decoded.not_before,
+			ValidityField::NotAfter => decoded.not_after,
+		};
+
+		value.is_some_and(|v| {
+			self.since.is_none_or(|since| v >= since) && self.until.is_none_or(|until| v < until)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::filter::tests::{accepts, x509_response};
+	use crate::parse_time;
+
+	fn time(s: &str) -> u64 {
+		parse_time(s).unwrap()
+	}
+
+	#[test]
+	fn matches_not_before() {
+		// The certificate is valid from 2022-10-19 to 2023-01-17
+		let filter = ValidityRange::new(ValidityField::NotBefore);
+
+		assert!(accepts(filter.since(time("2022-10-01")), &x509_response()));
+		assert!(accepts(filter.since(time("2022-10-19")), &x509_response()));
+		assert!(!accepts(filter.until(time("2022-10-19")), &x509_response()));
+		assert!(accepts(
+			filter.since(time("2022-10-18")).until(time("2022-10-20")),
+			&x509_response()
+		));
+	}
+
+	#[test]
+	fn matches_not_after() {
+		let filter = ValidityRange::new(ValidityField::NotAfter);
+
+		assert!(accepts(filter.until(time("2023-02-01")), &x509_response()));
+		assert!(!accepts(filter.since(time("2023-02-01")), &x509_response()));
+		assert!(accepts(filter, &x509_response()));
+	}
+
+	#[test]
+	fn describes_itself() {
+		let filter = ValidityRange::new(ValidityField::NotAfter)
+			.since(time("2023-01-01"))
+			.until(time("2023-02-01"));
+
+		assert_eq!(
+			"notAfter since 2023-01-01T00:00:00.000Z until 2023-02-01T00:00:00.000Z",
+			filter.to_string()
+		);
+	}
+}
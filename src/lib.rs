@@ -1,17 +1,42 @@
 //! Fast, efficient scraping for Certificate Transparency logs
 //!
 
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "decode")]
+pub mod decode;
+pub mod document;
+#[cfg(feature = "decode")]
+pub mod domain_writer;
 pub mod file_writer;
+pub mod filter;
+pub mod locate;
+pub mod log_list;
+#[cfg(feature = "parquet")]
+pub mod parquet_writer;
+pub mod pem_writer;
+pub mod precert_link;
 pub mod processor;
+pub mod reader;
 pub mod runner;
+#[cfg(feature = "serve")]
+pub mod server;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_writer;
+#[cfg(feature = "decode")]
+pub mod summary;
+pub mod template;
 
 pub(crate) mod fetcher;
 
+mod der;
 mod error;
+mod sth;
+mod tls;
 mod utils;
 
 pub use error::Error;
-pub use utils::fix_url;
+pub use utils::{fix_url, parse_time};
 
 // These deps are used in the binary, not the library
 mod binary_deps {
@@ -43,7 +68,6 @@ mod dev_deps {
 	use lazy_static as _;
 	use predicates as _;
 	use regex as _;
-	use serde as _;
 	use temp_dir as _;
 	use x509_parser as _;
 }
@@ -0,0 +1,256 @@
+//! Finding the entries that were logged around particular times
+//!
+//! Logs don't have any way to look entries up by when they were logged, but an entry's
+//! timestamp goes up (more or less) as its entry number does, so a binary search over the
+//! log, fetching one entry at a time, finds the right spot in a few dozen requests.
+//!
+//! "More or less" is the catch.  Logs only promise to incorporate an entry within their
+//! maximum merge delay of issuing its SCT, so entries can turn up somewhat out of order.  To
+//! make up for that, the search looks for a range that's been widened by a safety margin at
+//! each end, and it's up to the caller to leave out any entries that turn out to have been
+//! logged outside the times they were after.
+//!
+
+use ct_structs::v1::{
+	response::{GetEntries as GetEntriesResponse, GetSth as GetSthResponse},
+	TreeLeafEntry,
+};
+use serde::de::DeserializeOwned;
+use std::cmp::max;
+use std::ops::Range;
+use url::Url;
+
+use crate::{error::Error, fetcher::Retryer, fix_url};
+
+/// How far out of order entries' timestamps are assumed to be, unless told otherwise
+pub const DEFAULT_MARGIN: u64 = 3_600_000;
+
+/// Finds the entries in a log that were logged between two times
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Locator {
+	client: ureq::Agent,
+	sth_url: Url,
+	entries_url: Url,
+	margin: u64,
+}
+
+impl Locator {
+	/// Get ready to search the log at the given URL
+	///
+	/// # Errors
+	///
+	/// If the URLs for the log's endpoints can't be worked out.
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn new(log_url: &Url, user_agent: &str) -> Result<Self, Error> {
+		let log_url = fix_url(log_url.clone());
+
+		Ok(Self {
+			client: ureq::Agent::config_builder()
+				.tls_config(
+					ureq::tls::TlsConfig::builder()
+						.root_certs(ureq::tls::RootCerts::PlatformVerifier)
+						.build(),
+				)
+				.user_agent(user_agent)
+				.http_status_as_error(false)
+				.build()
+				.new_agent(),
+			sth_url: log_url
+				.join("ct/v1/get-sth")
+				.map_err(|e| Error::URLError("STH".to_string(), e))?,
+			entries_url: log_url
+				.join("ct/v1/get-entries")
+				.map_err(|e| Error::URLError("get-entries".to_string(), e))?,
+			margin: DEFAULT_MARGIN,
+		})
+	}
+
+	/// How far (in milliseconds) an entry's timestamp might be out of order with the entries
+	/// around it
+	#[must_use]
+	pub fn margin(mut self, margin: u64) -> Self {
+		self.margin = margin;
+		self
+	}
+
+	/// The range of entries that covers everything logged at or after `since`, and before
+	/// `until` (both in milliseconds since the epoch), plus the safety margin
+	///
+	/// Nothing before entry number `from` is ever included.  Leaving out `since` starts the
+	/// range at `from`, and leaving out `until` ends it at the log's current tree size.
+	///
+	/// # Errors
+	///
+	/// If the log can't be talked to, or sends back entries that make no sense.
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn entry_range(
+		&self,
+		from: u64,
+		since: Option<u64>,
+		until: Option<u64>,
+	) -> Result<Range<u64>, Error> {
+		let tree_size = self.get::<GetSthResponse>(&self.sth_url, None)?.tree_size;
+
+		let start = if let Some(time) = since {
+			first_entry_from(from..tree_size, time.saturating_sub(self.margin), |n| {
+				self.timestamp(n)
+			})?
+		} else {
+			from
+		};
+		let end = if let Some(time) = until {
+			first_entry_from(start..tree_size, time.saturating_add(self.margin), |n| {
+				self.timestamp(n)
+			})?
+		} else {
+			tree_size
+		};
+
+		Ok(start..max(start, end))
+	}
+
+	/// When the given entry was logged
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn timestamp(&self, entry_number: u64) -> Result<u64, Error> {
+		let response = self.get::<GetEntriesResponse>(&self.entries_url, Some(entry_number))?;
+		let Some(entry) = response.entries.first() else {
+			return Err(Error::EntryDecodingError(format!(
+				"log sent no entries when asked for entry {entry_number}"
+			)));
+		};
+
+		if let TreeLeafEntry::TimestampedEntry(ts_entry) = &entry.leaf_input.entry {
+			log::debug!("Entry {entry_number} was logged at {}", ts_entry.timestamp);
+			Ok(ts_entry.timestamp)
+		} else {
+			Err(Error::EntryDecodingError(format!(
+				"leaf_input of entry {entry_number} was not a TimestampedEntry"
+			)))
+		}
+	}
+
+	/// Make a request of the log (for a single entry, if one's given), trying again for as
+	/// long as the log says it's having trouble
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn get<T: DeserializeOwned>(&self, url: &Url, entry_number: Option<u64>) -> Result<T, Error> {
+		let mut retryer = Retryer::new();
+
+		loop {
+			let mut request = self.client.get(url.as_ref());
+			if let Some(n) = entry_number {
+				request = request
+					.query("start", format!("{n}"))
+					.query("end", format!("{n}"));
+			}
+
+			match request.call() {
+				Ok(response)
+					if response.status().as_u16() == 429 || response.status().as_u16() >= 500 =>
+				{
+					log::debug!("Got HTTP {} from {url}", response.status().as_u16());
+					retryer.failure()?;
+				}
+				Ok(response) if !response.status().is_success() => {
+					return Err(Error::system(
+						format!("request to {url} failed"),
+						response.status(),
+					));
+				}
+				Ok(response) => {
+					return serde_json::from_reader(response.into_body().into_reader())
+						.map_err(|e| Error::json_parse(url, e));
+				}
+				Err(ureq::Error::Io(e)) => {
+					log::info!("HTTP I/O error: {e}");
+					retryer.failure()?;
+				}
+				Err(e) => return Err(Error::RequestError(e)),
+			}
+		}
+	}
+}
+
+/// The first entry in the range that was logged at or after `time`, or the end of the range
+/// if there isn't one
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn first_entry_from(
+	entries: Range<u64>,
+	time: u64,
+	mut timestamp: impl FnMut(u64) -> Result<u64, Error>,
+) -> Result<u64, Error> {
+	let (mut low, mut high) = (entries.start, entries.end);
+
+	while low < high {
+		let middle = high
+			.saturating_sub(low)
+			.div_euclid(2)
+			.checked_add(low)
+			.ok_or_else(|| Error::arithmetic("finding the middle of the search range"))?;
+		if timestamp(middle)? < time {
+			low = middle
+				.checked_add(1)
+				.ok_or_else(|| Error::arithmetic("moving up the search range"))?;
+		} else {
+			high = middle;
+		}
+	}
+
+	Ok(low)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn first_entry(timestamps: &[u64], time: u64) -> u64 {
+		first_entry_from(0..timestamps.len() as u64, time, |n| {
+			Ok(timestamps[usize::try_from(n).unwrap()])
+		})
+		.unwrap()
+	}
+
+	#[test]
+	fn finds_the_first_entry_at_or_after_a_time() {
+		let timestamps = [10, 20, 20, 30, 40, 50];
+
+		assert_eq!(0, first_entry(&timestamps, 0));
+		assert_eq!(0, first_entry(&timestamps, 10));
+		assert_eq!(1, first_entry(&timestamps, 15));
+		assert_eq!(1, first_entry(&timestamps, 20));
+		assert_eq!(3, first_entry(&timestamps, 21));
+		assert_eq!(5, first_entry(&timestamps, 50));
+	}
+
+	#[test]
+	fn runs_off_the_end_when_everything_is_earlier() {
+		assert_eq!(3, first_entry(&[1, 2, 3], 4));
+	}
+
+	#[test]
+	fn copes_with_empty_logs() {
+		assert_eq!(0, first_entry(&[], 4));
+	}
+
+	#[test]
+	fn lands_nearby_when_timestamps_are_out_of_order() {
+		// Not much can be promised here, except that it doesn't go off into the weeds
+		let entry = first_entry(&[10, 20, 35, 30, 40, 50], 30);
+
+		assert!((2..=3).contains(&entry));
+	}
+
+	#[test]
+	fn only_looks_within_the_range() {
+		let mut probed = vec![];
+		let entry = first_entry_from(100..200, 0, |n| {
+			probed.push(n);
+			Ok(n)
+		})
+		.unwrap();
+
+		assert_eq!(100, entry);
+		assert!(probed.iter().all(|n| (100..200).contains(n)));
+		assert!(probed.len() <= 8);
+	}
+}
@@ -0,0 +1,386 @@
+//! Finding logs in a CT log list
+//!
+//! Browser vendors publish lists of the logs they know about, in the [v3 log list
+//! format](https://www.gstatic.com/ct/log_list/v3/log_list_schema.json).  Rather than having to
+//! copy-and-paste URLs (and keys) out of those lists, they can be loaded from here, and the
+//! logs of interest picked out with a [`Selector`].
+//!
+
+use base64::{
+	alphabet,
+	engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+	Engine as _,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use url::Url;
+
+use crate::Error;
+
+/// Log lists are "proper" base64, but we'll be lenient about padding in case someone's
+/// hand-crafted a list (or a log ID on the command line)
+const B64: GeneralPurpose = GeneralPurpose::new(
+	&alphabet::STANDARD,
+	GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Where a log is in its lifecycle, according to the log list
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LogState {
+	Pending,
+	Qualified,
+	Usable,
+	Readonly,
+	Retired,
+	Rejected,
+}
+
+impl std::fmt::Display for LogState {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		formatter.write_str(match self {
+			LogState::Pending => "pending",
+			LogState::Qualified => "qualified",
+			LogState::Usable => "usable",
+			LogState::Readonly => "readonly",
+			LogState::Retired => "retired",
+			LogState::Rejected => "rejected",
+		})
+	}
+}
+
+impl TryFrom<&str> for LogState {
+	type Error = String;
+
+	fn try_from(s: &str) -> Result<Self, Self::Error> {
+		match s {
+			"pending" => Ok(Self::Pending),
+			"qualified" => Ok(Self::Qualified),
+			"usable" => Ok(Self::Usable),
+			"readonly" => Ok(Self::Readonly),
+			"retired" => Ok(Self::Retired),
+			"rejected" => Ok(Self::Rejected),
+			_ => Err(format!("unknown log state {s:?}")),
+		}
+	}
+}
+
+/// Everything we know about a single log from the log list
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Log {
+	/// The name of the organisation that runs the log
+	pub operator: String,
+	pub description: String,
+	/// The SHA-256 hash of the log's public key
+	pub log_id: Vec<u8>,
+	/// The DER-encoded `SubjectPublicKeyInfo` of the log's public key
+	pub key: Vec<u8>,
+	pub url: Url,
+	/// Maximum Merge Delay, in seconds
+	pub mmd: u64,
+	/// The log's current state, if the list says what it is
+	pub state: Option<LogState>,
+}
+
+/// A parsed log list
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct LogList {
+	pub logs: Vec<Log>,
+}
+
+/// Criteria for picking logs out of a log list
+///
+/// Each kind of criteria is optional; if any values for a kind are given, a log must match at
+/// least one of them to be selected.  A log must match every kind of criteria that is given.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Selector {
+	log_ids: Vec<Vec<u8>>,
+	descriptions: Vec<String>,
+	operators: Vec<String>,
+	states: Vec<LogState>,
+}
+
+impl Selector {
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Select the log with the given log ID
+	#[must_use]
+	pub fn log_id(mut self, log_id: Vec<u8>) -> Self {
+		self.log_ids.push(log_id);
+		self
+	}
+
+	/// Select logs whose description contains the given string (case-insensitively)
+	#[must_use]
+	pub fn description<S: std::fmt::Display>(mut self, description: S) -> Self {
+		self.descriptions
+			.push(description.to_string().to_lowercase());
+		self
+	}
+
+	/// Select logs run by the operator with the given name (case-insensitively)
+	#[must_use]
+	pub fn operator<S: std::fmt::Display>(mut self, operator: S) -> Self {
+		self.operators.push(operator.to_string().to_lowercase());
+		self
+	}
+
+	/// Select logs in the given state
+	#[must_use]
+	pub fn state(mut self, state: LogState) -> Self {
+		self.states.push(state);
+		self
+	}
+
+	fn matches(&self, log: &Log) -> bool {
+		(self.log_ids.is_empty() || self.log_ids.contains(&log.log_id))
+			&& (self.descriptions.is_empty()
+				|| self
+					.descriptions
+					.iter()
+					.any(|d| log.description.to_lowercase().contains(d)))
+			&& (self.operators.is_empty() || self.operators.contains(&log.operator.to_lowercase()))
+			&& (self.states.is_empty() || log.state.is_some_and(|s| self.states.contains(&s)))
+	}
+}
+
+/// Decode a log ID, in the base64 form that log lists use
+pub fn parse_log_id(s: &str) -> Result<Vec<u8>, String> {
+	B64.decode(s)
+		.map_err(|e| format!("invalid log ID {s:?}: {e}"))
+}
+
+impl LogList {
+	/// Load a log list from either a local file, or an `http`/`https` URL
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn load(source: &str) -> Result<Self, Error> {
+		match Url::parse(source) {
+			Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+				log::debug!("Fetching log list from {url}");
+				let response = ureq::get(url.as_ref())
+					.call()
+					.map_err(|e| Error::log_list(source, e))?;
+				Self::from_reader(response.into_body().into_reader())
+					.map_err(|e| Error::log_list(source, e))
+			}
+			_ => {
+				log::debug!("Reading log list from file {source}");
+				let file = std::fs::File::open(source).map_err(|e| Error::log_list(source, e))?;
+				Self::from_reader(std::io::BufReader::new(file))
+					.map_err(|e| Error::log_list(source, e))
+			}
+		}
+	}
+
+	/// Parse a log list from its JSON representation
+	pub fn from_reader(reader: impl Read) -> Result<Self, String> {
+		let raw: RawLogList = serde_json::from_reader(reader).map_err(|e| e.to_string())?;
+
+		let mut logs = vec![];
+		for operator in raw.operators {
+			for log in operator.logs {
+				logs.push(Log {
+					operator: operator.name.clone(),
+					log_id: parse_log_id(&log.log_id)?,
+					key: B64
+						.decode(&log.key)
+						.map_err(|e| format!("invalid key for log {:?}: {e}", log.description))?,
+					url: log.url,
+					mmd: log.mmd,
+					state: log.state.and_then(|s| {
+						s.into_keys()
+							.find_map(|k| LogState::try_from(k.as_str()).ok())
+					}),
+					description: log.description,
+				});
+			}
+		}
+
+		Ok(Self { logs })
+	}
+
+	/// Find all the logs in the list that match the given criteria
+	#[must_use]
+	pub fn select(&self, selector: &Selector) -> Vec<&Log> {
+		self.logs.iter().filter(|l| selector.matches(l)).collect()
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct RawLogList {
+	operators: Vec<RawOperator>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawOperator {
+	name: String,
+	#[serde(default)]
+	logs: Vec<RawLog>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawLog {
+	#[serde(default)]
+	description: String,
+	log_id: String,
+	key: String,
+	url: Url,
+	#[serde(default)]
+	mmd: u64,
+	// The state is an object with a single key naming the state; we don't care about the
+	// details inside it (timestamps, final tree heads, etc)
+	state: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const LOG_LIST: &str = r#"{
+		"version": "42.0",
+		"log_list_timestamp": "2023-06-01T12:00:00Z",
+		"operators": [
+			{
+				"name": "Example Corp",
+				"email": ["ct@example.com"],
+				"logs": [
+					{
+						"description": "Example 'Alpha' log",
+						"log_id": "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+						"key": "b2hhaSE=",
+						"url": "https://ct.example.com/alpha/",
+						"mmd": 86400,
+						"state": { "usable": { "timestamp": "2023-01-01T00:00:00Z" } }
+					},
+					{
+						"description": "Example 'Beta' log",
+						"log_id": "ICEiIyQlJicoKSorLC0uLzAxMjM0NTY3ODk6Ozw9Pj8=",
+						"key": "d29vdA==",
+						"url": "https://ct.example.com/beta/",
+						"mmd": 86400,
+						"state": { "retired": { "timestamp": "2023-02-01T00:00:00Z" } }
+					}
+				]
+			},
+			{
+				"name": "Other Org",
+				"email": ["ct@example.net"],
+				"logs": [
+					{
+						"description": "Other Alpha",
+						"log_id": "QEFCQ0RFRkdISUpLTE1OT1BRUlNUVVZXWFlaW1xdXl8",
+						"key": "eWF5",
+						"url": "https://ct.example.net/",
+						"mmd": 3600,
+						"state": { "readonly": { "timestamp": "2023-03-01T00:00:00Z", "final_tree_head": { "sha256_root_hash": "", "tree_size": 1 } } }
+					}
+				],
+				"tiled_logs": []
+			}
+		]
+	}"#;
+
+	fn list() -> LogList {
+		LogList::from_reader(LOG_LIST.as_bytes()).unwrap()
+	}
+
+	fn descriptions(logs: &[&Log]) -> Vec<String> {
+		logs.iter().map(|l| l.description.clone()).collect()
+	}
+
+	#[test]
+	fn parses_the_list() {
+		let list = list();
+
+		assert_eq!(3, list.logs.len());
+		let log = &list.logs[0];
+		assert_eq!("Example Corp", log.operator);
+		assert_eq!((0u8..32).collect::<Vec<_>>(), log.log_id);
+		assert_eq!(b"ohai!", &log.key[..]);
+		assert_eq!("https://ct.example.com/alpha/", log.url.as_str());
+		assert_eq!(86400, log.mmd);
+		assert_eq!(Some(LogState::Usable), log.state);
+		assert_eq!(Some(LogState::Readonly), list.logs[2].state);
+	}
+
+	#[test]
+	fn selects_everything_by_default() {
+		assert_eq!(3, list().select(&Selector::new()).len());
+	}
+
+	#[test]
+	fn selects_by_log_id() {
+		let list = list();
+		let selected = list.select(
+			&Selector::new()
+				.log_id(parse_log_id("QEFCQ0RFRkdISUpLTE1OT1BRUlNUVVZXWFlaW1xdXl8=").unwrap()),
+		);
+
+		assert_eq!(vec!["Other Alpha"], descriptions(&selected));
+	}
+
+	#[test]
+	fn selects_by_description() {
+		let list = list();
+
+		assert_eq!(
+			vec!["Example 'Alpha' log", "Other Alpha"],
+			descriptions(&list.select(&Selector::new().description("alpha")))
+		);
+	}
+
+	#[test]
+	fn selects_by_operator() {
+		let list = list();
+
+		assert_eq!(
+			vec!["Example 'Alpha' log", "Example 'Beta' log"],
+			descriptions(&list.select(&Selector::new().operator("example corp")))
+		);
+	}
+
+	#[test]
+	fn selects_by_state() {
+		let list = list();
+
+		assert_eq!(
+			vec!["Example 'Beta' log", "Other Alpha"],
+			descriptions(
+				&list.select(
+					&Selector::new()
+						.state(LogState::Retired)
+						.state(LogState::Readonly)
+				)
+			)
+		);
+	}
+
+	#[test]
+	fn combines_criteria() {
+		let list = list();
+
+		assert_eq!(
+			vec!["Example 'Alpha' log"],
+			descriptions(
+				&list.select(
+					&Selector::new()
+						.description("alpha")
+						.operator("Example Corp")
+						.state(LogState::Usable)
+				)
+			)
+		);
+	}
+
+	#[test]
+	fn rejects_bad_log_ids() {
+		assert!(parse_log_id("not base64!").is_err());
+	}
+}
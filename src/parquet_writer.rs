@@ -0,0 +1,386 @@
+//! Thread that writes the scraped entries out as an Apache Parquet file.
+//!
+//! Entries are gathered up into Arrow record batches, each of which is written out as a row
+//! group of its own.  Everything else that would be in a scrape document (the log URL, the
+//! STH, and so on) goes into the file's key-value metadata instead.
+//!
+
+use arrow_array::{
+	builder::{BinaryBuilder, ListBuilder},
+	ArrayRef, BinaryArray, RecordBatch, StructArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef};
+use base64::{engine::general_purpose::STANDARD_NO_PAD as b64, Engine as _};
+use gen_server::{GenServer, Status::Continue};
+use parquet::{arrow::ArrowWriter, format::KeyValue};
+use serde_json::json;
+use std::{
+	marker::PhantomData,
+	sync::{Arc, Mutex},
+};
+use url::Url;
+
+use crate::{
+	document::{ChainLink, Entry, Sth},
+	processor,
+	utils::current_time,
+	Error,
+};
+
+/// How many entries go into each row group, unless told otherwise
+const DEFAULT_ROW_GROUP_SIZE: usize = 10_000;
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Args<W: std::io::Write + Sync + Send> {
+	writer: W,
+	include_chains: bool,
+	include_precert_data: bool,
+	log_url: Url,
+	log_id: Option<Vec<u8>>,
+	row_group_size: usize,
+
+	_m: PhantomData<W>,
+}
+
+impl<W: std::io::Write + Sync + Send> Args<W> {
+	#[must_use]
+	pub fn new(writer: W, log_url: Url) -> Self {
+		Args {
+			writer,
+			log_url,
+			log_id: None,
+			include_chains: false,
+			include_precert_data: false,
+			row_group_size: DEFAULT_ROW_GROUP_SIZE,
+			_m: PhantomData,
+		}
+	}
+
+	#[must_use]
+	pub fn include_chains(mut self, include_chains: bool) -> Self {
+		self.include_chains = include_chains;
+		self
+	}
+
+	#[must_use]
+	pub fn include_precert_data(mut self, include_precert_data: bool) -> Self {
+		self.include_precert_data = include_precert_data;
+		self
+	}
+
+	#[must_use]
+	pub fn log_id(mut self, log_id: Vec<u8>) -> Self {
+		self.log_id = Some(log_id);
+		self
+	}
+
+	/// Set the number of entries that are buffered up and written out as each row group
+	#[must_use]
+	pub fn row_group_size(mut self, row_group_size: usize) -> Self {
+		self.row_group_size = row_group_size.max(1);
+		self
+	}
+}
+
+pub struct ParquetWriter<W: std::io::Write + Sync + Send> {
+	// ArrowWriter isn't Sync, and the runner wants processors to be; we only ever need to get at
+	// it through a `&mut self`, though, so the lock never actually gets taken
+	writer: Mutex<Option<ArrowWriter<W>>>,
+	schema: SchemaRef,
+	entries: Vec<Entry>,
+	include_chains: bool,
+	include_precert_data: bool,
+	row_group_size: usize,
+}
+
+impl<W: std::io::Write + Sync + Send> std::fmt::Debug for ParquetWriter<W> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		f.debug_struct("ParquetWriter").finish_non_exhaustive()
+	}
+}
+
+/// The columns of the file, which are the same as the fields of an `<entry>`
+///
+/// The chain and precert columns are always there, but are null unless they were asked for
+/// (and, for the precert, unless the entry is actually a precert).
+fn schema() -> Schema {
+	Schema::new(vec![
+		Field::new("entry_number", DataType::UInt64, false),
+		Field::new("timestamp", DataType::UInt64, false),
+		Field::new("certificate", DataType::Binary, false),
+		Field::new(
+			"chain",
+			DataType::List(Arc::new(Field::new_list_field(DataType::Binary, true))),
+			true,
+		),
+		Field::new("precert", DataType::Struct(precert_fields()), true),
+	])
+}
+
+fn precert_fields() -> Fields {
+	Fields::from(vec![
+		Field::new("issuer_key_hash", DataType::Binary, false),
+		Field::new("tbs_certificate", DataType::Binary, false),
+	])
+}
+
+/// Turn a pile of entries into the columns of a record batch
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn record_batch(schema: &SchemaRef, entries: &[Entry]) -> Result<RecordBatch, Error> {
+	let mut chains = ListBuilder::new(BinaryBuilder::new());
+	for entry in entries {
+		if let Some(chain) = &entry.chain {
+			for cert in chain.iter().filter_map(ChainLink::cert) {
+				chains.values().append_value(cert);
+			}
+			chains.append(true);
+		} else {
+			chains.append_null();
+		}
+	}
+
+	// Null precerts still need *something* in the struct's columns
+	let precert_columns: Vec<ArrayRef> = vec![
+		Arc::new(BinaryArray::from_iter_values(entries.iter().map(|e| {
+			e.precert.as_ref().map_or(&[][..], |p| &*p.issuer_key_hash)
+		}))),
+		Arc::new(BinaryArray::from_iter_values(entries.iter().map(|e| {
+			e.precert.as_ref().map_or(&[][..], |p| &*p.tbs_certificate)
+		}))),
+	];
+	let precerts = StructArray::try_new(
+		precert_fields(),
+		precert_columns,
+		Some(entries.iter().map(|e| e.precert.is_some()).collect()),
+	)
+	.map_err(|e| Error::output("parquet precert column", e))?;
+
+	RecordBatch::try_new(
+		Arc::clone(schema),
+		vec![
+			Arc::new(UInt64Array::from_iter_values(
+				entries.iter().map(|e| e.entry_number),
+			)),
+			Arc::new(UInt64Array::from_iter_values(
+				entries.iter().map(|e| e.timestamp),
+			)),
+			Arc::new(BinaryArray::from_iter_values(
+				entries
+					.iter()
+					.map(|e| e.certificate.as_deref().unwrap_or_default()),
+			)),
+			Arc::new(chains.finish()),
+			Arc::new(precerts),
+		],
+	)
+	.map_err(|e| Error::output("parquet record batch", e))
+}
+
+impl<W: std::io::Write + Sync + Send> ParquetWriter<W> {
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn writer(&mut self) -> Result<&mut ArrowWriter<W>, Error> {
+		self.writer
+			.get_mut()
+			.map_err(|e| Error::system("parquet writer lock poisoned", e))?
+			.as_mut()
+			.ok_or_else(|| Error::internal("parquet writer has already been closed"))
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn metadata(&mut self, key: &str, value: String) -> Result<(), Error> {
+		self.writer()?
+			.append_key_value_metadata(KeyValue::new(key.to_string(), value));
+		Ok(())
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn push(&mut self, entry: Entry) -> Result<(), Error> {
+		self.entries.push(entry);
+		if self.entries.len() >= self.row_group_size {
+			self.flush()?;
+		}
+		Ok(())
+	}
+
+	/// Write out whatever entries we've got as a row group of their own
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn flush(&mut self) -> Result<(), Error> {
+		if self.entries.is_empty() {
+			return Ok(());
+		}
+
+		let batch = record_batch(&self.schema, &self.entries)?;
+		self.entries.clear();
+
+		let writer = self.writer()?;
+		writer
+			.write(&batch)
+			.map_err(|e| Error::output("parquet row group", e))?;
+		writer
+			.flush()
+			.map_err(|e| Error::output("parquet row group", e))
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn finish(&mut self) -> Result<(), Error> {
+		self.flush()?;
+		if let Ok(time) = current_time() {
+			self.metadata("scrape_end_timestamp", time.to_string())?;
+		}
+		self.writer
+			.get_mut()
+			.map_err(|e| Error::system("parquet writer lock poisoned", e))?
+			.take()
+			.ok_or_else(|| Error::internal("parquet writer has already been closed"))?
+			.close()
+			.map_err(|e| Error::output("parquet footer", e))
+			.map(|_| ())
+	}
+}
+
+impl<W: std::io::Write + Sync + Send> GenServer for ParquetWriter<W> {
+	type Args = Args<W>;
+	type Error = Error;
+	type Request = processor::Request;
+	type StopReason = ();
+
+	fn init(args: Args<W>) -> Result<Self, Self::Error> {
+		let schema = Arc::new(schema());
+		let writer = ArrowWriter::try_new(args.writer, Arc::clone(&schema), None)
+			.map_err(|e| Error::output("parquet header", e))?;
+
+		let mut pw = Self {
+			writer: Mutex::new(Some(writer)),
+			schema,
+			entries: Vec::with_capacity(args.row_group_size),
+			include_chains: args.include_chains,
+			include_precert_data: args.include_precert_data,
+			row_group_size: args.row_group_size,
+		};
+
+		pw.metadata("log_url", args.log_url.to_string())?;
+		if let Some(log_id) = args.log_id {
+			pw.metadata("log_id", b64.encode(log_id))?;
+		}
+		pw.metadata("scrape_begin_timestamp", current_time()?.to_string())?;
+
+		Ok(pw)
+	}
+
+	fn handle_cast(
+		&mut self,
+		request: Self::Request,
+	) -> Result<gen_server::Status<Self>, Self::Error> {
+		match request {
+			processor::Request::Metadata(sth) => {
+				let sth = Sth::from(&sth);
+				self.metadata(
+					"sth",
+					json!({
+						"tree_size": sth.tree_size,
+						"timestamp": sth.timestamp,
+						"sha256_root_hash": b64.encode(&sth.sha256_root_hash),
+						"tree_head_signature": b64.encode(&sth.tree_head_signature),
+					})
+					.to_string(),
+				)?;
+
+				Ok(Continue)
+			}
+			processor::Request::Entry(id, entry, _) => {
+				self.push(Entry::from_response(
+					id,
+					&entry,
+					self.include_chains,
+					self.include_precert_data,
+				)?)?;
+
+				Ok(Continue)
+			}
+		}
+	}
+
+	fn terminate(&mut self, _reason: Result<(), Error>) {
+		if let Err(e) = self.finish() {
+			log::error!("Failed to finish writing parquet file: {e}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::document::Precert;
+	use arrow_array::{cast::AsArray as _, types::UInt64Type, Array as _};
+	use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+	fn write(entries: Vec<Entry>, row_group_size: usize) -> Vec<u8> {
+		let mut buf = vec![];
+		{
+			let mut pw = ParquetWriter::init(
+				Args::new(&mut buf, Url::parse("https://ct.example.com/").unwrap())
+					.row_group_size(row_group_size),
+			)
+			.unwrap();
+			for entry in entries {
+				pw.push(entry).unwrap();
+			}
+			pw.finish().unwrap();
+		}
+		buf
+	}
+
+	#[test]
+	fn writes_entries_as_row_groups() {
+		let buf = write(
+			vec![
+				Entry::new(0, 10, vec![1, 2, 3]).chain(Some(vec![vec![4], vec![5, 6]])),
+				Entry::new(1, 11, vec![7]).precert(Some(Precert::new(vec![8; 32], vec![9]))),
+				Entry::new(2, 12, vec![10]),
+			],
+			2,
+		);
+
+		let builder = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(buf)).unwrap();
+		assert_eq!(2, builder.metadata().num_row_groups());
+
+		let kv = builder
+			.metadata()
+			.file_metadata()
+			.key_value_metadata()
+			.unwrap()
+			.iter()
+			.map(|kv| (kv.key.clone(), kv.value.clone().unwrap()))
+			.collect::<std::collections::HashMap<_, _>>();
+		assert_eq!("https://ct.example.com/", kv["log_url"]);
+		assert!(kv.contains_key("scrape_begin_timestamp"));
+		assert!(kv.contains_key("scrape_end_timestamp"));
+
+		let mut entry_numbers: Vec<u64> = vec![];
+		let mut certificates = vec![];
+		let mut chains = vec![];
+		let mut tbs_certificates = vec![];
+		for batch in builder.build().unwrap() {
+			let batch = batch.unwrap();
+			entry_numbers.extend(batch.column(0).as_primitive::<UInt64Type>().values());
+			let certs = batch.column(2).as_binary::<i32>();
+			let chain = batch.column(3).as_list::<i32>();
+			let precert = batch.column(4).as_struct();
+			for row in 0..batch.num_rows() {
+				certificates.push(certs.value(row).to_vec());
+				chains.push(chain.is_valid(row).then(|| chain.value(row).len()));
+				tbs_certificates.push(
+					precert
+						.is_valid(row)
+						.then(|| precert.column(1).as_binary::<i32>().value(row).to_vec()),
+				);
+			}
+		}
+
+		assert_eq!(vec![0, 1, 2], entry_numbers);
+		assert_eq!(vec![vec![1, 2, 3], vec![7], vec![10]], certificates);
+		assert_eq!(vec![Some(2), None, None], chains);
+		assert_eq!(vec![None, Some(vec![9]), None], tbs_certificates);
+	}
+}
@@ -0,0 +1,297 @@
+//! Thread that writes each scraped entry out as a PEM file of its own.
+//!
+//! Everything goes under a single directory, which is split up into subdirectories of (by
+//! default) 10,000 entries each, named for the range of entry numbers they hold, so that no
+//! one directory ends up with millions of files in it.  Each entry's certificate goes in
+//! `<entry_number>.pem`, and (if chains are included) its chain in `<entry_number>.chain.pem`.
+//! The log's details and STH go in `metadata.json`, at the top of the directory.
+//!
+
+use base64::{engine::general_purpose::STANDARD as b64, Engine as _};
+use gen_server::{GenServer, Status::Continue};
+use serde_json::json;
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+use url::Url;
+
+use crate::{
+	document::{ChainLink, Entry, Sth},
+	processor,
+	utils::current_time,
+	Error,
+};
+
+/// How many entries go in each subdirectory, unless told otherwise
+const DEFAULT_SHARD_SIZE: u64 = 10_000;
+
+/// How many base64 characters go on each line of a PEM block
+const PEM_LINE_LENGTH: usize = 64;
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Args {
+	dir: PathBuf,
+	include_chains: bool,
+	log_url: Url,
+	log_id: Option<Vec<u8>>,
+	shard_size: u64,
+}
+
+impl Args {
+	#[must_use]
+	pub fn new(dir: impl AsRef<Path>, log_url: Url) -> Self {
+		Args {
+			dir: dir.as_ref().to_path_buf(),
+			log_url,
+			log_id: None,
+			include_chains: false,
+			shard_size: DEFAULT_SHARD_SIZE,
+		}
+	}
+
+	#[must_use]
+	pub fn include_chains(mut self, include_chains: bool) -> Self {
+		self.include_chains = include_chains;
+		self
+	}
+
+	#[must_use]
+	pub fn log_id(mut self, log_id: Vec<u8>) -> Self {
+		self.log_id = Some(log_id);
+		self
+	}
+
+	/// Set the number of entries that go into each subdirectory
+	#[must_use]
+	pub fn shard_size(mut self, shard_size: u64) -> Self {
+		self.shard_size = shard_size.max(1);
+		self
+	}
+}
+
+#[derive(Debug)]
+pub struct PemWriter {
+	dir: PathBuf,
+	include_chains: bool,
+	shard_size: u64,
+	/// Everything that goes in `metadata.json`, so it can be rewritten once the scrape is done
+	metadata: serde_json::Map<String, serde_json::Value>,
+	entry_count: u64,
+}
+
+/// Wrap up some DER as a PEM block
+fn pem(der: &[u8]) -> String {
+	let encoded = b64.encode(der);
+	let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+	// base64 is all ASCII, so chunking the bytes can't split a character
+	for line in encoded.as_bytes().chunks(PEM_LINE_LENGTH) {
+		pem.push_str(&String::from_utf8_lossy(line));
+		pem.push('\n');
+	}
+	pem.push_str("-----END CERTIFICATE-----\n");
+	pem
+}
+
+impl PemWriter {
+	/// The subdirectory that the given entry goes in
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn shard(&self, entry_number: u64) -> Result<PathBuf, Error> {
+		let start = entry_number
+			.checked_div(self.shard_size)
+			.and_then(|n| n.checked_mul(self.shard_size))
+			.ok_or_else(|| Error::arithmetic("shard start"))?;
+		let end = start.saturating_add(self.shard_size.saturating_sub(1));
+
+		Ok(self.dir.join(format!("{start}-{end}")))
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn write_file(path: &Path, contents: &str) -> Result<(), Error> {
+		fs::write(path, contents)
+			.map_err(|e| Error::output("PEM", format!("could not write {}: {e}", path.display())))
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn write_metadata(&self) -> Result<(), Error> {
+		Self::write_file(
+			&self.dir.join("metadata.json"),
+			&serde_json::to_string_pretty(&self.metadata)
+				.map_err(|e| Error::output("PEM metadata", e))?,
+		)
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn entry(&mut self, entry: &Entry) -> Result<(), Error> {
+		let shard = self.shard(entry.entry_number)?;
+		fs::create_dir_all(&shard).map_err(|e| {
+			Error::output(
+				"PEM",
+				format!("could not create directory {}: {e}", shard.display()),
+			)
+		})?;
+
+		if let Some(certificate) = &entry.certificate {
+			Self::write_file(
+				&shard.join(format!("{}.pem", entry.entry_number)),
+				&pem(certificate),
+			)?;
+		}
+		if let Some(chain) = &entry.chain {
+			Self::write_file(
+				&shard.join(format!("{}.chain.pem", entry.entry_number)),
+				&chain
+					.iter()
+					.filter_map(ChainLink::cert)
+					.map(pem)
+					.collect::<String>(),
+			)?;
+		}
+
+		self.entry_count = self
+			.entry_count
+			.checked_add(1)
+			.ok_or_else(|| Error::arithmetic("entry count overflowed"))?;
+
+		Ok(())
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn finish(&mut self) -> Result<(), Error> {
+		if let Ok(time) = current_time() {
+			self.metadata
+				.insert("scrape_end_timestamp".to_string(), json!(time));
+		}
+		self.metadata
+			.insert("entry_count".to_string(), json!(self.entry_count));
+		self.write_metadata()
+	}
+}
+
+impl GenServer for PemWriter {
+	type Args = Args;
+	type Error = Error;
+	type Request = processor::Request;
+	type StopReason = ();
+
+	fn init(args: Args) -> Result<Self, Self::Error> {
+		fs::create_dir_all(&args.dir).map_err(|e| {
+			Error::output(
+				"PEM",
+				format!("could not create directory {}: {e}", args.dir.display()),
+			)
+		})?;
+
+		let mut metadata = serde_json::Map::new();
+		metadata.insert("log_url".to_string(), json!(args.log_url.as_str()));
+		if let Some(log_id) = args.log_id {
+			metadata.insert("log_id".to_string(), json!(b64.encode(log_id)));
+		}
+		metadata.insert("scrape_begin_timestamp".to_string(), json!(current_time()?));
+
+		Ok(Self {
+			dir: args.dir,
+			include_chains: args.include_chains,
+			shard_size: args.shard_size,
+			metadata,
+			entry_count: 0,
+		})
+	}
+
+	fn handle_cast(
+		&mut self,
+		request: Self::Request,
+	) -> Result<gen_server::Status<Self>, Self::Error> {
+		match request {
+			processor::Request::Metadata(sth) => {
+				let sth = Sth::from(&sth);
+				self.metadata.insert(
+					"sth".to_string(),
+					json!({
+						"tree_size": sth.tree_size,
+						"timestamp": sth.timestamp,
+						"sha256_root_hash": b64.encode(&sth.sha256_root_hash),
+						"tree_head_signature": b64.encode(&sth.tree_head_signature),
+					}),
+				);
+				self.write_metadata()?;
+
+				Ok(Continue)
+			}
+			processor::Request::Entry(id, entry, _) => {
+				self.entry(&Entry::from_response(
+					id,
+					&entry,
+					self.include_chains,
+					false,
+				)?)?;
+
+				Ok(Continue)
+			}
+		}
+	}
+
+	fn terminate(&mut self, _reason: Result<(), Error>) {
+		if let Err(e) = self.finish() {
+			log::error!("Failed to write PEM metadata: {e}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wraps_pem_lines() {
+		let pem = pem(&[0u8; 78]);
+		let lines = pem.lines().collect::<Vec<_>>();
+
+		assert_eq!(
+			vec![
+				"-----BEGIN CERTIFICATE-----",
+				"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+				"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+				"-----END CERTIFICATE-----",
+			],
+			lines
+		);
+	}
+
+	#[test]
+	fn writes_entries_into_shards() {
+		let dir = temp_dir::TempDir::new().unwrap();
+		let mut pw = PemWriter::init(
+			Args::new(dir.path(), Url::parse("https://ct.example.com/").unwrap())
+				.include_chains(true)
+				.shard_size(2),
+		)
+		.unwrap();
+
+		pw.entry(&Entry::new(0, 10, vec![1]).chain(Some(vec![vec![2], vec![3]])))
+			.unwrap();
+		pw.entry(&Entry::new(3, 13, vec![4])).unwrap();
+		pw.finish().unwrap();
+
+		assert_eq!(
+			pem(&[1]),
+			fs::read_to_string(dir.child("0-1/0.pem")).unwrap()
+		);
+		assert_eq!(
+			pem(&[2]) + &pem(&[3]),
+			fs::read_to_string(dir.child("0-1/0.chain.pem")).unwrap()
+		);
+		assert_eq!(
+			pem(&[4]),
+			fs::read_to_string(dir.child("2-3/3.pem")).unwrap()
+		);
+		assert!(!dir.child("2-3/3.chain.pem").exists());
+
+		let metadata: serde_json::Value =
+			serde_json::from_str(&fs::read_to_string(dir.child("metadata.json")).unwrap()).unwrap();
+		assert_eq!("https://ct.example.com/", metadata["log_url"]);
+		assert_eq!(2, metadata["entry_count"]);
+		assert!(metadata["scrape_end_timestamp"].is_u64());
+	}
+}
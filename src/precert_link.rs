@@ -0,0 +1,401 @@
+//! Matching precertificates up with the certificates they became
+//!
+//! Most certificates get logged twice: once as a precertificate, so the CA can collect SCTs
+//! to embed in the final certificate, and then again as the final certificate itself.  The
+//! two don't have the same bytes, but once the CT poison extension (which only precerts
+//! have) and the SCT list extension (which only final certificates have) are taken out,
+//! their `TBSCertificate`s should be identical.
+//!
+//! [`PrecertLinks`] keeps track of the normalized `TBSCertificate` of every entry in a
+//! scrape, and at the end, pairs up each precert with the certificate entries that match it
+//! (or reports that there weren't any).
+//!
+//! A precert's `TBSCertificate` is the one the log built for its leaf, rather than the one
+//! in the precertificate itself.  That matters when the precert was signed by a separate
+//! precertificate signing CA: the precertificate names that CA as its issuer (and has its
+//! authority key identifier), but RFC 6962 has the log change both to those of the CA that
+//! will issue the final certificate.  A log that doesn't do that leaves the precert with a
+//! different issuer from its certificate, and it'll be reported as unlinked.
+//!
+
+use ring::digest::{digest, SHA256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::{der, filter::Candidate, utils::hex};
+
+/// The precertificate poison extension (1.3.6.1.4.1.11129.2.4.3), DER-encoded
+const POISON_OID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0xD6, 0x79, 0x02, 0x04, 0x03];
+/// The embedded SCT list extension (1.3.6.1.4.1.11129.2.4.2), DER-encoded
+const SCT_LIST_OID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0xD6, 0x79, 0x02, 0x04, 0x02];
+
+/// Tag for the `[3] EXPLICIT Extensions` field of a `TBSCertificate`
+const EXTENSIONS: u8 = 0xA3;
+
+/// A precert, and the certificate it became (if that was seen in the same scrape)
+///
+/// A precert that matches more than one certificate entry (because the certificate was
+/// logged more than once, say) gets a link for each of them.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub struct Link {
+	pub precert_entry_number: u64,
+	pub certificate_entry_number: Option<u64>,
+	/// The SHA-256 hash of the normalized `TBSCertificate` that the two have in common
+	pub tbs_sha256: Vec<u8>,
+}
+
+/// Written as a tab-separated line: the precert's entry number, the certificate's entry
+/// number (or `-`, if there wasn't one), and the hash of their normalized `TBSCertificate`
+impl std::fmt::Display for Link {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}\t", self.precert_entry_number)?;
+		if let Some(n) = self.certificate_entry_number {
+			write!(f, "{n}")?;
+		} else {
+			f.write_str("-")?;
+		}
+		write!(f, "\t{}", hex(&self.tbs_sha256))
+	}
+}
+
+/// The normalized `TBSCertificate` hashes of the entries in a scrape
+///
+/// Entries can be added from several threads at once.
+#[derive(Debug, Default)]
+pub struct PrecertLinks {
+	seen: Mutex<Seen>,
+}
+
+#[derive(Debug, Default)]
+struct Seen {
+	/// Precert entry numbers, by normalized `TBSCertificate` hash
+	precerts: HashMap<Vec<u8>, Vec<u64>>,
+	/// Certificate entry numbers, by normalized `TBSCertificate` hash
+	certificates: HashMap<Vec<u8>, Vec<u64>>,
+}
+
+impl PrecertLinks {
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Take note of an entry
+	///
+	/// Precerts are identified by their precertificate data, so the candidate has to have
+	/// been made with that included.  Entries whose `TBSCertificate` can't be picked apart
+	/// are left out.
+	pub fn add(&self, candidate: &Candidate<'_>) {
+		let entry = candidate.entry();
+		let normalized = match &entry.precert {
+			Some(precert) => normalized_tbs(&precert.tbs_certificate),
+			None => entry
+				.certificate
+				.as_deref()
+				.ok_or_else(|| "there's no certificate".to_string())
+				.and_then(tbs_of)
+				.and_then(normalized_tbs),
+		};
+		let tbs_sha256 = match normalized {
+			Ok(tbs) => digest(&SHA256, &tbs).as_ref().to_vec(),
+			Err(e) => {
+				log::debug!(
+					"Not linking entry {}, as its TBSCertificate couldn't be parsed: {e}",
+					entry.entry_number
+				);
+				return;
+			}
+		};
+
+		let Ok(mut seen) = self.seen.lock() else {
+			log::error!(
+				"Precert links lock poisoned; entry {} not linked",
+				entry.entry_number
+			);
+			return;
+		};
+		let entries = if entry.precert.is_some() {
+			&mut seen.precerts
+		} else {
+			&mut seen.certificates
+		};
+		entries
+			.entry(tbs_sha256)
+			.or_default()
+			.push(entry.entry_number);
+	}
+
+	/// Every precert seen, with the certificates they match, in entry number order
+	#[must_use]
+	pub fn links(&self) -> Vec<Link> {
+		let Ok(seen) = self.seen.lock() else {
+			log::error!("Precert links lock poisoned; no links to report");
+			return vec![];
+		};
+
+		let mut links = vec![];
+		for (tbs_sha256, precerts) in &seen.precerts {
+			let certificates = seen
+				.certificates
+				.get(tbs_sha256)
+				.map_or(vec![None], |c| c.iter().copied().map(Some).collect());
+			for &precert_entry_number in precerts {
+				links.extend(certificates.iter().map(|&certificate_entry_number| Link {
+					precert_entry_number,
+					certificate_entry_number,
+					tbs_sha256: tbs_sha256.clone(),
+				}));
+			}
+		}
+		links.sort();
+		links
+	}
+}
+
+/// The complete encoding of a certificate's `TBSCertificate`
+fn tbs_of(certificate: &[u8]) -> Result<&[u8], String> {
+	let (cert, _) = der::expect(certificate, der::SEQUENCE)?;
+	let (tbs, tlv, _) = der::parse_encoded(cert.value)?;
+	if tlv.tag == der::SEQUENCE {
+		Ok(tbs)
+	} else {
+		Err(format!(
+			"expected TBSCertificate, found tag {:#04x}",
+			tlv.tag
+		))
+	}
+}
+
+/// Re-encode a `TBSCertificate` without the precert poison and SCT list extensions
+///
+/// If those were the only extensions, the extensions field goes too.
+fn normalized_tbs(tbs: &[u8]) -> Result<Vec<u8>, String> {
+	let (tbs, _) = der::expect(tbs, der::SEQUENCE)?;
+
+	let mut fields = vec![];
+	let mut remaining = tbs.value;
+	while !remaining.is_empty() {
+		let (encoded, field, rest) = der::parse_encoded(remaining)?;
+		if field.tag == EXTENSIONS {
+			let extensions = without_ct_extensions(field.value)?;
+			if !extensions.is_empty() {
+				fields.extend(der::encode(
+					EXTENSIONS,
+					&der::encode(der::SEQUENCE, &extensions),
+				));
+			}
+		} else {
+			fields.extend_from_slice(encoded);
+		}
+		remaining = rest;
+	}
+
+	Ok(der::encode(der::SEQUENCE, &fields))
+}
+
+/// The encoded extensions in an `Extensions` field, other than the ones CT adds
+fn without_ct_extensions(field: &[u8]) -> Result<Vec<u8>, String> {
+	let (list, _) = der::expect(field, der::SEQUENCE)?;
+
+	let mut kept = vec![];
+	let mut remaining = list.value;
+	while !remaining.is_empty() {
+		let (encoded, extension, rest) = der::parse_encoded(remaining)?;
+		if extension.tag != der::SEQUENCE {
+			return Err(format!(
+				"expected Extension, found tag {:#04x}",
+				extension.tag
+			));
+		}
+		let (oid, _) = der::expect(extension.value, der::OID)?;
+		if oid.value != POISON_OID && oid.value != SCT_LIST_OID {
+			kept.extend_from_slice(encoded);
+		}
+		remaining = rest;
+	}
+
+	Ok(kept)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::document::{Entry, Precert};
+	use crate::filter::tests::{precert_response, response, x509_response};
+	use ct_structs::v1::response::ResponseEntry;
+
+	fn linked_precert_response() -> ResponseEntry {
+		response(
+			include_bytes!("bin/scrape_ct_log_tests/linked_precert_leaf_input"),
+			include_bytes!("bin/scrape_ct_log_tests/precert_extra_data"),
+		)
+	}
+
+	fn add(links: &PrecertLinks, entry_number: u64, response: &ResponseEntry) {
+		let entry = Entry::from_response(entry_number, response, false, true).unwrap();
+		links.add(&Candidate::new(response, entry));
+	}
+
+	fn certificate(response: &ResponseEntry) -> Vec<u8> {
+		Entry::from_response(0, response, false, false)
+			.unwrap()
+			.certificate
+			.unwrap()
+	}
+
+	#[test]
+	fn removes_the_sct_list() {
+		let cert = certificate(&x509_response());
+		let tbs = tbs_of(&cert).unwrap();
+
+		let normalized = normalized_tbs(tbs).unwrap();
+
+		assert!(normalized.len() < tbs.len());
+		assert!(!normalized
+			.windows(SCT_LIST_OID.len())
+			.any(|w| w == SCT_LIST_OID));
+		// Removing it again changes nothing
+		assert_eq!(normalized, normalized_tbs(&normalized).unwrap());
+	}
+
+	#[test]
+	fn removes_the_poison() {
+		let precert = certificate(&precert_response());
+		let tbs = tbs_of(&precert).unwrap();
+		assert!(tbs.windows(POISON_OID.len()).any(|w| w == POISON_OID));
+
+		let normalized = normalized_tbs(tbs).unwrap();
+
+		assert!(!normalized
+			.windows(POISON_OID.len())
+			.any(|w| w == POISON_OID));
+		assert_eq!(normalized, normalized_tbs(&normalized).unwrap());
+	}
+
+	#[test]
+	fn matches_what_the_log_did() {
+		let cert = certificate(&x509_response());
+		let Ok(Entry {
+			precert: Some(logged),
+			..
+		}) = Entry::from_response(0, &linked_precert_response(), false, true)
+		else {
+			panic!("precert entry has no precert data");
+		};
+
+		// The log took the poison out of the TBSCertificate it logged, so it's already
+		// normalized; the certificate needs its SCTs taken out to match
+		assert_eq!(
+			logged.tbs_certificate,
+			normalized_tbs(tbs_of(&cert).unwrap()).unwrap()
+		);
+		assert_eq!(
+			logged.tbs_certificate,
+			normalized_tbs(&logged.tbs_certificate).unwrap()
+		);
+	}
+
+	#[test]
+	fn rejects_garbage() {
+		assert!(normalized_tbs(&[0x30, 0x05, 0x01]).is_err());
+		assert!(tbs_of(&[0x04, 0x00]).is_err());
+	}
+
+	#[test]
+	fn links_precerts_to_their_certificates() {
+		let links = PrecertLinks::new();
+		add(&links, 0, &x509_response());
+		add(&links, 1, &linked_precert_response());
+		add(&links, 2, &precert_response());
+		add(&links, 3, &x509_response());
+
+		let found = links.links();
+
+		assert_eq!(3, found.len());
+		assert_eq!(
+			vec![(1, Some(0)), (1, Some(3)), (2, None)],
+			found
+				.iter()
+				.map(|l| (l.precert_entry_number, l.certificate_entry_number))
+				.collect::<Vec<_>>()
+		);
+		assert_eq!(found[0].tbs_sha256, found[1].tbs_sha256);
+		assert_ne!(found[0].tbs_sha256, found[2].tbs_sha256);
+	}
+
+	/// The encoded fields of a `TBSCertificate`
+	fn tbs_fields(tbs: &[u8]) -> Vec<&[u8]> {
+		let (tbs, _) = der::expect(tbs, der::SEQUENCE).unwrap();
+		let mut fields = vec![];
+		let mut remaining = tbs.value;
+		while !remaining.is_empty() {
+			let (encoded, _, rest) = der::parse_encoded(remaining).unwrap();
+			fields.push(encoded);
+			remaining = rest;
+		}
+		fields
+	}
+
+	#[test]
+	fn cant_link_precerts_whose_issuer_the_log_didnt_change() {
+		let response = linked_precert_response();
+		let mut entry = Entry::from_response(1, &response, false, true).unwrap();
+		let logged = entry.precert.take().unwrap();
+
+		// What the log would have logged if it had left a precertificate signing CA as the
+		// issuer, rather than changing it to the CA that issued the certificate
+		let other_precert = certificate(&precert_response());
+		let other_issuer = tbs_fields(tbs_of(&other_precert).unwrap())[3];
+		let mut fields = tbs_fields(&logged.tbs_certificate);
+		assert_ne!(other_issuer, fields[3]);
+		fields[3] = other_issuer;
+		entry.precert = Some(Precert::new(
+			logged.issuer_key_hash,
+			der::encode(der::SEQUENCE, &fields.concat()),
+		));
+
+		let links = PrecertLinks::new();
+		add(&links, 0, &x509_response());
+		links.add(&Candidate::new(&response, entry));
+
+		let found = links.links();
+
+		assert_eq!(1, found.len());
+		assert_eq!(1, found[0].precert_entry_number);
+		assert_eq!(None, found[0].certificate_entry_number);
+	}
+
+	#[test]
+	fn ignores_certificates_without_precerts() {
+		let links = PrecertLinks::new();
+		add(&links, 0, &x509_response());
+
+		assert!(links.links().is_empty());
+	}
+
+	#[test]
+	fn skips_entries_that_dont_parse() {
+		let links = PrecertLinks::new();
+		let response = x509_response();
+		links.add(&Candidate::new(&response, Entry::new(0, 0, vec![1, 2, 3])));
+
+		assert!(links.seen.lock().unwrap().certificates.is_empty());
+	}
+
+	#[test]
+	fn writes_links_as_lines() {
+		let link = Link {
+			precert_entry_number: 42,
+			certificate_entry_number: None,
+			tbs_sha256: vec![0xAB, 0xCD],
+		};
+		assert_eq!("42\t-\tabcd", link.to_string());
+
+		let link = Link {
+			certificate_entry_number: Some(69),
+			..link
+		};
+		assert_eq!("42\t69\tabcd", link.to_string());
+	}
+}
@@ -4,7 +4,27 @@ use ct_structs::v1::response::{GetSth as GetSthResponse, ResponseEntry};
 #[non_exhaustive]
 pub enum Request {
 	Metadata(GetSthResponse),
-	Entry(u64, ResponseEntry),
+	/// An entry, along with the bytes the log sent for it, if the run was configured to keep
+	/// them
+	Entry(u64, ResponseEntry, Option<RawEntry>),
+}
+
+/// An entry's `leaf_input` and `extra_data`, exactly as the log sent them
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct RawEntry {
+	pub leaf_input: Vec<u8>,
+	pub extra_data: Vec<u8>,
+}
+
+impl RawEntry {
+	#[must_use]
+	pub fn new(leaf_input: Vec<u8>, extra_data: Vec<u8>) -> Self {
+		Self {
+			leaf_input,
+			extra_data,
+		}
+	}
 }
 
 pub type Mic = gen_server::Mic<Request, ()>;
@@ -0,0 +1,194 @@
+//! Streaming CBOR documents
+//!
+//! ciborium doesn't let us get at its `Deserializer`, so we can't hand it a seed to visit the
+//! entries one at a time.  Instead, we walk the top-level containers ourselves, and get
+//! ciborium to deserialize each of the values inside them.
+//!
+//! CBOR sequences are much easier, as every record is a value all of its own.
+//!
+
+use ciborium::de::Error as CBORError;
+use ciborium_ll::{Decoder, Header as CBORHeader};
+use serde::de::{value::Error as ValueError, DeserializeOwned, IgnoredAny};
+use std::io::{BufRead, ErrorKind};
+
+use super::{records::Records, Handler, Header, Item};
+use crate::{
+	document::{Bytes, Issuers, Trailer},
+	file_writer::CBOR_SEQ_MAGIC,
+	Error,
+};
+
+/// The "break" stop code, which ends an indefinite-length container
+const BREAK: u8 = 0xFF;
+
+#[allow(clippy::result_large_err)] // Oh shoosh
+pub(super) fn stream<R: BufRead>(
+	mut reader: R,
+	handler: &mut dyn FnMut(Item) -> Result<(), Error>,
+) -> Result<(), Error> {
+	let mut h = Handler::new(handler);
+	let result = documents(&mut reader, &mut h);
+	h.result(result)
+}
+
+/// Either a single document, or an array of them (as written for several logs at once)
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn documents<R: BufRead>(reader: &mut R, h: &mut Handler<'_>) -> Result<(), Error> {
+	let top = pull(reader)?;
+	if let CBORHeader::Map(remaining) = top {
+		return document(reader, h, remaining);
+	}
+	let CBORHeader::Array(mut documents) = top else {
+		return Err(Error::input(
+			"CBOR",
+			format!("expected a map or an array, got {top:?}"),
+		));
+	};
+
+	while more(reader, &mut documents)? {
+		let doc = pull(reader)?;
+		let CBORHeader::Map(remaining) = doc else {
+			return Err(Error::input("CBOR", format!("expected a map, got {doc:?}")));
+		};
+		document(reader, h, remaining)?;
+	}
+
+	Ok(())
+}
+
+/// The rest of a document, once the header of its map has been read
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn document<R: BufRead>(
+	reader: &mut R,
+	h: &mut Handler<'_>,
+	mut remaining: Option<usize>,
+) -> Result<(), Error> {
+	let mut header = Header::default();
+	let mut trailer = Trailer::default();
+
+	while more(reader, &mut remaining)? {
+		let key: String = item(reader)?;
+		match key.as_str() {
+			"log_url" => header.log_url = Some(item(reader)?),
+			"log_id" => header.log_id = Some(item::<_, Bytes>(reader)?.0),
+			"scrape_begin_timestamp" => header.scrape_begin_timestamp = Some(item(reader)?),
+			"sth" => header.sth = Some(item(reader)?),
+			"entries" => {
+				header
+					.send::<ValueError>(h)
+					.map_err(|e| Error::input("CBOR", e))?;
+				entries(reader, h)?;
+			}
+			"scrape_end_timestamp" => trailer.scrape_end_timestamp = Some(item(reader)?),
+			"issuers" => trailer.issuers = Some(item::<_, Issuers>(reader)?.0),
+			_ => {
+				item::<_, IgnoredAny>(reader)?;
+			}
+		}
+	}
+
+	header
+		.send::<ValueError>(h)
+		.map_err(|e| Error::input("CBOR", e))?;
+	h.send::<ValueError>(Item::End(trailer))
+		.map_err(|e| Error::input("CBOR", e))
+}
+
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn entries<R: BufRead>(reader: &mut R, h: &mut Handler<'_>) -> Result<(), Error> {
+	let top = pull(reader)?;
+	let CBORHeader::Array(mut remaining) = top else {
+		return Err(Error::input(
+			"CBOR",
+			format!("expected an array of entries, got {top:?}"),
+		));
+	};
+
+	while more(reader, &mut remaining)? {
+		h.send::<ValueError>(Item::Entry(item(reader)?))
+			.map_err(|e| Error::input("CBOR", e))?;
+	}
+
+	Ok(())
+}
+
+pub(super) struct CborSeq<R: BufRead>(R);
+
+impl<R: BufRead> CborSeq<R> {
+	pub(super) fn new(reader: R) -> Self {
+		Self(reader)
+	}
+}
+
+impl<R: BufRead> Records for CborSeq<R> {
+	const FORMAT: &'static str = "CBOR sequence";
+
+	fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+		let buf = self
+			.0
+			.fill_buf()
+			.map_err(|e| Error::input(Self::FORMAT, e))?;
+		if buf.is_empty() {
+			return Ok(None);
+		}
+		// The magic number is optional, and carries no information besides "this is a CBOR
+		// sequence", which we already know
+		if buf.starts_with(&CBOR_SEQ_MAGIC) {
+			self.0.consume(CBOR_SEQ_MAGIC.len());
+			return self.next();
+		}
+
+		match ciborium::de::from_reader(&mut self.0) {
+			Ok(record) => Ok(Some(record)),
+			// The whole point of a CBOR sequence is that if it gets cut off part way through a
+			// record, everything before that is still usable
+			Err(CBORError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+				log::warn!("CBOR sequence ends part way through a record");
+				Ok(None)
+			}
+			Err(e) => Err(Error::input(Self::FORMAT, e)),
+		}
+	}
+}
+
+/// Read the header of a container
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn pull<R: BufRead>(reader: &mut R) -> Result<CBORHeader, Error> {
+	Decoder::from(reader)
+		.pull()
+		.map_err(|e| Error::input("CBOR", format!("{e:?}")))
+}
+
+/// Read a single (complete) value
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn item<R: BufRead, T: DeserializeOwned>(reader: &mut R) -> Result<T, Error> {
+	ciborium::de::from_reader(reader).map_err(|e| Error::input("CBOR", e))
+}
+
+/// Whether there's another element in the container, either because we haven't yet read as
+/// many as it said it had, or because we haven't hit the break yet
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn more<R: BufRead>(reader: &mut R, remaining: &mut Option<usize>) -> Result<bool, Error> {
+	if let Some(n) = remaining {
+		if *n == 0 {
+			Ok(false)
+		} else {
+			*n = n.saturating_sub(1);
+			Ok(true)
+		}
+	} else {
+		match reader
+			.fill_buf()
+			.map_err(|e| Error::input("CBOR", e))?
+			.first()
+		{
+			Some(&BREAK) => {
+				reader.consume(1);
+				Ok(false)
+			}
+			Some(_) => Ok(true),
+			None => Err(Error::input("CBOR", "unexpected end of input")),
+		}
+	}
+}
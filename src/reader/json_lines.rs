@@ -0,0 +1,58 @@
+//! Streaming JSON Lines documents
+//!
+//! Each record (see [`records`](super::records)) is a JSON value on a line of its own.
+//!
+
+use serde::de::DeserializeOwned;
+use std::io::BufRead;
+
+use super::records::Records;
+use crate::Error;
+
+pub(super) struct JsonLines<R: BufRead> {
+	reader: R,
+	line: String,
+}
+
+impl<R: BufRead> JsonLines<R> {
+	pub(super) fn new(reader: R) -> Self {
+		Self {
+			reader,
+			line: String::new(),
+		}
+	}
+}
+
+impl<R: BufRead> Records for JsonLines<R> {
+	const FORMAT: &'static str = "JSON Lines";
+
+	fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+		loop {
+			self.line.clear();
+			if self
+				.reader
+				.read_line(&mut self.line)
+				.map_err(|e| Error::input(Self::FORMAT, e))?
+				== 0
+			{
+				return Ok(None);
+			}
+
+			// Blank lines are allowed, and are skipped
+			if !self.line.trim().is_empty() {
+				return serde_json::from_str(&self.line)
+					.map(Some)
+					.map_err(|e| Error::input(Self::FORMAT, e));
+			}
+		}
+	}
+}
+
+/// Whether the start of a document looks like a JSON Lines header: an object on a line of its
+/// own, with something else after it
+pub(super) fn is_header(start: &[u8]) -> bool {
+	start.iter().position(|&b| b == b'\n').is_some_and(|nl| {
+		let (line, rest) = start.split_at(nl);
+		line.trim_ascii_end().ends_with(b"}") && !rest.trim_ascii().is_empty()
+	})
+}
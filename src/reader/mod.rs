@@ -0,0 +1,924 @@
+//! Reading back the output of a scrape
+//!
+//! The documents written by a [`FileWriter`](crate::file_writer::FileWriter) can be read back
+//! in, either all at once with [`read`], or an [`Item`] at a time with [`stream`], which
+//! doesn't need to hold the whole (potentially enormous) document in memory.
+//!
+//! A combined document, with the scrapes of several logs in it, can be streamed too: the items
+//! of each log's scrape come one after the other, each starting with its own
+//! [`Item::Metadata`].
+//!
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read};
+use url::Url;
+
+use crate::{
+	document::{Bytes, ChainLink, Entry, Issuers, Metadata, Sth, Trailer},
+	file_writer::OutputFormat,
+	utils::hex,
+	Error,
+};
+
+#[cfg(feature = "cbor")]
+mod cbor;
+mod json_lines;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+mod records;
+
+/// One piece of a scrape document, in the order they appear
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Item {
+	Metadata(Metadata),
+	Entry(Entry),
+	End(Trailer),
+}
+
+/// Everything that was written out for a single scraped log
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Scrape {
+	pub log_url: Option<Url>,
+	pub log_id: Option<Vec<u8>>,
+	pub scrape_begin_timestamp: Option<u64>,
+	pub scrape_end_timestamp: Option<u64>,
+	pub sth: Option<Sth>,
+	pub entries: Vec<Entry>,
+}
+
+/// Guess the format of a document from the first few bytes of it
+///
+/// JSON Lines looks just like JSON until the end of the first line, so to be able to tell them
+/// apart, `start` needs to include (at least) all of the header line.
+#[must_use]
+pub fn detect_format(start: &[u8]) -> Option<OutputFormat> {
+	match start.first()? {
+		b'{' if json_lines::is_header(start) => Some(OutputFormat::JSONLines),
+		b'{' | b'[' | b' ' | b'\t' | b'\r' | b'\n' => Some(OutputFormat::JSON),
+		#[cfg(feature = "cbor")]
+		0xD9 if start.starts_with(&crate::file_writer::CBOR_SEQ_MAGIC) => Some(OutputFormat::CBORSeq),
+		// Maps, and indefinite-length arrays (for combined documents)
+		#[cfg(feature = "cbor")]
+		0x9F..=0xBF => Some(OutputFormat::CBOR),
+		// Header records are small maps
+		#[cfg(feature = "msgpack")]
+		0x80..=0x8F | 0xDE | 0xDF => Some(OutputFormat::MsgPack),
+		_ => None,
+	}
+}
+
+/// Read a scrape document, in the given format, passing each [`Item`] to the `handler` as
+/// soon as it has been read
+///
+/// If the handler returns an error, reading stops, and that error is returned.
+#[allow(clippy::result_large_err)] // Oh shoosh
+pub fn stream<R, F>(reader: R, format: OutputFormat, mut handler: F) -> Result<(), Error>
+where
+	R: Read,
+	F: FnMut(Item) -> Result<(), Error>,
+{
+	let reader = BufReader::new(reader);
+
+	match format {
+		OutputFormat::JSON => {
+			let mut de = serde_json::Deserializer::from_reader(reader);
+			let mut h = Handler::new(&mut handler);
+			let result = DocumentsSeed(&mut h)
+				.deserialize(&mut de)
+				.and_then(|()| de.end());
+			h.result(result.map_err(|e| Error::input("JSON", e)))
+		}
+		OutputFormat::JSONLines => {
+			records::stream(json_lines::JsonLines::new(reader), &mut handler)
+		}
+		#[cfg(feature = "cbor")]
+		OutputFormat::CBOR => cbor::stream(reader, &mut handler),
+		#[cfg(feature = "cbor")]
+		OutputFormat::CBORSeq => records::stream(cbor::CborSeq::new(reader), &mut handler),
+		#[cfg(feature = "msgpack")]
+		OutputFormat::MsgPack => records::stream(msgpack::MsgPack::new(reader), &mut handler),
+	}
+}
+
+/// Read a whole scrape document into memory, in the given format
+///
+/// If the document's chains were written as lists of hashes, they're turned back into the
+/// certificates themselves.  Only a document with a single log's scrape in it can be read this
+/// way.
+#[allow(clippy::result_large_err)] // Oh shoosh
+pub fn read<R: Read>(reader: R, format: OutputFormat) -> Result<Scrape, Error> {
+	let mut metadata = None;
+	let mut entries = vec![];
+	let mut trailer = Trailer::default();
+
+	stream(reader, format, |item| {
+		match item {
+			Item::Metadata(_) if metadata.is_some() => {
+				return Err(Error::input(
+					"scrape",
+					"there are scrapes of more than one log in the document",
+				))
+			}
+			Item::Metadata(m) => metadata = Some(m),
+			Item::Entry(e) => entries.push(e),
+			Item::End(t) => trailer = t,
+		}
+		Ok(())
+	})?;
+
+	let metadata = metadata.ok_or_else(|| Error::input("scrape", "no metadata found"))?;
+
+	let issuers = trailer.issuers.unwrap_or_default();
+	for entry in &mut entries {
+		resolve_chain(entry, &issuers)?;
+	}
+
+	Ok(Scrape {
+		log_url: metadata.log_url,
+		log_id: metadata.log_id,
+		scrape_begin_timestamp: metadata.scrape_begin_timestamp,
+		scrape_end_timestamp: trailer.scrape_end_timestamp,
+		sth: metadata.sth,
+		entries,
+	})
+}
+
+/// Swap any hashes in the entry's chain for the certificates they refer to, from the
+/// document's `issuers`
+#[allow(clippy::result_large_err)] // Oh shoosh
+pub(crate) fn resolve_chain(
+	entry: &mut Entry,
+	issuers: &BTreeMap<Vec<u8>, Vec<u8>>,
+) -> Result<(), Error> {
+	for link in entry.chain.iter_mut().flatten() {
+		if let ChainLink::Hash(hash) = link {
+			*link = ChainLink::Cert(issuers.get(&hash[..]).cloned().ok_or_else(|| {
+				Error::input(
+					"scrape",
+					format!(
+						"entry {} refers to an issuer ({}) that isn't in the document",
+						entry.entry_number,
+						hex(hash)
+					),
+				)
+			})?);
+		}
+	}
+	Ok(())
+}
+
+/// Passes items to the caller's handler, keeping hold of any error it returns so that it can
+/// be handed back once the deserializer has finished unwinding
+struct Handler<'a> {
+	f: &'a mut dyn FnMut(Item) -> Result<(), Error>,
+	error: Option<Error>,
+}
+
+impl<'a> Handler<'a> {
+	fn new(f: &'a mut dyn FnMut(Item) -> Result<(), Error>) -> Self {
+		Self { f, error: None }
+	}
+
+	fn send<E: de::Error>(&mut self, item: Item) -> Result<(), E> {
+		(self.f)(item).map_err(|e| {
+			let msg = e.to_string();
+			self.error = Some(e);
+			E::custom(msg)
+		})
+	}
+
+	/// The handler's error, if it had one, otherwise the given result
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn result(self, result: Result<(), Error>) -> Result<(), Error> {
+		self.error.map_or(result, Err)
+	}
+}
+
+/// The top-level keys of a document that come before the entries
+#[derive(Debug, Default)]
+struct Header {
+	log_url: Option<Url>,
+	log_id: Option<Vec<u8>>,
+	scrape_begin_timestamp: Option<u64>,
+	sth: Option<Sth>,
+	sent: bool,
+}
+
+impl Header {
+	/// Pass the metadata to the handler, if it hasn't been already
+	fn send<E: de::Error>(&mut self, handler: &mut Handler<'_>) -> Result<(), E> {
+		if self.sent {
+			return Ok(());
+		}
+		self.sent = true;
+
+		let metadata = Metadata::new(self.log_url.take(), self.sth.take())
+			.log_id(self.log_id.take())
+			.scrape_begin_timestamp(self.scrape_begin_timestamp.take());
+
+		handler.send(Item::Metadata(metadata))
+	}
+}
+
+/// Either a single document, or a sequence of them (as written for several logs at once)
+struct DocumentsSeed<'h, 'a>(&'h mut Handler<'a>);
+
+impl<'de> DeserializeSeed<'de> for DocumentsSeed<'_, '_> {
+	type Value = ();
+
+	fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<(), D::Error> {
+		d.deserialize_any(self)
+	}
+}
+
+impl<'de> Visitor<'de> for DocumentsSeed<'_, '_> {
+	type Value = ();
+
+	fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		formatter.write_str("a scrape document, or a sequence of them")
+	}
+
+	fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<(), A::Error> {
+		DocumentSeed(self.0).visit_map(map)
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+		while seq.next_element_seed(DocumentSeed(self.0))?.is_some() {}
+		Ok(())
+	}
+}
+
+struct DocumentSeed<'h, 'a>(&'h mut Handler<'a>);
+
+impl<'de> DeserializeSeed<'de> for DocumentSeed<'_, '_> {
+	type Value = ();
+
+	fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<(), D::Error> {
+		d.deserialize_map(self)
+	}
+}
+
+impl<'de> Visitor<'de> for DocumentSeed<'_, '_> {
+	type Value = ();
+
+	fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		formatter.write_str("a scrape document")
+	}
+
+	fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+		let mut header = Header::default();
+		let mut trailer = Trailer::default();
+
+		while let Some(key) = map.next_key::<String>()? {
+			match key.as_str() {
+				"log_url" => header.log_url = Some(map.next_value()?),
+				"log_id" => header.log_id = Some(map.next_value::<Bytes>()?.0),
+				"scrape_begin_timestamp" => header.scrape_begin_timestamp = Some(map.next_value()?),
+				"sth" => header.sth = Some(map.next_value()?),
+				"entries" => {
+					header.send(self.0)?;
+					map.next_value_seed(EntriesSeed(self.0))?;
+				}
+				"scrape_end_timestamp" => trailer.scrape_end_timestamp = Some(map.next_value()?),
+				"issuers" => trailer.issuers = Some(map.next_value::<Issuers>()?.0),
+				_ => {
+					map.next_value::<IgnoredAny>()?;
+				}
+			}
+		}
+
+		header.send(self.0)?;
+		self.0.send(Item::End(trailer))
+	}
+}
+
+struct EntriesSeed<'h, 'a>(&'h mut Handler<'a>);
+
+impl<'de> DeserializeSeed<'de> for EntriesSeed<'_, '_> {
+	type Value = ();
+
+	fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<(), D::Error> {
+		d.deserialize_seq(self)
+	}
+}
+
+impl<'de> Visitor<'de> for EntriesSeed<'_, '_> {
+	type Value = ();
+
+	fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		formatter.write_str("a sequence of entries")
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+		while let Some(entry) = seq.next_element::<Entry>()? {
+			self.0.send(Item::Entry(entry))?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		document::{Decoded, EntryType, Precert},
+		file_writer::DocumentWriter,
+		processor::RawEntry,
+	};
+
+	/// A chain of certificates, as it comes out of a document
+	fn chain(certs: &[&[u8]]) -> Option<Vec<ChainLink>> {
+		Some(certs.iter().map(|c| ChainLink::Cert(c.to_vec())).collect())
+	}
+
+	const JSON_SCRAPE: &str = r#"{
+		"log_url": "https://ct.example.com/",
+		"scrape_begin_timestamp": 1000,
+		"sth": {
+			"tree_size": 2,
+			"timestamp": 1234567890,
+			"sha256_root_hash": "AAAA",
+			"tree_head_signature": "b2hhaSE"
+		},
+		"entries": [
+			{ "entry_number": 1, "timestamp": 42, "certificate": "AQID", "chain": ["BAU="] },
+			{
+				"entry_number": 0,
+				"timestamp": 41,
+				"certificate": "d29vdA",
+				"precert": { "issuer_key_hash": "AAAA", "tbs_certificate": "eWF5" }
+			}
+		],
+		"scrape_end_timestamp": 2000
+	}"#;
+
+	#[test]
+	fn reads_json() {
+		let scrape = read(JSON_SCRAPE.as_bytes(), OutputFormat::JSON).unwrap();
+
+		assert_eq!("https://ct.example.com/", scrape.log_url.unwrap().as_str());
+		assert_eq!(None, scrape.log_id);
+		assert_eq!(Some(2000), scrape.scrape_end_timestamp);
+		assert_eq!(2, scrape.sth.as_ref().unwrap().tree_size);
+		assert_eq!(
+			b"ohai!".to_vec(),
+			scrape.sth.as_ref().unwrap().tree_head_signature
+		);
+		assert_eq!(2, scrape.entries.len());
+
+		let entry = &scrape.entries[0];
+		assert_eq!(1, entry.entry_number);
+		assert_eq!(Some(vec![1, 2, 3]), entry.certificate);
+		assert_eq!(chain(&[&[4, 5]]), entry.chain);
+		assert!(entry.precert.is_none());
+
+		let entry = &scrape.entries[1];
+		assert_eq!(None, entry.chain);
+		assert_eq!(
+			b"yay".to_vec(),
+			entry.precert.as_ref().unwrap().tbs_certificate
+		);
+	}
+
+	#[test]
+	fn streams_items_in_order() {
+		let mut items = vec![];
+		stream(JSON_SCRAPE.as_bytes(), OutputFormat::JSON, |item| {
+			items.push(match item {
+				Item::Metadata(m) => format!("metadata {}", m.sth.unwrap().tree_size),
+				Item::Entry(e) => format!("entry {}", e.entry_number),
+				Item::End(t) => format!("end {:?}", t.scrape_end_timestamp),
+			});
+			Ok(())
+		})
+		.unwrap();
+
+		assert_eq!(
+			vec!["metadata 2", "entry 1", "entry 0", "end Some(2000)"],
+			items
+		);
+	}
+
+	#[test]
+	fn stops_when_the_handler_fails() {
+		let mut count = 0;
+		let result = stream(JSON_SCRAPE.as_bytes(), OutputFormat::JSON, |item| {
+			count += 1;
+			if let Item::Entry(_) = item {
+				Err(Error::internal("nope"))
+			} else {
+				Ok(())
+			}
+		});
+
+		assert!(matches!(result, Err(Error::InternalError(_))));
+		assert_eq!(2, count);
+	}
+
+	#[test]
+	fn streams_metadata_even_without_entries() {
+		let doc = r#"{"log_url":"https://ct.example.com/","sth":{"tree_size":0,"timestamp":0,"sha256_root_hash":"","tree_head_signature":""}}"#;
+
+		let scrape = read(doc.as_bytes(), OutputFormat::JSON).unwrap();
+		assert!(scrape.entries.is_empty());
+	}
+
+	fn round_trip(format: OutputFormat) {
+		let mut buf = vec![];
+		{
+			let mut doc = DocumentWriter::new(
+				&mut buf,
+				format,
+				&Url::parse("https://ct.example.com/").unwrap(),
+				Some(b"ohai!"),
+				Some(1000),
+			)
+			.unwrap();
+			doc.sth(
+				&read(JSON_SCRAPE.as_bytes(), OutputFormat::JSON)
+					.unwrap()
+					.sth
+					.unwrap(),
+			)
+			.unwrap();
+			doc.entry(
+				// A chain certificate that's the same size as a hash is still a certificate
+				&Entry::new(0, 1, vec![1, 2, 3])
+					.chain(Some(vec![vec![4], vec![5; 32]]))
+					.precert(Some(Precert::new(vec![0; 32], vec![6])))
+					.raw(Some(RawEntry::new(vec![8, 9], vec![10])))
+					.leaf_hash(Some(vec![11]))
+					.certificate_sha256(Some(vec![12]))
+					.entry_type(Some(EntryType::Precert))
+					.decoded(Some(decoded())),
+			)
+			.unwrap();
+			doc.entry(
+				&Entry::new(1, 2, vec![7]).decoded(Some(Decoded::error("not a certificate"))),
+			)
+			.unwrap();
+			doc.finish(&Trailer::new(Some(2000))).unwrap();
+		}
+
+		let scrape = read(&buf[..], format).unwrap();
+		assert_eq!(Some(b"ohai!".to_vec()), scrape.log_id);
+		assert_eq!(Some(1000), scrape.scrape_begin_timestamp);
+		assert_eq!(Some(2000), scrape.scrape_end_timestamp);
+		assert_eq!(
+			b"ohai!".to_vec(),
+			scrape.sth.as_ref().unwrap().tree_head_signature
+		);
+		assert_eq!(2, scrape.entries.len());
+		assert_eq!(chain(&[&[4], &[5; 32]]), scrape.entries[0].chain);
+		assert_eq!(
+			vec![6],
+			scrape.entries[0].precert.as_ref().unwrap().tbs_certificate
+		);
+		assert_eq!(Some(vec![8, 9]), scrape.entries[0].leaf_input);
+		assert_eq!(Some(vec![10]), scrape.entries[0].extra_data);
+		assert_eq!(Some(vec![11]), scrape.entries[0].leaf_hash);
+		assert_eq!(Some(vec![12]), scrape.entries[0].certificate_sha256);
+		assert_eq!(Some(EntryType::Precert), scrape.entries[0].entry_type);
+		assert_eq!(None, scrape.entries[1].chain);
+		assert_eq!(None, scrape.entries[1].leaf_input);
+		assert_eq!(None, scrape.entries[1].entry_type);
+		assert_eq!(
+			Some(decoded()),
+			scrape.entries[0].decoded.as_deref().cloned()
+		);
+		assert_eq!(
+			Some(Decoded::error("not a certificate")),
+			scrape.entries[1].decoded.as_deref().cloned()
+		);
+	}
+
+	fn decoded() -> Decoded {
+		Decoded {
+			subject: Some("CN=example.com".to_string()),
+			common_name: Some("example.com".to_string()),
+			issuer: Some("CN=Example CA".to_string()),
+			serial: Some(vec![13]),
+			not_before: Some(3000),
+			not_after: Some(4000),
+			dns_names: vec!["example.com".to_string(), "www.example.com".to_string()],
+			ip_addresses: vec!["192.0.2.1".to_string()],
+			key_type: Some("ed25519".to_string()),
+			error: None,
+		}
+	}
+
+	#[test]
+	fn round_trips_json() {
+		round_trip(OutputFormat::JSON);
+	}
+
+	#[cfg(feature = "cbor")]
+	#[test]
+	fn round_trips_cbor() {
+		round_trip(OutputFormat::CBOR);
+	}
+
+	#[cfg(feature = "cbor")]
+	#[test]
+	fn round_trips_cbor_seq() {
+		round_trip(OutputFormat::CBORSeq);
+	}
+
+	/// A document with two entries whose chains share a certificate, deduplicated
+	fn deduped(format: OutputFormat) -> Vec<u8> {
+		let mut buf = vec![];
+		{
+			let mut doc = DocumentWriter::new(
+				&mut buf,
+				format,
+				&Url::parse("https://ct.example.com/").unwrap(),
+				None,
+				Some(1000),
+			)
+			.unwrap()
+			.dedupe_chains(true);
+			doc.sth(
+				&read(JSON_SCRAPE.as_bytes(), OutputFormat::JSON)
+					.unwrap()
+					.sth
+					.unwrap(),
+			)
+			.unwrap();
+			doc.entry(&Entry::new(0, 1, vec![1]).chain(Some(vec![vec![4], vec![5]])))
+				.unwrap();
+			doc.entry(&Entry::new(1, 2, vec![2]).chain(Some(vec![vec![5]])))
+				.unwrap();
+			doc.finish(&Trailer::new(Some(2000))).unwrap();
+		}
+		buf
+	}
+
+	fn dedupe_round_trip(format: OutputFormat) {
+		let buf = deduped(format);
+
+		let mut hashes = vec![];
+		let mut issuers = None;
+		stream(&buf[..], format, |item| {
+			match item {
+				Item::Metadata(_) => (),
+				Item::Entry(e) => hashes.push(e.chain.unwrap()),
+				Item::End(t) => issuers = t.issuers,
+			}
+			Ok(())
+		})
+		.unwrap();
+		assert!(matches!(hashes[0][0], ChainLink::Hash(_)));
+		assert_eq!(hashes[0][1], hashes[1][0]);
+		assert_eq!(2, issuers.unwrap().len());
+
+		let scrape = read(&buf[..], format).unwrap();
+		assert_eq!(Some(2000), scrape.scrape_end_timestamp);
+		assert_eq!(chain(&[&[4], &[5]]), scrape.entries[0].chain);
+		assert_eq!(chain(&[&[5]]), scrape.entries[1].chain);
+	}
+
+	#[test]
+	fn dedupes_chains_in_json() {
+		dedupe_round_trip(OutputFormat::JSON);
+	}
+
+	#[test]
+	fn dedupes_chains_in_json_lines() {
+		dedupe_round_trip(OutputFormat::JSONLines);
+	}
+
+	#[cfg(feature = "cbor")]
+	#[test]
+	fn dedupes_chains_in_cbor() {
+		dedupe_round_trip(OutputFormat::CBOR);
+	}
+
+	#[cfg(feature = "cbor")]
+	#[test]
+	fn dedupes_chains_in_cbor_seq() {
+		dedupe_round_trip(OutputFormat::CBORSeq);
+	}
+
+	#[test]
+	fn deduped_chains_can_be_looked_up_in_the_issuers() {
+		let doc: serde_json::Value = serde_json::from_slice(&deduped(OutputFormat::JSON)).unwrap();
+
+		let chains = doc["entries"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|e| {
+				e["chain"]
+					.as_array()
+					.unwrap()
+					.iter()
+					.map(|link| doc["issuers"][link.as_str().unwrap()].as_str().unwrap())
+					.collect::<Vec<_>>()
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(vec![vec!["BA", "BQ"], vec!["BQ"]], chains);
+	}
+
+	#[test]
+	fn converts_deduped_chains() {
+		let mut buf = vec![];
+		{
+			let mut out = Some(&mut buf);
+			let mut doc = None;
+			stream(
+				&deduped(OutputFormat::JSON)[..],
+				OutputFormat::JSON,
+				|item| {
+					match item {
+						Item::Metadata(m) => {
+							doc = Some(
+								DocumentWriter::from_metadata(
+									out.take().unwrap(),
+									OutputFormat::JSONLines,
+									&m,
+								)
+								.unwrap(),
+							);
+						}
+						Item::Entry(e) => doc.as_mut().unwrap().entry(&e).unwrap(),
+						Item::End(t) => doc.as_mut().unwrap().finish(&t).unwrap(),
+					}
+					Ok(())
+				},
+			)
+			.unwrap();
+		}
+
+		let scrape = read(&buf[..], OutputFormat::JSONLines).unwrap();
+		assert_eq!(chain(&[&[4], &[5]]), scrape.entries[0].chain);
+		assert_eq!(chain(&[&[5]]), scrape.entries[1].chain);
+	}
+
+	/// Two deduplicated documents, put together as they would be for a scrape of two logs
+	fn combined(format: OutputFormat) -> Vec<u8> {
+		let mut buf = vec![];
+		let (one, other) = (deduped(format), deduped(format));
+		crate::file_writer::combine(&mut buf, format, [&one[..], &other[..]]).unwrap();
+		buf
+	}
+
+	/// How many times each sort of item turns up in a streamed document
+	fn count_items(buf: &[u8], format: OutputFormat) -> Result<[usize; 3], Error> {
+		let mut counts = [0; 3];
+		stream(buf, format, |item| {
+			match item {
+				Item::Metadata(_) => counts[0] += 1,
+				Item::Entry(_) => counts[1] += 1,
+				Item::End(t) => {
+					assert_eq!(2, t.issuers.unwrap().len());
+					counts[2] += 1;
+				}
+			}
+			Ok(())
+		})?;
+		Ok(counts)
+	}
+
+	fn streams_combined_documents(format: OutputFormat) {
+		let buf = combined(format);
+
+		assert_eq!([2, 4, 2], count_items(&buf, format).unwrap());
+		let err = read(&buf[..], format).unwrap_err();
+		assert!(err.to_string().contains("more than one log"), "{err}");
+	}
+
+	#[test]
+	fn streams_combined_json_lines() {
+		streams_combined_documents(OutputFormat::JSONLines);
+	}
+
+	#[cfg(feature = "cbor")]
+	#[test]
+	fn streams_combined_cbor_seq() {
+		streams_combined_documents(OutputFormat::CBORSeq);
+	}
+
+	#[test]
+	fn streams_combined_json() {
+		streams_combined_documents(OutputFormat::JSON);
+	}
+
+	#[cfg(feature = "cbor")]
+	#[test]
+	fn streams_combined_cbor() {
+		streams_combined_documents(OutputFormat::CBOR);
+	}
+
+	#[test]
+	fn refuses_entries_after_the_end() {
+		let mut buf = deduped(OutputFormat::JSONLines);
+		buf.extend_from_slice(b"{\"entry_number\":2,\"timestamp\":3,\"certificate\":\"AQ\"}\n");
+
+		let err = count_items(&buf, OutputFormat::JSONLines).unwrap_err();
+		assert!(err.to_string().contains("after the end"), "{err}");
+	}
+
+	#[test]
+	fn refuses_a_missing_issuer() {
+		let json = JSON_SCRAPE
+			.replace(r#""BAU=""#, &format!(r#""{}""#, "00".repeat(32)))
+			.replace(
+				r#""scrape_end_timestamp": 2000"#,
+				r#""issuers": {}, "scrape_end_timestamp": 2000"#,
+			);
+		let err = read(json.as_bytes(), OutputFormat::JSON).unwrap_err();
+		assert!(err.to_string().contains("isn't in the document"), "{err}");
+	}
+
+	#[cfg(feature = "cbor")]
+	#[test]
+	fn reads_truncated_cbor_seq() {
+		let mut buf = vec![];
+		{
+			let mut doc = DocumentWriter::new(
+				&mut buf,
+				OutputFormat::CBORSeq,
+				&Url::parse("https://ct.example.com/").unwrap(),
+				None,
+				None,
+			)
+			.unwrap();
+			doc.sth(
+				&read(JSON_SCRAPE.as_bytes(), OutputFormat::JSON)
+					.unwrap()
+					.sth
+					.unwrap(),
+			)
+			.unwrap();
+			doc.entry(&Entry::new(0, 1, vec![1, 2, 3])).unwrap();
+			doc.entry(&Entry::new(1, 2, vec![4, 5, 6])).unwrap();
+		}
+		assert!(matches!(detect_format(&buf), Some(OutputFormat::CBORSeq)));
+
+		// Lop off the end of the last entry
+		buf.truncate(buf.len() - 3);
+
+		let scrape = read(&buf[..], OutputFormat::CBORSeq).unwrap();
+		assert_eq!(1, scrape.entries.len());
+		assert_eq!(Some(vec![1, 2, 3]), scrape.entries[0].certificate);
+		assert_eq!(None, scrape.scrape_end_timestamp);
+	}
+
+	#[test]
+	fn round_trips_json_lines() {
+		round_trip(OutputFormat::JSONLines);
+	}
+
+	#[test]
+	fn reads_json_lines() {
+		let doc = concat!(
+			r#"{"log_url":"https://ct.example.com/","sth":{"tree_size":2,"timestamp":0,"sha256_root_hash":"","tree_head_signature":""}}"#,
+			"\n",
+			r#"{"entry_number":1,"timestamp":42,"certificate":"AQID"}"#,
+			"\n\n",
+			r#"{"entry_number":0,"timestamp":41,"certificate":"d29vdA"}"#,
+			"\n",
+			r#"{"scrape_end_timestamp":2000,"entry_count":2}"#,
+			"\n",
+		);
+
+		let mut trailer = None;
+		let mut entries = vec![];
+		stream(doc.as_bytes(), OutputFormat::JSONLines, |item| {
+			match item {
+				Item::Entry(e) => entries.push(e.entry_number),
+				Item::End(t) => trailer = Some(t),
+				Item::Metadata(_) => (),
+			}
+			Ok(())
+		})
+		.unwrap();
+
+		assert_eq!(vec![1, 0], entries);
+		let trailer = trailer.unwrap();
+		assert_eq!(Some(2000), trailer.scrape_end_timestamp);
+		assert_eq!(Some(2), trailer.entry_count);
+	}
+
+	#[test]
+	fn refuses_a_broken_json_lines_entry() {
+		let doc = concat!(
+			r#"{"log_url":"https://ct.example.com/","sth":{"tree_size":2,"timestamp":0,"sha256_root_hash":"","tree_head_signature":""}}"#,
+			"\n",
+			r#"{"entry_number":1,"timestamp":42,"certificate":42}"#,
+			"\n",
+		);
+
+		assert!(matches!(
+			read(doc.as_bytes(), OutputFormat::JSONLines),
+			Err(Error::InputError(..))
+		));
+	}
+
+	#[cfg(feature = "msgpack")]
+	#[test]
+	fn round_trips_msgpack() {
+		round_trip(OutputFormat::MsgPack);
+	}
+
+	#[cfg(feature = "msgpack")]
+	#[test]
+	fn reads_truncated_msgpack() {
+		let mut buf = vec![];
+		{
+			let mut doc = DocumentWriter::new(
+				&mut buf,
+				OutputFormat::MsgPack,
+				&Url::parse("https://ct.example.com/").unwrap(),
+				None,
+				None,
+			)
+			.unwrap();
+			doc.sth(
+				&read(JSON_SCRAPE.as_bytes(), OutputFormat::JSON)
+					.unwrap()
+					.sth
+					.unwrap(),
+			)
+			.unwrap();
+			doc.entry(&Entry::new(0, 1, vec![1, 2, 3])).unwrap();
+		}
+
+		let scrape = read(&buf[..], OutputFormat::MsgPack).unwrap();
+		assert_eq!(1, scrape.entries.len());
+		assert_eq!(None, scrape.scrape_end_timestamp);
+	}
+
+	#[test]
+	fn detects_formats() {
+		assert!(matches!(detect_format(b"{"), Some(OutputFormat::JSON)));
+		assert!(matches!(
+			detect_format(b"{\"log_url\":\"x\"}\n"),
+			Some(OutputFormat::JSON)
+		));
+		assert!(matches!(
+			detect_format(b"{\n  \"log_url\": \"x\"\n}\n"),
+			Some(OutputFormat::JSON)
+		));
+		assert!(matches!(
+			detect_format(b"{\"log_url\":\"x\"}\n{\"entry_number\":0"),
+			Some(OutputFormat::JSONLines)
+		));
+		assert!(detect_format(b"x").is_none());
+		assert!(detect_format(b"").is_none());
+	}
+
+	#[test]
+	fn rejects_garbage() {
+		assert!(matches!(
+			read(&b"{\"log_url\": 42}"[..], OutputFormat::JSON),
+			Err(Error::InputError(..))
+		));
+	}
+
+	#[cfg(feature = "cbor")]
+	#[test]
+	fn reads_cbor() {
+		use ciborium_ll::{Encoder, Header};
+
+		// Built the same way the streaming serializer does it, with indefinite-length
+		// containers and real byte strings
+		let mut doc: Vec<u8> = vec![];
+		let mut enc = Encoder::from(&mut doc);
+		enc.push(Header::Map(None)).unwrap();
+		enc.text("log_url", None).unwrap();
+		enc.text("https://ct.example.com/", None).unwrap();
+		enc.text("sth", None).unwrap();
+		enc.push(Header::Map(None)).unwrap();
+		for (k, v) in [("tree_size", 1), ("timestamp", 2)] {
+			enc.text(k, None).unwrap();
+			enc.push(Header::Positive(v)).unwrap();
+		}
+		for k in ["sha256_root_hash", "tree_head_signature"] {
+			enc.text(k, None).unwrap();
+			enc.bytes(&[0, 1], None).unwrap();
+		}
+		enc.push(Header::Break).unwrap();
+		enc.text("entries", None).unwrap();
+		enc.push(Header::Array(None)).unwrap();
+		enc.push(Header::Map(None)).unwrap();
+		for (k, v) in [("entry_number", 0), ("timestamp", 3)] {
+			enc.text(k, None).unwrap();
+			enc.push(Header::Positive(v)).unwrap();
+		}
+		enc.text("certificate", None).unwrap();
+		enc.bytes(&[1, 2, 3], None).unwrap();
+		enc.push(Header::Break).unwrap();
+		enc.push(Header::Break).unwrap();
+		enc.push(Header::Break).unwrap();
+
+		assert!(matches!(detect_format(&doc), Some(OutputFormat::CBOR)));
+		let scrape = read(&doc[..], OutputFormat::CBOR).unwrap();
+		assert_eq!(vec![0, 1], scrape.sth.as_ref().unwrap().sha256_root_hash);
+		assert_eq!(1, scrape.entries.len());
+		assert_eq!(Some(vec![1, 2, 3]), scrape.entries[0].certificate);
+	}
+}
@@ -0,0 +1,40 @@
+//! Streaming msgpack documents
+//!
+//! msgpack has no indefinite-length containers, so documents are written as a stream of
+//! records (see [`records`](super::records)), one msgpack value after another.
+//!
+
+use rmp_serde::{decode::ReadReader, Deserializer};
+use serde::de::DeserializeOwned;
+use std::io::BufRead;
+
+use super::records::Records;
+use crate::Error;
+
+pub(super) struct MsgPack<R: BufRead>(Deserializer<ReadReader<R>>);
+
+impl<R: BufRead> MsgPack<R> {
+	pub(super) fn new(reader: R) -> Self {
+		Self(Deserializer::new(reader))
+	}
+}
+
+impl<R: BufRead> Records for MsgPack<R> {
+	const FORMAT: &'static str = "MessagePack";
+
+	fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+		if self
+			.0
+			.get_mut()
+			.fill_buf()
+			.map_err(|e| Error::input(Self::FORMAT, e))?
+			.is_empty()
+		{
+			return Ok(None);
+		}
+
+		T::deserialize(&mut self.0)
+			.map(Some)
+			.map_err(|e| Error::input(Self::FORMAT, e))
+	}
+}
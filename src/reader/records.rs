@@ -0,0 +1,108 @@
+//! Streaming documents that are written as a sequence of records
+//!
+//! Rather than one big map, these documents are a header record (holding everything that
+//! comes before the entries), one record per entry, and then a trailer record.  If the trailer
+//! is missing, because whatever was writing the document fell over part way through, we still
+//! pass along all the entries we *did* get.
+//!
+//! The records of several logs' documents can also follow one another in the one stream, in
+//! which case the items for each of them are passed along in turn.
+//!
+
+use serde::{de::DeserializeOwned, Deserialize};
+use url::Url;
+
+use super::Item;
+use crate::{
+	document::{Bytes, Entry, Issuers, Metadata, Sth, Trailer},
+	Error,
+};
+
+/// Somewhere to get records from
+pub(super) trait Records {
+	/// What the records are written in, for error messages
+	const FORMAT: &'static str;
+
+	/// The next record, or `None` if there aren't any more
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error>;
+}
+
+#[derive(Debug, Deserialize)]
+struct HeaderRecord {
+	log_url: Option<Url>,
+	log_id: Option<Bytes>,
+	scrape_begin_timestamp: Option<u64>,
+	sth: Option<Sth>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Record {
+	Entry(Entry),
+	Trailer(TrailerRecord),
+}
+
+/// Anything that isn't an entry *must* be a trailer, but we don't want a broken entry to be
+/// mistaken for one, so we're strict about what can be in it
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TrailerRecord {
+	scrape_end_timestamp: Option<u64>,
+	entry_count: Option<u64>,
+	issuers: Option<Issuers>,
+}
+
+/// What can come after a trailer: either nothing at all, or the header of the next document,
+/// in a stream of several logs' documents one after the other
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AfterTrailer {
+	Entry(Entry),
+	Header(HeaderRecord),
+}
+
+#[allow(clippy::result_large_err)] // Oh shoosh
+pub(super) fn stream<R: Records>(
+	mut records: R,
+	handler: &mut dyn FnMut(Item) -> Result<(), Error>,
+) -> Result<(), Error> {
+	let mut header: HeaderRecord = records
+		.next()?
+		.ok_or_else(|| Error::input(R::FORMAT, "no header record found"))?;
+
+	loop {
+		handler(Item::Metadata(
+			Metadata::new(header.log_url, header.sth)
+				.log_id(header.log_id.map(|b| b.0))
+				.scrape_begin_timestamp(header.scrape_begin_timestamp),
+		))?;
+
+		let trailer = loop {
+			match records.next::<Record>()? {
+				Some(Record::Entry(entry)) => handler(Item::Entry(entry))?,
+				Some(Record::Trailer(trailer)) => break trailer,
+				None => return handler(Item::End(Trailer::default())),
+			}
+		};
+		handler(Item::End(
+			Trailer::new(trailer.scrape_end_timestamp)
+				.entry_count(trailer.entry_count)
+				.issuers(trailer.issuers.map(|i| i.0)),
+		))?;
+
+		header = match records.next::<AfterTrailer>()? {
+			Some(AfterTrailer::Header(next)) => next,
+			Some(AfterTrailer::Entry(entry)) => {
+				return Err(Error::input(
+					R::FORMAT,
+					format!(
+						"found entry {} after the end of the document",
+						entry.entry_number
+					),
+				))
+			}
+			None => return Ok(()),
+		};
+	}
+}
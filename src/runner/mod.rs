@@ -9,20 +9,31 @@ use num::integer::div_floor;
 use std::any::type_name;
 use std::cell::RefCell;
 use std::cmp::{max, min};
-use std::sync::mpsc;
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::thread::available_parallelism;
 use std::time::{SystemTime, UNIX_EPOCH};
 use url::Url;
 
+#[cfg(feature = "decode")]
+use crate::summary::Summary;
 use crate::{
 	error::Error,
-	fetcher::{FetchStatus, Fetcher},
-	fix_url, processor,
+	fetcher::{FetchStatus, Fetcher, PageSize},
+	filter::{Filter, Filters},
+	fix_url,
+	precert_link::PrecertLinks,
+	processor, sth,
 };
 
+mod reorderer;
+pub(crate) use reorderer::EntrySink;
+use reorderer::Reorderer;
+
 const MIN_BATCH_SIZE: u64 = 100;
 const MAX_BATCH_SIZE: u64 = 10_000;
 const SUCCESS_STEP: usize = 5;
+const DEFAULT_REORDER_BUFFER: u64 = 50_000;
 
 #[derive(Clone, Debug)]
 pub(crate) struct RunCtl {
@@ -61,6 +72,89 @@ impl RunCtl {
 	}
 }
 
+/// Limits the number of fetchers that may be run against any one host at once
+///
+/// When scraping several logs concurrently, some of those logs may well be run by the same
+/// operator on the same host.  By sharing a single `HostLimiter` between the configs for all
+/// those runs, the total number of fetchers hitting any one host is kept under control.
+#[derive(Clone, Debug)]
+pub struct HostLimiter {
+	limit: usize,
+	counts: Arc<(Mutex<HashMap<String, usize>>, Condvar)>,
+}
+
+impl HostLimiter {
+	#[must_use]
+	pub fn new(limit: usize) -> Self {
+		HostLimiter {
+			limit: max(limit, 1),
+			counts: Arc::new((Mutex::new(HashMap::new()), Condvar::new())),
+		}
+	}
+
+	/// Wait until a fetcher can be started against the given host
+	fn acquire(&self, host: &str) -> Result<HostPermit, Error> {
+		let (counts, cv) = &*self.counts;
+		let mut counts = counts
+			.lock()
+			.map_err(|e| Error::system("host limiter lock poisoned", e))?;
+
+		while counts.get(host).copied().unwrap_or_default() >= self.limit {
+			log::debug!("Waiting for a free fetcher slot for {host}");
+			counts = cv
+				.wait(counts)
+				.map_err(|e| Error::system("host limiter lock poisoned", e))?;
+		}
+
+		self.take(&mut counts, host)
+	}
+
+	/// Start a fetcher against the given host, if the limit allows it
+	fn try_acquire(&self, host: &str) -> Result<Option<HostPermit>, Error> {
+		let (counts, _) = &*self.counts;
+		let mut counts = counts
+			.lock()
+			.map_err(|e| Error::system("host limiter lock poisoned", e))?;
+
+		if counts.get(host).copied().unwrap_or_default() >= self.limit {
+			Ok(None)
+		} else {
+			self.take(&mut counts, host).map(Some)
+		}
+	}
+
+	fn take(&self, counts: &mut HashMap<String, usize>, host: &str) -> Result<HostPermit, Error> {
+		let count = counts.entry(host.to_string()).or_default();
+		*count = count
+			.checked_add(1)
+			.ok_or_else(|| Error::arithmetic("incrementing host fetcher count"))?;
+
+		Ok(HostPermit {
+			limiter: self.clone(),
+			host: host.to_string(),
+		})
+	}
+}
+
+/// A claim on one of a host's fetcher slots, which is given back when dropped
+#[derive(Debug)]
+struct HostPermit {
+	limiter: HostLimiter,
+	host: String,
+}
+
+impl Drop for HostPermit {
+	fn drop(&mut self) {
+		let (counts, cv) = &*self.limiter.counts;
+		if let Ok(mut counts) = counts.lock() {
+			if let Some(count) = counts.get_mut(&self.host) {
+				*count = count.saturating_sub(1);
+			}
+			cv.notify_all();
+		}
+	}
+}
+
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct Config {
@@ -70,6 +164,15 @@ pub struct Config {
 	offset: u64,
 	initial_fetchers: usize,
 	max_fetchers: Option<usize>,
+	public_key: Option<Vec<u8>>,
+	host_limiter: Option<HostLimiter>,
+	ordered: bool,
+	reorder_buffer: u64,
+	raw_entries: bool,
+	filters: Vec<Arc<dyn Filter>>,
+	precert_links: Option<Arc<PrecertLinks>>,
+	#[cfg(feature = "decode")]
+	summary: Option<Arc<Summary>>,
 }
 
 impl Config {
@@ -82,6 +185,15 @@ impl Config {
 			offset: 0,
 			initial_fetchers: 1,
 			max_fetchers: None,
+			public_key: None,
+			host_limiter: None,
+			ordered: false,
+			reorder_buffer: DEFAULT_REORDER_BUFFER,
+			raw_entries: false,
+			filters: vec![],
+			precert_links: None,
+			#[cfg(feature = "decode")]
+			summary: None,
 		}
 	}
 
@@ -114,6 +226,75 @@ impl Config {
 		self.max_fetchers = Some(max_fetchers);
 		self
 	}
+
+	/// Verify the log's STH against the given public key (a DER-encoded `SubjectPublicKeyInfo`)
+	/// before starting the scrape
+	#[must_use]
+	pub fn public_key(mut self, public_key: Vec<u8>) -> Self {
+		self.public_key = Some(public_key);
+		self
+	}
+
+	/// Share fetcher limits with other runs that use the same `HostLimiter`
+	#[must_use]
+	pub fn host_limiter(mut self, host_limiter: HostLimiter) -> Self {
+		self.host_limiter = Some(host_limiter);
+		self
+	}
+
+	/// Send entries to the processor strictly in log order
+	///
+	/// Entries that turn up early are held back until everything before them has been sent,
+	/// and fetchers that get too far ahead are made to wait (see
+	/// [`reorder_buffer`](Self::reorder_buffer)).
+	#[must_use]
+	pub fn ordered(mut self, ordered: bool) -> Self {
+		self.ordered = ordered;
+		self
+	}
+
+	/// How far ahead of the next entry the processor needs that fetchers are allowed to get,
+	/// when entries are being sent in order
+	///
+	/// This limits how many entries are held in memory waiting for their turn.
+	#[must_use]
+	pub fn reorder_buffer(mut self, entries: u64) -> Self {
+		self.reorder_buffer = entries;
+		self
+	}
+
+	/// Send the processor the `leaf_input` and `extra_data` of each entry exactly as the log
+	/// sent them, along with the decoded entry
+	#[must_use]
+	pub fn raw_entries(mut self, raw_entries: bool) -> Self {
+		self.raw_entries = raw_entries;
+		self
+	}
+
+	/// Only send the processor the entries that this filter accepts
+	///
+	/// A run can have any number of filters, and an entry has to be accepted by all of them
+	/// to be kept.
+	#[must_use]
+	pub fn filter(mut self, filter: Arc<dyn Filter>) -> Self {
+		self.filters.push(filter);
+		self
+	}
+
+	/// Link up the precerts and certificates sent to the processor in these links, as well
+	#[must_use]
+	pub fn precert_links(mut self, links: Arc<PrecertLinks>) -> Self {
+		self.precert_links = Some(links);
+		self
+	}
+
+	/// Count up every entry sent to the processor in this summary, as well
+	#[cfg(feature = "decode")]
+	#[must_use]
+	pub fn summary(mut self, summary: Arc<Summary>) -> Self {
+		self.summary = Some(summary);
+		self
+	}
 }
 
 #[derive(Debug, Default)]
@@ -123,6 +304,15 @@ pub struct RunStats {
 	pub sth_retrieved_at: u64,
 	pub sth_timestamp: u64,
 	pub sth_tree_size: u64,
+	/// The maximum number of entries the log returned per get-entries request, if the log was
+	/// seen to truncate any responses
+	pub page_size: Option<u64>,
+	/// How many of the fetched entries weren't sent to the processor, because the run's
+	/// filters turned them away
+	pub filtered_count: u64,
+	/// How many entries each filter turned away, alongside what the filter was looking for,
+	/// in the order the filters were added
+	pub filtered_by: Vec<(String, u64)>,
 }
 
 /// Run a scrape according to the specified configuration, feeding the entries
@@ -158,6 +348,11 @@ where
 		.map_err(|e| Error::json_parse("get-sth response", e))?;
 
 	log::info!("Fetched STH; tree_size={}", sth.tree_size);
+
+	if let Some(key) = &cfg.public_key {
+		sth::verify(&sth, key)?;
+		log::info!("STH signature verified");
+	}
 	let tree_size = sth.tree_size;
 
 	#[allow(clippy::expect_used)] // I'll take the risk
@@ -176,7 +371,10 @@ where
 		.map_err(|e| Error::system(format!("failed to start {}", type_name::<O>()), e))?;
 	o.cast(processor::Request::Metadata(sth));
 
-	stats.fetched_count = if cfg.offset >= tree_size {
+	stats.fetched_count = if cfg.limit == 0 {
+		log::info!("Not fetching any entries because the range to fetch is empty");
+		0
+	} else if cfg.offset >= tree_size {
 		log::warn!("Not fetching any entries because the log's tree_size {tree_size} is less than the requested start position {}", cfg.offset);
 		0
 	} else {
@@ -197,11 +395,46 @@ where
 			.checked_sub(1)
 			.ok_or_else(|| Error::arithmetic("adjusting last_entry"))?;
 
+		let host = log_url.host_str().unwrap_or_default().to_string();
+		let mut permits: Vec<HostPermit> = vec![];
+
 		let mut fetchers: Vec<Fetcher> = vec![];
 		let mut success_count: usize = 0;
 		let mut success_threshold: usize = 0;
 
 		let (status_rx, run_ctl) = RunCtl::new();
+		let page_size = PageSize::default();
+
+		#[allow(clippy::map_err_ignore)] // The error we map provides no useful information
+		let reorderer = if cfg.ordered {
+			Some(Arc::new(Reorderer::for_processor(
+				o.mic()
+					.map_err(|_| Error::internal("output thread has already been stopped"))?,
+				cfg.offset,
+				cfg.reorder_buffer,
+			)))
+		} else {
+			None
+		};
+		let filters = Filters::new(cfg.filters.clone()).link_precerts(cfg.precert_links.clone());
+		#[cfg(feature = "decode")]
+		let filters = filters.summarise(cfg.summary.clone());
+		let filters = Arc::new(filters);
+		#[allow(clippy::map_err_ignore)] // The error we map provides no useful information
+		let sink = || {
+			let sink = if let Some(reorderer) = &reorderer {
+				EntrySink::Ordered(Arc::clone(reorderer))
+			} else {
+				o.mic()
+					.map(EntrySink::Processor)
+					.map_err(|_| Error::internal("output thread has already been stopped"))?
+			};
+			Ok(if filters.is_empty() {
+				sink
+			} else {
+				EntrySink::Filtered(Arc::clone(&filters), Box::new(sink))
+			})
+		};
 
 		let next_entry = RefCell::new(min(last_entry, cfg.offset));
 		let next_batch = || {
@@ -216,29 +449,37 @@ where
 			let batch_size = div_floor(entries_to_fetch, max_fetchers as u64)
 				.clamp(MIN_BATCH_SIZE, MAX_BATCH_SIZE);
 
-			let range = *ne..=min(
-				last_entry,
-				(*ne)
-					.checked_add(batch_size)
-					.ok_or_else(|| Error::arithmetic("calculating next fetch range"))?
-					.checked_sub(1)
-					.ok_or_else(|| Error::arithmetic("adjusting next fetch range"))?,
-			);
-			*ne = (*ne)
-				.checked_add(batch_size)
+			let batch_end = aligned_batch_end(*ne, batch_size, page_size.get())
+				.ok_or_else(|| Error::arithmetic("calculating next fetch range"))?;
+
+			let range = *ne..=min(last_entry, batch_end);
+			*ne = batch_end
+				.checked_add(1)
 				.ok_or_else(|| Error::arithmetic("calculating next_entry"))?;
 			Ok(range)
 		};
 
-		#[allow(clippy::map_err_ignore)] // The error we map provides no useful information
 		for i in 0..max(1, min(cfg.initial_fetchers, max_fetchers)) {
+			if let Some(limiter) = &cfg.host_limiter {
+				// We always need at least one fetcher, so we'll wait for that one, but any
+				// extras are only started if there's room for them
+				if i == 0 {
+					permits.push(limiter.acquire(&host)?);
+				} else if let Some(permit) = limiter.try_acquire(&host)? {
+					permits.push(permit);
+				} else {
+					break;
+				}
+			}
+
 			let fetcher = Fetcher::start(
 				i,
 				log_url.clone(),
 				cfg.user_agent.clone(),
 				run_ctl.clone(),
-				o.mic()
-					.map_err(|_| Error::internal("output thread has already been stopped"))?,
+				sink()?,
+				page_size.clone(),
+				cfg.raw_entries,
 			)?;
 			fetcher.ctl().fetch(next_batch()?)?;
 
@@ -258,21 +499,23 @@ where
 					success_count = success_count
 						.checked_add(1)
 						.ok_or_else(|| Error::arithmetic("incrementing success_count"))?;
-					if success_count > success_threshold && fetchers.len() < max_fetchers {
+					if success_count > success_threshold
+						&& fetchers.len() < max_fetchers
+						&& have_room(cfg, &host, &mut permits)?
+					{
 						log::debug!("Spawning fetch worker {}", fetchers.len());
 						success_count = 0;
 						success_threshold = success_threshold
 							.checked_add(SUCCESS_STEP)
 							.ok_or_else(|| Error::arithmetic("advancing success_threshold"))?;
-						#[allow(clippy::map_err_ignore)] // This error provides no information
 						let new_fetcher = Fetcher::start(
 							fetchers.len(),
 							log_url.clone(),
 							cfg.user_agent.clone(),
 							run_ctl.clone(),
-							o.mic().map_err(|_| {
-								Error::internal("output thread has already been stopped")
-							})?,
+							sink()?,
+							page_size.clone(),
+							cfg.raw_entries,
 						)?;
 						new_fetcher.ctl().fetch(next_batch()?)?;
 						fetchers.push(new_fetcher);
@@ -295,6 +538,13 @@ where
 				log::warn!("Fetcher {i} crashed: {e}");
 			}
 		}
+		if let Some(reorderer) = reorderer {
+			reorderer.finish()?;
+		}
+		drop(permits);
+		stats.page_size = page_size.get();
+		stats.filtered_count = filters.filtered();
+		stats.filtered_by = filters.filtered_by();
 
 		last_entry.saturating_sub(cfg.offset).saturating_add(1)
 	};
@@ -304,3 +554,63 @@ where
 
 	Ok(stats)
 }
+
+/// Figure out where a batch of (approximately) `batch_size` entries starting at `start` should
+/// end
+///
+/// Once we know the log's page size, batches are made a whole number of pages long, and end on
+/// a page boundary, so that the fetchers' requests line up with the pages the log (hopefully)
+/// has cached.
+fn aligned_batch_end(start: u64, batch_size: u64, page_size: Option<u64>) -> Option<u64> {
+	if let Some(page) = page_size {
+		let batch_size = max(batch_size.checked_div(page)?, 1).checked_mul(page)?;
+		start
+			.checked_add(batch_size)?
+			.checked_div(page)?
+			.checked_mul(page)?
+			.checked_sub(1)
+	} else {
+		start.checked_add(batch_size)?.checked_sub(1)
+	}
+}
+
+/// Check whether the host limiter (if any) will allow another fetcher to be started, grabbing
+/// the permit if so
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn have_room(cfg: &Config, host: &str, permits: &mut Vec<HostPermit>) -> Result<bool, Error> {
+	if let Some(limiter) = &cfg.host_limiter {
+		if let Some(permit) = limiter.try_acquire(host)? {
+			permits.push(permit);
+			Ok(true)
+		} else {
+			Ok(false)
+		}
+	} else {
+		Ok(true)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn batches_are_unaligned_without_a_page_size() {
+		assert_eq!(Some(1099), aligned_batch_end(1000, 100, None));
+		assert_eq!(Some(1149), aligned_batch_end(1050, 100, None));
+	}
+
+	#[test]
+	fn batches_end_on_page_boundaries() {
+		assert_eq!(Some(767), aligned_batch_end(0, 1000, Some(256)));
+		assert_eq!(Some(1279), aligned_batch_end(1024, 300, Some(256)));
+		// An unaligned start gets pulled back into line
+		assert_eq!(Some(1279), aligned_batch_end(1050, 300, Some(256)));
+	}
+
+	#[test]
+	fn batches_are_at_least_a_page_long() {
+		assert_eq!(Some(999), aligned_batch_end(0, 100, Some(1000)));
+		assert_eq!(Some(1999), aligned_batch_end(1500, 100, Some(1000)));
+	}
+}
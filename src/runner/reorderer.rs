@@ -0,0 +1,330 @@
+//! Putting entries back in order
+//!
+//! Fetchers work on different ranges of the log at the same time, so the entries they fetch
+//! reach the processor all mixed up.  When the processor needs to see entries in log order,
+//! they go through a [`Reorderer`] instead, which holds on to any entry that turns up early,
+//! and passes entries on to the processor as soon as everything before them has been sent.
+//!
+//! So that a fetcher that's racing ahead can't fill up memory with entries that can't be
+//! sent yet, the reorderer only holds so many entries' worth of the log at a time; a fetcher
+//! with an entry beyond that has to wait until the entries before it have been sent.
+//!
+
+use ct_structs::v1::response::ResponseEntry;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::{
+	error::Error,
+	filter::Filters,
+	processor::{self, RawEntry},
+};
+
+/// Where fetchers send the entries they've fetched
+#[derive(Clone)]
+pub(crate) enum EntrySink {
+	/// Straight to the processor, in whatever order they arrive
+	Processor(processor::Mic),
+	/// Through a reorderer, which passes them on in log order
+	Ordered(Arc<Reorderer<(ResponseEntry, Option<RawEntry>)>>),
+	/// Past the run's filters, and then on to another sink if they let it through
+	Filtered(Arc<Filters>, Box<EntrySink>),
+}
+
+impl std::fmt::Debug for EntrySink {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		match self {
+			EntrySink::Processor(_) => f.write_str("EntrySink::Processor"),
+			EntrySink::Ordered(_) => f.write_str("EntrySink::Ordered"),
+			EntrySink::Filtered(_, next) => write!(f, "EntrySink::Filtered({next:?})"),
+		}
+	}
+}
+
+impl EntrySink {
+	/// Send an entry on its way to the processor
+	///
+	/// If entries are being kept in order, this blocks until the entry is close enough to the
+	/// next one the processor needs.
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub(crate) fn entry(
+		&self,
+		id: u64,
+		entry: ResponseEntry,
+		raw: Option<RawEntry>,
+	) -> Result<(), Error> {
+		match self {
+			EntrySink::Processor(mic) => {
+				mic.cast(processor::Request::Entry(id, entry, raw));
+				Ok(())
+			}
+			EntrySink::Ordered(reorderer) => reorderer.entry(id, (entry, raw)),
+			EntrySink::Filtered(filters, next) => {
+				if filters.accepts(id, &entry) {
+					next.entry(id, entry, raw)
+				} else {
+					next.filtered(id)
+				}
+			}
+		}
+	}
+
+	/// Note that the entries in the range aren't going to turn up, so there's no point waiting
+	/// for them
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub(crate) fn skip(&self, range: RangeInclusive<u64>) -> Result<(), Error> {
+		match self {
+			EntrySink::Processor(_) => Ok(()),
+			EntrySink::Ordered(reorderer) => reorderer.skip(range),
+			EntrySink::Filtered(_, next) => next.skip(range),
+		}
+	}
+
+	/// Note that the entry has been deliberately left out, so nobody should wait for it
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub(crate) fn filtered(&self, id: u64) -> Result<(), Error> {
+		match self {
+			EntrySink::Processor(_) => Ok(()),
+			EntrySink::Ordered(reorderer) => reorderer.leave_out(id..=id),
+			EntrySink::Filtered(_, next) => next.filtered(id),
+		}
+	}
+}
+
+type Deliver<T> = dyn Fn(u64, T) + Send;
+
+struct State<T> {
+	/// Passes an entry on, once its turn has come
+	deliver: Box<Deliver<T>>,
+	/// The entry number that the processor is waiting for
+	next: u64,
+	/// Entries that have turned up before their turn, or `None` if they were skipped
+	pending: BTreeMap<u64, Option<T>>,
+}
+
+/// Passes entries on in log order, however they arrive
+pub(crate) struct Reorderer<T> {
+	capacity: u64,
+	state: Mutex<State<T>>,
+	advanced: Condvar,
+}
+
+impl<T> std::fmt::Debug for Reorderer<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		f.debug_struct("Reorderer")
+			.field("capacity", &self.capacity)
+			.finish_non_exhaustive()
+	}
+}
+
+impl Reorderer<(ResponseEntry, Option<RawEntry>)> {
+	/// Pass entries from `first` onwards on to the processor, holding at most `capacity`
+	/// entries' worth of the log at once
+	pub(crate) fn for_processor(processor: processor::Mic, first: u64, capacity: u64) -> Self {
+		Self::new(
+			move |id, (entry, raw)| processor.cast(processor::Request::Entry(id, entry, raw)),
+			first,
+			capacity,
+		)
+	}
+}
+
+impl<T> Reorderer<T> {
+	fn new(deliver: impl Fn(u64, T) + Send + 'static, first: u64, capacity: u64) -> Self {
+		Self {
+			capacity: capacity.max(1),
+			state: Mutex::new(State {
+				deliver: Box::new(deliver),
+				next: first,
+				pending: BTreeMap::new(),
+			}),
+			advanced: Condvar::new(),
+		}
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn lock(&self) -> Result<std::sync::MutexGuard<'_, State<T>>, Error> {
+		self.state
+			.lock()
+			.map_err(|e| Error::system("reorderer lock poisoned", e))
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn entry(&self, id: u64, entry: T) -> Result<(), Error> {
+		let mut state = self.lock()?;
+
+		while id >= state.next.saturating_add(self.capacity) {
+			log::debug!(
+				"Entry {id} is too far ahead of entry {}; waiting for it to catch up",
+				state.next
+			);
+			state = self
+				.advanced
+				.wait(state)
+				.map_err(|e| Error::system("reorderer lock poisoned", e))?;
+		}
+
+		if id < state.next {
+			log::debug!("Entry {id} has already been passed over; dropping it");
+			return Ok(());
+		}
+
+		state.pending.insert(id, Some(entry));
+		self.flush(&mut state)
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn skip(&self, range: RangeInclusive<u64>) -> Result<(), Error> {
+		let start = (*range.start()).max(self.lock()?.next);
+		if start <= *range.end() {
+			log::warn!(
+				"Entries {start}..={} will be missing from the output",
+				range.end()
+			);
+		}
+
+		self.leave_out(range)
+	}
+
+	/// Stop waiting for the entries in the range, without making a fuss about it
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn leave_out(&self, range: RangeInclusive<u64>) -> Result<(), Error> {
+		let mut state = self.lock()?;
+
+		let start = (*range.start()).max(state.next);
+		for id in start..=*range.end() {
+			state.pending.entry(id).or_insert(None);
+		}
+		self.flush(&mut state)
+	}
+
+	/// Send on everything that's next in line
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn flush(&self, state: &mut State<T>) -> Result<(), Error> {
+		let before = state.next;
+
+		while let Some(entry) = state.pending.remove(&state.next) {
+			if let Some(entry) = entry {
+				(state.deliver)(state.next, entry);
+			}
+			state.next = state
+				.next
+				.checked_add(1)
+				.ok_or_else(|| Error::arithmetic("advancing reorderer"))?;
+		}
+
+		if state.next != before {
+			self.advanced.notify_all();
+		}
+
+		Ok(())
+	}
+
+	/// Send on whatever's left, gaps and all
+	///
+	/// Only to be called once all the fetchers are done, so nothing else is going to turn up.
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub(crate) fn finish(&self) -> Result<(), Error> {
+		let mut state = self.lock()?;
+
+		if let Some(first) = state.pending.keys().next() {
+			log::warn!(
+				"Entries {}..{first} never turned up; passing on later entries regardless",
+				state.next
+			);
+		}
+		for (id, entry) in std::mem::take(&mut state.pending) {
+			if let Some(entry) = entry {
+				(state.deliver)(id, entry);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn reorderer(
+		first: u64,
+		capacity: u64,
+	) -> (Arc<Reorderer<&'static str>>, Arc<Mutex<Vec<u64>>>) {
+		let delivered = Arc::new(Mutex::new(vec![]));
+		let d = Arc::clone(&delivered);
+		(
+			Arc::new(Reorderer::new(
+				move |id, _| d.lock().unwrap().push(id),
+				first,
+				capacity,
+			)),
+			delivered,
+		)
+	}
+
+	#[test]
+	fn delivers_in_order() {
+		let (r, delivered) = reorderer(10, 100);
+
+		r.entry(12, "c").unwrap();
+		r.entry(11, "b").unwrap();
+		assert!(delivered.lock().unwrap().is_empty());
+
+		r.entry(10, "a").unwrap();
+		assert_eq!(vec![10, 11, 12], *delivered.lock().unwrap());
+	}
+
+	#[test]
+	fn skipped_entries_dont_hold_things_up() {
+		let (r, delivered) = reorderer(0, 100);
+
+		r.entry(3, "d").unwrap();
+		r.entry(0, "a").unwrap();
+		r.skip(1..=2).unwrap();
+
+		assert_eq!(vec![0, 3], *delivered.lock().unwrap());
+	}
+
+	#[test]
+	fn left_out_entries_dont_hold_things_up() {
+		let (r, delivered) = reorderer(0, 100);
+
+		r.entry(2, "c").unwrap();
+		r.leave_out(0..=0).unwrap();
+		r.entry(1, "b").unwrap();
+
+		assert_eq!(vec![1, 2], *delivered.lock().unwrap());
+	}
+
+	#[test]
+	fn finish_delivers_whatever_is_left() {
+		let (r, delivered) = reorderer(0, 100);
+
+		r.entry(2, "c").unwrap();
+		r.entry(5, "f").unwrap();
+		r.finish().unwrap();
+
+		assert_eq!(vec![2, 5], *delivered.lock().unwrap());
+	}
+
+	#[test]
+	fn entries_too_far_ahead_wait_their_turn() {
+		let (r, delivered) = reorderer(0, 3);
+
+		let ahead = {
+			let r = Arc::clone(&r);
+			std::thread::spawn(move || r.entry(3, "d").unwrap())
+		};
+		r.entry(2, "c").unwrap();
+		std::thread::sleep(std::time::Duration::from_millis(50));
+		assert!(!ahead.is_finished());
+
+		r.entry(0, "a").unwrap();
+		r.entry(1, "b").unwrap();
+		ahead.join().unwrap();
+
+		assert_eq!(vec![0, 1, 2, 3], *delivered.lock().unwrap());
+	}
+}
@@ -0,0 +1,716 @@
+//! Serving a scrape back out as a (read-only) RFC 6962 log
+//!
+//! Anything that speaks the CT API can be pointed at a [`Server`] to get at the entries in a
+//! previously-scraped document, without bothering the real log.  Only `get-sth` and
+//! `get-entries` are supported, because that's all the data we've got.
+//!
+//! Scrapes done with raw entries included have each entry's `leaf_input` and `extra_data`
+//! served exactly as the log sent them.  Otherwise, they have to be reconstructed, and to do
+//! that faithfully, the scrape needs to have been done with chains and precert data included.
+//! Entries without a chain are served with an empty one, but precert entries without their
+//! precert data can't be served at all.
+//!
+//! A reconstructed `leaf_input` can't have any of the extensions the original might have had,
+//! so if the entry has a leaf hash, the reconstruction is only served if it matches.  Serving
+//! anything else would be serving up a different log.  An entry without a leaf hash can't be
+//! checked, so it's served as reconstructed, with a warning that it might not be exactly what
+//! the log has.
+//!
+//! The scrape is read twice, one entry at a time (first to find the STH and any
+//! deduplicated chain certificates, then to encode the entries), and the encoded entries are
+//! kept in a temporary file rather than in memory, so even enormous scrapes can be served.
+//!
+
+use base64::{engine::general_purpose::STANDARD as b64, Engine as _};
+use ct_structs::v1::response::GetSth as GetSthResponse;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read as _, Seek as _, SeekFrom, Write as _};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tiny_http::{Header, Method, Request, Response};
+use url::Url;
+
+use crate::{
+	document::{Entry, Metadata},
+	file_writer::OutputFormat,
+	reader::{self, Item},
+	tls::{leaf_hash, merkle_tree_leaf, push_chain, push_opaque, LeafEntry},
+	Error,
+};
+
+/// How many entries we hand out per get-entries request, unless told otherwise
+const DEFAULT_PAGE_SIZE: u64 = 1000;
+
+/// The DER encoding of the OID for the CT poison extension (1.3.6.1.4.1.11129.2.4.3), which
+/// only appears in precertificates
+const CT_POISON_OID: &[u8] = &[
+	0x06, 0x0A, 0x2B, 0x06, 0x01, 0x04, 0x01, 0xD6, 0x79, 0x02, 0x04, 0x03,
+];
+
+/// How many spools have been created by this process, so each one gets a name of its own
+static SPOOLS: AtomicUsize = AtomicUsize::new(0);
+
+/// An HTTP server that answers CT API requests from the contents of a scrape
+pub struct Server {
+	http: tiny_http::Server,
+	sth: GetSthResponse,
+	entries: Spool,
+	page_size: u64,
+}
+
+impl std::fmt::Debug for Server {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		f.debug_struct("Server")
+			.field("addr", &self.local_addr())
+			.field("tree_size", &self.sth.tree_size)
+			.field("entries", &self.entries.index.len())
+			.finish_non_exhaustive()
+	}
+}
+
+/// The encoded entries, written out to a temporary file, along with where to find each one
+#[derive(Debug)]
+struct Spool {
+	path: PathBuf,
+	file: File,
+	/// Sorted by entry number
+	index: Vec<Spooled>,
+}
+
+/// An entry, encoded exactly as it was in the log
+#[derive(Clone, Debug)]
+struct ServedEntry {
+	leaf_input: Vec<u8>,
+	extra_data: Vec<u8>,
+}
+
+/// Where an entry's encoded `leaf_input` and `extra_data` are in the spool file (one right
+/// after the other)
+#[derive(Clone, Copy, Debug)]
+struct Spooled {
+	entry_number: u64,
+	offset: u64,
+	leaf_input_len: usize,
+	extra_data_len: usize,
+}
+
+impl Spool {
+	/// Encode every entry in the scrape into a new spool file
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn new(scrape: &Path, format: OutputFormat, survey: &Survey) -> Result<Self, Error> {
+		let path = std::env::temp_dir().join(format!(
+			".scrape-ct-log-serve-{}-{}.tmp",
+			std::process::id(),
+			SPOOLS.fetch_add(1, Ordering::Relaxed)
+		));
+		let file = File::options()
+			.read(true)
+			.write(true)
+			.create_new(true)
+			.open(&path)
+			.map_err(|e| Error::system(format!("creating {}", path.display()), e))?;
+
+		let mut spool = Self {
+			path,
+			file,
+			index: vec![],
+		};
+		spool.fill(scrape, format, survey)?;
+		Ok(spool)
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn fill(&mut self, scrape: &Path, format: OutputFormat, survey: &Survey) -> Result<(), Error> {
+		let mut w = BufWriter::new(&self.file);
+		let mut offset = 0u64;
+		let mut unchained = 0u64;
+		let mut unchecked = 0u64;
+
+		reader::stream(open(scrape)?, format, |item| {
+			let Item::Entry(mut entry) = item else {
+				return Ok(());
+			};
+			reader::resolve_chain(&mut entry, &survey.issuers)?;
+
+			if entry.chain.is_none() && entry.extra_data.is_none() {
+				unchained = unchained.saturating_add(1);
+			}
+			if entry.leaf_input.is_none() && entry.leaf_hash.is_none() {
+				unchecked = unchecked.saturating_add(1);
+			}
+
+			let leaf_input = leaf_input(&entry).map_err(Error::server)?;
+			let extra_data = extra_data(&entry).map_err(Error::server)?;
+			w.write_all(&leaf_input)
+				.and_then(|()| w.write_all(&extra_data))
+				.map_err(|e| Error::system("writing to the spool", e))?;
+
+			self.index.push(Spooled {
+				entry_number: entry.entry_number,
+				offset,
+				leaf_input_len: leaf_input.len(),
+				extra_data_len: extra_data.len(),
+			});
+			offset = leaf_input
+				.len()
+				.checked_add(extra_data.len())
+				.and_then(|len| u64::try_from(len).ok())
+				.and_then(|len| offset.checked_add(len))
+				.ok_or_else(|| Error::arithmetic("spool offset"))?;
+			Ok(())
+		})?;
+		w.flush()
+			.map_err(|e| Error::system("writing to the spool", e))?;
+
+		if unchained > 0 {
+			log::warn!("{unchained} entries in the scrape have no certificate chain; they will be served with empty chains");
+		}
+		if unchecked > 0 {
+			log::warn!("{unchecked} entries in the scrape have no leaf hash to check their reconstruction against; they will be served as reconstructed, which might not be exactly what the log has");
+		}
+
+		self.index.sort_by_key(|e| e.entry_number);
+		self.index.dedup_by_key(|e| e.entry_number);
+		Ok(())
+	}
+
+	/// The encoded `leaf_input` and `extra_data` of each of the entries from `start` to `end`,
+	/// stopping at the first one that isn't in the scrape
+	fn get(&self, start: u64, end: u64) -> Result<Vec<ServedEntry>, std::io::Error> {
+		let first = self.index.partition_point(|e| e.entry_number < start);
+
+		self.index
+			.iter()
+			.skip(first)
+			.zip(start..=end)
+			.take_while(|(e, i)| e.entry_number == *i)
+			.map(|(e, _)| self.read(e))
+			.collect()
+	}
+
+	fn read(&self, entry: &Spooled) -> Result<ServedEntry, std::io::Error> {
+		let mut leaf_input = vec![0; entry.leaf_input_len];
+		let mut extra_data = vec![0; entry.extra_data_len];
+
+		let mut file = &self.file;
+		file.seek(SeekFrom::Start(entry.offset))?;
+		file.read_exact(&mut leaf_input)?;
+		file.read_exact(&mut extra_data)?;
+
+		Ok(ServedEntry {
+			leaf_input,
+			extra_data,
+		})
+	}
+}
+
+impl Drop for Spool {
+	fn drop(&mut self) {
+		if let Err(e) = std::fs::remove_file(&self.path) {
+			log::debug!("Failed to remove spool file {}: {e}", self.path.display());
+		}
+	}
+}
+
+/// What has to be known about a scrape before its entries can be encoded
+#[derive(Debug)]
+struct Survey {
+	metadata: Metadata,
+	issuers: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Survey {
+	/// Read through the scrape, picking up its metadata and deduplicated chain certificates
+	/// (which, being in the trailer, don't turn up until after all the entries)
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn new(scrape: &Path, format: OutputFormat) -> Result<Self, Error> {
+		let mut metadata = None;
+		let mut issuers = BTreeMap::new();
+
+		reader::stream(open(scrape)?, format, |item| {
+			match item {
+				Item::Metadata(_) if metadata.is_some() => {
+					return Err(Error::server(format!(
+						"{} has the scrapes of more than one log in it, and only one log can be served at a time",
+						scrape.display()
+					)));
+				}
+				Item::Metadata(m) => metadata = Some(m),
+				Item::Entry(_) => (),
+				Item::End(t) => issuers = t.issuers.unwrap_or_default(),
+			}
+			Ok(())
+		})?;
+
+		Ok(Self {
+			metadata: metadata.ok_or_else(|| Error::input("scrape", "no metadata found"))?,
+			issuers,
+		})
+	}
+}
+
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn open(scrape: &Path) -> Result<BufReader<File>, Error> {
+	File::open(scrape)
+		.map(BufReader::new)
+		.map_err(|e| Error::system(format!("opening {}", scrape.display()), e))
+}
+
+impl Server {
+	/// Get ready to serve the scrape in the given file, listening on the given address
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn new(
+		scrape: &Path,
+		format: OutputFormat,
+		addr: impl ToSocketAddrs,
+	) -> Result<Self, Error> {
+		let survey = Survey::new(scrape, format)?;
+		let sth = survey
+			.metadata
+			.sth
+			.clone()
+			.ok_or_else(|| Error::server("the scrape has no STH to serve"))?;
+
+		let entries = Spool::new(scrape, format, &survey)?;
+		log::info!(
+			"Loaded {} entries from scrape of {}",
+			entries.index.len(),
+			survey
+				.metadata
+				.log_url
+				.as_ref()
+				.map_or_else(|| "an unknown log".to_string(), Url::to_string)
+		);
+
+		let http = tiny_http::Server::http(addr).map_err(Error::server)?;
+
+		Ok(Self {
+			http,
+			sth: sth.into(),
+			entries,
+			page_size: DEFAULT_PAGE_SIZE,
+		})
+	}
+
+	/// Set the maximum number of entries to return from a single get-entries request
+	#[must_use]
+	pub fn page_size(mut self, page_size: u64) -> Self {
+		self.page_size = page_size.max(1);
+		self
+	}
+
+	/// The address the server is actually listening on
+	///
+	/// Handy if you asked for port 0, and need to know what you got.
+	#[must_use]
+	pub fn local_addr(&self) -> Option<SocketAddr> {
+		self.http.server_addr().to_ip()
+	}
+
+	/// Answer requests until the end of time (or something goes horribly wrong)
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	pub fn run(&self) -> Result<(), Error> {
+		loop {
+			let request = self
+				.http
+				.recv()
+				.map_err(|e| Error::server(format!("failed to receive request: {e}")))?;
+			self.respond(request);
+		}
+	}
+
+	fn respond(&self, request: Request) {
+		let (status, body) = self.handle(request.method(), request.url());
+		log::info!("{} {} -> {status}", request.method(), request.url());
+
+		let mut response = Response::from_string(body).with_status_code(status);
+		if status == 200 {
+			if let Ok(h) = "Content-Type: application/json".parse::<Header>() {
+				response = response.with_header(h);
+			}
+		}
+
+		if let Err(e) = request.respond(response) {
+			log::warn!("Failed to send response: {e}");
+		}
+	}
+
+	/// Figure out the status code and body of the response to a request
+	fn handle(&self, method: &Method, path: &str) -> (u16, String) {
+		if *method != Method::Get {
+			return (405, "only GET requests are supported".to_string());
+		}
+
+		#[allow(clippy::expect_used)] // It's a constant, it'll be fine
+		let base = Url::parse("http://localhost/").expect("base URL to parse");
+		let Ok(url) = base.join(path) else {
+			return (400, "invalid request URL".to_string());
+		};
+
+		// We don't care what prefix the log is served under, so any path that looks
+		// CT-ish is good enough
+		if url.path().ends_with("/ct/v1/get-sth") {
+			match serde_json::to_string(&self.sth) {
+				Ok(body) => (200, body),
+				Err(e) => (500, format!("failed to serialize STH: {e}")),
+			}
+		} else if url.path().ends_with("/ct/v1/get-entries") {
+			let param = |name: &str| {
+				url.query_pairs()
+					.find(|(k, _)| k == name)
+					.and_then(|(_, v)| v.parse::<u64>().ok())
+			};
+			match (param("start"), param("end")) {
+				(Some(start), Some(end)) => self.get_entries(start, end),
+				_ => (
+					400,
+					"start and end must both be given, as non-negative integers".to_string(),
+				),
+			}
+		} else {
+			(404, "not found".to_string())
+		}
+	}
+
+	fn get_entries(&self, start: u64, end: u64) -> (u16, String) {
+		if start > end {
+			return (400, "start must not be greater than end".to_string());
+		}
+		if start >= self.sth.tree_size {
+			return (
+				400,
+				format!(
+					"start must be less than the tree size ({})",
+					self.sth.tree_size
+				),
+			);
+		}
+
+		let end = [
+			end,
+			self.sth.tree_size.saturating_sub(1),
+			start.saturating_add(self.page_size).saturating_sub(1),
+		]
+		.into_iter()
+		.min()
+		.unwrap_or(start);
+
+		let entries = match self.entries.get(start, end) {
+			Ok(entries) => entries
+				.iter()
+				.map(|e| {
+					json!({ "leaf_input": b64.encode(&e.leaf_input), "extra_data": b64.encode(&e.extra_data) })
+				})
+				.collect::<Vec<_>>(),
+			Err(e) => return (500, format!("failed to read entries: {e}")),
+		};
+
+		if entries.is_empty() {
+			(404, format!("entry {start} is not in this scrape"))
+		} else {
+			(200, json!({ "entries": entries }).to_string())
+		}
+	}
+}
+
+/// Whether a certificate is actually a precertificate
+fn is_precert(cert: &[u8]) -> bool {
+	cert.windows(CT_POISON_OID.len())
+		.any(|w| w == CT_POISON_OID)
+}
+
+/// Reconstruct the TLS encoding of the entry's `MerkleTreeLeaf` (unless we've got the
+/// original), making sure it's the same as what the log had
+fn leaf_input(entry: &Entry) -> Result<Vec<u8>, String> {
+	if let Some(leaf_input) = &entry.leaf_input {
+		return Ok(leaf_input.clone());
+	}
+
+	let certificate = certificate(entry)?;
+	let leaf_input = if let Some(precert) = &entry.precert {
+		merkle_tree_leaf(
+			entry.timestamp,
+			LeafEntry::Precert {
+				issuer_key_hash: &precert.issuer_key_hash,
+				tbs_certificate: &precert.tbs_certificate,
+			},
+		)?
+	} else if is_precert(certificate) {
+		return Err(format!("entry {} is a precertificate, but the scrape does not include precert data (scrape with --include-precert-data to be able to serve it)", entry.entry_number));
+	} else {
+		merkle_tree_leaf(entry.timestamp, LeafEntry::X509 { certificate })?
+	};
+
+	match &entry.leaf_hash {
+		Some(hash) if *hash != leaf_hash(&leaf_input) => Err(format!("entry {} can't be reconstructed exactly, probably because it has extensions, and the scrape does not include its original leaf_input", entry.entry_number)),
+		Some(_) | None => Ok(leaf_input),
+	}
+}
+
+/// The entry's certificate, which it can't be served without (unless we've got the original
+/// `leaf_input` and `extra_data`)
+fn certificate(entry: &Entry) -> Result<&[u8], String> {
+	entry.certificate.as_deref().ok_or_else(|| {
+		format!(
+			"entry {} has no certificate, so it can't be reconstructed",
+			entry.entry_number
+		)
+	})
+}
+
+/// Reconstruct the TLS encoding of the entry's `extra_data` (unless we've got the original)
+fn extra_data(entry: &Entry) -> Result<Vec<u8>, String> {
+	if let Some(extra_data) = &entry.extra_data {
+		return Ok(extra_data.clone());
+	}
+
+	let mut v = vec![];
+	let chain = entry
+		.chain
+		.iter()
+		.flatten()
+		.map(|link| {
+			link.cert().ok_or_else(|| {
+				format!(
+					"entry {}'s chain refers to an issuer that isn't in the scrape",
+					entry.entry_number
+				)
+			})
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	if entry.precert.is_some() {
+		push_opaque(&mut v, certificate(entry)?, 3)?;
+	}
+	push_chain(&mut v, &chain)?;
+
+	Ok(v)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::reader::Scrape;
+
+	const SCRAPE: &str = r#"{
+				"log_url": "https://ct.example.com/",
+				"sth": {
+					"tree_size": 5,
+					"timestamp": 1234567890,
+					"sha256_root_hash": "AAAA",
+					"tree_head_signature": "AAAA"
+				},
+				"entries": [
+					{
+						"entry_number": 1,
+						"timestamp": 2,
+						"certificate": "AQID",
+						"chain": ["BAU"],
+						"leaf_hash": "UBB29SHOqZIQbGjHuBDowebzv0OPnkO/2WJ8fRNMtds"
+					},
+					{
+						"entry_number": 0,
+						"timestamp": 1,
+						"certificate": "AQID",
+						"chain": [],
+						"leaf_hash": "Ed9O37domwG4jQF9KJwk6yNm5my16AfaUHiqztRx1jI"
+					},
+					{
+						"entry_number": 2,
+						"timestamp": 3,
+						"certificate": "BgIK",
+						"chain": [],
+						"leaf_hash": "aWjlftHa+oOYxHET0Er8odqPNruAFHaqbBL4c22n/5Q",
+						"precert": { "issuer_key_hash": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA", "tbs_certificate": "Bw" }
+					}
+				]
+			}"#;
+
+	fn scrape() -> Scrape {
+		reader::read(SCRAPE.as_bytes(), OutputFormat::JSON).unwrap()
+	}
+
+	/// A server for the given scrape document
+	fn serve(doc: &str) -> Result<Server, Error> {
+		let tmpdir = temp_dir::TempDir::new().unwrap();
+		let path = tmpdir.child("scrape.json");
+		std::fs::write(&path, doc).unwrap();
+
+		Server::new(&path, OutputFormat::JSON, "127.0.0.1:0")
+	}
+
+	fn server() -> Server {
+		serve(SCRAPE).unwrap().page_size(2)
+	}
+
+	#[test]
+	fn encodes_x509_entries() {
+		let s = scrape();
+
+		assert_eq!(
+			vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 3, 1, 2, 3, 0, 0],
+			leaf_input(&s.entries[0]).unwrap()
+		);
+		assert_eq!(
+			vec![0, 0, 5, 0, 0, 2, 4, 5],
+			extra_data(&s.entries[0]).unwrap()
+		);
+	}
+
+	#[test]
+	fn encodes_precert_entries() {
+		let s = scrape();
+		let mut expected = vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 1];
+		expected.extend_from_slice(&[0; 32]);
+		expected.extend_from_slice(&[0, 0, 1, 7, 0, 0]);
+
+		assert_eq!(expected, leaf_input(&s.entries[2]).unwrap());
+		assert_eq!(
+			vec![0, 0, 3, 6, 2, 10, 0, 0, 0],
+			extra_data(&s.entries[2]).unwrap()
+		);
+	}
+
+	#[test]
+	fn refuses_precerts_without_precert_data() {
+		let mut s = scrape();
+		s.entries[0].certificate = Some(CT_POISON_OID.to_vec());
+
+		assert!(leaf_input(&s.entries[0]).is_err());
+	}
+
+	#[test]
+	fn refuses_entries_that_dont_match_their_leaf_hash() {
+		let mut s = scrape();
+		s.entries[2].leaf_hash = Some(vec![0; 32]);
+
+		assert!(leaf_input(&s.entries[2]).is_err());
+	}
+
+	#[test]
+	fn serves_entries_without_a_leaf_hash() {
+		let mut s = scrape();
+		let expected = leaf_input(&s.entries[0]).unwrap();
+		s.entries[0].leaf_hash = None;
+		assert_eq!(expected, leaf_input(&s.entries[0]).unwrap());
+
+		let doc = SCRAPE.replace(
+			r#""leaf_hash": "UBB29SHOqZIQbGjHuBDowebzv0OPnkO/2WJ8fRNMtds""#,
+			r#""certificate_sha256": "AAAA""#,
+		);
+		let (status, body) = serve(&doc)
+			.unwrap()
+			.handle(&Method::Get, "/ct/v1/get-entries?start=1&end=1");
+
+		assert_eq!(200, status);
+		let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(b64.encode(expected), response["entries"][0]["leaf_input"]);
+	}
+
+	#[test]
+	fn refuses_scrapes_that_dont_match_their_leaf_hashes() {
+		let doc = SCRAPE.replace(
+			"UBB29SHOqZIQbGjHuBDowebzv0OPnkO/2WJ8fRNMtds",
+			"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+		);
+		let err = serve(&doc).unwrap_err();
+		assert!(err.to_string().contains("entry 1"), "{err}");
+	}
+
+	#[test]
+	fn refuses_scrapes_of_more_than_one_log() {
+		let err = serve(&format!("[{SCRAPE}, {SCRAPE}]")).unwrap_err();
+		assert!(err.to_string().contains("more than one log"), "{err}");
+	}
+
+	#[test]
+	fn looks_up_deduplicated_chains() {
+		let doc = SCRAPE
+			.replace(r#"["BAU"]"#, &format!(r#"["{}"]"#, "11".repeat(32)))
+			.replace(
+				r#""entries": ["#,
+				&format!(
+					r#""issuers": {{"{}": "BAU"}}, "entries": ["#,
+					"11".repeat(32)
+				),
+			);
+		let (status, body) = serve(&doc)
+			.unwrap()
+			.handle(&Method::Get, "/ct/v1/get-entries?start=1&end=1");
+
+		assert_eq!(200, status);
+		let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(
+			b64.encode(extra_data(&scrape().entries[0]).unwrap()),
+			response["entries"][0]["extra_data"]
+		);
+	}
+
+	#[test]
+	fn prefers_the_original_encoding() {
+		let mut s = scrape();
+		s.entries[2].leaf_input = Some(vec![1, 2, 3]);
+		s.entries[2].extra_data = Some(vec![4, 5]);
+
+		assert_eq!(vec![1, 2, 3], leaf_input(&s.entries[2]).unwrap());
+		assert_eq!(vec![4, 5], extra_data(&s.entries[2]).unwrap());
+	}
+
+	#[test]
+	fn serves_the_sth() {
+		let (status, body) = server().handle(&Method::Get, "/ct/v1/get-sth");
+
+		assert_eq!(200, status);
+		assert!(body.contains("1234567890"));
+	}
+
+	#[test]
+	fn serves_entries_a_page_at_a_time() {
+		let (status, body) =
+			server().handle(&Method::Get, "/logs/foo/ct/v1/get-entries?start=0&end=4");
+
+		assert_eq!(200, status);
+		let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(2, response["entries"].as_array().unwrap().len());
+		assert_eq!(
+			b64.encode(leaf_input(&scrape().entries[1]).unwrap()),
+			response["entries"][0]["leaf_input"]
+		);
+	}
+
+	#[test]
+	fn stops_at_missing_entries() {
+		let (status, body) = server().handle(&Method::Get, "/ct/v1/get-entries?start=2&end=4");
+
+		assert_eq!(200, status);
+		let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(1, response["entries"].as_array().unwrap().len());
+
+		assert_eq!(
+			404,
+			server()
+				.handle(&Method::Get, "/ct/v1/get-entries?start=3&end=4")
+				.0
+		);
+	}
+
+	#[test]
+	fn rejects_bad_requests() {
+		let s = server();
+
+		assert_eq!(400, s.handle(&Method::Get, "/ct/v1/get-entries?start=2").0);
+		assert_eq!(
+			400,
+			s.handle(&Method::Get, "/ct/v1/get-entries?start=2&end=1").0
+		);
+		assert_eq!(
+			400,
+			s.handle(&Method::Get, "/ct/v1/get-entries?start=5&end=6").0
+		);
+		assert_eq!(404, s.handle(&Method::Get, "/ct/v1/get-roots").0);
+		assert_eq!(405, s.handle(&Method::Post, "/ct/v1/get-sth").0);
+	}
+}
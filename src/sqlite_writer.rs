@@ -0,0 +1,571 @@
+//! Thread that writes the scraped entries into a SQLite database.
+//!
+//! Unlike the other output formats, a database isn't written from scratch each time; if it
+//! already exists, each scrape is added to what's already there.  That way, a log can be
+//! scraped a bit at a time (say, once a day), and the database always has everything seen so
+//! far.
+//!
+//! The tables are:
+//!
+//! * `logs`: one row per log that has ever been scraped into the database;
+//! * `scrapes`: one row per scrape, with the STH that was retrieved at the start of it;
+//! * `entries`: one row per log entry, which is replaced if the entry is scraped again;
+//! * `chain_certificates`: every certificate that has appeared in an entry's chain, stored
+//!   once no matter how many entries it appears in; and
+//! * `entry_chains`: which chain certificates go with which entry, in order.
+//!
+
+use gen_server::{GenServer, Status::Continue};
+use ring::digest::{digest, SHA256};
+use rusqlite::{params, Connection, OptionalExtension as _};
+use std::{
+	path::{Path, PathBuf},
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+use url::Url;
+
+use crate::{
+	document::{ChainLink, Entry, Sth},
+	processor,
+	utils::current_time,
+	Error,
+};
+
+/// How many entries get written in each transaction, unless told otherwise
+const DEFAULT_BATCH_SIZE: usize = 1_000;
+
+/// How long a transaction can be kept open waiting for its batch to fill up, unless told
+/// otherwise; other scrapes writing to the same database have to wait for it, and entries can
+/// arrive very slowly from a log that's rate-limiting us
+const DEFAULT_COMMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for another scrape writing to the same database to get out of our way
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+const SCHEMA: &str = "
+	CREATE TABLE IF NOT EXISTS logs (
+		id INTEGER PRIMARY KEY,
+		url TEXT NOT NULL UNIQUE,
+		log_id BLOB
+	);
+
+	CREATE TABLE IF NOT EXISTS scrapes (
+		id INTEGER PRIMARY KEY,
+		log INTEGER NOT NULL REFERENCES logs (id),
+		scrape_begin_timestamp INTEGER NOT NULL,
+		scrape_end_timestamp INTEGER,
+		entry_count INTEGER NOT NULL DEFAULT 0,
+		tree_size INTEGER,
+		sth_timestamp INTEGER,
+		sha256_root_hash BLOB,
+		tree_head_signature BLOB
+	);
+
+	CREATE TABLE IF NOT EXISTS entries (
+		log INTEGER NOT NULL REFERENCES logs (id),
+		entry_number INTEGER NOT NULL,
+		scrape INTEGER NOT NULL REFERENCES scrapes (id),
+		timestamp INTEGER NOT NULL,
+		certificate BLOB NOT NULL,
+		issuer_key_hash BLOB,
+		tbs_certificate BLOB,
+		PRIMARY KEY (log, entry_number)
+	);
+	CREATE INDEX IF NOT EXISTS entries_entry_number ON entries (entry_number);
+	CREATE INDEX IF NOT EXISTS entries_timestamp ON entries (timestamp);
+
+	CREATE TABLE IF NOT EXISTS chain_certificates (
+		id INTEGER PRIMARY KEY,
+		sha256 BLOB NOT NULL UNIQUE,
+		certificate BLOB NOT NULL
+	);
+
+	CREATE TABLE IF NOT EXISTS entry_chains (
+		log INTEGER NOT NULL,
+		entry_number INTEGER NOT NULL,
+		position INTEGER NOT NULL,
+		certificate INTEGER NOT NULL REFERENCES chain_certificates (id),
+		PRIMARY KEY (log, entry_number, position),
+		FOREIGN KEY (log, entry_number) REFERENCES entries (log, entry_number)
+	);
+";
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Args {
+	path: PathBuf,
+	include_chains: bool,
+	include_precert_data: bool,
+	log_url: Url,
+	log_id: Option<Vec<u8>>,
+	batch_size: usize,
+	commit_interval: Duration,
+}
+
+impl Args {
+	#[must_use]
+	pub fn new(path: impl AsRef<Path>, log_url: Url) -> Self {
+		Args {
+			path: path.as_ref().to_path_buf(),
+			log_url,
+			log_id: None,
+			include_chains: false,
+			include_precert_data: false,
+			batch_size: DEFAULT_BATCH_SIZE,
+			commit_interval: DEFAULT_COMMIT_INTERVAL,
+		}
+	}
+
+	#[must_use]
+	pub fn include_chains(mut self, include_chains: bool) -> Self {
+		self.include_chains = include_chains;
+		self
+	}
+
+	#[must_use]
+	pub fn include_precert_data(mut self, include_precert_data: bool) -> Self {
+		self.include_precert_data = include_precert_data;
+		self
+	}
+
+	#[must_use]
+	pub fn log_id(mut self, log_id: Vec<u8>) -> Self {
+		self.log_id = Some(log_id);
+		self
+	}
+
+	/// Set the number of entries that are written in each transaction
+	#[must_use]
+	pub fn batch_size(mut self, batch_size: usize) -> Self {
+		self.batch_size = batch_size.max(1);
+		self
+	}
+
+	/// Set how long a transaction can be kept open before it's committed, even if it hasn't
+	/// got a whole batch of entries in it yet
+	#[must_use]
+	pub fn commit_interval(mut self, commit_interval: Duration) -> Self {
+		self.commit_interval = commit_interval;
+		self
+	}
+}
+
+pub struct SqliteWriter {
+	// Connection isn't Sync, and the runner wants processors to be; we only ever need to get at
+	// it through a `&mut self`, though, so the lock never actually gets taken
+	conn: Mutex<Connection>,
+	log: i64,
+	scrape: i64,
+	entry_count: u64,
+	/// How many entries have been written since the last commit
+	pending: usize,
+	/// When the current transaction was started, if there is one
+	batch_started: Option<Instant>,
+	include_chains: bool,
+	include_precert_data: bool,
+	batch_size: usize,
+	commit_interval: Duration,
+}
+
+impl std::fmt::Debug for SqliteWriter {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		f.debug_struct("SqliteWriter")
+			.field("log", &self.log)
+			.field("scrape", &self.scrape)
+			.finish_non_exhaustive()
+	}
+}
+
+/// SQLite only does signed integers, so the occasional log-supplied `u64` needs converting
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn int(n: u64) -> Result<i64, Error> {
+	i64::try_from(n).map_err(|e| Error::output("SQLite", format!("{n} is too large: {e}")))
+}
+
+fn sql_error(e: rusqlite::Error) -> Error {
+	Error::output("SQLite", e)
+}
+
+impl SqliteWriter {
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn conn(&mut self) -> Result<&mut Connection, Error> {
+		self.conn
+			.get_mut()
+			.map_err(|e| Error::system("SQLite connection lock poisoned", e))
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn metadata(&mut self, sth: &Sth) -> Result<(), Error> {
+		let (tree_size, timestamp, scrape) =
+			(int(sth.tree_size)?, int(sth.timestamp)?, self.scrape);
+		self.conn()?
+			.execute(
+				"UPDATE scrapes
+				 SET tree_size = ?, sth_timestamp = ?, sha256_root_hash = ?, tree_head_signature = ?
+				 WHERE id = ?",
+				params![
+					tree_size,
+					timestamp,
+					sth.sha256_root_hash,
+					sth.tree_head_signature,
+					scrape
+				],
+			)
+			.map_err(sql_error)?;
+		Ok(())
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn entry(&mut self, entry: &Entry) -> Result<(), Error> {
+		let (log, scrape) = (self.log, self.scrape);
+		let (entry_number, timestamp) = (int(entry.entry_number)?, int(entry.timestamp)?);
+		if self.batch_started.is_none() {
+			self.conn()?.execute_batch("BEGIN").map_err(sql_error)?;
+			self.batch_started = Some(Instant::now());
+		}
+		let conn = self.conn()?;
+
+		conn.prepare_cached(
+			"INSERT OR REPLACE INTO entries
+			 (log, entry_number, scrape, timestamp, certificate, issuer_key_hash, tbs_certificate)
+			 VALUES (?, ?, ?, ?, ?, ?, ?)",
+		)
+		.and_then(|mut stmt| {
+			stmt.execute(params![
+				log,
+				entry_number,
+				scrape,
+				timestamp,
+				entry.certificate,
+				entry.precert.as_ref().map(|p| &p.issuer_key_hash),
+				entry.precert.as_ref().map(|p| &p.tbs_certificate),
+			])
+		})
+		.map_err(sql_error)?;
+
+		if let Some(chain) = &entry.chain {
+			conn.prepare_cached("DELETE FROM entry_chains WHERE log = ? AND entry_number = ?")
+				.and_then(|mut stmt| stmt.execute(params![log, entry_number]))
+				.map_err(sql_error)?;
+
+			for (position, cert) in chain.iter().filter_map(ChainLink::cert).enumerate() {
+				let id = chain_certificate(conn, cert)?;
+				conn.prepare_cached(
+					"INSERT INTO entry_chains (log, entry_number, position, certificate)
+					 VALUES (?, ?, ?, ?)",
+				)
+				.and_then(|mut stmt| stmt.execute(params![log, entry_number, position, id]))
+				.map_err(sql_error)?;
+			}
+		}
+
+		self.entry_count = self
+			.entry_count
+			.checked_add(1)
+			.ok_or_else(|| Error::arithmetic("entry count overflowed"))?;
+		self.pending = self
+			.pending
+			.checked_add(1)
+			.ok_or_else(|| Error::arithmetic("pending entry count overflowed"))?;
+		if self.pending >= self.batch_size
+			|| self
+				.batch_started
+				.is_some_and(|t| t.elapsed() >= self.commit_interval)
+		{
+			self.commit()?;
+		}
+
+		Ok(())
+	}
+
+	/// Make everything written so far permanent
+	///
+	/// The next transaction isn't started until there's another entry to write, so that the
+	/// database isn't kept locked while we're waiting for it.
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn commit(&mut self) -> Result<(), Error> {
+		let (entry_count, scrape) = (int(self.entry_count)?, self.scrape);
+		let conn = self.conn()?;
+		conn.execute(
+			"UPDATE scrapes SET entry_count = ? WHERE id = ?",
+			params![entry_count, scrape],
+		)
+		.and_then(|_| conn.execute_batch("COMMIT"))
+		.map_err(sql_error)?;
+		self.pending = 0;
+		self.batch_started = None;
+		Ok(())
+	}
+
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn finish(&mut self) -> Result<(), Error> {
+		let (entry_count, scrape) = (int(self.entry_count)?, self.scrape);
+		let end = current_time().ok().map(int).transpose()?;
+		let in_transaction = self.batch_started.take().is_some();
+		let conn = self.conn()?;
+		conn.execute(
+			"UPDATE scrapes SET entry_count = ?, scrape_end_timestamp = ? WHERE id = ?",
+			params![entry_count, end, scrape],
+		)
+		.and_then(|_| {
+			if in_transaction {
+				conn.execute_batch("COMMIT")
+			} else {
+				Ok(())
+			}
+		})
+		.map_err(sql_error)
+	}
+}
+
+/// Find (or add) a chain certificate, returning its ID
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn chain_certificate(conn: &Connection, cert: &[u8]) -> Result<i64, Error> {
+	let hash = digest(&SHA256, cert);
+
+	if let Some(id) = conn
+		.prepare_cached("SELECT id FROM chain_certificates WHERE sha256 = ?")
+		.and_then(|mut stmt| stmt.query_row([hash.as_ref()], |row| row.get(0)).optional())
+		.map_err(sql_error)?
+	{
+		return Ok(id);
+	}
+
+	conn.prepare_cached("INSERT INTO chain_certificates (sha256, certificate) VALUES (?, ?)")
+		.and_then(|mut stmt| stmt.insert(params![hash.as_ref(), cert]))
+		.map_err(sql_error)
+}
+
+/// Open the database, making sure all the tables are there, and that we know about the log
+#[allow(clippy::result_large_err)] // Oh shoosh
+fn open(args: &Args) -> Result<(Connection, i64), Error> {
+	let conn = Connection::open(&args.path).map_err(|e| {
+		Error::output(
+			"SQLite",
+			format!("could not open {}: {e}", args.path.display()),
+		)
+	})?;
+	conn.busy_timeout(BUSY_TIMEOUT).map_err(sql_error)?;
+	// WAL lets several scrapes write to the same database without falling over each other
+	// (as much)
+	conn.pragma_update(None, "journal_mode", "WAL")
+		.map_err(sql_error)?;
+	conn.execute_batch(SCHEMA).map_err(sql_error)?;
+
+	conn.execute(
+		"INSERT INTO logs (url, log_id) VALUES (?, ?)
+		 ON CONFLICT (url) DO UPDATE SET log_id = COALESCE(excluded.log_id, log_id)",
+		params![args.log_url.as_str(), args.log_id],
+	)
+	.map_err(sql_error)?;
+	let log = conn
+		.query_row(
+			"SELECT id FROM logs WHERE url = ?",
+			[args.log_url.as_str()],
+			|row| row.get(0),
+		)
+		.map_err(sql_error)?;
+
+	Ok((conn, log))
+}
+
+impl GenServer for SqliteWriter {
+	type Args = Args;
+	type Error = Error;
+	type Request = processor::Request;
+	type StopReason = ();
+
+	fn init(args: Args) -> Result<Self, Self::Error> {
+		let (conn, log) = open(&args)?;
+
+		conn.execute(
+			"INSERT INTO scrapes (log, scrape_begin_timestamp) VALUES (?, ?)",
+			params![log, int(current_time()?)?],
+		)
+		.map_err(sql_error)?;
+		let scrape = conn.last_insert_rowid();
+
+		Ok(Self {
+			conn: Mutex::new(conn),
+			log,
+			scrape,
+			entry_count: 0,
+			pending: 0,
+			batch_started: None,
+			include_chains: args.include_chains,
+			include_precert_data: args.include_precert_data,
+			batch_size: args.batch_size,
+			commit_interval: args.commit_interval,
+		})
+	}
+
+	fn handle_cast(
+		&mut self,
+		request: Self::Request,
+	) -> Result<gen_server::Status<Self>, Self::Error> {
+		match request {
+			processor::Request::Metadata(sth) => {
+				self.metadata(&Sth::from(&sth))?;
+
+				Ok(Continue)
+			}
+			processor::Request::Entry(id, entry, _) => {
+				self.entry(&Entry::from_response(
+					id,
+					&entry,
+					self.include_chains,
+					self.include_precert_data,
+				)?)?;
+
+				Ok(Continue)
+			}
+		}
+	}
+
+	fn terminate(&mut self, _reason: Result<(), Error>) {
+		if let Err(e) = self.finish() {
+			log::error!("Failed to finish writing SQLite database: {e}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::document::Precert;
+
+	fn writer(path: &Path, batch_size: usize, commit_interval: Duration) -> SqliteWriter {
+		let mut sw = SqliteWriter::init(
+			Args::new(path, Url::parse("https://ct.example.com/").unwrap())
+				.batch_size(batch_size)
+				.commit_interval(commit_interval),
+		)
+		.unwrap();
+		sw.metadata(&Sth {
+			tree_size: 3,
+			timestamp: 1234567890,
+			sha256_root_hash: vec![0; 32],
+			tree_head_signature: vec![0; 64],
+		})
+		.unwrap();
+		sw
+	}
+
+	fn scrape(path: &Path, entries: Vec<Entry>) {
+		let mut sw = writer(path, 2, DEFAULT_COMMIT_INTERVAL);
+		for entry in entries {
+			sw.entry(&entry).unwrap();
+		}
+		sw.finish().unwrap();
+	}
+
+	fn count(conn: &Connection, table: &str) -> i64 {
+		conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+			row.get(0)
+		})
+		.unwrap()
+	}
+
+	#[test]
+	fn appends_scrapes_to_the_database() {
+		let dir = temp_dir::TempDir::new().unwrap();
+		let path = dir.child("scrapes.sqlite");
+
+		scrape(
+			&path,
+			vec![
+				Entry::new(0, 10, vec![1]).chain(Some(vec![vec![4], vec![5]])),
+				Entry::new(1, 11, vec![2])
+					.chain(Some(vec![vec![4]]))
+					.precert(Some(Precert::new(vec![8; 32], vec![9]))),
+				Entry::new(2, 12, vec![3]),
+			],
+		);
+		// Going over some of the same ground again shouldn't duplicate anything
+		scrape(
+			&path,
+			vec![
+				Entry::new(2, 12, vec![3]).chain(Some(vec![vec![5], vec![6]])),
+				Entry::new(3, 13, vec![7]),
+			],
+		);
+
+		let conn = Connection::open(&path).unwrap();
+		assert_eq!(1, count(&conn, "logs"));
+		assert_eq!(2, count(&conn, "scrapes"));
+		assert_eq!(4, count(&conn, "entries"));
+		assert_eq!(3, count(&conn, "chain_certificates"));
+		assert_eq!(5, count(&conn, "entry_chains"));
+
+		let (entry_count, tree_size, end): (i64, i64, Option<i64>) = conn
+			.query_row(
+				"SELECT entry_count, tree_size, scrape_end_timestamp FROM scrapes ORDER BY id LIMIT 1",
+				[],
+				|row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+			)
+			.unwrap();
+		assert_eq!(3, entry_count);
+		assert_eq!(3, tree_size);
+		assert!(end.is_some());
+
+		let tbs: Vec<u8> = conn
+			.query_row(
+				"SELECT tbs_certificate FROM entries WHERE entry_number = 1",
+				[],
+				|row| row.get(0),
+			)
+			.unwrap();
+		assert_eq!(vec![9], tbs);
+
+		let chain: Vec<Vec<u8>> = conn
+			.prepare(
+				"SELECT c.certificate FROM entry_chains AS ec
+				 JOIN chain_certificates AS c ON c.id = ec.certificate
+				 WHERE ec.entry_number = 2 ORDER BY ec.position",
+			)
+			.unwrap()
+			.query_map([], |row| row.get(0))
+			.unwrap()
+			.collect::<Result<_, _>>()
+			.unwrap();
+		assert_eq!(vec![vec![5], vec![6]], chain);
+	}
+
+	/// Whether some other scrape could write to the database right now
+	fn writable(path: &Path) -> bool {
+		let conn = Connection::open(path).unwrap();
+		conn.busy_timeout(Duration::ZERO).unwrap();
+		conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK").is_ok()
+	}
+
+	#[test]
+	fn commits_a_slow_batch_without_waiting_for_it_to_fill() {
+		let dir = temp_dir::TempDir::new().unwrap();
+		let path = dir.child("scrapes.sqlite");
+
+		let mut sw = writer(&path, 1000, Duration::ZERO);
+		assert!(writable(&path));
+
+		sw.entry(&Entry::new(0, 10, vec![1])).unwrap();
+		assert!(writable(&path));
+		assert_eq!(1, count(&Connection::open(&path).unwrap(), "entries"));
+
+		sw.finish().unwrap();
+	}
+
+	#[test]
+	fn holds_a_batch_open_until_it_fills() {
+		let dir = temp_dir::TempDir::new().unwrap();
+		let path = dir.child("scrapes.sqlite");
+
+		let mut sw = writer(&path, 1000, Duration::from_secs(60));
+		sw.entry(&Entry::new(0, 10, vec![1])).unwrap();
+		assert!(!writable(&path));
+		assert_eq!(0, count(&Connection::open(&path).unwrap(), "entries"));
+
+		sw.finish().unwrap();
+		assert!(writable(&path));
+		assert_eq!(1, count(&Connection::open(&path).unwrap(), "entries"));
+	}
+}
@@ -0,0 +1,192 @@
+//! Verification of Signed Tree Heads against a log's public key
+//!
+
+use ct_structs::v1::response::GetSth as GetSthResponse;
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1, RSA_PKCS1_2048_8192_SHA256};
+
+use crate::{der, Error};
+
+/// DER-encoded OID for rsaEncryption (1.2.840.113549.1.1.1)
+const RSA_ENCRYPTION: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
+/// DER-encoded OID for id-ecPublicKey (1.2.840.10045.2.1)
+const EC_PUBLIC_KEY: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+/// DER-encoded OID for prime256v1 (1.2.840.10045.3.1.7)
+const PRIME256V1: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+
+/// TLS `HashAlgorithm` value for SHA-256
+const HASH_SHA256: u8 = 4;
+/// TLS `SignatureAlgorithm` value for RSA
+const SIG_RSA: u8 = 1;
+/// TLS `SignatureAlgorithm` value for ECDSA
+const SIG_ECDSA: u8 = 3;
+
+/// Check that the `tree_head_signature` on an STH was made by the private key corresponding
+/// to the given DER-encoded `SubjectPublicKeyInfo`.
+#[allow(clippy::result_large_err)] // Oh shoosh
+pub(crate) fn verify(sth: &GetSthResponse, spki: &[u8]) -> Result<(), Error> {
+	let (algorithm, key) = parse_spki(spki).map_err(Error::sth_verification)?;
+
+	let (&hash_alg, rest) = sth
+		.tree_head_signature
+		.split_first()
+		.ok_or_else(|| Error::sth_verification("tree_head_signature is empty"))?;
+	let (&sig_alg, rest) = rest
+		.split_first()
+		.ok_or_else(|| Error::sth_verification("tree_head_signature is truncated"))?;
+	let (len, signature) = rest
+		.split_first_chunk::<2>()
+		.ok_or_else(|| Error::sth_verification("tree_head_signature is truncated"))?;
+	let len = u16::from_be_bytes(*len);
+	if usize::from(len) != signature.len() {
+		return Err(Error::sth_verification(format!(
+			"tree_head_signature claims a {len} byte signature, but has {} bytes",
+			signature.len()
+		)));
+	}
+
+	if hash_alg != HASH_SHA256 {
+		return Err(Error::sth_verification(format!(
+			"unsupported hash algorithm {hash_alg}"
+		)));
+	}
+
+	let verifier = match (algorithm, sig_alg) {
+		(KeyAlgorithm::Ecdsa, SIG_ECDSA) => UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, key),
+		(KeyAlgorithm::Rsa, SIG_RSA) => UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, key),
+		(_, _) => {
+			return Err(Error::sth_verification(format!(
+				"signature algorithm {sig_alg} does not match the log's {algorithm:?} key"
+			)))
+		}
+	};
+
+	verifier
+		.verify(&signed_data(sth), signature)
+		.map_err(|e| Error::sth_verification(format!("signature is invalid ({e})")))
+}
+
+/// The TLS encoding of the `TreeHeadSignature` structure from RFC 6962 section 3.5, which is
+/// what the log actually signs.
+pub(crate) fn signed_data(sth: &GetSthResponse) -> Vec<u8> {
+	let mut v = vec![
+		0, // version: v1
+		1, // signature_type: tree_hash
+	];
+	v.extend_from_slice(&sth.timestamp.to_be_bytes());
+	v.extend_from_slice(&sth.tree_size.to_be_bytes());
+	v.extend_from_slice(&sth.sha256_root_hash);
+	v
+}
+
+#[derive(Clone, Copy, Debug)]
+enum KeyAlgorithm {
+	Rsa,
+	Ecdsa,
+}
+
+/// Pull the algorithm and raw key bits out of a `SubjectPublicKeyInfo`.
+fn parse_spki(spki: &[u8]) -> Result<(KeyAlgorithm, &[u8]), String> {
+	let (outer, _) =
+		der::expect(spki, der::SEQUENCE).map_err(|e| format!("invalid log key: {e}"))?;
+	let (alg_id, rest) = der::expect(outer.value, der::SEQUENCE)
+		.map_err(|e| format!("invalid log key algorithm: {e}"))?;
+	let (key_bits, _) =
+		der::expect(rest, der::BIT_STRING).map_err(|e| format!("invalid log key bits: {e}"))?;
+	let (oid, params) = der::expect(alg_id.value, der::OID)
+		.map_err(|e| format!("invalid log key algorithm: {e}"))?;
+
+	let algorithm = if oid.value == RSA_ENCRYPTION {
+		KeyAlgorithm::Rsa
+	} else if oid.value == EC_PUBLIC_KEY {
+		let (curve, _) =
+			der::expect(params, der::OID).map_err(|e| format!("invalid log key curve: {e}"))?;
+		if curve.value != PRIME256V1 {
+			return Err("only P-256 ECDSA log keys are supported".to_string());
+		}
+		KeyAlgorithm::Ecdsa
+	} else {
+		return Err("unsupported log key algorithm".to_string());
+	};
+
+	match key_bits.value.split_first() {
+		Some((0, key)) => Ok((algorithm, key)),
+		Some(_) => Err("log key bit string has unused bits".to_string()),
+		None => Err("log key bit string is empty".to_string()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ring::{
+		rand::SystemRandom,
+		signature::{EcdsaKeyPair, KeyPair as _, ECDSA_P256_SHA256_ASN1_SIGNING},
+	};
+
+	/// The SPKI header for a P-256 key, which just needs the uncompressed point tacked on the end
+	const P256_SPKI_PREFIX: &[u8] = &[
+		0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01, 0x06, 0x08,
+		0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+	];
+
+	fn keypair() -> (EcdsaKeyPair, Vec<u8>) {
+		let rng = SystemRandom::new();
+		let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+		let kp = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+			.unwrap();
+		let mut spki = P256_SPKI_PREFIX.to_vec();
+		spki.extend_from_slice(kp.public_key().as_ref());
+
+		(kp, spki)
+	}
+
+	fn signed_sth(kp: &EcdsaKeyPair) -> GetSthResponse {
+		let mut sth = GetSthResponse {
+			tree_size: 42,
+			timestamp: 1_234_567_890,
+			sha256_root_hash: vec![0x55; 32],
+			tree_head_signature: vec![],
+		};
+		let sig = kp.sign(&SystemRandom::new(), &signed_data(&sth)).unwrap();
+		sth.tree_head_signature = vec![HASH_SHA256, SIG_ECDSA];
+		sth.tree_head_signature
+			.extend_from_slice(&u16::try_from(sig.as_ref().len()).unwrap().to_be_bytes());
+		sth.tree_head_signature.extend_from_slice(sig.as_ref());
+
+		sth
+	}
+
+	#[test]
+	fn accepts_a_valid_signature() {
+		let (kp, spki) = keypair();
+
+		verify(&signed_sth(&kp), &spki).unwrap();
+	}
+
+	#[test]
+	fn rejects_a_tampered_sth() {
+		let (kp, spki) = keypair();
+		let mut sth = signed_sth(&kp);
+		sth.tree_size = 43;
+
+		assert!(matches!(
+			verify(&sth, &spki),
+			Err(Error::SthVerificationError(_))
+		));
+	}
+
+	#[test]
+	fn rejects_a_signature_from_a_different_key() {
+		let (kp, _) = keypair();
+		let (_, other_spki) = keypair();
+
+		assert!(verify(&signed_sth(&kp), &other_spki).is_err());
+	}
+
+	#[test]
+	fn rejects_garbage_keys() {
+		let (kp, _) = keypair();
+
+		assert!(verify(&signed_sth(&kp), b"ohai!").is_err());
+	}
+}
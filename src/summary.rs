@@ -0,0 +1,336 @@
+//! Counting up what's in a log, rather than writing it all out
+//!
+//! Plenty of questions about a log -- who's issuing the most certificates, how many were
+//! logged each day, how long they're valid for -- only need a tally, and it'd be a shame to
+//! write out terabytes of entries just to count them.  A [`Summary`] keeps count of the
+//! entries' issuers, the days they were logged, their types, the certificates' key
+//! algorithms, and how long they're valid for, and can be reported at the end of a scrape.
+//!
+//! A summary can be kept alongside any other output (see
+//! [`runner::Config::summary`](crate::runner::Config::summary)), or written out on its own
+//! with a [`SummaryWriter`].
+//!
+
+use gen_server::{GenServer, Status::Continue};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use url::Url;
+
+use crate::{
+	document::{Entry, EntryType},
+	filter::Candidate,
+	processor,
+	utils::format_date,
+	Error,
+};
+
+/// The validity periods that certificates are counted up by, as the longest period (in days)
+/// that goes in each bucket
+const VALIDITY_BUCKETS: [u64; 5] = [7, 47, 100, 200, 398];
+
+const DAY_MILLIS: u64 = 86_400_000;
+
+/// Counts of the entries in a scrape
+///
+/// A summary can be added to from several threads at once.
+#[derive(Debug, Default)]
+pub struct Summary {
+	counts: Mutex<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+	entries: u64,
+	entry_types: BTreeMap<String, u64>,
+	issuers: BTreeMap<String, u64>,
+	days: BTreeMap<String, u64>,
+	key_types: BTreeMap<String, u64>,
+	/// Keyed by the bucket's upper limit, in days, or `None` for certificates whose validity
+	/// period is longer than the longest bucket
+	validity: BTreeMap<Option<u64>, u64>,
+	/// Entries whose certificate couldn't be decoded, so most of the counts are missing them
+	undecodable: u64,
+}
+
+/// Count one more of something
+fn increment<K: Ord>(map: &mut BTreeMap<K, u64>, key: K) {
+	let count = map.entry(key).or_default();
+	*count = count.saturating_add(1);
+}
+
+impl Summary {
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Count an entry
+	pub fn add(&self, candidate: &Candidate<'_>) {
+		let entry_type = EntryType::of(candidate.response()).ok();
+		let decoded = candidate.decoded();
+		let Ok(mut counts) = self.counts.lock() else {
+			log::error!(
+				"Summary lock poisoned; entry {} not counted",
+				candidate.entry().entry_number
+			);
+			return;
+		};
+
+		counts.entries = counts.entries.saturating_add(1);
+		increment(
+			&mut counts.entry_types,
+			entry_type.map_or("unknown", EntryType::as_str).to_string(),
+		);
+		increment(&mut counts.days, format_date(candidate.entry().timestamp));
+
+		if decoded.error.is_some() && decoded.issuer.is_none() {
+			counts.undecodable = counts.undecodable.saturating_add(1);
+			return;
+		}
+		increment(
+			&mut counts.issuers,
+			decoded.issuer.clone().unwrap_or_default(),
+		);
+		increment(
+			&mut counts.key_types,
+			decoded
+				.key_type
+				.clone()
+				.unwrap_or_else(|| "unknown".to_string()),
+		);
+		if let (Some(not_before), Some(not_after)) = (decoded.not_before, decoded.not_after) {
+			let period = not_after.saturating_sub(not_before);
+			let bucket = VALIDITY_BUCKETS
+				.into_iter()
+				.find(|days| period <= days.saturating_mul(DAY_MILLIS));
+			increment(&mut counts.validity, bucket);
+		}
+	}
+
+	/// How many entries have been counted
+	#[must_use]
+	pub fn entry_count(&self) -> u64 {
+		self.counts.lock().map_or(0, |counts| counts.entries)
+	}
+}
+
+impl std::fmt::Display for Summary {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let Ok(counts) = self.counts.lock() else {
+			return writeln!(f, "(the summary was lost when a thread crashed)");
+		};
+
+		writeln!(f, "{:>12}  entries", counts.entries)?;
+		if counts.entries == 0 {
+			return Ok(());
+		}
+		if counts.undecodable > 0 {
+			writeln!(
+				f,
+				"{:>12}  entries whose certificate couldn't be decoded",
+				counts.undecodable
+			)?;
+		}
+
+		writeln!(f, "\nEntry types:")?;
+		for (entry_type, count) in &counts.entry_types {
+			writeln!(f, "{count:>12}  {entry_type}")?;
+		}
+
+		// Busiest issuers first, since there can be rather a lot of them
+		let mut issuers = counts.issuers.iter().collect::<Vec<_>>();
+		issuers.sort_by(|(a_name, a_count), (b_name, b_count)| {
+			b_count.cmp(a_count).then_with(|| a_name.cmp(b_name))
+		});
+		writeln!(f, "\nIssuers:")?;
+		for (issuer, count) in issuers {
+			writeln!(f, "{count:>12}  {issuer}")?;
+		}
+
+		writeln!(f, "\nDays logged:")?;
+		for (day, count) in &counts.days {
+			writeln!(f, "{count:>12}  {day}")?;
+		}
+
+		writeln!(f, "\nKey types:")?;
+		for (key_type, count) in &counts.key_types {
+			writeln!(f, "{count:>12}  {key_type}")?;
+		}
+
+		writeln!(f, "\nValidity periods:")?;
+		for (bucket, count) in &counts.validity {
+			if let Some(days) = bucket {
+				writeln!(f, "{count:>12}  up to {days} days")?;
+			} else {
+				writeln!(
+					f,
+					"{count:>12}  more than {} days",
+					VALIDITY_BUCKETS.last().unwrap_or(&0)
+				)?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Args<W: Write + Sync + Send> {
+	writer: W,
+	log_url: Url,
+
+	_m: PhantomData<W>,
+}
+
+impl<W: Write + Sync + Send> Args<W> {
+	#[must_use]
+	pub fn new(writer: W, log_url: Url) -> Self {
+		Args {
+			writer,
+			log_url,
+			_m: PhantomData,
+		}
+	}
+}
+
+/// Thread that counts up the scraped entries, and writes out the [`Summary`] (and nothing
+/// else) once the scrape is done
+#[derive(Debug)]
+pub struct SummaryWriter<W: Write + Sync + Send> {
+	writer: W,
+	log_url: Url,
+	summary: Summary,
+}
+
+impl<W: Write + Sync + Send> SummaryWriter<W> {
+	#[allow(clippy::result_large_err)] // Oh shoosh
+	fn finish(&mut self) -> Result<(), Error> {
+		write!(
+			self.writer,
+			"Summary of {}\n\n{}",
+			self.log_url, self.summary
+		)
+		.and_then(|()| self.writer.flush())
+		.map_err(|e| Error::output("summary", e))
+	}
+}
+
+impl<W: Write + Sync + Send> GenServer for SummaryWriter<W> {
+	type Args = Args<W>;
+	type Error = Error;
+	type Request = processor::Request;
+	type StopReason = ();
+
+	fn init(args: Args<W>) -> Result<Self, Self::Error> {
+		Ok(Self {
+			writer: args.writer,
+			log_url: args.log_url,
+			summary: Summary::new(),
+		})
+	}
+
+	fn handle_cast(
+		&mut self,
+		request: Self::Request,
+	) -> Result<gen_server::Status<Self>, Self::Error> {
+		match request {
+			processor::Request::Metadata(_) => Ok(Continue),
+			processor::Request::Entry(id, response, _) => {
+				let entry = Entry::from_response(id, &response, false, false)?;
+				self.summary.add(&Candidate::new(&response, entry));
+
+				Ok(Continue)
+			}
+		}
+	}
+
+	fn terminate(&mut self, _reason: Result<(), Error>) {
+		if let Err(e) = self.finish() {
+			log::error!("Failed to write summary: {e}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::filter::tests::{precert_response, x509_response};
+	use ct_structs::v1::response::ResponseEntry;
+
+	fn add(summary: &Summary, entry_number: u64, response: &ResponseEntry) {
+		let entry = Entry::from_response(entry_number, response, false, false).unwrap();
+		summary.add(&Candidate::new(response, entry));
+	}
+
+	#[test]
+	fn counts_everything() {
+		let summary = Summary::new();
+		add(&summary, 0, &x509_response());
+		add(&summary, 1, &x509_response());
+		add(&summary, 2, &precert_response());
+
+		assert_eq!(3, summary.entry_count());
+		assert_eq!(
+			"           3  entries
+
+Entry types:
+           1  precert
+           2  x509
+
+Issuers:
+           2  C=GB, ST=Greater Manchester, L=Salford, O=Sectigo Limited, CN=Sectigo RSA Domain Validation Secure Server CA
+           1  CN=Test SSLAdmin 4
+
+Days logged:
+           1  2018-07-24
+           2  2022-10-19
+
+Key types:
+           3  rsa-2048
+
+Validity periods:
+           1  up to 47 days
+           2  up to 100 days
+",
+			summary.to_string()
+		);
+	}
+
+	#[test]
+	fn leaves_out_empty_sections() {
+		assert_eq!("           0  entries\n", Summary::new().to_string());
+	}
+
+	#[test]
+	fn counts_certificates_that_dont_decode() {
+		let summary = Summary::new();
+		let response = x509_response();
+		summary.add(&Candidate::new(&response, Entry::new(0, 0, vec![1, 2, 3])));
+
+		let report = summary.to_string();
+		assert!(report.contains("1  entries whose certificate couldn't be decoded"));
+		assert!(report.contains("1  1970-01-01"));
+		assert!(!report.contains("rsa-2048"));
+	}
+
+	#[test]
+	fn writes_a_report() {
+		let mut buf = vec![];
+		{
+			let mut sw = SummaryWriter::init(Args::new(
+				&mut buf,
+				Url::parse("https://ct.example.com/").unwrap(),
+			))
+			.unwrap();
+			sw.summary
+				.add(&Candidate::new(&x509_response(), Entry::new(0, 0, vec![])));
+			sw.finish().unwrap();
+		}
+
+		let report = String::from_utf8(buf).unwrap();
+		assert!(report.starts_with("Summary of https://ct.example.com/\n\n           1  entries\n"));
+	}
+}
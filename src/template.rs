@@ -0,0 +1,143 @@
+//! Filename templates
+//!
+//! Where we need to write more than one output file, the names of those files are generated
+//! from a template, with placeholders (such as `{log}`) that get filled in with values relevant
+//! to each file.
+//!
+
+use std::collections::HashMap;
+use url::Url;
+
+/// A filename template, with placeholders surrounded by curly braces
+#[derive(Clone, Debug)]
+pub struct Template {
+	template: String,
+}
+
+impl Template {
+	/// Parse a template, checking that it only uses the given placeholders
+	pub fn new(template: &str, placeholders: &[&str]) -> Result<Self, String> {
+		for name in Self::placeholders(template)? {
+			if !placeholders.contains(&name) {
+				return Err(format!(
+					"unknown placeholder {{{name}}} in template {template:?} (valid placeholders are: {})",
+					placeholders
+						.iter()
+						.map(|p| format!("{{{p}}}"))
+						.collect::<Vec<_>>()
+						.join(", ")
+				));
+			}
+		}
+
+		Ok(Self {
+			template: template.to_string(),
+		})
+	}
+
+	/// Whether the template contains the given placeholder
+	#[must_use]
+	pub fn uses(&self, placeholder: &str) -> bool {
+		Self::placeholders(&self.template).is_ok_and(|p| p.contains(&placeholder))
+	}
+
+	/// Fill in the template's placeholders with the given values
+	///
+	/// Placeholders that don't have a value are left as-is.
+	#[must_use]
+	pub fn render(&self, values: &HashMap<&str, String>) -> String {
+		let mut s = self.template.clone();
+		for (name, value) in values {
+			s = s.replace(&format!("{{{name}}}"), value);
+		}
+		s
+	}
+
+	fn placeholders(template: &str) -> Result<Vec<&str>, String> {
+		let mut names = vec![];
+		let mut rest = template;
+
+		while let Some(start) = rest.find('{') {
+			let after = rest.get(start.saturating_add(1)..).unwrap_or_default();
+			let end = after
+				.find('}')
+				.ok_or_else(|| format!("unterminated placeholder in template {template:?}"))?;
+			names.push(after.get(..end).unwrap_or_default());
+			rest = after.get(end.saturating_add(1)..).unwrap_or_default();
+		}
+
+		Ok(names)
+	}
+}
+
+/// Turn a log URL into something that can be safely used as (part of) a filename
+///
+/// The scheme is dropped, and anything that isn't alphanumeric, a dash, or a dot, is turned
+/// into an underscore.
+#[must_use]
+pub fn log_slug(url: &Url) -> String {
+	let s = format!(
+		"{}{}{}",
+		url.host_str().unwrap_or_default(),
+		url.port().map(|p| format!("_{p}")).unwrap_or_default(),
+		url.path().trim_end_matches('/')
+	);
+
+	s.chars()
+		.map(|c| {
+			if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+				c
+			} else {
+				'_'
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn renders_placeholders() {
+		let t = Template::new("{log}-{n}.json", &["log", "n"]).unwrap();
+
+		assert_eq!(
+			"foo-42.json",
+			t.render(&HashMap::from([
+				("log", "foo".to_string()),
+				("n", "42".to_string())
+			]))
+		);
+	}
+
+	#[test]
+	fn rejects_unknown_placeholders() {
+		assert!(Template::new("{wat}.json", &["log"]).is_err());
+	}
+
+	#[test]
+	fn rejects_unterminated_placeholders() {
+		assert!(Template::new("{log.json", &["log"]).is_err());
+	}
+
+	#[test]
+	fn knows_what_it_uses() {
+		let t = Template::new("{log}.json", &["log", "n"]).unwrap();
+
+		assert!(t.uses("log"));
+		assert!(!t.uses("n"));
+	}
+
+	#[test]
+	fn slugs_log_urls() {
+		assert_eq!(
+			"ct.googleapis.com_logs_us1_argon2025h1",
+			log_slug(&Url::parse("https://ct.googleapis.com/logs/us1/argon2025h1/").unwrap())
+		);
+		assert_eq!(
+			"127.0.0.1_8080",
+			log_slug(&Url::parse("http://127.0.0.1:8080/").unwrap())
+		);
+	}
+}
@@ -0,0 +1,130 @@
+//! Just enough of the TLS presentation language to encode the CT structures we need
+//!
+//! Logs hash the TLS encoding of each entry's `MerkleTreeLeaf`, and serve it (along with the
+//! entry's `extra_data`) in that encoding, so when we don't have the bytes the log sent us, we
+//! have to put them back together ourselves.
+//!
+
+use ring::digest::{digest, SHA256};
+
+#[cfg(feature = "serve")]
+/// The largest value that fits in the 24-bit length prefixes TLS uses for certificates
+const MAX_U24: usize = 0x00FF_FFFF;
+
+#[cfg(feature = "serve")]
+/// What a `MerkleTreeLeaf` is a leaf for
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum LeafEntry<'a> {
+	X509 {
+		certificate: &'a [u8],
+	},
+	Precert {
+		issuer_key_hash: &'a [u8],
+		tbs_certificate: &'a [u8],
+	},
+}
+
+#[cfg(feature = "serve")]
+/// Append a TLS variable-length vector, with a length prefix of `len_bytes` bytes
+pub(crate) fn push_opaque(v: &mut Vec<u8>, data: &[u8], len_bytes: usize) -> Result<(), String> {
+	let max = if len_bytes == 3 {
+		MAX_U24
+	} else {
+		usize::from(u16::MAX)
+	};
+	if data.len() > max {
+		return Err(format!(
+			"{} byte value is too long to encode with a {len_bytes} byte length",
+			data.len()
+		));
+	}
+
+	let len = (data.len() as u64).to_be_bytes();
+	v.extend(len.iter().skip(len.len().saturating_sub(len_bytes)));
+	v.extend_from_slice(data);
+	Ok(())
+}
+
+#[cfg(feature = "serve")]
+/// Encode a list of certificates, as an `ASN.1Cert certificate_chain<0..2^24-1>`
+pub(crate) fn push_chain(v: &mut Vec<u8>, chain: &[&[u8]]) -> Result<(), String> {
+	let mut certs = vec![];
+	for c in chain {
+		push_opaque(&mut certs, c, 3)?;
+	}
+	push_opaque(v, &certs, 3)
+}
+
+#[cfg(feature = "serve")]
+/// The TLS encoding of a v1 `MerkleTreeLeaf` for a timestamped entry (with no extensions)
+pub(crate) fn merkle_tree_leaf(timestamp: u64, entry: LeafEntry<'_>) -> Result<Vec<u8>, String> {
+	let mut v = vec![
+		0, // version: v1
+		0, // leaf_type: timestamped_entry
+	];
+	v.extend_from_slice(&timestamp.to_be_bytes());
+
+	match entry {
+		LeafEntry::X509 { certificate } => {
+			v.extend_from_slice(&0u16.to_be_bytes()); // entry_type: x509_entry
+			push_opaque(&mut v, certificate, 3)?;
+		}
+		LeafEntry::Precert {
+			issuer_key_hash,
+			tbs_certificate,
+		} => {
+			v.extend_from_slice(&1u16.to_be_bytes()); // entry_type: precert_entry
+			v.extend_from_slice(issuer_key_hash);
+			push_opaque(&mut v, tbs_certificate, 3)?;
+		}
+	}
+
+	// No extensions
+	push_opaque(&mut v, &[], 2)?;
+
+	Ok(v)
+}
+
+/// The RFC 6962 leaf hash of an encoded `MerkleTreeLeaf`
+pub(crate) fn leaf_hash(leaf_input: &[u8]) -> Vec<u8> {
+	let mut prefixed = Vec::with_capacity(leaf_input.len().saturating_add(1));
+	prefixed.push(0);
+	prefixed.extend_from_slice(leaf_input);
+	digest(&SHA256, &prefixed).as_ref().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::hex;
+
+	#[test]
+	fn hashes_leaves() {
+		// The hash of an empty leaf, from the RFC 6962 test vectors
+		assert_eq!(
+			"6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+			hex(&leaf_hash(&[]))
+		);
+	}
+
+	#[cfg(feature = "serve")]
+	#[test]
+	fn encodes_x509_leaves() {
+		assert_eq!(
+			vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 0, 2, 1, 2, 0, 0],
+			merkle_tree_leaf(
+				42,
+				LeafEntry::X509 {
+					certificate: &[1, 2]
+				}
+			)
+			.unwrap()
+		);
+	}
+
+	#[cfg(feature = "serve")]
+	#[test]
+	fn refuses_oversized_values() {
+		assert!(push_opaque(&mut vec![], &[0; 0x1_0000], 2).is_err());
+	}
+}
@@ -1,5 +1,201 @@
+use std::time::{SystemTime, UNIX_EPOCH};
 use url::Url;
 
+use crate::Error;
+
+/// The number of milliseconds since the epoch
+#[allow(clippy::result_large_err)] // Oh shoosh
+pub(crate) fn current_time() -> Result<u64, Error> {
+	#[allow(clippy::expect_used)] // I'll take the risk
+	Ok(SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_err(|e| Error::system("we went back in time somehow", e))?
+		.as_millis()
+		.try_into()
+		.expect("wow this code has excellent shelf life"))
+}
+
+/// Lowercase hex, for when bytes need to be used as a string
+pub(crate) fn hex(bytes: &[u8]) -> String {
+	bytes
+		.iter()
+		.flat_map(|b| [b >> 4u8, b & 0xfu8])
+		.filter_map(|nybble| char::from_digit(u32::from(nybble), 16))
+		.collect()
+}
+
+/// Turn a hex string back into bytes, if it is one
+pub(crate) fn unhex(s: &str) -> Option<Vec<u8>> {
+	s.as_bytes()
+		.chunks(2)
+		.map(|pair| {
+			std::str::from_utf8(pair)
+				.ok()
+				.filter(|p| p.len() == 2)
+				.and_then(|p| u8::from_str_radix(p, 16).ok())
+		})
+		.collect()
+}
+
+/// Turn an RFC 3339 timestamp (like `2024-03-01T12:30:00Z`), or just a date (which is taken
+/// to be midnight UTC), into the number of milliseconds since the epoch
+///
+/// # Errors
+///
+/// If it isn't a timestamp we understand, or it's from before the epoch.
+pub fn parse_time(s: &str) -> Result<u64, String> {
+	rfc3339_millis(s).ok_or_else(|| {
+		format!("{s:?} isn't an RFC 3339 timestamp (like 2024-03-01T12:30:00Z) or a date (like 2024-03-01) since 1970")
+	})
+}
+
+/// Write out a number of milliseconds since the epoch as an RFC 3339 timestamp, the other way
+/// around from [`parse_time`]
+#[must_use]
+pub(crate) fn format_time(millis: u64) -> String {
+	let time = millis.rem_euclid(86_400_000);
+	let seconds = time.div_euclid(1000);
+
+	format!(
+		"{}T{:02}:{:02}:{:02}.{:03}Z",
+		format_date(millis),
+		seconds.div_euclid(3600),
+		seconds.div_euclid(60).rem_euclid(60),
+		seconds.rem_euclid(60),
+		time.rem_euclid(1000),
+	)
+}
+
+/// The (UTC) date of a number of milliseconds since the epoch, like `2024-03-01`
+#[must_use]
+pub(crate) fn format_date(millis: u64) -> String {
+	let mut days = millis.div_euclid(86_400_000);
+
+	let mut year = 1970;
+	while days >= days_in_year(year) {
+		days = days.saturating_sub(days_in_year(year));
+		year = year.saturating_add(1);
+	}
+	let mut month = 1;
+	while days >= days_in_month(year, month) {
+		days = days.saturating_sub(days_in_month(year, month));
+		month = month.saturating_add(1);
+	}
+
+	format!("{year:04}-{month:02}-{:02}", days.saturating_add(1))
+}
+
+fn rfc3339_millis(s: &str) -> Option<u64> {
+	let (date, time) = match s.split_once(['T', 't', ' ']) {
+		Some((date, time)) => (date, Some(time)),
+		None => (s, None),
+	};
+
+	let mut date_parts = date.split('-');
+	let year = number(date_parts.next()?, 4)?;
+	let month = number(date_parts.next()?, 2)?;
+	let day = number(date_parts.next()?, 2)?;
+	if date_parts.next().is_some() {
+		return None;
+	}
+	let midnight = days_since_epoch(year, month, day)?.checked_mul(86_400_000)?;
+
+	let Some(time) = time else {
+		return Some(midnight);
+	};
+
+	// What's left is HH:MM:SS, maybe some fractional seconds, and then the UTC offset
+	let (time, offset) = if let Some(t) = time.strip_suffix(['Z', 'z']) {
+		(t, None)
+	} else {
+		let (i, sign) = time.char_indices().rfind(|(_, c)| *c == '+' || *c == '-')?;
+		(time.get(..i)?, Some((sign, time.get(i.checked_add(1)?..)?)))
+	};
+
+	let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+	let mut time_parts = time.split(':');
+	let hours = number(time_parts.next()?, 2)?;
+	let minutes = number(time_parts.next()?, 2)?;
+	let seconds = number(time_parts.next()?, 2)?;
+	if time_parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+		return None;
+	}
+	if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	// Anything past milliseconds is more precision than we've got a use for
+	let millis = format!("{:0<3}", fraction.get(..3).unwrap_or(fraction))
+		.parse::<u64>()
+		.ok()?;
+
+	let local = midnight
+		.checked_add(hours.checked_mul(3_600_000)?)?
+		.checked_add(minutes.checked_mul(60_000)?)?
+		.checked_add(seconds.checked_mul(1000)?)?
+		.checked_add(millis)?;
+
+	let Some((sign, offset)) = offset else {
+		return Some(local);
+	};
+	let (offset_hours, offset_minutes) = offset.split_once(':')?;
+	let offset_millis = number(offset_hours, 2)?
+		.checked_mul(3_600_000)?
+		.checked_add(number(offset_minutes, 2)?.checked_mul(60_000)?)?;
+	// A time that's ahead of UTC happened *earlier* than the same time in UTC
+	if sign == '+' {
+		local.checked_sub(offset_millis)
+	} else {
+		local.checked_add(offset_millis)
+	}
+}
+
+/// A number with exactly the given number of digits
+fn number(s: &str, digits: usize) -> Option<u64> {
+	if s.len() == digits && s.bytes().all(|b| b.is_ascii_digit()) {
+		s.parse().ok()
+	} else {
+		None
+	}
+}
+
+fn is_leap_year(year: u64) -> bool {
+	year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_year(year: u64) -> u64 {
+	if is_leap_year(year) {
+		366
+	} else {
+		365
+	}
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+	match month {
+		2 if is_leap_year(year) => 29,
+		2 => 28,
+		4 | 6 | 9 | 11 => 30,
+		_ => 31,
+	}
+}
+
+/// How many days there are between the epoch and the given date, if it's a real date that
+/// isn't before the epoch
+fn days_since_epoch(year: u64, month: u64, day: u64) -> Option<u64> {
+	if year < 1970 || !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+		return None;
+	}
+
+	let mut days = 0u64;
+	for y in 1970..year {
+		days = days.checked_add(days_in_year(y))?;
+	}
+	for m in 1..month {
+		days = days.checked_add(days_in_month(year, m))?;
+	}
+	days.checked_add(day.checked_sub(1)?)
+}
+
 /// Url has some rather irritatingly precise ideas about joining URLs together:
 ///
 /// "Note: a trailing slash is significant. Without it, the last path component is considered to
@@ -20,6 +216,73 @@ pub fn fix_url(mut url: Url) -> Url {
 mod tests {
 	use super::*;
 
+	#[test]
+	fn parses_timestamps() {
+		assert_eq!(Ok(0), parse_time("1970-01-01T00:00:00Z"));
+		assert_eq!(Ok(1_709_296_200_000), parse_time("2024-03-01T12:30:00Z"));
+		assert_eq!(
+			Ok(1_709_296_200_123),
+			parse_time("2024-03-01t12:30:00.123456z")
+		);
+		assert_eq!(Ok(1_709_296_200_500), parse_time("2024-03-01 12:30:00.5Z"));
+	}
+
+	#[test]
+	fn parses_timestamps_with_offsets() {
+		assert_eq!(
+			Ok(1_709_296_200_000),
+			parse_time("2024-03-01T22:00:00+09:30")
+		);
+		assert_eq!(
+			Ok(1_709_296_200_000),
+			parse_time("2024-03-01T07:30:00-05:00")
+		);
+	}
+
+	#[test]
+	fn formats_timestamps() {
+		assert_eq!("1970-01-01T00:00:00.000Z", format_time(0));
+		assert_eq!("2024-02-29T23:59:59.999Z", format_time(1_709_251_199_999));
+		assert_eq!("2022-10-19T00:00:00.000Z", format_time(1_666_137_600_000));
+	}
+
+	#[test]
+	fn formats_dates() {
+		assert_eq!("1970-01-01", format_date(0));
+		assert_eq!("2024-02-29", format_date(1_709_251_199_999));
+		assert_eq!("2024-03-01", format_date(1_709_251_200_000));
+	}
+
+	#[test]
+	fn formats_what_it_parses() {
+		for s in ["2000-12-31T12:34:56.789Z", "2100-03-01T00:00:00.000Z"] {
+			assert_eq!(s, format_time(parse_time(s).unwrap()));
+		}
+	}
+
+	#[test]
+	fn parses_dates() {
+		assert_eq!(Ok(1_709_251_200_000), parse_time("2024-03-01"));
+		assert_eq!(Ok(951_782_400_000), parse_time("2000-02-29"));
+	}
+
+	#[test]
+	fn refuses_things_that_arent_timestamps() {
+		for s in [
+			"",
+			"yesterday",
+			"2024-3-1",
+			"2023-02-29",
+			"1969-12-31T23:59:59Z",
+			"2024-03-01T12:30Z",
+			"2024-03-01T12:30:00",
+			"2024-03-01T25:00:00Z",
+			"2024-03-01T12:30:00.1a2Z",
+		] {
+			assert!(parse_time(s).is_err(), "{s:?}");
+		}
+	}
+
 	#[test]
 	fn fixed_url_mangles_unslashed_base_domain() {
 		assert_eq!(
@@ -80,6 +343,19 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn hex_round_trips() {
+		assert_eq!("00ff1a", hex(&[0, 255, 26]));
+		assert_eq!(Some(vec![0, 255, 26]), unhex("00ff1a"));
+		assert_eq!(Some(vec![0, 255, 26]), unhex("00FF1A"));
+	}
+
+	#[test]
+	fn unhex_refuses_non_hex() {
+		assert_eq!(None, unhex("00f"));
+		assert_eq!(None, unhex("zz"));
+	}
+
 	#[test]
 	fn crate_example() {
 		assert_eq!(